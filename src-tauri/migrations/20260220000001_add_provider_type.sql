@@ -0,0 +1,14 @@
+-- Provider type and credential reference so providers can be instantiated from DB rows
+ALTER TABLE llm_providers
+    ADD COLUMN IF NOT EXISTS provider_type VARCHAR(50) NOT NULL DEFAULT 'openai_compatible';
+ALTER TABLE llm_providers
+    ADD COLUMN IF NOT EXISTS api_key_ref VARCHAR(255);
+
+-- Existing built-in providers keep their native API format
+UPDATE llm_providers
+SET provider_type = 'anthropic', api_key_ref = 'ANTHROPIC_API_KEY'
+WHERE name = 'anthropic';
+
+UPDATE llm_providers
+SET provider_type = 'google', api_key_ref = 'GOOGLE_AI_STUDIO_API_KEY'
+WHERE name = 'google';
@@ -0,0 +1,12 @@
+-- Per-provider rate limits (NULL = unlimited)
+ALTER TABLE llm_providers
+    ADD COLUMN IF NOT EXISTS requests_per_minute INTEGER CHECK (requests_per_minute > 0);
+ALTER TABLE llm_providers
+    ADD COLUMN IF NOT EXISTS tokens_per_minute INTEGER CHECK (tokens_per_minute > 0);
+ALTER TABLE llm_providers
+    ADD COLUMN IF NOT EXISTS max_concurrency INTEGER CHECK (max_concurrency > 0);
+
+-- Executions waiting for a provider slot are 'queued'
+ALTER TABLE agent_executions DROP CONSTRAINT IF EXISTS agent_executions_status_check;
+ALTER TABLE agent_executions ADD CONSTRAINT agent_executions_status_check
+    CHECK (status IN ('pending', 'queued', 'running', 'completed', 'failed'));
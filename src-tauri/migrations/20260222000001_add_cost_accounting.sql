@@ -0,0 +1,76 @@
+-- Model price table (USD per 1M tokens).
+-- `model` is matched as a prefix of the response model id; the longest match wins,
+-- so dated ids like 'claude-sonnet-4-5-20250929' resolve to 'claude-sonnet-4-5'.
+CREATE TABLE IF NOT EXISTS model_prices (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    provider_name VARCHAR(100) NOT NULL,
+    model VARCHAR(200) NOT NULL,
+    input_price DOUBLE PRECISION NOT NULL CHECK (input_price >= 0),
+    output_price DOUBLE PRECISION NOT NULL CHECK (output_price >= 0),
+    cache_read_price DOUBLE PRECISION CHECK (cache_read_price >= 0),
+    cache_write_price DOUBLE PRECISION CHECK (cache_write_price >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (provider_name, model)
+);
+
+CREATE TRIGGER update_model_prices_updated_at
+    BEFORE UPDATE ON model_prices
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+INSERT INTO model_prices (provider_name, model, input_price, output_price, cache_read_price, cache_write_price)
+VALUES
+    ('anthropic', 'claude-opus-4-5', 5.0, 25.0, 0.5, 6.25),
+    ('anthropic', 'claude-opus-4', 15.0, 75.0, 1.5, 18.75),
+    ('anthropic', 'claude-sonnet-4', 3.0, 15.0, 0.3, 3.75),
+    ('anthropic', 'claude-3-7-sonnet', 3.0, 15.0, 0.3, 3.75),
+    ('anthropic', 'claude-haiku-4-5', 1.0, 5.0, 0.1, 1.25),
+    ('anthropic', 'claude-3-5-haiku', 0.8, 4.0, 0.08, 1.0),
+    ('google', 'gemini-2.5-pro', 1.25, 10.0, 0.31, NULL),
+    ('google', 'gemini-2.5-flash-lite', 0.1, 0.4, 0.025, NULL),
+    ('google', 'gemini-2.5-flash', 0.3, 2.5, 0.075, NULL),
+    ('google', 'gemini-2.0-flash', 0.1, 0.4, 0.025, NULL)
+ON CONFLICT (provider_name, model) DO NOTHING;
+
+-- One row per LLM call (cost_usd is NULL when no price matched)
+CREATE TABLE IF NOT EXISTS llm_calls (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    execution_id UUID NOT NULL REFERENCES agent_executions(id) ON DELETE CASCADE,
+    orchestration_run_id UUID REFERENCES orchestration_runs(id) ON DELETE CASCADE,
+    provider_name VARCHAR(100) NOT NULL,
+    model VARCHAR(200) NOT NULL,
+    input_tokens INTEGER NOT NULL DEFAULT 0,
+    output_tokens INTEGER NOT NULL DEFAULT 0,
+    cache_read_tokens INTEGER NOT NULL DEFAULT 0,
+    cache_write_tokens INTEGER NOT NULL DEFAULT 0,
+    cost_usd DOUBLE PRECISION,
+    duration_ms BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_llm_calls_execution ON llm_calls(execution_id);
+CREATE INDEX IF NOT EXISTS idx_llm_calls_orchestration_run ON llm_calls(orchestration_run_id);
+
+-- Aggregated usage (input_tokens includes cache read/write tokens)
+ALTER TABLE agent_executions
+    ADD COLUMN IF NOT EXISTS total_input_tokens BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS total_output_tokens BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS total_cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0;
+ALTER TABLE workflow_runs
+    ADD COLUMN IF NOT EXISTS total_input_tokens BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS total_output_tokens BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS total_cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0;
+ALTER TABLE orchestration_runs
+    ADD COLUMN IF NOT EXISTS total_input_tokens BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS total_output_tokens BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS total_cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+-- Hard budget for orchestration runs (NULL = unlimited)
+ALTER TABLE orchestration_runs
+    ADD COLUMN IF NOT EXISTS budget_max_tokens BIGINT CHECK (budget_max_tokens > 0),
+    ADD COLUMN IF NOT EXISTS budget_max_cost_usd DOUBLE PRECISION CHECK (budget_max_cost_usd > 0);
+
+ALTER TABLE orchestration_runs DROP CONSTRAINT IF EXISTS orchestration_runs_status_check;
+ALTER TABLE orchestration_runs ADD CONSTRAINT orchestration_runs_status_check
+    CHECK (status IN ('running', 'awaiting_approval', 'completed', 'failed', 'rejected', 'budget_exceeded'));
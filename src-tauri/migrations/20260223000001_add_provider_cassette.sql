@@ -0,0 +1,5 @@
+-- Record/replay cassettes for offline tests and demos.
+-- provider_type 'replay' serves recorded responses from cassette_path;
+-- any other provider with cassette_path set records its calls there.
+ALTER TABLE llm_providers
+    ADD COLUMN IF NOT EXISTS cassette_path TEXT;
@@ -0,0 +1,3 @@
+-- Per-agent prompt caching (Anthropic cache_control breakpoints on system, tools and conversation)
+ALTER TABLE agents
+    ADD COLUMN IF NOT EXISTS prompt_cache BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,7 @@
+-- Per-agent extended thinking budget (NULL = disabled; Anthropic requires at least 1024 tokens)
+ALTER TABLE agents
+    ADD COLUMN IF NOT EXISTS thinking_budget_tokens INTEGER CHECK (thinking_budget_tokens >= 1024);
+
+-- Thinking output is stored apart from output_text
+ALTER TABLE agent_executions
+    ADD COLUMN IF NOT EXISTS thinking_text TEXT;
@@ -0,0 +1,8 @@
+-- Structured output: JSON Schema the agent's output must match (NULL = free text)
+ALTER TABLE agents
+    ADD COLUMN IF NOT EXISTS output_schema JSONB;
+
+-- Schema applied to the execution (agent default or per-execution override) and the parsed output
+ALTER TABLE agent_executions
+    ADD COLUMN IF NOT EXISTS output_schema JSONB,
+    ADD COLUMN IF NOT EXISTS output_json JSONB;
@@ -0,0 +1,5 @@
+-- Conversation-history compaction for orchestration tool loops
+ALTER TABLE orchestration_runs
+    ADD COLUMN IF NOT EXISTS context_token_limit INTEGER CHECK (context_token_limit > 0),
+    ADD COLUMN IF NOT EXISTS compaction_model VARCHAR(200),
+    ADD COLUMN IF NOT EXISTS compaction_log JSONB NOT NULL DEFAULT '[]'::jsonb;
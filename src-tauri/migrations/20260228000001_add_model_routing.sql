@@ -0,0 +1,29 @@
+-- Provider fallback chains and model routing policies
+
+-- Ordered fallback candidates tried when the agent's own provider/model fails
+CREATE TABLE IF NOT EXISTS agent_fallback_models (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    agent_id UUID NOT NULL REFERENCES agents(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL CHECK (position >= 0),
+    llm_provider_id UUID NOT NULL REFERENCES llm_providers(id) ON DELETE CASCADE,
+    model VARCHAR(200) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(agent_id, position)
+);
+
+CREATE INDEX IF NOT EXISTS idx_agent_fallback_models_agent_id
+    ON agent_fallback_models(agent_id);
+
+-- fallback_on: which failures move on to the next candidate
+-- sub_agent_*: provider/model for sub-agents created by this agent as an orchestrator
+ALTER TABLE agents
+    ADD COLUMN IF NOT EXISTS fallback_on VARCHAR(20) NOT NULL DEFAULT 'unavailable'
+        CHECK (fallback_on IN ('unavailable', 'server_error', 'any_error')),
+    ADD COLUMN IF NOT EXISTS sub_agent_llm_provider_id UUID REFERENCES llm_providers(id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS sub_agent_model VARCHAR(200);
+
+-- The provider/model that actually produced the (last) response
+ALTER TABLE agent_executions
+    ADD COLUMN IF NOT EXISTS answered_provider VARCHAR(100),
+    ADD COLUMN IF NOT EXISTS answered_model VARCHAR(200);
@@ -0,0 +1,7 @@
+-- Sampling parameters and tool-choice per agent
+ALTER TABLE agents
+    ADD COLUMN IF NOT EXISTS top_p DOUBLE PRECISION CHECK (top_p >= 0 AND top_p <= 1),
+    ADD COLUMN IF NOT EXISTS top_k INTEGER CHECK (top_k >= 1),
+    ADD COLUMN IF NOT EXISTS stop_sequences TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN IF NOT EXISTS seed BIGINT,
+    ADD COLUMN IF NOT EXISTS tool_choice JSONB;
@@ -0,0 +1,12 @@
+-- Automatic continuation of responses cut off at max_tokens, and the 'truncated' status
+ALTER TABLE agents
+    ADD COLUMN IF NOT EXISTS max_continuations INTEGER NOT NULL DEFAULT 0
+        CHECK (max_continuations >= 0 AND max_continuations <= 10);
+
+ALTER TABLE agent_executions DROP CONSTRAINT IF EXISTS agent_executions_status_check;
+ALTER TABLE agent_executions ADD CONSTRAINT agent_executions_status_check
+    CHECK (status IN ('pending', 'queued', 'running', 'completed', 'failed', 'truncated'));
+
+ALTER TABLE orchestration_runs DROP CONSTRAINT IF EXISTS orchestration_runs_status_check;
+ALTER TABLE orchestration_runs ADD CONSTRAINT orchestration_runs_status_check
+    CHECK (status IN ('running', 'awaiting_approval', 'completed', 'failed', 'rejected', 'budget_exceeded', 'truncated'));
@@ -0,0 +1,11 @@
+-- Audit trail of tool invocations: link to the orchestration run and the LLM tool_use block
+ALTER TABLE tool_executions
+    ADD COLUMN IF NOT EXISTS orchestration_run_id UUID REFERENCES orchestration_runs(id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS tool_use_id VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS output_truncated BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX IF NOT EXISTS idx_tool_executions_orchestration_run_id
+    ON tool_executions(orchestration_run_id);
+
+CREATE INDEX IF NOT EXISTS idx_tool_executions_created_at
+    ON tool_executions(created_at);
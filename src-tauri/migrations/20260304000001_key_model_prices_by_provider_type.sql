@@ -0,0 +1,26 @@
+-- Key model prices by provider type instead of the user-chosen provider name,
+-- so renamed providers and every openai_compatible provider get a price.
+ALTER TABLE model_prices RENAME COLUMN provider_name TO provider_type;
+ALTER TABLE model_prices RENAME CONSTRAINT model_prices_provider_name_model_key
+    TO model_prices_provider_type_model_key;
+
+-- Prices added for a specific provider name move to that provider's type
+-- (unless the type already has a price for the same model).
+UPDATE model_prices mp
+SET provider_type = p.provider_type
+FROM llm_providers p
+WHERE mp.provider_type = p.name
+  AND p.name <> p.provider_type
+  AND NOT EXISTS (
+      SELECT 1 FROM model_prices existing
+      WHERE existing.provider_type = p.provider_type AND existing.model = mp.model
+  );
+
+INSERT INTO model_prices (provider_type, model, input_price, output_price, cache_read_price, cache_write_price)
+VALUES
+    ('openai_compatible', 'gpt-4.1-nano', 0.1, 0.4, 0.025, NULL),
+    ('openai_compatible', 'gpt-4.1-mini', 0.4, 1.6, 0.1, NULL),
+    ('openai_compatible', 'gpt-4.1', 2.0, 8.0, 0.5, NULL),
+    ('openai_compatible', 'gpt-4o-mini', 0.15, 0.6, 0.075, NULL),
+    ('openai_compatible', 'gpt-4o', 2.5, 10.0, 1.25, NULL)
+ON CONFLICT (provider_type, model) DO NOTHING;
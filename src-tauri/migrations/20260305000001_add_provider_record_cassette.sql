@@ -0,0 +1,5 @@
+-- Recording is opt-in: only rows with record_cassette = true record their calls,
+-- to cassette_path relative to the app data directory's cassettes folder.
+-- Existing rows keep cassette_path but no longer record implicitly.
+ALTER TABLE llm_providers
+    ADD COLUMN IF NOT EXISTS record_cassette BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,4 @@
+-- A workflow run whose answer was cut off at max_tokens ends as 'truncated', like its execution
+ALTER TABLE workflow_runs DROP CONSTRAINT IF EXISTS workflow_runs_status_check;
+ALTER TABLE workflow_runs ADD CONSTRAINT workflow_runs_status_check
+    CHECK (status IN ('pending', 'running', 'completed', 'failed', 'cancelled', 'truncated'));
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use tauri::State;
 use uuid::Uuid;
 
@@ -9,13 +10,16 @@ use crate::error::AppError;
 use crate::event_bus::EventBus;
 use crate::llm::LlmRegistry;
 use crate::models::{
-    Agent, AgentExecution, AgentMessage, CreateAgentRequest, CreateUserRequest,
-    CreateWorkflowRequest, ExecuteAgentRequest, LlmProvider, OrchestrateRequest, OrchestrationRun,
-    User, Workflow,
+    Agent, AgentExecution, AgentMessage, AgentRouting, Attachment, CreateAgentRequest,
+    CreateLlmProviderRequest, CreateUserRequest, CreateWorkflowRequest, CredentialInfo,
+    EmbeddingsRequest, EmbeddingsResponse, ExecuteAgentRequest, FallbackModelEntry, LlmProvider,
+    ModelCatalog, OrchestrateRequest, OrchestrationRun, ProviderStatus, ToolExecution,
+    ToolExecutionQuery, UpdateAgentRoutingRequest, UpdateLlmProviderRequest,
+    UpdateToolPermissionsRequest, User, Workflow,
 };
 use crate::services::{
-    agent_service, auth_service, execution_service, orchestration, user_service,
-    workflow_service,
+    agent_service, auth_service, credential_service, embedding_service, execution_service,
+    llm_provider_service, orchestration, tool_service, user_service, workflow_service,
 };
 use crate::tools::ToolRegistry;
 
@@ -118,6 +122,7 @@ pub async fn get_workflow(db: State<'_, DbPool>, id: Uuid) -> Result<Workflow, A
 #[tauri::command(rename_all = "snake_case")]
 pub async fn create_agent(
     db: State<'_, DbPool>,
+    registry: State<'_, Arc<LlmRegistry>>,
     workflow_id: Uuid,
     llm_provider_id: Uuid,
     name: String,
@@ -126,6 +131,15 @@ pub async fn create_agent(
     model: Option<String>,
     temperature: Option<f64>,
     max_tokens: Option<i32>,
+    prompt_cache: Option<bool>,
+    thinking_budget_tokens: Option<i32>,
+    output_schema: Option<serde_json::Value>,
+    top_p: Option<f64>,
+    top_k: Option<i32>,
+    stop_sequences: Option<Vec<String>>,
+    seed: Option<i64>,
+    tool_choice: Option<serde_json::Value>,
+    max_continuations: Option<i32>,
 ) -> Result<Agent, AppError> {
     let request = CreateAgentRequest {
         workflow_id,
@@ -136,8 +150,17 @@ pub async fn create_agent(
         model,
         temperature,
         max_tokens,
+        prompt_cache,
+        thinking_budget_tokens,
+        output_schema,
+        top_p,
+        top_k,
+        stop_sequences,
+        seed,
+        tool_choice,
+        max_continuations,
     };
-    agent_service::create_agent(&db, &request).await
+    agent_service::create_agent(&db, &registry, &request).await
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -145,11 +168,175 @@ pub async fn get_agents(db: State<'_, DbPool>, workflow_id: Uuid) -> Result<Vec<
     agent_service::get_agents_by_workflow(&db, workflow_id).await
 }
 
+#[tauri::command(rename_all = "snake_case")]
+pub async fn update_agent_routing(
+    db: State<'_, DbPool>,
+    agent_id: Uuid,
+    fallbacks: Vec<FallbackModelEntry>,
+    fallback_on: Option<String>,
+    sub_agent_llm_provider_id: Option<Uuid>,
+    sub_agent_model: Option<String>,
+) -> Result<AgentRouting, AppError> {
+    let request = UpdateAgentRoutingRequest {
+        agent_id,
+        fallbacks,
+        fallback_on,
+        sub_agent_llm_provider_id,
+        sub_agent_model,
+    };
+    agent_service::update_agent_routing(&db, &request).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_agent_routing(
+    db: State<'_, DbPool>,
+    agent_id: Uuid,
+) -> Result<AgentRouting, AppError> {
+    agent_service::get_agent_routing(&db, agent_id).await
+}
+
 #[tauri::command]
 pub async fn get_llm_providers(db: State<'_, DbPool>) -> Result<Vec<LlmProvider>, AppError> {
     agent_service::get_llm_providers(&db).await
 }
 
+#[tauri::command(rename_all = "snake_case")]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_llm_provider(
+    db: State<'_, DbPool>,
+    registry: State<'_, Arc<LlmRegistry>>,
+    name: String,
+    display_name: String,
+    provider_type: String,
+    api_base_url: Option<String>,
+    api_key_ref: Option<String>,
+    is_enabled: Option<bool>,
+    requests_per_minute: Option<i32>,
+    tokens_per_minute: Option<i32>,
+    max_concurrency: Option<i32>,
+    cassette_path: Option<String>,
+    record_cassette: Option<bool>,
+) -> Result<LlmProvider, AppError> {
+    let request = CreateLlmProviderRequest {
+        name,
+        display_name,
+        provider_type,
+        api_base_url,
+        api_key_ref,
+        is_enabled,
+        requests_per_minute,
+        tokens_per_minute,
+        max_concurrency,
+        cassette_path,
+        record_cassette,
+    };
+    llm_provider_service::create_provider(&db, &registry, &request).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_llm_provider(
+    db: State<'_, DbPool>,
+    registry: State<'_, Arc<LlmRegistry>>,
+    id: Uuid,
+    display_name: Option<String>,
+    provider_type: Option<String>,
+    api_base_url: Option<String>,
+    api_key_ref: Option<String>,
+    is_enabled: Option<bool>,
+    requests_per_minute: Option<i32>,
+    tokens_per_minute: Option<i32>,
+    max_concurrency: Option<i32>,
+    cassette_path: Option<String>,
+    record_cassette: Option<bool>,
+) -> Result<LlmProvider, AppError> {
+    let request = UpdateLlmProviderRequest {
+        display_name,
+        provider_type,
+        api_base_url,
+        api_key_ref,
+        is_enabled,
+        requests_per_minute,
+        tokens_per_minute,
+        max_concurrency,
+        cassette_path,
+        record_cassette,
+    };
+    llm_provider_service::update_provider(&db, &registry, id, &request).await
+}
+
+#[tauri::command]
+pub async fn test_llm_provider(
+    db: State<'_, DbPool>,
+    id: Uuid,
+) -> Result<ProviderStatus, AppError> {
+    llm_provider_service::test_provider(&db, id).await
+}
+
+#[tauri::command]
+pub async fn list_llm_provider_models(
+    db: State<'_, DbPool>,
+    registry: State<'_, Arc<LlmRegistry>>,
+    id: Uuid,
+) -> Result<ModelCatalog, AppError> {
+    llm_provider_service::list_models(&db, &registry, id).await
+}
+
+#[tauri::command]
+pub async fn reload_llm_providers(
+    db: State<'_, DbPool>,
+    registry: State<'_, Arc<LlmRegistry>>,
+) -> Result<Vec<ProviderStatus>, AppError> {
+    llm_provider_service::reload_registry(&db, &registry).await
+}
+
+// --- Embedding commands ---
+
+#[tauri::command]
+pub async fn create_embeddings(
+    registry: State<'_, Arc<LlmRegistry>>,
+    provider_name: String,
+    model: Option<String>,
+    inputs: Vec<String>,
+    task: Option<String>,
+    dimensions: Option<u32>,
+) -> Result<EmbeddingsResponse, AppError> {
+    let request = EmbeddingsRequest {
+        provider_name,
+        model,
+        inputs,
+        task,
+        dimensions,
+    };
+    embedding_service::create_embeddings(&registry, request).await
+}
+
+// --- Credential commands ---
+
+#[tauri::command]
+pub async fn list_credentials() -> Result<Vec<CredentialInfo>, AppError> {
+    credential_service::list_credentials()
+}
+
+#[tauri::command]
+pub async fn set_credential(
+    db: State<'_, DbPool>,
+    registry: State<'_, Arc<LlmRegistry>>,
+    name: String,
+    value: String,
+) -> Result<CredentialInfo, AppError> {
+    credential_service::set_credential(&db, &registry, &name, &value).await
+}
+
+#[tauri::command]
+pub async fn delete_credential(
+    db: State<'_, DbPool>,
+    registry: State<'_, Arc<LlmRegistry>>,
+    name: String,
+) -> Result<(), AppError> {
+    credential_service::delete_credential(&db, &registry, &name).await
+}
+
 // --- Execution commands ---
 
 #[tauri::command(rename_all = "snake_case")]
@@ -159,8 +346,15 @@ pub async fn execute_agent(
     event_bus: State<'_, EventBus>,
     agent_id: Uuid,
     input: String,
+    attachments: Option<Vec<Attachment>>,
+    output_schema: Option<serde_json::Value>,
 ) -> Result<AgentExecution, AppError> {
-    let request = ExecuteAgentRequest { agent_id, input };
+    let request = ExecuteAgentRequest {
+        agent_id,
+        input,
+        attachments: attachments.unwrap_or_default(),
+        output_schema,
+    };
     execution_service::execute_agent(&db, &registry, &event_bus, &request).await
 }
 
@@ -180,6 +374,7 @@ pub async fn get_execution_messages(
 // --- Orchestration commands ---
 
 #[tauri::command(rename_all = "snake_case")]
+#[allow(clippy::too_many_arguments)]
 pub async fn orchestrate_agent(
     db: State<'_, DbPool>,
     registry: State<'_, Arc<LlmRegistry>>,
@@ -188,11 +383,19 @@ pub async fn orchestrate_agent(
     agent_id: Uuid,
     input: String,
     mode: String,
+    max_tokens_budget: Option<i64>,
+    max_cost_usd: Option<f64>,
+    context_token_limit: Option<i32>,
+    compaction_model: Option<String>,
 ) -> Result<OrchestrationRun, AppError> {
     let request = OrchestrateRequest {
         agent_id,
         input,
         mode,
+        max_tokens_budget,
+        max_cost_usd,
+        context_token_limit,
+        compaction_model,
     };
     orchestration::orchestrate_agent(&db, &registry, &event_bus, &tool_registry, &request).await
 }
@@ -238,26 +441,12 @@ pub async fn list_tools(
 #[tauri::command(rename_all = "snake_case")]
 pub async fn update_tool_permissions(
     db: State<'_, DbPool>,
+    tool_registry: State<'_, Arc<ToolRegistry>>,
     agent_id: Uuid,
     tools: Vec<crate::models::ToolPermissionEntry>,
 ) -> Result<serde_json::Value, AppError> {
-    let pool = db.get()?;
-    for entry in &tools {
-        sqlx::query(
-            r#"
-            INSERT INTO agent_tool_permissions (agent_id, tool_name, is_enabled, config)
-            VALUES ($1, $2, $3, $4)
-            ON CONFLICT (agent_id, tool_name) DO UPDATE
-            SET is_enabled = EXCLUDED.is_enabled, config = EXCLUDED.config, updated_at = NOW()
-            "#,
-        )
-        .bind(agent_id)
-        .bind(&entry.tool_name)
-        .bind(entry.is_enabled)
-        .bind(&entry.config)
-        .execute(&pool)
-        .await?;
-    }
+    let request = UpdateToolPermissionsRequest { agent_id, tools };
+    tool_service::update_tool_permissions(&db, &tool_registry, &request).await?;
     Ok(serde_json::json!({ "status": "ok" }))
 }
 
@@ -266,12 +455,26 @@ pub async fn get_tool_permissions(
     db: State<'_, DbPool>,
     agent_id: Uuid,
 ) -> Result<Vec<crate::models::AgentToolPermission>, AppError> {
-    let pool = db.get()?;
-    let permissions = sqlx::query_as::<_, crate::models::AgentToolPermission>(
-        "SELECT * FROM agent_tool_permissions WHERE agent_id = $1 ORDER BY tool_name",
-    )
-    .bind(agent_id)
-    .fetch_all(&pool)
-    .await?;
-    Ok(permissions)
+    tool_service::get_tool_permissions(&db, agent_id).await
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn list_tool_executions(
+    db: State<'_, DbPool>,
+    execution_id: Option<Uuid>,
+    orchestration_run_id: Option<Uuid>,
+    tool_name: Option<String>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+) -> Result<Vec<ToolExecution>, AppError> {
+    let query = ToolExecutionQuery {
+        execution_id,
+        orchestration_run_id,
+        tool_name,
+        since,
+        until,
+        limit,
+    };
+    tool_service::list_tool_executions(&db, &query).await
 }
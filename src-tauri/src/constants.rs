@@ -1,29 +1,79 @@
 // ステータス
-#[allow(dead_code)]
+pub const STATUS_QUEUED: &str = "queued";
 pub const STATUS_RUNNING: &str = "running";
 pub const STATUS_COMPLETED: &str = "completed";
 pub const STATUS_FAILED: &str = "failed";
 pub const STATUS_REJECTED: &str = "rejected";
 pub const STATUS_AWAITING_APPROVAL: &str = "awaiting_approval";
+pub const STATUS_BUDGET_EXCEEDED: &str = "budget_exceeded";
+/// 出力トークンの上限で打ち切られたまま終わった
+pub const STATUS_TRUNCATED: &str = "truncated";
 
 // オーケストレーションモード
 pub const MODE_AUTOMATIC: &str = "automatic";
 pub const MODE_APPROVAL: &str = "approval";
 
+// LLMプロバイダー種別（llm_providers.provider_type）
+pub const PROVIDER_TYPE_ANTHROPIC: &str = "anthropic";
+pub const PROVIDER_TYPE_GOOGLE: &str = "google";
+pub const PROVIDER_TYPE_OPENAI_COMPATIBLE: &str = "openai_compatible";
+/// 記録済みカセットを再生するモック
+pub const PROVIDER_TYPE_REPLAY: &str = "replay";
+
+// モデル未指定でエージェントを作成したときの既定モデル
+pub const DEFAULT_MODEL_ANTHROPIC: &str = "claude-sonnet-4-5-20250929";
+pub const DEFAULT_MODEL_GOOGLE: &str = "gemini-2.5-flash";
+
+/// 埋め込みモデル未指定時の既定モデル（Gemini）
+pub const DEFAULT_EMBEDDING_MODEL_GOOGLE: &str = "gemini-embedding-001";
+
+// 埋め込みの用途（/api/embeddings の task）
+pub const EMBEDDING_TASK_QUERY: &str = "query";
+pub const EMBEDDING_TASK_DOCUMENT: &str = "document";
+pub const EMBEDDING_TASK_SIMILARITY: &str = "similarity";
+
+/// 1回の埋め込みリクエストで受け付ける入力の最大件数（プロバイダーへはバッチに分けて送る）
+pub const MAX_EMBEDDING_INPUTS: usize = 2048;
+
+// モデル一覧の取得元
+pub const MODEL_CATALOG_SOURCE_API: &str = "api";
+pub const MODEL_CATALOG_SOURCE_STATIC: &str = "static";
+
+// フォールバック方針（agents.fallback_on）
+pub const FALLBACK_ON_UNAVAILABLE: &str = "unavailable";
+pub const FALLBACK_ON_SERVER_ERROR: &str = "server_error";
+pub const FALLBACK_ON_ANY_ERROR: &str = "any_error";
+
 // メッセージロール
 pub const ROLE_USER: &str = "user";
 pub const ROLE_ASSISTANT: &str = "assistant";
 
 // LLMストップリーズン
 pub const STOP_END_TURN: &str = "end_turn";
+pub const STOP_MAX_TOKENS: &str = "max_tokens";
 pub const STOP_STOP: &str = "stop";
 pub const STOP_TOOL_USE: &str = "tool_use";
 
+/// 出力上限で打ち切られた応答の続きを書かせる回数の上限（agents.max_continuations）
+pub const MAX_CONTINUATIONS: i32 = 10;
+
+/// 拡張思考の予算の下限（Anthropic の最小値）
+pub const MIN_THINKING_BUDGET_TOKENS: i32 = 1024;
+
 // ツール名（オーケストレーション組み込み）
 pub const TOOL_CREATE_SUB_AGENT: &str = "create_sub_agent";
 pub const TOOL_EXECUTE_SUB_AGENT: &str = "execute_sub_agent";
 pub const TOOL_GET_SUB_AGENT_RESULT: &str = "get_sub_agent_result";
 
+/// tool_executions に保存するツール出力の上限（バイト）。超えた分は切り詰める
+pub const TOOL_AUDIT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+/// tool_executions に保存するツール入力の上限（バイト）。超えたら先頭だけを保存する
+pub const TOOL_AUDIT_MAX_INPUT_BYTES: usize = 64 * 1024;
+
+// ツール実行履歴の取得件数
+pub const TOOL_EXECUTIONS_DEFAULT_LIMIT: i64 = 100;
+pub const TOOL_EXECUTIONS_MAX_LIMIT: i64 = 1000;
+
 // ツール名（外部ツール）
 #[allow(dead_code)]
 pub const TOOL_WEB_FETCH: &str = "web_fetch";
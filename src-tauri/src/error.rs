@@ -31,6 +31,15 @@ pub enum AppError {
     #[error("LLM error: {0}")]
     LlmError(String),
 
+    /// 過負荷・レート制限・接続断など、時間をおけば成功しうるLLMエラー
+    #[error("LLM provider temporarily unavailable: {message}")]
+    LlmUnavailable {
+        message: String,
+        retry_after: Option<std::time::Duration>,
+        /// レート制限（429）による失敗。フォールバック方針の判定に使う
+        rate_limited: bool,
+    },
+
     #[error("WebSocket error: {0}")]
     #[allow(dead_code)]
     WebSocketError(String),
@@ -67,6 +76,7 @@ impl AppError {
             AppError::ProviderNotConfigured(_) => "provider_not_configured",
             AppError::Internal(_) => "internal_error",
             AppError::LlmError(_) => "llm_error",
+            AppError::LlmUnavailable { .. } => "llm_unavailable",
             AppError::WebSocketError(_) => "websocket_error",
             AppError::ToolExecutionFailed(_) => "tool_execution_failed",
             AppError::ToolPermissionDenied(_) => "tool_permission_denied",
@@ -87,6 +97,7 @@ impl IntoResponse for AppError {
             AppError::ProviderNotConfigured(_) => StatusCode::BAD_REQUEST,
             AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::LlmError(_) => StatusCode::BAD_GATEWAY,
+            AppError::LlmUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
             AppError::WebSocketError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::ToolExecutionFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::ToolPermissionDenied(_) => StatusCode::FORBIDDEN,
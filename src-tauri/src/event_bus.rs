@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 use uuid::Uuid;
 
+use crate::llm::types::StreamDelta;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ExecutionEvent {
@@ -19,17 +21,61 @@ pub enum ExecutionEvent {
         execution_id: Uuid,
         agent_id: Uuid,
     },
+    /// プロバイダーの同時実行数・レート制限の空き待ち
+    AgentExecutionQueued {
+        execution_id: Uuid,
+        agent_id: Uuid,
+        provider: String,
+    },
+    /// 待ちが解消され、LLM呼び出しを開始した
+    AgentExecutionDequeued {
+        execution_id: Uuid,
+        agent_id: Uuid,
+    },
     AgentExecutionProgress {
         execution_id: Uuid,
         agent_id: Uuid,
         message: String,
     },
+    /// LLM ストリーミングの増分（テキスト / tool_use）
+    AgentExecutionDelta {
+        execution_id: Uuid,
+        agent_id: Uuid,
+        delta: StreamDelta,
+    },
+    /// 一時的なLLMエラーによる再試行（それまでに流した増分は破棄される）
+    AgentExecutionRetrying {
+        execution_id: Uuid,
+        agent_id: Uuid,
+        attempt: u32,
+        max_attempts: u32,
+        delay_ms: u64,
+        error: String,
+    },
+    /// 失敗したモデルからフォールバック先への切り替え（それまでに流した増分は破棄される）
+    AgentExecutionFallback {
+        execution_id: Uuid,
+        agent_id: Uuid,
+        from_provider: String,
+        from_model: String,
+        to_provider: String,
+        to_model: String,
+        error: String,
+    },
     AgentExecutionCompleted {
         execution_id: Uuid,
         agent_id: Uuid,
         output: String,
         duration_ms: i64,
     },
+    /// 続きを書かせても出力トークンの上限で終わった（output は途中まで）
+    AgentExecutionTruncated {
+        execution_id: Uuid,
+        agent_id: Uuid,
+        output: String,
+        duration_ms: i64,
+        continuations: i32,
+    },
     AgentExecutionFailed {
         execution_id: Uuid,
         agent_id: Uuid,
@@ -61,6 +107,12 @@ pub enum ExecutionEvent {
         orchestrator_agent_id: Uuid,
         output: String,
     },
+    OrchestratorTruncated {
+        orchestration_run_id: Uuid,
+        orchestrator_agent_id: Uuid,
+        output: String,
+        continuations: i32,
+    },
     OrchestratorFailed {
         orchestration_run_id: Uuid,
         orchestrator_agent_id: Uuid,
@@ -69,10 +121,12 @@ pub enum ExecutionEvent {
     // --- Tool execution events ---
     ToolExecutionStarted {
         execution_id: Uuid,
+        tool_use_id: String,
         tool_name: String,
     },
     ToolExecutionCompleted {
         execution_id: Uuid,
+        tool_use_id: String,
         tool_name: String,
         duration_ms: u64,
         is_error: bool,
@@ -9,13 +9,16 @@ use crate::error::AppError;
 use crate::event_bus::EventBus;
 use crate::llm::LlmRegistry;
 use crate::models::{
-    Agent, AgentExecution, AgentMessage, CreateAgentRequest, CreateUserRequest,
-    CreateWorkflowRequest, ExecuteAgentRequest, LlmProvider, OrchestrateRequest, OrchestrationRun,
+    Agent, AgentExecution, AgentMessage, AgentRouting, CreateAgentRequest,
+    CreateLlmProviderRequest, CreateUserRequest, CreateWorkflowRequest, CredentialInfo,
+    EmbeddingsRequest, EmbeddingsResponse, ExecuteAgentRequest, LlmProvider, ModelCatalog,
+    OrchestrateRequest, OrchestrationRun, ProviderStatus, SetCredentialRequest, ToolExecution,
+    ToolExecutionQuery, UpdateAgentRoutingRequest, UpdateLlmProviderRequest,
     UpdateToolPermissionsRequest, User, Workflow,
 };
 use crate::services::{
-    agent_service, auth_service, execution_service, orchestration, user_service,
-    workflow_service,
+    agent_service, auth_service, credential_service, embedding_service, execution_service,
+    llm_provider_service, orchestration, tool_service, user_service, workflow_service,
 };
 use crate::tools::ToolRegistry;
 
@@ -127,10 +130,26 @@ pub async fn create_agent_handler(
     State(state): State<AppState>,
     Json(request): Json<CreateAgentRequest>,
 ) -> Result<Json<Agent>, AppError> {
-    let agent = agent_service::create_agent(&state.db, &request).await?;
+    let agent = agent_service::create_agent(&state.db, &state.llm_registry, &request).await?;
     Ok(Json(agent))
 }
 
+pub async fn update_agent_routing_handler(
+    State(state): State<AppState>,
+    Json(request): Json<UpdateAgentRoutingRequest>,
+) -> Result<Json<AgentRouting>, AppError> {
+    let routing = agent_service::update_agent_routing(&state.db, &request).await?;
+    Ok(Json(routing))
+}
+
+pub async fn get_agent_routing_handler(
+    State(state): State<AppState>,
+    Path(agent_id): Path<Uuid>,
+) -> Result<Json<AgentRouting>, AppError> {
+    let routing = agent_service::get_agent_routing(&state.db, agent_id).await?;
+    Ok(Json(routing))
+}
+
 #[derive(serde::Deserialize)]
 pub struct WorkflowIdQuery {
     pub workflow_id: Uuid,
@@ -151,6 +170,87 @@ pub async fn get_llm_providers_handler(
     Ok(Json(providers))
 }
 
+pub async fn create_llm_provider_handler(
+    State(state): State<AppState>,
+    Json(request): Json<CreateLlmProviderRequest>,
+) -> Result<Json<LlmProvider>, AppError> {
+    let provider =
+        llm_provider_service::create_provider(&state.db, &state.llm_registry, &request).await?;
+    Ok(Json(provider))
+}
+
+pub async fn update_llm_provider_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<UpdateLlmProviderRequest>,
+) -> Result<Json<LlmProvider>, AppError> {
+    let provider =
+        llm_provider_service::update_provider(&state.db, &state.llm_registry, id, &request).await?;
+    Ok(Json(provider))
+}
+
+pub async fn test_llm_provider_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ProviderStatus>, AppError> {
+    let status = llm_provider_service::test_provider(&state.db, id).await?;
+    Ok(Json(status))
+}
+
+pub async fn list_llm_provider_models_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ModelCatalog>, AppError> {
+    let catalog = llm_provider_service::list_models(&state.db, &state.llm_registry, id).await?;
+    Ok(Json(catalog))
+}
+
+pub async fn reload_llm_providers_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProviderStatus>>, AppError> {
+    let statuses = llm_provider_service::reload_registry(&state.db, &state.llm_registry).await?;
+    Ok(Json(statuses))
+}
+
+// --- Embedding handlers ---
+
+pub async fn create_embeddings_handler(
+    State(state): State<AppState>,
+    Json(request): Json<EmbeddingsRequest>,
+) -> Result<Json<EmbeddingsResponse>, AppError> {
+    let response = embedding_service::create_embeddings(&state.llm_registry, request).await?;
+    Ok(Json(response))
+}
+
+// --- Credential handlers ---
+
+pub async fn list_credentials_handler() -> Result<Json<Vec<CredentialInfo>>, AppError> {
+    let credentials = credential_service::list_credentials()?;
+    Ok(Json(credentials))
+}
+
+pub async fn set_credential_handler(
+    State(state): State<AppState>,
+    Json(request): Json<SetCredentialRequest>,
+) -> Result<Json<CredentialInfo>, AppError> {
+    let info = credential_service::set_credential(
+        &state.db,
+        &state.llm_registry,
+        &request.name,
+        &request.value,
+    )
+    .await?;
+    Ok(Json(info))
+}
+
+pub async fn delete_credential_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<()>, AppError> {
+    credential_service::delete_credential(&state.db, &state.llm_registry, &name).await?;
+    Ok(Json(()))
+}
+
 // --- Execution handlers ---
 
 pub async fn execute_agent_handler(
@@ -242,30 +342,12 @@ pub async fn list_tools_handler(
     Ok(Json(definitions))
 }
 
-/// エージェントのツール権限を更新
+/// エージェントのツール権限を更新（ツール設定は保存前に検証する）
 pub async fn update_tool_permissions_handler(
     State(state): State<AppState>,
     Json(request): Json<UpdateToolPermissionsRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let pool = state.db.get()?;
-
-    for entry in &request.tools {
-        sqlx::query(
-            r#"
-            INSERT INTO agent_tool_permissions (agent_id, tool_name, is_enabled, config)
-            VALUES ($1, $2, $3, $4)
-            ON CONFLICT (agent_id, tool_name) DO UPDATE
-            SET is_enabled = EXCLUDED.is_enabled, config = EXCLUDED.config, updated_at = NOW()
-            "#,
-        )
-        .bind(request.agent_id)
-        .bind(&entry.tool_name)
-        .bind(entry.is_enabled)
-        .bind(&entry.config)
-        .execute(&pool)
-        .await?;
-    }
-
+    tool_service::update_tool_permissions(&state.db, &state.tool_registry, &request).await?;
     Ok(Json(serde_json::json!({ "status": "ok" })))
 }
 
@@ -274,13 +356,15 @@ pub async fn get_tool_permissions_handler(
     State(state): State<AppState>,
     Path(agent_id): Path<Uuid>,
 ) -> Result<Json<Vec<crate::models::AgentToolPermission>>, AppError> {
-    let pool = state.db.get()?;
-    let permissions = sqlx::query_as::<_, crate::models::AgentToolPermission>(
-        "SELECT * FROM agent_tool_permissions WHERE agent_id = $1 ORDER BY tool_name",
-    )
-    .bind(agent_id)
-    .fetch_all(&pool)
-    .await?;
-
+    let permissions = tool_service::get_tool_permissions(&state.db, agent_id).await?;
     Ok(Json(permissions))
 }
+
+/// ツール実行履歴を取得（実行・ツール名・期間で絞り込み）
+pub async fn list_tool_executions_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ToolExecutionQuery>,
+) -> Result<Json<Vec<ToolExecution>>, AppError> {
+    let executions = tool_service::list_tool_executions(&state.db, &query).await?;
+    Ok(Json(executions))
+}
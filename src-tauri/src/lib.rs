@@ -7,10 +7,12 @@ mod event_bus;
 mod handlers;
 mod llm;
 mod models;
+mod secrets;
 mod services;
 mod tools;
 mod ws;
 
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use axum::routing::{get, post};
@@ -39,14 +41,22 @@ fn get_api_port(port: tauri::State<'_, ApiPort>) -> u16 {
 }
 
 /// Initialize ToolRegistry with all available tools
-fn init_tool_registry() -> tools::ToolRegistry {
+fn init_tool_registry(llm_registry: Arc<LlmRegistry>, data_dir: PathBuf) -> tools::ToolRegistry {
     let mut registry = tools::ToolRegistry::new();
-    registry.register(Box::new(tools::web_fetch::WebFetchTool::new()));
+    registry.register(Box::new(tools::web_fetch::WebFetchTool));
     registry.register(Box::new(tools::web_search::WebSearchTool::new()));
+    registry.register(Box::new(tools::file_read::FileReadTool));
+    registry.register(Box::new(tools::list_dir::ListDirTool));
+    registry.register(Box::new(tools::grep_files::GrepFilesTool));
     registry.register(Box::new(tools::file_write::FileWriteTool));
+    registry.register(Box::new(tools::file_edit::FileEditTool));
     registry.register(Box::new(tools::shell_exec::ShellExecTool));
     registry.register(Box::new(tools::git_ops::GitOpsTool));
     registry.register(Box::new(tools::self_eval::SelfEvalTool));
+    registry.register(Box::new(tools::code_search::CodeSearchTool::new(
+        llm_registry,
+        data_dir,
+    )));
     registry
 }
 
@@ -64,35 +74,22 @@ pub fn run() {
             let db_pool = DbPool::unavailable();
             app.manage(db_pool.clone());
 
-            // Initialize LLM Registry
-            let mut registry = LlmRegistry::new();
-            match llm::anthropic::AnthropicProvider::from_env() {
-                Ok(provider) => {
-                    println!("[tebiki] Anthropic provider registered.");
-                    registry.register(Box::new(provider));
-                }
-                Err(e) => {
-                    eprintln!("[tebiki] Anthropic provider not available: {e}");
-                }
-            }
-            match llm::gemini::GeminiProvider::from_env() {
-                Ok(provider) => {
-                    println!("[tebiki] Google AI Studio provider registered.");
-                    registry.register(Box::new(provider));
-                }
-                Err(e) => {
-                    eprintln!("[tebiki] Google AI Studio provider not available: {e}");
-                }
-            }
+            // Initialize LLM Registry（DB接続後に llm_providers の内容で置き換える。
+            // カセットの記録とコードインデックスはアプリのデータディレクトリに置く）
+            let data_dir = app.path().app_data_dir()?;
+            let registry = LlmRegistry::with_data_dir(&data_dir);
+            services::llm_provider_service::register_fallback_providers(&registry);
             let registry = Arc::new(registry);
             app.manage(registry.clone());
 
+            let db_registry = registry.clone();
+
             // Initialize EventBus
             let event_bus = EventBus::new(256);
             app.manage(event_bus.clone());
 
             // Initialize ToolRegistry
-            let tool_registry = Arc::new(init_tool_registry());
+            let tool_registry = Arc::new(init_tool_registry(registry.clone(), data_dir));
             println!(
                 "[tebiki] ToolRegistry initialized with {} tools: {:?}",
                 tool_registry.tool_names().len(),
@@ -135,10 +132,47 @@ pub fn run() {
                     // Agent routes
                     .route("/api/agents", get(handlers::get_agents_handler))
                     .route("/api/agents", post(handlers::create_agent_handler))
+                    .route(
+                        "/api/agents/routing",
+                        post(handlers::update_agent_routing_handler),
+                    )
+                    .route(
+                        "/api/agents/{agent_id}/routing",
+                        get(handlers::get_agent_routing_handler),
+                    )
                     .route(
                         "/api/llm-providers",
                         get(handlers::get_llm_providers_handler),
                     )
+                    .route(
+                        "/api/llm-providers",
+                        post(handlers::create_llm_provider_handler),
+                    )
+                    .route(
+                        "/api/llm-providers/reload",
+                        post(handlers::reload_llm_providers_handler),
+                    )
+                    .route(
+                        "/api/llm-providers/{id}",
+                        post(handlers::update_llm_provider_handler),
+                    )
+                    .route(
+                        "/api/llm-providers/{id}/test",
+                        post(handlers::test_llm_provider_handler),
+                    )
+                    .route(
+                        "/api/llm-providers/{id}/models",
+                        get(handlers::list_llm_provider_models_handler),
+                    )
+                    // Embedding routes
+                    .route("/api/embeddings", post(handlers::create_embeddings_handler))
+                    // Credential routes
+                    .route("/api/credentials", get(handlers::list_credentials_handler))
+                    .route("/api/credentials", post(handlers::set_credential_handler))
+                    .route(
+                        "/api/credentials/{name}/delete",
+                        post(handlers::delete_credential_handler),
+                    )
                     // Execution routes
                     .route("/api/execute", post(handlers::execute_agent_handler))
                     .route("/api/executions/{id}", get(handlers::get_execution_handler))
@@ -173,6 +207,10 @@ pub fn run() {
                         "/api/tools/permissions/{agent_id}",
                         get(handlers::get_tool_permissions_handler),
                     )
+                    .route(
+                        "/api/tool-executions",
+                        get(handlers::list_tool_executions_handler),
+                    )
                     // WebSocket
                     .route("/api/ws", get(ws::ws_handler))
                     .layer(cors)
@@ -221,6 +259,16 @@ pub fn run() {
                             }
                         }
                         db_pool.set_pool(pool);
+
+                        // 以降は llm_providers の内容でレジストリを構築し直す
+                        if let Err(e) = services::llm_provider_service::reload_registry(
+                            &db_pool,
+                            &db_registry,
+                        )
+                        .await
+                        {
+                            eprintln!("[tebiki] Failed to load LLM providers: {e}");
+                        }
                     }
                     Err(e) => {
                         eprintln!("[tebiki] Database connection failed: {e}");
@@ -259,7 +307,18 @@ pub fn run() {
             commands::get_workflow,
             commands::create_agent,
             commands::get_agents,
+            commands::update_agent_routing,
+            commands::get_agent_routing,
             commands::get_llm_providers,
+            commands::create_llm_provider,
+            commands::update_llm_provider,
+            commands::test_llm_provider,
+            commands::list_llm_provider_models,
+            commands::reload_llm_providers,
+            commands::create_embeddings,
+            commands::list_credentials,
+            commands::set_credential,
+            commands::delete_credential,
             commands::execute_agent,
             commands::get_execution,
             commands::get_execution_messages,
@@ -270,6 +329,7 @@ pub fn run() {
             commands::list_tools,
             commands::update_tool_permissions,
             commands::get_tool_permissions,
+            commands::list_tool_executions,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,27 +1,70 @@
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
+use crate::constants::PROVIDER_TYPE_ANTHROPIC;
 use crate::error::AppError;
+use crate::models::{LlmProvider, ModelInfo};
+use crate::secrets;
 
-use super::types::{ContentBlock, LlmRequest, LlmResponse, TokenUsage};
+use super::media;
+use super::retry;
+use super::sampling::{self, ToolChoice};
+use super::sse::{incomplete_stream, read_sse};
+use super::structured::{self, STRUCTURED_OUTPUT_TOOL, STRUCTURED_OUTPUT_TOOL_DESCRIPTION};
+use super::types::{
+    ContentBlock, DeltaSink, LlmRequest, LlmResponse, MediaSource, StreamDelta, TokenUsage,
+};
 use super::LlmProviderTrait;
 
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
+/// モデル一覧の1ページあたりの件数（API の上限）
+const MODEL_LIST_PAGE_SIZE: &str = "1000";
+
+/// ストリーミング時のタイムアウト（長い生成でも途中で切らないよう長めに取る）
+const STREAM_TIMEOUT_SECS: u64 = 600;
+
 pub struct AnthropicProvider {
+    name: String,
+    base_url: String,
     api_key: String,
     client: reqwest::Client,
 }
 
 impl AnthropicProvider {
-    pub fn from_env() -> Result<Self, AppError> {
-        let api_key = std::env::var("ANTHROPIC_API_KEY").map_err(|_| {
-            AppError::LlmError("ANTHROPIC_API_KEY environment variable not set".to_string())
-        })?;
+    fn new(name: &str, base_url: &str, api_key: String) -> Result<Self, AppError> {
         let client = reqwest::Client::builder()
             .connect_timeout(std::time::Duration::from_secs(10))
             .timeout(std::time::Duration::from_secs(120))
             .build()
             .map_err(|e| AppError::LlmError(format!("Failed to build HTTP client: {e}")))?;
-        Ok(Self { api_key, client })
+        Ok(Self {
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            client,
+        })
+    }
+
+    /// DB未接続時のフォールバック（資格情報ボルト → 環境変数から構築）
+    pub fn from_env() -> Result<Self, AppError> {
+        let api_key = secrets::resolve("ANTHROPIC_API_KEY")?.ok_or_else(|| {
+            AppError::LlmError("Credential 'ANTHROPIC_API_KEY' is not set".to_string())
+        })?;
+        Self::new("anthropic", DEFAULT_BASE_URL, api_key)
+    }
+
+    /// llm_providers の行から構築する（api_base_url 未設定時は公式エンドポイント）
+    pub fn from_row(row: &LlmProvider) -> Result<Self, AppError> {
+        let api_key = super::require_api_key(row)?;
+        let base_url = row
+            .api_base_url
+            .as_deref()
+            .filter(|u| !u.trim().is_empty())
+            .unwrap_or(DEFAULT_BASE_URL);
+        Self::new(&row.name, base_url, api_key)
     }
 }
 
@@ -32,11 +75,49 @@ struct AnthropicRequest {
     model: String,
     messages: Vec<AnthropicMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    system: Option<String>,
-    temperature: f64,
+    system: Option<AnthropicSystem>,
+    /// 拡張思考中は指定できないため省略する
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
     max_tokens: i32,
     #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<AnthropicTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<AnthropicThinking>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicToolChoice {
+    #[serde(rename = "type")]
+    choice_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl AnthropicToolChoice {
+    fn of(choice_type: &str) -> Self {
+        Self {
+            choice_type: choice_type.to_string(),
+            name: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicThinking {
+    #[serde(rename = "type")]
+    thinking_type: String,
+    budget_tokens: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,7 +130,47 @@ struct AnthropicMessage {
 #[serde(untagged)]
 enum AnthropicMessageContent {
     Text(String),
-    Blocks(Vec<AnthropicContentBlock>),
+    Blocks(Vec<AnthropicBlockParam>),
+}
+
+/// リクエスト側のブロック。キャッシュのブレークポイントを置くときだけ cache_control を付ける。
+#[derive(Debug, Serialize, Deserialize)]
+struct AnthropicBlockParam {
+    #[serde(flatten)]
+    block: AnthropicContentBlock,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache_control: Option<AnthropicCacheControl>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AnthropicCacheControl {
+    #[serde(rename = "type")]
+    cache_type: String,
+}
+
+impl AnthropicCacheControl {
+    fn ephemeral() -> Self {
+        Self {
+            cache_type: "ephemeral".to_string(),
+        }
+    }
+}
+
+/// system は通常は文字列、キャッシュ時は cache_control 付きのブロック配列で送る
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum AnthropicSystem {
+    Text(String),
+    Blocks(Vec<AnthropicSystemBlock>),
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicSystemBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<AnthropicCacheControl>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,6 +191,25 @@ enum AnthropicContentBlock {
         #[serde(skip_serializing_if = "Option::is_none")]
         is_error: Option<bool>,
     },
+    #[serde(rename = "image")]
+    Image { source: AnthropicMediaSource },
+    #[serde(rename = "document")]
+    Document {
+        source: AnthropicMediaSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    #[serde(rename = "thinking")]
+    Thinking { thinking: String, signature: String },
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum AnthropicMediaSource {
+    #[serde(rename = "base64")]
+    Base64 { media_type: String, data: String },
 }
 
 #[derive(Debug, Serialize)]
@@ -77,6 +217,8 @@ struct AnthropicTool {
     name: String,
     description: String,
     input_schema: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<AnthropicCacheControl>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,12 +240,36 @@ enum AnthropicResponseContent {
         name: String,
         input: serde_json::Value,
     },
+    /// ストリーミング時の content_block_start では signature が空で届く
+    #[serde(rename = "thinking")]
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
 }
 
 #[derive(Debug, Deserialize)]
 struct AnthropicUsage {
     input_tokens: u32,
     output_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
+}
+
+impl From<AnthropicUsage> for TokenUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cache_read_tokens: usage.cache_read_input_tokens.unwrap_or(0),
+            cache_write_tokens: usage.cache_creation_input_tokens.unwrap_or(0),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -113,13 +279,147 @@ struct AnthropicError {
 
 #[derive(Debug, Deserialize)]
 struct AnthropicErrorDetail {
+    #[serde(rename = "type", default)]
+    error_type: String,
     message: String,
 }
 
-fn content_block_to_anthropic(block: &ContentBlock) -> AnthropicContentBlock {
-    match block {
+#[derive(Debug, Deserialize)]
+struct AnthropicModelList {
+    data: Vec<AnthropicModel>,
+    #[serde(default)]
+    has_more: bool,
+    last_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicModel {
+    id: String,
+    display_name: Option<String>,
+}
+
+// --- Streaming (SSE) event types ---
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum AnthropicStreamEvent {
+    #[serde(rename = "message_start")]
+    MessageStart { message: AnthropicStreamMessage },
+    #[serde(rename = "content_block_start")]
+    ContentBlockStart {
+        index: usize,
+        content_block: AnthropicResponseContent,
+    },
+    #[serde(rename = "content_block_delta")]
+    ContentBlockDelta {
+        index: usize,
+        delta: AnthropicStreamDelta,
+    },
+    #[serde(rename = "message_delta")]
+    MessageDelta {
+        delta: AnthropicMessageDelta,
+        usage: Option<AnthropicDeltaUsage>,
+    },
+    #[serde(rename = "message_stop")]
+    MessageStop,
+    #[serde(rename = "error")]
+    Error { error: AnthropicErrorDetail },
+    /// content_block_stop / ping など、集約に不要なもの
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicStreamMessage {
+    model: String,
+    usage: AnthropicUsage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum AnthropicStreamDelta {
+    #[serde(rename = "text_delta")]
+    TextDelta { text: String },
+    #[serde(rename = "input_json_delta")]
+    InputJsonDelta { partial_json: String },
+    #[serde(rename = "thinking_delta")]
+    ThinkingDelta { thinking: String },
+    #[serde(rename = "signature_delta")]
+    SignatureDelta { signature: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicMessageDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicDeltaUsage {
+    output_tokens: u32,
+}
+
+/// AnthropicResponseContent で扱うブロック種別
+const KNOWN_BLOCK_TYPES: [&str; 4] = ["text", "tool_use", "thinking", "redacted_thinking"];
+
+/// SSE の data を解析する。未対応のブロック種別の content_block_start は読み飛ばす（None）。
+/// それ以外で解析できない data は応答を取りこぼさないようエラーにする。
+fn parse_stream_event(data: &str) -> Result<Option<AnthropicStreamEvent>, AppError> {
+    let error = match serde_json::from_str(data) {
+        Ok(event) => return Ok(Some(event)),
+        Err(e) => e,
+    };
+    let value: Option<serde_json::Value> = serde_json::from_str(data).ok();
+    let unsupported_block = value
+        .as_ref()
+        .filter(|v| v["type"] == "content_block_start")
+        .and_then(|v| v["content_block"]["type"].as_str())
+        .filter(|t| !KNOWN_BLOCK_TYPES.contains(t));
+    if let Some(block_type) = unsupported_block {
+        eprintln!("[tebiki] Skipping unsupported Anthropic content block '{block_type}'");
+        return Ok(None);
+    }
+    let excerpt: String = data.chars().take(200).collect();
+    Err(AppError::LlmError(format!(
+        "Failed to parse Anthropic stream event: {error} (data: {excerpt})"
+    )))
+}
+
+/// ストリーム中に組み立て途中のコンテンツブロック
+enum PartialBlock {
+    Text(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input_json: String,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking(String),
+}
+
+/// Base64 ソースを Anthropic 形式に変換する（未解決の File は代替テキストを Err で返す）
+fn media_source_to_anthropic(source: &MediaSource) -> Result<AnthropicMediaSource, String> {
+    match source {
+        MediaSource::Base64 { media_type, data } => Ok(AnthropicMediaSource::Base64 {
+            media_type: media_type.clone(),
+            data: data.clone(),
+        }),
+        MediaSource::File { path, .. } => Err(media::unresolved_note(path)),
+    }
+}
+
+/// 内部の ContentBlock を Anthropic 形式に変換する。
+/// signature のない思考ブロック（他プロバイダー由来）は受け付けられないため None を返す。
+fn content_block_to_anthropic(block: &ContentBlock) -> Option<AnthropicContentBlock> {
+    let block = match block {
         ContentBlock::Text { text } => AnthropicContentBlock::Text { text: text.clone() },
-        ContentBlock::ToolUse { id, name, input } => AnthropicContentBlock::ToolUse {
+        ContentBlock::ToolUse {
+            id, name, input, ..
+        } => AnthropicContentBlock::ToolUse {
             id: id.clone(),
             name: name.clone(),
             input: input.clone(),
@@ -133,17 +433,56 @@ fn content_block_to_anthropic(block: &ContentBlock) -> AnthropicContentBlock {
             content: content.clone(),
             is_error: if *is_error { Some(true) } else { None },
         },
-    }
+        ContentBlock::Image { source } => match media_source_to_anthropic(source) {
+            Ok(source) => AnthropicContentBlock::Image { source },
+            Err(text) => AnthropicContentBlock::Text { text },
+        },
+        ContentBlock::Document { source, title } => match media_source_to_anthropic(source) {
+            Ok(source) => AnthropicContentBlock::Document {
+                source,
+                title: title.clone(),
+            },
+            Err(text) => AnthropicContentBlock::Text { text },
+        },
+        ContentBlock::Thinking {
+            thinking,
+            signature,
+        } => AnthropicContentBlock::Thinking {
+            thinking: thinking.clone(),
+            signature: signature.clone()?,
+        },
+        ContentBlock::RedactedThinking { data } => {
+            AnthropicContentBlock::RedactedThinking { data: data.clone() }
+        }
+    };
+    Some(block)
 }
 
-#[async_trait]
-impl LlmProviderTrait for AnthropicProvider {
-    fn name(&self) -> &str {
-        "anthropic"
+/// メッセージの最後のブロックにキャッシュのブレークポイントを置く
+fn add_cache_breakpoint(message: &mut AnthropicMessage) {
+    if let AnthropicMessageContent::Text(text) = &message.content {
+        message.content = AnthropicMessageContent::Blocks(vec![AnthropicBlockParam {
+            block: AnthropicContentBlock::Text { text: text.clone() },
+            cache_control: None,
+        }]);
+    }
+    if let AnthropicMessageContent::Blocks(blocks) = &mut message.content {
+        if let Some(last) = blocks.last_mut() {
+            last.cache_control = Some(AnthropicCacheControl::ephemeral());
+        }
     }
+}
 
-    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
-        let messages: Vec<AnthropicMessage> = request
+impl AnthropicProvider {
+    /// 共通の LlmRequest を Anthropic API のリクエストに変換
+    fn build_request(
+        &self,
+        request: &LlmRequest,
+        stream: bool,
+    ) -> Result<AnthropicRequest, AppError> {
+        sampling::validate_request(PROVIDER_TYPE_ANTHROPIC, request)?;
+
+        let mut messages: Vec<AnthropicMessage> = request
             .messages
             .iter()
             .filter(|m| m.role != "system")
@@ -154,7 +493,14 @@ impl LlmProviderTrait for AnthropicProvider {
                     }
                     super::types::MessageContent::Blocks(blocks) => {
                         AnthropicMessageContent::Blocks(
-                            blocks.iter().map(content_block_to_anthropic).collect(),
+                            blocks
+                                .iter()
+                                .filter_map(content_block_to_anthropic)
+                                .map(|block| AnthropicBlockParam {
+                                    block,
+                                    cache_control: None,
+                                })
+                                .collect(),
                         )
                     }
                 };
@@ -165,52 +511,152 @@ impl LlmProviderTrait for AnthropicProvider {
             })
             .collect();
 
-        let tools = request.tools.as_ref().map(|tool_defs| {
+        let mut tools = request.tools.as_ref().map(|tool_defs| {
             tool_defs
                 .iter()
                 .map(|t| AnthropicTool {
                     name: t.name.clone(),
                     description: t.description.clone(),
                     input_schema: t.input_schema.clone(),
+                    cache_control: None,
                 })
-                .collect()
+                .collect::<Vec<_>>()
         });
 
-        let api_request = AnthropicRequest {
+        let mut tool_choice = request.tool_choice.as_ref().map(|choice| match choice {
+            ToolChoice::Auto => AnthropicToolChoice::of("auto"),
+            ToolChoice::Any => AnthropicToolChoice::of("any"),
+            ToolChoice::None => AnthropicToolChoice::of("none"),
+            ToolChoice::Tool { name } => AnthropicToolChoice {
+                choice_type: "tool".to_string(),
+                name: Some(name.clone()),
+            },
+        });
+
+        // 構造化出力は専用ツールの呼び出しとして受け取る（JSON モードがないため）
+        if let Some(schema) = &request.output_schema {
+            tools.get_or_insert_with(Vec::new).push(AnthropicTool {
+                name: STRUCTURED_OUTPUT_TOOL.to_string(),
+                description: STRUCTURED_OUTPUT_TOOL_DESCRIPTION.to_string(),
+                input_schema: schema.clone(),
+                cache_control: None,
+            });
+            // 拡張思考中はツールを強制できないため auto にする（テキストで返った場合も検証側で解析する）
+            tool_choice = Some(if request.thinking_budget.is_some_and(|b| b > 0) {
+                AnthropicToolChoice::of("auto")
+            } else {
+                AnthropicToolChoice {
+                    choice_type: "tool".to_string(),
+                    name: Some(STRUCTURED_OUTPUT_TOOL.to_string()),
+                }
+            });
+        }
+
+        let system = if request.prompt_cache {
+            request.system.clone().map(|text| {
+                AnthropicSystem::Blocks(vec![AnthropicSystemBlock {
+                    block_type: "text".to_string(),
+                    text,
+                    cache_control: Some(AnthropicCacheControl::ephemeral()),
+                }])
+            })
+        } else {
+            request.system.clone().map(AnthropicSystem::Text)
+        };
+
+        // ブレークポイントは上限4つ: tools / system / 直近2つの user メッセージ。
+        // 前回のリクエストで置いた位置までの接頭辞がキャッシュから読まれる。
+        if request.prompt_cache {
+            if let Some(last) = tools.as_mut().and_then(|t| t.last_mut()) {
+                last.cache_control = Some(AnthropicCacheControl::ephemeral());
+            }
+            let user_indices: Vec<usize> = messages
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| m.role == "user")
+                .map(|(i, _)| i)
+                .collect();
+            for &i in user_indices.iter().rev().take(2) {
+                add_cache_breakpoint(&mut messages[i]);
+            }
+        }
+
+        // 拡張思考の予算は max_tokens に上乗せして送る（API は max_tokens > budget_tokens を要求する）
+        let thinking = request
+            .thinking_budget
+            .filter(|budget| *budget > 0)
+            .map(|budget_tokens| AnthropicThinking {
+                thinking_type: "enabled".to_string(),
+                budget_tokens,
+            });
+
+        Ok(AnthropicRequest {
             model: request.model.clone(),
             messages,
-            system: request.system.clone(),
-            temperature: request.temperature,
-            max_tokens: request.max_tokens,
+            system,
+            temperature: thinking.is_none().then_some(request.temperature),
+            max_tokens: request.max_output_tokens(),
+            top_p: request.sampling.top_p,
+            top_k: request.sampling.top_k,
+            stop_sequences: request.sampling.stop_sequences.clone(),
             tools,
-        };
+            tool_choice,
+            thinking,
+            stream,
+        })
+    }
 
-        let response = self
+    async fn send(&self, api_request: &AnthropicRequest) -> Result<reqwest::Response, AppError> {
+        let mut builder = self
             .client
-            .post("https://api.anthropic.com/v1/messages")
+            .post(format!("{}/v1/messages", self.base_url))
             .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&api_request)
+            .header("anthropic-version", API_VERSION)
+            .header("content-type", "application/json");
+        if api_request.stream {
+            builder = builder.timeout(std::time::Duration::from_secs(STREAM_TIMEOUT_SECS));
+        }
+
+        let response = builder
+            .json(api_request)
             .send()
             .await
-            .map_err(|e| AppError::LlmError(format!("Request failed: {e}")))?;
+            .map_err(retry::request_error)?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            if let Ok(err) = serde_json::from_str::<AnthropicError>(&body) {
-                return Err(AppError::LlmError(format!(
-                    "Anthropic API error ({}): {}",
-                    status, err.error.message
-                )));
-            }
-            return Err(AppError::LlmError(format!(
-                "Anthropic API error ({}): {}",
-                status, body
-            )));
+            return Err(api_error(response).await);
         }
 
+        Ok(response)
+    }
+}
+
+/// 失敗したレスポンスをエラー種別付きの AppError にする
+async fn api_error(response: reqwest::Response) -> AppError {
+    let status = response.status();
+    let retry_after = retry::retry_after(response.headers());
+    let body = response.text().await.unwrap_or_default();
+    let message = match serde_json::from_str::<AnthropicError>(&body) {
+        Ok(err) => err.error.message,
+        Err(_) => body,
+    };
+    retry::status_error(
+        status,
+        retry_after,
+        format!("Anthropic API error ({status}): {message}"),
+    )
+}
+
+#[async_trait]
+impl LlmProviderTrait for AnthropicProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
+        let api_request = self.build_request(request, false)?;
+        let response = self.send(&api_request).await?;
+
         let api_response: AnthropicResponse = response
             .json()
             .await
@@ -228,7 +674,18 @@ impl LlmProviderTrait for AnthropicProvider {
                     id: id.clone(),
                     name: name.clone(),
                     input: input.clone(),
+                    signature: None,
                 },
+                AnthropicResponseContent::Thinking {
+                    thinking,
+                    signature,
+                } => ContentBlock::Thinking {
+                    thinking: thinking.clone(),
+                    signature: Some(signature.clone()),
+                },
+                AnthropicResponseContent::RedactedThinking { data } => {
+                    ContentBlock::RedactedThinking { data: data.clone() }
+                }
             })
             .collect();
 
@@ -243,15 +700,207 @@ impl LlmProviderTrait for AnthropicProvider {
             .collect::<Vec<_>>()
             .join("");
 
-        Ok(LlmResponse {
+        let response = LlmResponse {
             content,
             model: api_response.model,
-            token_usage: TokenUsage {
-                input_tokens: api_response.usage.input_tokens,
-                output_tokens: api_response.usage.output_tokens,
-            },
+            token_usage: api_response.usage.into(),
             content_blocks,
             stop_reason: api_response.stop_reason,
+        };
+        Ok(if request.output_schema.is_some() {
+            structured::tool_call_to_output(response)
+        } else {
+            response
+        })
+    }
+
+    async fn complete_stream(
+        &self,
+        request: &LlmRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<LlmResponse, AppError> {
+        let api_request = self.build_request(request, true)?;
+        let response = self.send(&api_request).await?;
+
+        let mut model = request.model.clone();
+        let mut token_usage = TokenUsage::default();
+        let mut stop_reason: Option<String> = None;
+        let mut message_stopped = false;
+        // SSE の index をキーに保持する（未対応のブロック種別があっても位置がずれないように）
+        let mut blocks: BTreeMap<usize, PartialBlock> = BTreeMap::new();
+
+        read_sse(response, |sse| {
+            let Some(event) = parse_stream_event(&sse.data)? else {
+                return Ok(());
+            };
+            match event {
+                AnthropicStreamEvent::MessageStart { message } => {
+                    model = message.model;
+                    token_usage = message.usage.into();
+                }
+                AnthropicStreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                } => {
+                    let block = match content_block {
+                        AnthropicResponseContent::Text { text } => {
+                            if !text.is_empty() {
+                                on_delta(StreamDelta::Text { text: text.clone() });
+                            }
+                            PartialBlock::Text(text)
+                        }
+                        AnthropicResponseContent::ToolUse { id, name, .. } => {
+                            on_delta(StreamDelta::ToolUseStart {
+                                id: id.clone(),
+                                name: name.clone(),
+                            });
+                            PartialBlock::ToolUse {
+                                id,
+                                name,
+                                input_json: String::new(),
+                            }
+                        }
+                        AnthropicResponseContent::Thinking {
+                            thinking,
+                            signature,
+                        } => {
+                            if !thinking.is_empty() {
+                                on_delta(StreamDelta::Thinking {
+                                    text: thinking.clone(),
+                                });
+                            }
+                            PartialBlock::Thinking {
+                                thinking,
+                                signature,
+                            }
+                        }
+                        AnthropicResponseContent::RedactedThinking { data } => {
+                            PartialBlock::RedactedThinking(data)
+                        }
+                    };
+                    blocks.insert(index, block);
+                }
+                AnthropicStreamEvent::ContentBlockDelta { index, delta } => {
+                    match (blocks.get_mut(&index), delta) {
+                        (
+                            Some(PartialBlock::Text(buf)),
+                            AnthropicStreamDelta::TextDelta { text },
+                        ) => {
+                            buf.push_str(&text);
+                            on_delta(StreamDelta::Text { text });
+                        }
+                        (
+                            Some(PartialBlock::ToolUse { id, input_json, .. }),
+                            AnthropicStreamDelta::InputJsonDelta { partial_json },
+                        ) => {
+                            input_json.push_str(&partial_json);
+                            on_delta(StreamDelta::ToolUseInput {
+                                id: id.clone(),
+                                partial_json,
+                            });
+                        }
+                        (
+                            Some(PartialBlock::Thinking { thinking: buf, .. }),
+                            AnthropicStreamDelta::ThinkingDelta { thinking },
+                        ) => {
+                            buf.push_str(&thinking);
+                            on_delta(StreamDelta::Thinking { text: thinking });
+                        }
+                        (
+                            Some(PartialBlock::Thinking { signature: buf, .. }),
+                            AnthropicStreamDelta::SignatureDelta { signature },
+                        ) => buf.push_str(&signature),
+                        _ => {}
+                    }
+                }
+                AnthropicStreamEvent::MessageDelta { delta, usage } => {
+                    if delta.stop_reason.is_some() {
+                        stop_reason = delta.stop_reason;
+                    }
+                    if let Some(usage) = usage {
+                        token_usage.output_tokens = usage.output_tokens;
+                    }
+                }
+                AnthropicStreamEvent::MessageStop => message_stopped = true,
+                AnthropicStreamEvent::Error { error } => {
+                    let message = format!("Anthropic stream error: {}", error.message);
+                    // ストリーム途中の過負荷・内部エラーは再試行で回復しうる
+                    return Err(match error.error_type.as_str() {
+                        "overloaded_error" | "api_error" | "rate_limit_error" => {
+                            AppError::LlmUnavailable {
+                                message,
+                                retry_after: None,
+                                rate_limited: error.error_type == "rate_limit_error",
+                            }
+                        }
+                        _ => AppError::LlmError(message),
+                    });
+                }
+                AnthropicStreamEvent::Other => {}
+            }
+            Ok(())
+        })
+        .await?;
+        if !message_stopped {
+            return Err(incomplete_stream("Anthropic"));
+        }
+
+        let mut content_blocks = Vec::with_capacity(blocks.len());
+        for block in blocks.into_values() {
+            content_blocks.push(match block {
+                PartialBlock::Text(text) => ContentBlock::Text { text },
+                PartialBlock::ToolUse {
+                    id,
+                    name,
+                    input_json,
+                } => {
+                    let input = if input_json.trim().is_empty() {
+                        serde_json::json!({})
+                    } else {
+                        serde_json::from_str(&input_json).map_err(|e| {
+                            AppError::LlmError(format!(
+                                "Failed to parse streamed tool input for '{name}': {e}"
+                            ))
+                        })?
+                    };
+                    ContentBlock::ToolUse {
+                        id,
+                        name,
+                        input,
+                        signature: None,
+                    }
+                }
+                PartialBlock::Thinking {
+                    thinking,
+                    signature,
+                } => ContentBlock::Thinking {
+                    thinking,
+                    signature: Some(signature),
+                },
+                PartialBlock::RedactedThinking(data) => ContentBlock::RedactedThinking { data },
+            });
+        }
+
+        let content = content_blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("");
+
+        let response = LlmResponse {
+            content,
+            model,
+            token_usage,
+            content_blocks,
+            stop_reason,
+        };
+        Ok(if request.output_schema.is_some() {
+            structured::tool_call_to_output(response)
+        } else {
+            response
         })
     }
 
@@ -259,6 +908,109 @@ impl LlmProviderTrait for AnthropicProvider {
         if self.api_key.is_empty() {
             return Err(AppError::LlmError("Anthropic API key is empty".to_string()));
         }
+        // モデル一覧の取得でキーと接続先を確認する
+        let response = self
+            .client
+            .get(format!("{}/v1/models", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .send()
+            .await
+            .map_err(|e| AppError::LlmError(format!("{} is unreachable: {e}", self.name)))?;
+        if !response.status().is_success() {
+            return Err(AppError::LlmError(format!(
+                "Anthropic API error ({})",
+                response.status()
+            )));
+        }
         Ok(())
     }
+
+    /// GET /v1/models をページ送りしながら全件取得する（新しいモデルが先頭）
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError> {
+        let mut models = Vec::new();
+        let mut after_id: Option<String> = None;
+        loop {
+            let mut builder = self
+                .client
+                .get(format!("{}/v1/models", self.base_url))
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", API_VERSION)
+                .query(&[("limit", MODEL_LIST_PAGE_SIZE)]);
+            if let Some(after_id) = &after_id {
+                builder = builder.query(&[("after_id", after_id)]);
+            }
+            let response = builder.send().await.map_err(retry::request_error)?;
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+            let page: AnthropicModelList = response
+                .json()
+                .await
+                .map_err(|e| AppError::LlmError(format!("Failed to parse model list: {e}")))?;
+
+            models.extend(page.data.into_iter().map(|m| ModelInfo {
+                id: m.id,
+                display_name: m.display_name,
+                context_window: None,
+                max_output_tokens: None,
+                input_price: None,
+                output_price: None,
+            }));
+            match page.last_id.filter(|_| page.has_more) {
+                Some(last_id) => after_id = Some(last_id),
+                None => break,
+            }
+        }
+        Ok(models)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_stream_events() {
+        let event = parse_stream_event(
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"hi"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            event,
+            Some(AnthropicStreamEvent::ContentBlockDelta {
+                index: 0,
+                delta: AnthropicStreamDelta::TextDelta { .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn ignores_unneeded_event_types_and_unsupported_blocks() {
+        assert!(matches!(
+            parse_stream_event(r#"{"type":"ping"}"#).unwrap(),
+            Some(AnthropicStreamEvent::Other)
+        ));
+        assert!(matches!(
+            parse_stream_event(r#"{"type":"message_stop"}"#).unwrap(),
+            Some(AnthropicStreamEvent::MessageStop)
+        ));
+        assert!(parse_stream_event(
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"server_tool_use","id":"x"}}"#,
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn rejects_unparseable_data() {
+        let error = parse_stream_event(r#"{"type":"content_block_delta","index":0"#).unwrap_err();
+        assert!(matches!(error, AppError::LlmError(_)), "{error}");
+        // 既知のイベントで形が違うものも読み飛ばさない
+        assert!(parse_stream_event(r#"{"type":"message_start","message":{}}"#).is_err());
+        assert!(parse_stream_event(
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text"}}"#,
+        )
+        .is_err());
+    }
 }
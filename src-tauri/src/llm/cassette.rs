@@ -0,0 +1,447 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::models::{LlmProvider, ModelInfo};
+
+use super::types::{ContentBlock, DeltaSink, LlmRequest, LlmResponse, StreamDelta};
+use super::LlmProviderTrait;
+
+/// 記録先のカセットを置く、アプリのデータディレクトリ配下のディレクトリ
+pub const RECORDING_DIR: &str = "cassettes";
+
+/// 記録された1回分のリクエストとレスポンス
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: LlmRequest,
+    response: LlmResponse,
+}
+
+/// 再生時のリクエストの照合方法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Matching {
+    /// 記録と完全一致するリクエストだけを再生し、一致しなければエラーにする
+    #[default]
+    Exact,
+    /// 完全一致するものがなければ記録順で次のものを返す。
+    /// サブエージェントIDのように実行ごとに変わる値を含む会話を再生するときに明示的に指定する。
+    InOrder,
+}
+
+/// カセットファイル（JSON）の中身
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cassette {
+    #[serde(default, skip_serializing_if = "is_exact")]
+    matching: Matching,
+    interactions: Vec<Interaction>,
+}
+
+fn is_exact(matching: &Matching) -> bool {
+    *matching == Matching::Exact
+}
+
+fn cassette_path(row: &LlmProvider) -> Result<PathBuf, AppError> {
+    row.cassette_path
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| PathBuf::from(p.trim()))
+        .ok_or_else(|| {
+            AppError::InvalidInput(format!("Provider '{}' has no cassette_path", row.name))
+        })
+}
+
+/// 記録先の cassette_path は RECORDING_DIR からの相対パスに限る（任意の場所へ書かせない）
+pub fn validate_recording_path(path: &str) -> Result<(), AppError> {
+    let path = Path::new(path.trim());
+    let confined = path.components().next().is_some()
+        && path.components().all(|c| matches!(c, Component::Normal(_)));
+    if confined {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "cassette_path for recording must be a relative path inside the app's '{RECORDING_DIR}' directory"
+        )))
+    }
+}
+
+fn parse_cassette(path: &std::path::Path, json: &str) -> Result<Cassette, AppError> {
+    serde_json::from_str(json)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid cassette '{}': {e}", path.display())))
+}
+
+/// リクエストの比較用表現（JSON として一致すれば同じリクエストとみなす）
+fn request_key(request: &LlmRequest) -> serde_json::Value {
+    serde_json::to_value(request).unwrap_or(serde_json::Value::Null)
+}
+
+/// 2つの JSON が最初に食い違う位置（例: `messages[2].content`）。一致すれば None
+fn first_difference(
+    expected: &serde_json::Value,
+    actual: &serde_json::Value,
+    path: &str,
+) -> Option<String> {
+    use serde_json::Value;
+    match (expected, actual) {
+        (Value::Object(a), Value::Object(b)) => a
+            .keys()
+            .chain(b.keys().filter(|k| !a.contains_key(*k)))
+            .find_map(|k| {
+                let child = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{path}.{k}")
+                };
+                match (a.get(k), b.get(k)) {
+                    (Some(x), Some(y)) => first_difference(x, y, &child),
+                    _ => Some(child),
+                }
+            }),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .enumerate()
+            .find_map(|(i, (x, y))| first_difference(x, y, &format!("{path}[{i}]")))
+            .or_else(|| (a.len() != b.len()).then(|| format!("{path}[{}]", a.len().min(b.len())))),
+        _ => (expected != actual).then(|| path.to_string()),
+    }
+}
+
+/// 記録済みカセットを再生するモックプロバイダー（API を呼ばない）。
+/// 未使用の記録のうちリクエストが完全一致するものを返し、なければエラーにする。
+/// カセットに `"matching": "in_order"` を指定したときだけ、一致しなければ記録順で次のものを返す。
+/// 記録を使い切ったら次の実行のために最初から再生し直す。
+pub struct ReplayProvider {
+    name: String,
+    path: PathBuf,
+    matching: Matching,
+    interactions: Vec<(serde_json::Value, LlmResponse)>,
+    used: Mutex<Vec<bool>>,
+}
+
+impl ReplayProvider {
+    pub fn from_row(row: &LlmProvider) -> Result<Self, AppError> {
+        let path = cassette_path(row)?;
+        let json = std::fs::read_to_string(&path).map_err(|e| {
+            AppError::InvalidInput(format!("Failed to read cassette '{}': {e}", path.display()))
+        })?;
+        let cassette = parse_cassette(&path, &json)?;
+        let interactions: Vec<_> = cassette
+            .interactions
+            .into_iter()
+            .map(|i| (request_key(&i.request), i.response))
+            .collect();
+
+        Ok(Self {
+            name: row.name.clone(),
+            path,
+            matching: cassette.matching,
+            used: Mutex::new(vec![false; interactions.len()]),
+            interactions,
+        })
+    }
+
+    fn next_response(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
+        let key = request_key(request);
+        let mut used = self.used.lock().expect("ReplayProvider lock poisoned");
+        if used.iter().all(|u| *u) {
+            used.fill(false);
+        }
+        let unused = |i: &usize| !used[*i];
+        let next = (0..self.interactions.len()).find(unused).ok_or_else(|| {
+            AppError::LlmError(format!(
+                "Cassette '{}' has no more recorded interactions ({} recorded)",
+                self.path.display(),
+                self.interactions.len()
+            ))
+        })?;
+        let exact = (0..self.interactions.len())
+            .filter(unused)
+            .find(|i| self.interactions[*i].0 == key);
+        let index = match (exact, self.matching) {
+            (Some(index), _) => index,
+            (None, Matching::InOrder) => next,
+            (None, Matching::Exact) => {
+                let at = first_difference(&self.interactions[next].0, &key, "")
+                    .filter(|p| !p.is_empty())
+                    .unwrap_or_else(|| "(root)".to_string());
+                return Err(AppError::LlmError(format!(
+                    "Request does not match cassette '{}': interaction {} differs at {at} \
+                     (set \"matching\": \"in_order\" in the cassette to replay in recorded order)",
+                    self.path.display(),
+                    next + 1
+                )));
+            }
+        };
+        used[index] = true;
+        Ok(self.interactions[index].1.clone())
+    }
+}
+
+#[async_trait]
+impl LlmProviderTrait for ReplayProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
+        self.next_response(request)
+    }
+
+    /// 記録されたブロックを実プロバイダーと同じ形の増分として流す
+    async fn complete_stream(
+        &self,
+        request: &LlmRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<LlmResponse, AppError> {
+        let response = self.next_response(request)?;
+        if response.content_blocks.is_empty() && !response.content.is_empty() {
+            on_delta(StreamDelta::Text {
+                text: response.content.clone(),
+            });
+        }
+        for block in &response.content_blocks {
+            match block {
+                ContentBlock::Text { text } => on_delta(StreamDelta::Text { text: text.clone() }),
+                ContentBlock::Thinking { thinking, .. } => on_delta(StreamDelta::Thinking {
+                    text: thinking.clone(),
+                }),
+                ContentBlock::ToolUse {
+                    id, name, input, ..
+                } => {
+                    on_delta(StreamDelta::ToolUseStart {
+                        id: id.clone(),
+                        name: name.clone(),
+                    });
+                    on_delta(StreamDelta::ToolUseInput {
+                        id: id.clone(),
+                        partial_json: input.to_string(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(response)
+    }
+
+    async fn health_check(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+/// 実プロバイダーの呼び出しをカセットに追記するラッパー。
+/// 成功したレスポンスだけを記録し、書き込みに失敗しても呼び出し自体は失敗させない。
+pub struct RecordingProvider {
+    inner: Box<dyn LlmProviderTrait>,
+    path: PathBuf,
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl RecordingProvider {
+    /// 行の cassette_path（recording_dir からの相対パス）へ記録するラッパーで包む
+    pub fn wrap(
+        inner: Box<dyn LlmProviderTrait>,
+        row: &LlmProvider,
+        recording_dir: &Path,
+    ) -> Result<Self, AppError> {
+        let path = cassette_path(row)?;
+        validate_recording_path(&path.to_string_lossy())?;
+        Ok(Self {
+            inner,
+            path: recording_dir.join(path),
+            write_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    async fn record(&self, request: &LlmRequest, response: &LlmResponse) {
+        if let Err(e) = self.append(request, response).await {
+            eprintln!(
+                "[tebiki] Failed to record cassette '{}': {e}",
+                self.path.display()
+            );
+        }
+    }
+
+    async fn append(&self, request: &LlmRequest, response: &LlmResponse) -> Result<(), AppError> {
+        let _guard = self.write_lock.lock().await;
+        let mut cassette = match tokio::fs::read_to_string(&self.path).await {
+            Ok(json) => parse_cassette(&self.path, &json)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Cassette::default(),
+            Err(e) => return Err(AppError::Internal(e.to_string())),
+        };
+        cassette.interactions.push(Interaction {
+            request: request.clone(),
+            response: response.clone(),
+        });
+
+        let json = serde_json::to_string_pretty(&cassette)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+        }
+        tokio::fs::write(&self.path, json)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl LlmProviderTrait for RecordingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
+        let response = self.inner.complete(request).await?;
+        self.record(request, &response).await;
+        Ok(response)
+    }
+
+    async fn complete_stream(
+        &self,
+        request: &LlmRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<LlmResponse, AppError> {
+        let response = self.inner.complete_stream(request, on_delta).await?;
+        self.record(request, &response).await;
+        Ok(response)
+    }
+
+    async fn health_check(&self) -> Result<(), AppError> {
+        self.inner.health_check().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError> {
+        self.inner.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::types::{LlmMessage, MessageContent, TokenUsage};
+
+    fn request(prompt: &str) -> LlmRequest {
+        LlmRequest {
+            model: "test-model".to_string(),
+            messages: vec![LlmMessage {
+                role: "user".to_string(),
+                content: MessageContent::Text(prompt.to_string()),
+            }],
+            system: None,
+            temperature: 0.0,
+            max_tokens: 16,
+            tools: None,
+            prompt_cache: false,
+            thinking_budget: None,
+            output_schema: None,
+            sampling: Default::default(),
+            tool_choice: None,
+        }
+    }
+
+    fn replay(matching: Matching, prompts: &[&str]) -> ReplayProvider {
+        let interactions: Vec<_> = prompts
+            .iter()
+            .map(|p| {
+                let response = LlmResponse {
+                    content: format!("answer to {p}"),
+                    model: "test-model".to_string(),
+                    token_usage: TokenUsage::default(),
+                    content_blocks: Vec::new(),
+                    stop_reason: Some("end_turn".to_string()),
+                };
+                (request_key(&request(p)), response)
+            })
+            .collect();
+        ReplayProvider {
+            name: "replay".to_string(),
+            path: PathBuf::from("test.json"),
+            matching,
+            used: Mutex::new(vec![false; interactions.len()]),
+            interactions,
+        }
+    }
+
+    #[test]
+    fn exact_matching_replays_matching_requests_in_any_order() {
+        let provider = replay(Matching::Exact, &["a", "b"]);
+        assert_eq!(
+            provider.next_response(&request("b")).unwrap().content,
+            "answer to b"
+        );
+        assert_eq!(
+            provider.next_response(&request("a")).unwrap().content,
+            "answer to a"
+        );
+    }
+
+    #[test]
+    fn exhausted_cassette_starts_over_for_the_next_run() {
+        let provider = replay(Matching::Exact, &["a", "b"]);
+        for _ in 0..2 {
+            assert_eq!(
+                provider.next_response(&request("a")).unwrap().content,
+                "answer to a"
+            );
+            assert_eq!(
+                provider.next_response(&request("b")).unwrap().content,
+                "answer to b"
+            );
+        }
+    }
+
+    #[test]
+    fn recording_path_stays_inside_the_recording_dir() {
+        assert!(validate_recording_path("run.json").is_ok());
+        assert!(validate_recording_path("demo/run.json").is_ok());
+        assert!(validate_recording_path("").is_err());
+        assert!(validate_recording_path("../run.json").is_err());
+        assert!(validate_recording_path("demo/../../run.json").is_err());
+        assert!(validate_recording_path("/tmp/run.json").is_err());
+    }
+
+    #[test]
+    fn exact_matching_rejects_unrecorded_request() {
+        let provider = replay(Matching::Exact, &["a"]);
+        let error = provider
+            .next_response(&request("x"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("differs at messages[0].content"), "{error}");
+        // 失敗したリクエストは記録を消費しない
+        assert_eq!(
+            provider.next_response(&request("a")).unwrap().content,
+            "answer to a"
+        );
+    }
+
+    #[test]
+    fn in_order_matching_falls_back_to_next_recorded_interaction() {
+        let provider = replay(Matching::InOrder, &["a", "b"]);
+        assert_eq!(
+            provider.next_response(&request("x")).unwrap().content,
+            "answer to a"
+        );
+        assert_eq!(
+            provider.next_response(&request("b")).unwrap().content,
+            "answer to b"
+        );
+    }
+
+    #[test]
+    fn matching_defaults_to_exact_and_round_trips() {
+        let cassette: Cassette = serde_json::from_str(r#"{"interactions": []}"#).unwrap();
+        assert_eq!(cassette.matching, Matching::Exact);
+        let cassette: Cassette =
+            serde_json::from_str(r#"{"matching": "in_order", "interactions": []}"#).unwrap();
+        assert_eq!(cassette.matching, Matching::InOrder);
+        let json = serde_json::to_value(&cassette).unwrap();
+        assert_eq!(json["matching"], "in_order");
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::constants::{
+    DEFAULT_MODEL_ANTHROPIC, DEFAULT_MODEL_GOOGLE, MODEL_CATALOG_SOURCE_API,
+    MODEL_CATALOG_SOURCE_STATIC, PROVIDER_TYPE_ANTHROPIC, PROVIDER_TYPE_GOOGLE,
+};
+use crate::error::AppError;
+use crate::models::{LlmProvider, ModelCatalog, ModelInfo};
+
+use super::{provider_from_row, LlmRegistry};
+
+/// モデル一覧エンドポイントの結果を使い回す時間
+const CATALOG_TTL: Duration = Duration::from_secs(60 * 60);
+/// 取得に失敗したときの組み込みの一覧を使い回す時間（呼び出しのたびに待たされないようにする）
+const FALLBACK_TTL: Duration = Duration::from_secs(60);
+
+/// 組み込みのモデル表。単価はコスト計算と同じ model_prices から補うので、ここには持たない
+struct StaticModel {
+    provider_type: &'static str,
+    /// 一覧エンドポイントのモデルに前方一致でメタデータを補う（最長一致）
+    prefix: &'static str,
+    id: &'static str,
+    display_name: &'static str,
+    context_window: u32,
+    max_output_tokens: u32,
+}
+
+const STATIC_MODELS: &[StaticModel] = &[
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-opus-4-5",
+        id: "claude-opus-4-5-20251101",
+        display_name: "Claude Opus 4.5",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-opus-4-1",
+        id: "claude-opus-4-1-20250805",
+        display_name: "Claude Opus 4.1",
+        context_window: 200_000,
+        max_output_tokens: 32_000,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-opus-4",
+        id: "claude-opus-4-20250514",
+        display_name: "Claude Opus 4",
+        context_window: 200_000,
+        max_output_tokens: 32_000,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-sonnet-4-5",
+        id: "claude-sonnet-4-5-20250929",
+        display_name: "Claude Sonnet 4.5",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-sonnet-4",
+        id: "claude-sonnet-4-20250514",
+        display_name: "Claude Sonnet 4",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-3-7-sonnet",
+        id: "claude-3-7-sonnet-20250219",
+        display_name: "Claude Sonnet 3.7",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-haiku-4-5",
+        id: "claude-haiku-4-5-20251001",
+        display_name: "Claude Haiku 4.5",
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_ANTHROPIC,
+        prefix: "claude-3-5-haiku",
+        id: "claude-3-5-haiku-20241022",
+        display_name: "Claude Haiku 3.5",
+        context_window: 200_000,
+        max_output_tokens: 8_192,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_GOOGLE,
+        prefix: "gemini-2.5-pro",
+        id: "gemini-2.5-pro",
+        display_name: "Gemini 2.5 Pro",
+        context_window: 1_048_576,
+        max_output_tokens: 65_536,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_GOOGLE,
+        prefix: "gemini-2.5-flash",
+        id: "gemini-2.5-flash",
+        display_name: "Gemini 2.5 Flash",
+        context_window: 1_048_576,
+        max_output_tokens: 65_536,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_GOOGLE,
+        prefix: "gemini-2.5-flash-lite",
+        id: "gemini-2.5-flash-lite",
+        display_name: "Gemini 2.5 Flash-Lite",
+        context_window: 1_048_576,
+        max_output_tokens: 65_536,
+    },
+    StaticModel {
+        provider_type: PROVIDER_TYPE_GOOGLE,
+        prefix: "gemini-2.0-flash",
+        id: "gemini-2.0-flash",
+        display_name: "Gemini 2.0 Flash",
+        context_window: 1_048_576,
+        max_output_tokens: 8_192,
+    },
+];
+
+impl StaticModel {
+    fn to_info(&self) -> ModelInfo {
+        ModelInfo {
+            id: self.id.to_string(),
+            display_name: Some(self.display_name.to_string()),
+            context_window: Some(self.context_window),
+            max_output_tokens: Some(self.max_output_tokens),
+            input_price: None,
+            output_price: None,
+        }
+    }
+}
+
+/// モデル未指定でエージェントを作成したときの既定モデル（None ならモデルの指定が必須）
+pub fn default_model(provider_type: &str) -> Option<&'static str> {
+    match provider_type {
+        PROVIDER_TYPE_ANTHROPIC => Some(DEFAULT_MODEL_ANTHROPIC),
+        PROVIDER_TYPE_GOOGLE => Some(DEFAULT_MODEL_GOOGLE),
+        _ => None,
+    }
+}
+
+fn static_models(provider_type: &str) -> Vec<ModelInfo> {
+    STATIC_MODELS
+        .iter()
+        .filter(|m| m.provider_type == provider_type)
+        .map(StaticModel::to_info)
+        .collect()
+}
+
+/// 一覧エンドポイントが返さない項目を組み込みの表から補う
+fn enrich(provider_type: &str, models: &mut [ModelInfo]) {
+    for model in models {
+        let Some(known) = STATIC_MODELS
+            .iter()
+            .filter(|m| m.provider_type == provider_type && model.id.starts_with(m.prefix))
+            .max_by_key(|m| m.prefix.len())
+        else {
+            continue;
+        };
+        model
+            .display_name
+            .get_or_insert_with(|| known.display_name.to_string());
+        model.context_window.get_or_insert(known.context_window);
+        model
+            .max_output_tokens
+            .get_or_insert(known.max_output_tokens);
+    }
+}
+
+impl ModelCatalog {
+    /// 指定されたモデルが使えるか。組み込みの一覧は網羅していないので照合しない。
+    /// 日付なしの別名（claude-sonnet-4-5）やタグなしの名前（llama3）も受け付ける。
+    pub fn accepts(&self, model: &str) -> bool {
+        if self.source != MODEL_CATALOG_SOURCE_API {
+            return true;
+        }
+        self.models.iter().any(|m| {
+            m.id == model
+                || m.id
+                    .strip_prefix(model)
+                    .is_some_and(|rest| rest.starts_with('-') || rest.starts_with(':'))
+        })
+    }
+}
+
+/// プロバイダー名 → (期限, モデル一覧)
+#[derive(Default)]
+pub(super) struct CatalogCache {
+    entries: Mutex<HashMap<String, (Instant, ModelCatalog)>>,
+}
+
+impl CatalogCache {
+    fn get(&self, provider_name: &str) -> Option<ModelCatalog> {
+        let entries = self.entries.lock().expect("CatalogCache lock poisoned");
+        entries
+            .get(provider_name)
+            .filter(|(expires_at, _)| Instant::now() < *expires_at)
+            .map(|(_, catalog)| catalog.clone())
+    }
+
+    fn insert(&self, catalog: ModelCatalog, ttl: Duration) {
+        let mut entries = self.entries.lock().expect("CatalogCache lock poisoned");
+        entries.insert(
+            catalog.provider_name.clone(),
+            (Instant::now() + ttl, catalog),
+        );
+    }
+
+    pub(super) fn clear(&self) {
+        self.entries
+            .lock()
+            .expect("CatalogCache lock poisoned")
+            .clear();
+    }
+}
+
+impl LlmRegistry {
+    /// プロバイダーのモデル一覧を返す。一覧エンドポイントの結果をメモリに保持し、
+    /// 取得できなければ組み込みの一覧で代用する。未登録（無効化中など）なら行から生成して問い合わせる。
+    pub async fn model_catalog(&self, row: &LlmProvider) -> ModelCatalog {
+        if let Some(catalog) = self.catalog.get(&row.name) {
+            return catalog;
+        }
+
+        let result: Result<Vec<ModelInfo>, AppError> = match self.require(&row.name) {
+            Ok(handle) => handle.provider.list_models().await,
+            Err(_) => match provider_from_row(row) {
+                Ok(provider) => provider.list_models().await,
+                Err(e) => Err(e),
+            },
+        };
+        let (source, error, models, ttl) = match result {
+            Ok(mut models) if !models.is_empty() => {
+                enrich(&row.provider_type, &mut models);
+                (MODEL_CATALOG_SOURCE_API, None, models, CATALOG_TTL)
+            }
+            Ok(_) => (
+                MODEL_CATALOG_SOURCE_STATIC,
+                None,
+                static_models(&row.provider_type),
+                FALLBACK_TTL,
+            ),
+            Err(e) => {
+                eprintln!("[tebiki] Failed to list models of '{}': {e}", row.name);
+                (
+                    MODEL_CATALOG_SOURCE_STATIC,
+                    Some(e.to_string()),
+                    static_models(&row.provider_type),
+                    FALLBACK_TTL,
+                )
+            }
+        };
+
+        let catalog = ModelCatalog {
+            provider_name: row.name.clone(),
+            source: source.to_string(),
+            error,
+            models,
+        };
+        self.catalog.insert(catalog.clone(), ttl);
+        catalog
+    }
+}
@@ -0,0 +1,30 @@
+use crate::constants::{ROLE_ASSISTANT, ROLE_USER, STOP_MAX_TOKENS};
+
+use super::types::{LlmMessage, LlmResponse, MessageContent};
+
+/// 打ち切られた応答の続きを書かせるメッセージ
+const CONTINUE_PROMPT: &str = "Your previous response was cut off because it reached the output token limit. Continue exactly where it stopped. Do not repeat anything you already wrote and do not add any commentary.";
+
+/// 出力トークンの上限で打ち切られた応答か
+pub fn is_truncated(response: &LlmResponse) -> bool {
+    response.stop_reason.as_deref() == Some(STOP_MAX_TOKENS)
+}
+
+/// 続きを書かせられるか。テキストが出る前に打ち切られた（思考で上限を使い切った）場合は
+/// 続けても同じ結果になるので続けない。
+pub fn can_continue(response: &LlmResponse) -> bool {
+    is_truncated(response) && !response.content.trim().is_empty()
+}
+
+/// 打ち切られた応答のテキストと続きの依頼を履歴に足す。
+/// 途中までの tool_use は結果を返せないため送り返さない。
+pub fn push_continuation(messages: &mut Vec<LlmMessage>, partial: &LlmResponse) {
+    messages.push(LlmMessage {
+        role: ROLE_ASSISTANT.to_string(),
+        content: MessageContent::Text(partial.content.clone()),
+    });
+    messages.push(LlmMessage {
+        role: ROLE_USER.to_string(),
+        content: MessageContent::Text(CONTINUE_PROMPT.to_string()),
+    });
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::constants::{
+    EMBEDDING_TASK_DOCUMENT, EMBEDDING_TASK_QUERY, EMBEDDING_TASK_SIMILARITY, PROVIDER_TYPE_GOOGLE,
+    PROVIDER_TYPE_OPENAI_COMPATIBLE,
+};
+use crate::error::AppError;
+use crate::models::LlmProvider;
+
+use super::gemini::GeminiProvider;
+use super::limiter::ProviderLimiter;
+use super::openai_compat::OpenAiCompatProvider;
+use super::retry::{retry_delay, MAX_ATTEMPTS};
+use super::LlmRegistry;
+
+/// 埋め込みの用途。Gemini では taskType として送り、他のプロバイダーでは無視する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingTask {
+    /// 検索クエリ
+    Query,
+    /// 検索対象の文書
+    Document,
+    /// 文同士の類似度
+    Similarity,
+}
+
+impl EmbeddingTask {
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            EMBEDDING_TASK_QUERY => Ok(Self::Query),
+            EMBEDDING_TASK_DOCUMENT => Ok(Self::Document),
+            EMBEDDING_TASK_SIMILARITY => Ok(Self::Similarity),
+            other => Err(AppError::InvalidInput(format!(
+                "Invalid task '{other}'. Must be '{EMBEDDING_TASK_QUERY}', '{EMBEDDING_TASK_DOCUMENT}' or '{EMBEDDING_TASK_SIMILARITY}'."
+            ))),
+        }
+    }
+}
+
+/// 埋め込みのリクエスト（件数はバッチの上限を超えてもよい）
+#[derive(Debug, Clone)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub inputs: Vec<String>,
+    pub task: Option<EmbeddingTask>,
+    /// 出力次元数（対応するモデルのみ。None はモデルの既定値）
+    pub dimensions: Option<u32>,
+}
+
+#[async_trait]
+pub trait EmbeddingProviderTrait: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// 1回の API 呼び出しで送れる入力の最大件数
+    fn max_batch_size(&self) -> usize;
+
+    /// モデル未指定時に使うモデル（None ならモデルの指定が必須）
+    fn default_model(&self) -> Option<&str> {
+        None
+    }
+
+    /// inputs と同じ順序でベクトルを返す（inputs は max_batch_size 件以下）
+    async fn embed_batch(
+        &self,
+        request: &EmbeddingRequest,
+        inputs: &[String],
+    ) -> Result<Vec<Vec<f32>>, AppError>;
+}
+
+/// llm_providers の行から埋め込みに対応するプロバイダーを生成する（非対応の種別は None）
+pub fn embedding_provider_from_row(
+    row: &LlmProvider,
+) -> Result<Option<Box<dyn EmbeddingProviderTrait>>, AppError> {
+    let provider: Box<dyn EmbeddingProviderTrait> = match row.provider_type.as_str() {
+        PROVIDER_TYPE_GOOGLE => Box::new(GeminiProvider::from_row(row)?),
+        PROVIDER_TYPE_OPENAI_COMPATIBLE => Box::new(OpenAiCompatProvider::from_row(row)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(provider))
+}
+
+/// 登録済みの埋め込みプロバイダーと、補完と共有するレート制限
+#[derive(Clone)]
+pub struct EmbedderHandle {
+    pub embedder: Arc<dyn EmbeddingProviderTrait>,
+    pub limiter: Arc<ProviderLimiter>,
+}
+
+/// 入力トークン数の概算（4文字≒1トークン）
+fn estimate_input_tokens(inputs: &[String]) -> u32 {
+    let chars: usize = inputs.iter().map(|s| s.len()).sum();
+    u32::try_from(chars / 4).unwrap_or(u32::MAX)
+}
+
+impl LlmRegistry {
+    pub fn register_embedder(&self, embedder: Box<dyn EmbeddingProviderTrait>) {
+        let mut guard = self.state.write().expect("LlmRegistry lock poisoned");
+        guard
+            .embedders
+            .insert(embedder.name().to_string(), Arc::from(embedder));
+    }
+
+    /// 埋め込みプロバイダーを取得する。レート制限は同じ名前の補完プロバイダーと共有する。
+    pub fn require_embedder(&self, name: &str) -> Result<EmbedderHandle, AppError> {
+        let guard = self.state.read().expect("LlmRegistry lock poisoned");
+        let Some(embedder) = guard.embedders.get(name) else {
+            return Err(match guard.failures.get(name) {
+                Some(reason) => {
+                    AppError::LlmError(format!("Provider '{name}' is not available: {reason}"))
+                }
+                None => AppError::LlmError(format!(
+                    "Provider '{name}' does not support embeddings or is not registered"
+                )),
+            });
+        };
+        let limiter = guard
+            .providers
+            .get(name)
+            .map(|h| h.limiter.clone())
+            .unwrap_or_else(|| Arc::new(ProviderLimiter::unlimited()));
+        Ok(EmbedderHandle {
+            embedder: embedder.clone(),
+            limiter,
+        })
+    }
+
+    /// 入力をプロバイダーのバッチ上限ごとに分けて埋め込む。
+    /// バッチごとに枠を確保し、一時的な障害はリトライする。結果は inputs と同じ順序。
+    pub async fn embed(
+        &self,
+        provider_name: &str,
+        request: &EmbeddingRequest,
+    ) -> Result<Vec<Vec<f32>>, AppError> {
+        let handle = self.require_embedder(provider_name)?;
+        let embedder = handle.embedder.as_ref();
+        let mut embeddings = Vec::with_capacity(request.inputs.len());
+
+        for batch in request.inputs.chunks(embedder.max_batch_size().max(1)) {
+            let estimated_tokens = estimate_input_tokens(batch);
+            let _permit = handle.limiter.acquire(estimated_tokens).await;
+            let mut attempt = 1;
+            let vectors = loop {
+                let error = match embedder.embed_batch(request, batch).await {
+                    Ok(vectors) => break vectors,
+                    Err(e) => e,
+                };
+                let Some(delay) = retry_delay(&error, attempt) else {
+                    return Err(error);
+                };
+                attempt += 1;
+                eprintln!(
+                    "[tebiki] {} embedding call failed, retrying in {}ms (attempt {attempt}/{MAX_ATTEMPTS}): {error}",
+                    embedder.name(),
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+                handle.limiter.wait_rate(estimated_tokens).await;
+            };
+
+            if vectors.len() != batch.len() {
+                return Err(AppError::LlmError(format!(
+                    "{} returned {} embeddings for {} inputs",
+                    embedder.name(),
+                    vectors.len(),
+                    batch.len()
+                )));
+            }
+            embeddings.extend(vectors);
+        }
+        Ok(embeddings)
+    }
+}
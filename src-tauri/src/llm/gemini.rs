@@ -1,29 +1,79 @@
+use std::collections::HashMap;
+
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+use crate::constants::{DEFAULT_EMBEDDING_MODEL_GOOGLE, PROVIDER_TYPE_GOOGLE};
 use crate::error::AppError;
+use crate::models::{LlmProvider, ModelInfo};
+use crate::secrets;
 
-use super::types::{ContentBlock, LlmRequest, LlmResponse, MessageContent, TokenUsage};
+use super::embeddings::{EmbeddingProviderTrait, EmbeddingRequest, EmbeddingTask};
+use super::media;
+use super::retry;
+use super::sampling::{self, ToolChoice};
+use super::sse::{incomplete_stream, read_sse};
+use super::types::{
+    ContentBlock, DeltaSink, LlmMessage, LlmRequest, LlmResponse, MediaSource, MessageContent,
+    StreamDelta, TokenUsage,
+};
 use super::LlmProviderTrait;
 
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+
+/// ストリーミング時のタイムアウト（長い生成でも途中で切らないよう長めに取る）
+const STREAM_TIMEOUT_SECS: u64 = 600;
+
+/// モデル一覧の1ページあたりの件数（API の上限）
+const MODEL_LIST_PAGE_SIZE: &str = "1000";
+
+/// batchEmbedContents の1回あたりの入力件数の上限
+const EMBED_BATCH_SIZE: usize = 100;
+
+/// Gemini が id を返さなかった関数呼び出しに振る id の接頭辞（API には送り返さない）
+const GENERATED_CALL_ID_PREFIX: &str = "gemini_call_";
+
+#[derive(Clone)]
 pub struct GeminiProvider {
+    name: String,
+    base_url: String,
     api_key: String,
     client: reqwest::Client,
 }
 
 impl GeminiProvider {
-    pub fn from_env() -> Result<Self, AppError> {
-        let api_key = std::env::var("GOOGLE_AI_STUDIO_API_KEY").map_err(|_| {
-            AppError::LlmError(
-                "GOOGLE_AI_STUDIO_API_KEY environment variable not set".to_string(),
-            )
-        })?;
+    fn new(name: &str, base_url: &str, api_key: String) -> Result<Self, AppError> {
         let client = reqwest::Client::builder()
             .connect_timeout(std::time::Duration::from_secs(10))
             .timeout(std::time::Duration::from_secs(120))
             .build()
             .map_err(|e| AppError::LlmError(format!("Failed to build HTTP client: {e}")))?;
-        Ok(Self { api_key, client })
+        Ok(Self {
+            name: name.to_string(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            client,
+        })
+    }
+
+    /// DB未接続時のフォールバック（資格情報ボルト → 環境変数から構築）
+    pub fn from_env() -> Result<Self, AppError> {
+        let api_key = secrets::resolve("GOOGLE_AI_STUDIO_API_KEY")?.ok_or_else(|| {
+            AppError::LlmError("Credential 'GOOGLE_AI_STUDIO_API_KEY' is not set".to_string())
+        })?;
+        Self::new("google", DEFAULT_BASE_URL, api_key)
+    }
+
+    /// llm_providers の行から構築する（api_base_url 未設定時は公式エンドポイント）
+    pub fn from_row(row: &LlmProvider) -> Result<Self, AppError> {
+        let api_key = super::require_api_key(row)?;
+        let base_url = row
+            .api_base_url
+            .as_deref()
+            .filter(|u| !u.trim().is_empty())
+            .unwrap_or(DEFAULT_BASE_URL);
+        Self::new(&row.name, base_url, api_key)
     }
 }
 
@@ -39,11 +89,15 @@ struct GeminiRequest {
     generation_config: Option<GeminiGenerationConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<GeminiToolDeclaration>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<GeminiToolConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct GeminiContent {
     role: String,
+    /// 出力上限で打ち切られた候補などでは省略される
+    #[serde(default)]
     parts: Vec<GeminiPart>,
 }
 
@@ -58,31 +112,61 @@ struct GeminiTextPart {
     text: String,
 }
 
-/// Gemini API の parts は text / functionCall / functionResponse のいずれか
+/// Gemini API の parts は text / functionCall / functionResponse / inlineData のいずれか。
+/// 思考の要約は thought: true 付きの text として届く。
+/// 思考ありの関数呼び出しには thoughtSignature が付き、履歴として同じ part に付けて送り返す。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 enum GeminiPart {
+    InlineData {
+        #[serde(rename = "inlineData")]
+        inline_data: GeminiBlob,
+    },
     FunctionCall {
         #[serde(rename = "functionCall")]
         function_call: GeminiFunctionCall,
+        #[serde(
+            rename = "thoughtSignature",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        thought_signature: Option<String>,
     },
     FunctionResponse {
         #[serde(rename = "functionResponse")]
         function_response: GeminiFunctionResponse,
     },
+    Thought {
+        text: String,
+        thought: bool,
+    },
     Text {
         text: String,
     },
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiBlob {
+    mime_type: String,
+    data: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct GeminiFunctionCall {
+    /// Gemini が振った呼び出し id（返さないモデルもある）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     name: String,
+    #[serde(default)]
     args: serde_json::Value,
 }
 
+/// name は呼び出した関数名。id があれば対応する functionCall の id を入れる。
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct GeminiFunctionResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     name: String,
     response: serde_json::Value,
 }
@@ -92,6 +176,28 @@ struct GeminiFunctionResponse {
 struct GeminiGenerationConfig {
     temperature: f64,
     max_output_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
+    /// 構造化出力時は application/json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_json_schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiThinkingConfig {
+    thinking_budget: i32,
+    include_thoughts: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -100,6 +206,38 @@ struct GeminiToolDeclaration {
     function_declarations: Vec<GeminiFunctionDeclaration>,
 }
 
+/// ツール使用の指定。mode は AUTO / ANY / NONE、ANY で関数を限定するときは allowedFunctionNames を付ける。
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiToolConfig {
+    function_calling_config: GeminiFunctionCallingConfig,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiFunctionCallingConfig {
+    mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_function_names: Option<Vec<String>>,
+}
+
+impl GeminiToolConfig {
+    fn from_choice(choice: &ToolChoice) -> Self {
+        let (mode, allowed_function_names) = match choice {
+            ToolChoice::Auto => ("AUTO", None),
+            ToolChoice::Any => ("ANY", None),
+            ToolChoice::None => ("NONE", None),
+            ToolChoice::Tool { name } => ("ANY", Some(vec![name.clone()])),
+        };
+        Self {
+            function_calling_config: GeminiFunctionCallingConfig {
+                mode: mode.to_string(),
+                allowed_function_names,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct GeminiFunctionDeclaration {
     name: String,
@@ -128,6 +266,8 @@ struct GeminiCandidate {
 struct GeminiUsageMetadata {
     prompt_token_count: Option<u32>,
     candidates_token_count: Option<u32>,
+    /// prompt_token_count のうちキャッシュから読み込んだ分
+    cached_content_token_count: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -137,32 +277,158 @@ struct GeminiErrorResponse {
 
 #[derive(Debug, Deserialize)]
 struct GeminiErrorDetail {
+    /// HTTP ステータス相当のコード
+    code: Option<u16>,
     message: String,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModelList {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModel {
+    /// "models/gemini-2.5-flash" の形
+    name: String,
+    display_name: Option<String>,
+    input_token_limit: Option<u32>,
+    output_token_limit: Option<u32>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiBatchEmbedRequest<'a> {
+    requests: Vec<GeminiEmbedRequest<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiEmbedRequest<'a> {
+    model: &'a str,
+    content: GeminiEmbedContent<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    task_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_dimensionality: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiEmbedContent<'a> {
+    parts: [GeminiEmbedText<'a>; 1],
+}
+
+#[derive(Debug, Serialize)]
+struct GeminiEmbedText<'a> {
+    text: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiBatchEmbedResponse {
+    #[serde(default)]
+    embeddings: Vec<GeminiEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiEmbedding {
+    values: Vec<f32>,
+}
+
 // --- Conversion helpers ---
 
-/// 内部の ContentBlock を GeminiPart に変換
-fn content_block_to_gemini(block: &ContentBlock) -> GeminiPart {
-    match block {
+/// 画像・ドキュメントを inlineData に変換（未解決の File は代替テキストにする）
+fn media_source_to_gemini(source: &MediaSource) -> GeminiPart {
+    match source {
+        MediaSource::Base64 { media_type, data } => GeminiPart::InlineData {
+            inline_data: GeminiBlob {
+                mime_type: media_type.clone(),
+                data: data.clone(),
+            },
+        },
+        MediaSource::File { path, .. } => GeminiPart::Text {
+            text: media::unresolved_note(path),
+        },
+    }
+}
+
+/// functionCall の id を tool_use の id にする。Gemini が id を返さなければ一意な id を振る。
+fn tool_use_id(call: &GeminiFunctionCall) -> String {
+    call.id
+        .clone()
+        .unwrap_or_else(|| format!("{GENERATED_CALL_ID_PREFIX}{}", Uuid::new_v4().simple()))
+}
+
+/// こちらで振った id は Gemini が知らないため送り返さない
+fn api_call_id(tool_use_id: &str) -> Option<String> {
+    Some(tool_use_id.to_string()).filter(|id| !id.starts_with(GENERATED_CALL_ID_PREFIX))
+}
+
+/// 会話中の tool_use の id → 関数名。functionResponse には tool_use_id ではなく関数名が必要なため、
+/// messages_json に残っている tool_use から引き直す。
+fn tool_call_names(messages: &[LlmMessage]) -> HashMap<&str, &str> {
+    messages
+        .iter()
+        .filter_map(|m| match &m.content {
+            MessageContent::Blocks(blocks) => Some(blocks),
+            MessageContent::Text(_) => None,
+        })
+        .flatten()
+        .filter_map(|b| match b {
+            ContentBlock::ToolUse { id, name, .. } => Some((id.as_str(), name.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// 内部の ContentBlock を GeminiPart に変換（思考ブロックは送り返さないため None）
+fn content_block_to_gemini(
+    block: &ContentBlock,
+    call_names: &HashMap<&str, &str>,
+) -> Option<GeminiPart> {
+    let part = match block {
         ContentBlock::Text { text } => GeminiPart::Text { text: text.clone() },
-        ContentBlock::ToolUse { name, input, .. } => GeminiPart::FunctionCall {
+        ContentBlock::ToolUse {
+            id,
+            name,
+            input,
+            signature,
+        } => GeminiPart::FunctionCall {
             function_call: GeminiFunctionCall {
+                id: api_call_id(id),
                 name: name.clone(),
                 args: input.clone(),
             },
+            thought_signature: signature.clone(),
         },
         ContentBlock::ToolResult {
             tool_use_id,
             content,
-            ..
+            is_error,
         } => GeminiPart::FunctionResponse {
             function_response: GeminiFunctionResponse {
-                name: tool_use_id.clone(),
-                response: serde_json::json!({ "result": content }),
+                id: api_call_id(tool_use_id),
+                // 対応する tool_use が履歴にない場合（圧縮で消えた等）は id で代用する
+                name: call_names
+                    .get(tool_use_id.as_str())
+                    .map_or_else(|| tool_use_id.clone(), |name| name.to_string()),
+                response: if *is_error {
+                    serde_json::json!({ "error": content })
+                } else {
+                    serde_json::json!({ "result": content })
+                },
             },
         },
-    }
+        ContentBlock::Image { source } | ContentBlock::Document { source, .. } => {
+            media_source_to_gemini(source)
+        }
+        ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => return None,
+    };
+    Some(part)
 }
 
 /// Gemini の finishReason を内部の stop_reason に変換
@@ -174,14 +440,99 @@ fn map_finish_reason(reason: &str) -> String {
     }
 }
 
-#[async_trait]
-impl LlmProviderTrait for GeminiProvider {
-    fn name(&self) -> &str {
-        "google"
+/// Gemini の parts・finishReason・usageMetadata から LlmResponse を組み立てる
+fn build_llm_response(
+    parts: &[GeminiPart],
+    finish_reason: Option<&str>,
+    usage_metadata: Option<&GeminiUsageMetadata>,
+    model: &str,
+) -> LlmResponse {
+    // ContentBlock に変換（1ターンに複数の functionCall が並ぶこともある）
+    let mut content_blocks: Vec<ContentBlock> = Vec::new();
+
+    for part in parts {
+        match part {
+            GeminiPart::Text { text } => {
+                content_blocks.push(ContentBlock::Text { text: text.clone() });
+            }
+            GeminiPart::Thought { text, .. } => {
+                content_blocks.push(ContentBlock::Thinking {
+                    thinking: text.clone(),
+                    signature: None,
+                });
+            }
+            GeminiPart::FunctionCall {
+                function_call,
+                thought_signature,
+            } => {
+                content_blocks.push(ContentBlock::ToolUse {
+                    id: tool_use_id(function_call),
+                    name: function_call.name.clone(),
+                    input: function_call.args.clone(),
+                    signature: thought_signature.clone(),
+                });
+            }
+            GeminiPart::FunctionResponse { .. } | GeminiPart::InlineData { .. } => {
+                // レスポンスに functionResponse / inlineData が含まれることは通常ない
+            }
+        }
     }
 
-    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
+    // テキスト内容を結合
+    let content = parts
+        .iter()
+        .filter_map(|p| match p {
+            GeminiPart::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("");
+
+    // stop_reason を変換
+    let stop_reason = finish_reason.map(map_finish_reason);
+
+    // tool_use がある場合は stop_reason を "tool_use" に設定
+    let stop_reason = if content_blocks
+        .iter()
+        .any(|b| matches!(b, ContentBlock::ToolUse { .. }))
+    {
+        Some("tool_use".to_string())
+    } else {
+        stop_reason
+    };
+
+    // トークン使用量
+    let prompt_tokens = usage_metadata
+        .and_then(|u| u.prompt_token_count)
+        .unwrap_or(0);
+    let cached_tokens = usage_metadata
+        .and_then(|u| u.cached_content_token_count)
+        .unwrap_or(0);
+    let token_usage = TokenUsage {
+        input_tokens: prompt_tokens.saturating_sub(cached_tokens),
+        output_tokens: usage_metadata
+            .and_then(|u| u.candidates_token_count)
+            .unwrap_or(0),
+        cache_read_tokens: cached_tokens,
+        cache_write_tokens: 0,
+    };
+
+    LlmResponse {
+        content,
+        model: model.to_string(),
+        token_usage,
+        content_blocks,
+        stop_reason,
+    }
+}
+
+impl GeminiProvider {
+    /// 共通の LlmRequest を Gemini API のリクエストに変換
+    fn build_request(&self, request: &LlmRequest) -> Result<GeminiRequest, AppError> {
+        sampling::validate_request(PROVIDER_TYPE_GOOGLE, request)?;
+
         // 1. メッセージを Gemini 形式に変換
+        let call_names = tool_call_names(&request.messages);
         let contents: Vec<GeminiContent> = request
             .messages
             .iter()
@@ -194,9 +545,10 @@ impl LlmProviderTrait for GeminiProvider {
                 };
                 let parts = match &m.content {
                     MessageContent::Text(s) => vec![GeminiPart::Text { text: s.clone() }],
-                    MessageContent::Blocks(blocks) => {
-                        blocks.iter().map(content_block_to_gemini).collect()
-                    }
+                    MessageContent::Blocks(blocks) => blocks
+                        .iter()
+                        .filter_map(|b| content_block_to_gemini(b, &call_names))
+                        .collect(),
                 };
                 GeminiContent { role, parts }
             })
@@ -222,49 +574,122 @@ impl LlmProviderTrait for GeminiProvider {
         });
 
         // 4. リクエスト構築
-        let api_request = GeminiRequest {
+        Ok(GeminiRequest {
             contents,
             system_instruction,
             generation_config: Some(GeminiGenerationConfig {
                 temperature: request.temperature,
-                max_output_tokens: request.max_tokens,
+                max_output_tokens: request.max_output_tokens(),
+                top_p: request.sampling.top_p,
+                top_k: request.sampling.top_k,
+                stop_sequences: request.sampling.stop_sequences.clone(),
+                seed: request.sampling.seed,
+                thinking_config: request.thinking_budget.filter(|b| *b > 0).map(|budget| {
+                    GeminiThinkingConfig {
+                        thinking_budget: budget,
+                        include_thoughts: true,
+                    }
+                }),
+                response_mime_type: request
+                    .output_schema
+                    .as_ref()
+                    .map(|_| "application/json".to_string()),
+                response_json_schema: request.output_schema.clone(),
             }),
             tools,
-        };
-
-        // 5. API 呼び出し
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
-            request.model
-        );
+            tool_config: request
+                .tool_choice
+                .as_ref()
+                .map(GeminiToolConfig::from_choice),
+        })
+    }
 
-        let response = self
+    /// API 呼び出しとエラーハンドリング
+    async fn send(
+        &self,
+        url: &str,
+        api_request: &GeminiRequest,
+        stream: bool,
+    ) -> Result<reqwest::Response, AppError> {
+        let mut builder = self
             .client
-            .post(&url)
+            .post(url)
             .header("x-goog-api-key", &self.api_key)
-            .header("content-type", "application/json")
-            .json(&api_request)
+            .header("content-type", "application/json");
+        if stream {
+            builder = builder.timeout(std::time::Duration::from_secs(STREAM_TIMEOUT_SECS));
+        }
+
+        let response = builder
+            .json(api_request)
             .send()
             .await
-            .map_err(|e| AppError::LlmError(format!("Request failed: {e}")))?;
+            .map_err(retry::request_error)?;
 
-        // 6. エラーハンドリング
         if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            if let Ok(err) = serde_json::from_str::<GeminiErrorResponse>(&body) {
-                return Err(AppError::LlmError(format!(
-                    "Gemini API error ({}): {}",
-                    status, err.error.message
-                )));
-            }
-            return Err(AppError::LlmError(format!(
-                "Gemini API error ({}): {}",
-                status, body
-            )));
+            return Err(api_error(response).await);
         }
 
-        // 7. レスポンス解析
+        Ok(response)
+    }
+}
+
+/// ストリームの data を解析する。全フィールドが省略可能なチャンクとして読めてしまうため、
+/// error キーを先に確認する。解析できない data は応答を取りこぼさないようエラーにする。
+fn parse_stream_chunk(data: &str) -> Result<GeminiResponse, AppError> {
+    let parse_error = |e: serde_json::Error| {
+        let excerpt: String = data.chars().take(200).collect();
+        AppError::LlmError(format!(
+            "Failed to parse Gemini stream chunk: {e} (data: {excerpt})"
+        ))
+    };
+    let value: serde_json::Value = serde_json::from_str(data).map_err(parse_error)?;
+    if value.get("error").is_none() {
+        return serde_json::from_value(value).map_err(parse_error);
+    }
+    let err: GeminiErrorResponse = serde_json::from_value(value).map_err(parse_error)?;
+    let message = format!("Gemini stream error: {}", err.error.message);
+    // ストリーム途中の過負荷・内部エラーは再試行で回復しうる
+    Err(match err.error.code {
+        Some(code @ (429 | 500 | 503)) => AppError::LlmUnavailable {
+            message,
+            retry_after: None,
+            rate_limited: code == 429,
+        },
+        _ => AppError::LlmError(message),
+    })
+}
+
+/// 失敗したレスポンスをエラー種別付きの AppError にする
+async fn api_error(response: reqwest::Response) -> AppError {
+    let status = response.status();
+    let retry_after = retry::retry_after(response.headers());
+    let body = response.text().await.unwrap_or_default();
+    let message = match serde_json::from_str::<GeminiErrorResponse>(&body) {
+        Ok(err) => err.error.message,
+        Err(_) => body,
+    };
+    retry::status_error(
+        status,
+        retry_after,
+        format!("Gemini API error ({status}): {message}"),
+    )
+}
+
+#[async_trait]
+impl LlmProviderTrait for GeminiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
+        let api_request = self.build_request(request)?;
+        let url = format!(
+            "{}/v1beta/models/{}:generateContent",
+            self.base_url, request.model
+        );
+        let response = self.send(&url, &api_request, false).await?;
+
         let api_response: GeminiResponse = response
             .json()
             .await
@@ -283,76 +708,101 @@ impl LlmProviderTrait for GeminiProvider {
             .cloned()
             .unwrap_or_default();
 
-        // 8. ContentBlock に変換
-        let mut content_blocks: Vec<ContentBlock> = Vec::new();
-        let mut tool_use_counter = 0u32;
+        Ok(build_llm_response(
+            &parts,
+            candidate.finish_reason.as_deref(),
+            api_response.usage_metadata.as_ref(),
+            &request.model,
+        ))
+    }
 
-        for part in &parts {
-            match part {
-                GeminiPart::Text { text } => {
-                    content_blocks.push(ContentBlock::Text { text: text.clone() });
-                }
-                GeminiPart::FunctionCall { function_call } => {
-                    tool_use_counter += 1;
-                    content_blocks.push(ContentBlock::ToolUse {
-                        id: format!("toolu_{:08x}", tool_use_counter),
-                        name: function_call.name.clone(),
-                        input: function_call.args.clone(),
-                    });
-                }
-                GeminiPart::FunctionResponse { .. } => {
-                    // レスポンスに functionResponse が含まれることは通常ない
-                }
-            }
-        }
+    async fn complete_stream(
+        &self,
+        request: &LlmRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<LlmResponse, AppError> {
+        let api_request = self.build_request(request)?;
+        let url = format!(
+            "{}/v1beta/models/{}:streamGenerateContent?alt=sse",
+            self.base_url, request.model
+        );
+        let response = self.send(&url, &api_request, true).await?;
 
-        // 9. テキスト内容を結合
-        let content = parts
-            .iter()
-            .filter_map(|p| match p {
-                GeminiPart::Text { text } => Some(text.as_str()),
-                _ => None,
-            })
-            .collect::<Vec<_>>()
-            .join("");
+        let mut parts: Vec<GeminiPart> = Vec::new();
+        let mut finish_reason: Option<String> = None;
+        let mut usage_metadata: Option<GeminiUsageMetadata> = None;
 
-        // 10. stop_reason を変換
-        let stop_reason = candidate
-            .finish_reason
-            .as_deref()
-            .map(map_finish_reason);
+        read_sse(response, |sse| {
+            let chunk = parse_stream_chunk(&sse.data)?;
 
-        // tool_use がある場合は stop_reason を "tool_use" に設定
-        let stop_reason = if content_blocks
-            .iter()
-            .any(|b| matches!(b, ContentBlock::ToolUse { .. }))
-        {
-            Some("tool_use".to_string())
-        } else {
-            stop_reason
-        };
+            // usageMetadata は累計値で届くため最新で上書きする
+            if chunk.usage_metadata.is_some() {
+                usage_metadata = chunk.usage_metadata;
+            }
 
-        // 11. トークン使用量
-        let token_usage = TokenUsage {
-            input_tokens: api_response
-                .usage_metadata
-                .as_ref()
-                .and_then(|u| u.prompt_token_count)
-                .unwrap_or(0),
-            output_tokens: api_response
-                .usage_metadata
-                .as_ref()
-                .and_then(|u| u.candidates_token_count)
-                .unwrap_or(0),
-        };
+            let Some(candidate) = chunk.candidates.and_then(|c| c.into_iter().next()) else {
+                return Ok(());
+            };
+            if candidate.finish_reason.is_some() {
+                finish_reason = candidate.finish_reason;
+            }
 
-        Ok(LlmResponse {
-            content,
-            model: request.model.clone(),
-            token_usage,
-            content_blocks,
-            stop_reason,
+            for part in candidate.content.map(|c| c.parts).unwrap_or_default() {
+                match part {
+                    GeminiPart::Text { text } => {
+                        on_delta(StreamDelta::Text { text: text.clone() });
+                        // 連続するテキストは1つの part にまとめる
+                        if let Some(GeminiPart::Text { text: last }) = parts.last_mut() {
+                            last.push_str(&text);
+                        } else {
+                            parts.push(GeminiPart::Text { text });
+                        }
+                    }
+                    GeminiPart::Thought { text, thought } => {
+                        on_delta(StreamDelta::Thinking { text: text.clone() });
+                        if let Some(GeminiPart::Thought { text: last, .. }) = parts.last_mut() {
+                            last.push_str(&text);
+                        } else {
+                            parts.push(GeminiPart::Thought { text, thought });
+                        }
+                    }
+                    GeminiPart::FunctionCall {
+                        mut function_call,
+                        thought_signature,
+                    } => {
+                        // functionCall は分割されず1チャンクで届く。
+                        // 増分と最終レスポンスで同じ id になるよう、ここで id を確定させる。
+                        let id = tool_use_id(&function_call);
+                        function_call.id = Some(id.clone());
+                        on_delta(StreamDelta::ToolUseStart {
+                            id: id.clone(),
+                            name: function_call.name.clone(),
+                        });
+                        on_delta(StreamDelta::ToolUseInput {
+                            id,
+                            partial_json: function_call.args.to_string(),
+                        });
+                        parts.push(GeminiPart::FunctionCall {
+                            function_call,
+                            thought_signature,
+                        });
+                    }
+                    GeminiPart::FunctionResponse { .. } | GeminiPart::InlineData { .. } => {}
+                }
+            }
+            Ok(())
         })
+        .await?;
+        if finish_reason.is_none() {
+            return Err(incomplete_stream("Gemini"));
+        }
+
+        Ok(build_llm_response(
+            &parts,
+            finish_reason.as_deref(),
+            usage_metadata.as_ref(),
+            &request.model,
+        ))
     }
 
     async fn health_check(&self) -> Result<(), AppError> {
@@ -361,6 +811,366 @@ impl LlmProviderTrait for GeminiProvider {
                 "Google AI Studio API key is empty".to_string(),
             ));
         }
+        // モデル一覧の取得でキーと接続先を確認する
+        let response = self
+            .client
+            .get(format!("{}/v1beta/models", self.base_url))
+            .header("x-goog-api-key", &self.api_key)
+            .send()
+            .await
+            .map_err(|e| AppError::LlmError(format!("{} is unreachable: {e}", self.name)))?;
+        if !response.status().is_success() {
+            return Err(AppError::LlmError(format!(
+                "Gemini API error ({})",
+                response.status()
+            )));
+        }
         Ok(())
     }
+
+    /// GET /v1beta/models をページ送りしながら取得し、generateContent に対応するモデルだけを返す
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError> {
+        let mut models = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut builder = self
+                .client
+                .get(format!("{}/v1beta/models", self.base_url))
+                .header("x-goog-api-key", &self.api_key)
+                .query(&[("pageSize", MODEL_LIST_PAGE_SIZE)]);
+            if let Some(page_token) = &page_token {
+                builder = builder.query(&[("pageToken", page_token)]);
+            }
+            let response = builder.send().await.map_err(retry::request_error)?;
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+            let page: GeminiModelList = response
+                .json()
+                .await
+                .map_err(|e| AppError::LlmError(format!("Failed to parse model list: {e}")))?;
+
+            models.extend(
+                page.models
+                    .into_iter()
+                    .filter(|m| {
+                        m.supported_generation_methods
+                            .iter()
+                            .any(|method| method == "generateContent")
+                    })
+                    .map(|m| ModelInfo {
+                        id: m
+                            .name
+                            .strip_prefix("models/")
+                            .unwrap_or(&m.name)
+                            .to_string(),
+                        display_name: m.display_name,
+                        context_window: m.input_token_limit,
+                        max_output_tokens: m.output_token_limit,
+                        input_price: None,
+                        output_price: None,
+                    }),
+            );
+            match page.next_page_token.filter(|t| !t.is_empty()) {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+        Ok(models)
+    }
+}
+
+fn embedding_task_type(task: EmbeddingTask) -> &'static str {
+    match task {
+        EmbeddingTask::Query => "RETRIEVAL_QUERY",
+        EmbeddingTask::Document => "RETRIEVAL_DOCUMENT",
+        EmbeddingTask::Similarity => "SEMANTIC_SIMILARITY",
+    }
+}
+
+#[async_trait]
+impl EmbeddingProviderTrait for GeminiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn max_batch_size(&self) -> usize {
+        EMBED_BATCH_SIZE
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        Some(DEFAULT_EMBEDDING_MODEL_GOOGLE)
+    }
+
+    async fn embed_batch(
+        &self,
+        request: &EmbeddingRequest,
+        inputs: &[String],
+    ) -> Result<Vec<Vec<f32>>, AppError> {
+        let model = format!("models/{}", request.model);
+        let api_request = GeminiBatchEmbedRequest {
+            requests: inputs
+                .iter()
+                .map(|text| GeminiEmbedRequest {
+                    model: &model,
+                    content: GeminiEmbedContent {
+                        parts: [GeminiEmbedText { text }],
+                    },
+                    task_type: request.task.map(embedding_task_type),
+                    output_dimensionality: request.dimensions,
+                })
+                .collect(),
+        };
+        let response = self
+            .client
+            .post(format!(
+                "{}/v1beta/models/{}:batchEmbedContents",
+                self.base_url, request.model
+            ))
+            .header("x-goog-api-key", &self.api_key)
+            .json(&api_request)
+            .send()
+            .await
+            .map_err(retry::request_error)?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        let body: GeminiBatchEmbedResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::LlmError(format!("Failed to parse embeddings: {e}")))?;
+        Ok(body.embeddings.into_iter().map(|e| e.values).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::sampling::SamplingParams;
+    use serde_json::json;
+
+    fn provider() -> GeminiProvider {
+        GeminiProvider::new("google", DEFAULT_BASE_URL, "test-key".to_string()).unwrap()
+    }
+
+    fn request(messages: Vec<LlmMessage>) -> LlmRequest {
+        LlmRequest {
+            model: "gemini-2.5-flash".to_string(),
+            messages,
+            system: None,
+            temperature: 0.0,
+            max_tokens: 1024,
+            tools: None,
+            prompt_cache: false,
+            thinking_budget: None,
+            output_schema: None,
+            sampling: SamplingParams::default(),
+            tool_choice: None,
+        }
+    }
+
+    fn call_part(id: Option<&str>, name: &str, args: serde_json::Value) -> GeminiPart {
+        GeminiPart::FunctionCall {
+            function_call: GeminiFunctionCall {
+                id: id.map(str::to_string),
+                name: name.to_string(),
+                args,
+            },
+            thought_signature: None,
+        }
+    }
+
+    fn tool_uses(response: &LlmResponse) -> Vec<(String, String, Option<String>)> {
+        response
+            .content_blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::ToolUse {
+                    id,
+                    name,
+                    signature,
+                    ..
+                } => Some((id.clone(), name.clone(), signature.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// 応答 → messages_json → 次のリクエスト、の往復で関数名・引数・署名・対応関係が保たれる
+    #[test]
+    fn parallel_calls_round_trip_through_messages_json() {
+        let mut first = call_part(None, "read_file", json!({ "path": "a.txt" }));
+        if let GeminiPart::FunctionCall {
+            thought_signature, ..
+        } = &mut first
+        {
+            *thought_signature = Some("sig-1".to_string());
+        }
+        let parts = vec![
+            GeminiPart::Thought {
+                text: "thinking".to_string(),
+                thought: true,
+            },
+            first,
+            call_part(None, "web_search", json!({ "query": "rust" })),
+        ];
+        let response = build_llm_response(&parts, Some("STOP"), None, "gemini-2.5-flash");
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+
+        let calls = tool_uses(&response);
+        assert_eq!(calls.len(), 2);
+        assert_ne!(calls[0].0, calls[1].0);
+        assert_eq!(calls[0].2.as_deref(), Some("sig-1"));
+        assert_eq!(calls[1].2, None);
+
+        let messages = vec![
+            LlmMessage {
+                role: "user".to_string(),
+                content: MessageContent::Text("task".to_string()),
+            },
+            LlmMessage {
+                role: "assistant".to_string(),
+                content: MessageContent::Blocks(response.content_blocks.clone()),
+            },
+            LlmMessage {
+                role: "user".to_string(),
+                content: MessageContent::Blocks(vec![
+                    ContentBlock::ToolResult {
+                        tool_use_id: calls[0].0.clone(),
+                        content: "hello".to_string(),
+                        is_error: false,
+                    },
+                    ContentBlock::ToolResult {
+                        tool_use_id: calls[1].0.clone(),
+                        content: "rate limited".to_string(),
+                        is_error: true,
+                    },
+                ]),
+            },
+        ];
+        // オーケストレーションの messages_json と同じくシリアライズを挟む
+        let stored = serde_json::to_value(&messages).unwrap();
+        let restored: Vec<LlmMessage> = serde_json::from_value(stored).unwrap();
+
+        let api_request = provider().build_request(&request(restored)).unwrap();
+        let body = serde_json::to_value(&api_request.contents).unwrap();
+        assert_eq!(
+            body[1],
+            json!({
+                "role": "model",
+                "parts": [
+                    {
+                        "functionCall": { "name": "read_file", "args": { "path": "a.txt" } },
+                        "thoughtSignature": "sig-1"
+                    },
+                    { "functionCall": { "name": "web_search", "args": { "query": "rust" } } }
+                ]
+            })
+        );
+        assert_eq!(
+            body[2],
+            json!({
+                "role": "user",
+                "parts": [
+                    { "functionResponse": { "name": "read_file", "response": { "result": "hello" } } },
+                    { "functionResponse": { "name": "web_search", "response": { "error": "rate limited" } } }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn gemini_issued_ids_are_sent_back() {
+        let parts = vec![call_part(Some("call-1"), "get_time", json!({}))];
+        let response = build_llm_response(&parts, None, None, "gemini-2.5-flash");
+        let calls = tool_uses(&response);
+        assert_eq!(calls[0].0, "call-1");
+
+        let messages = vec![
+            LlmMessage {
+                role: "assistant".to_string(),
+                content: MessageContent::Blocks(response.content_blocks),
+            },
+            LlmMessage {
+                role: "user".to_string(),
+                content: MessageContent::Blocks(vec![ContentBlock::ToolResult {
+                    tool_use_id: "call-1".to_string(),
+                    content: "12:00".to_string(),
+                    is_error: false,
+                }]),
+            },
+        ];
+        let api_request = provider().build_request(&request(messages)).unwrap();
+        let body = serde_json::to_value(&api_request.contents).unwrap();
+        assert_eq!(body[0]["parts"][0]["functionCall"]["id"], "call-1");
+        assert_eq!(body[1]["parts"][0]["functionResponse"]["id"], "call-1");
+        assert_eq!(body[1]["parts"][0]["functionResponse"]["name"], "get_time");
+    }
+
+    /// 以前はレスポンスごとに toolu_00000001 から振り直していたため、ターンをまたいで衝突した
+    #[test]
+    fn generated_ids_do_not_collide_across_responses() {
+        let parts = vec![call_part(None, "get_time", json!({}))];
+        let first = tool_uses(&build_llm_response(&parts, None, None, "m"));
+        let second = tool_uses(&build_llm_response(&parts, None, None, "m"));
+        assert!(first[0].0.starts_with(GENERATED_CALL_ID_PREFIX));
+        assert_ne!(first[0].0, second[0].0);
+    }
+
+    #[test]
+    fn parts_round_trip_through_api_json() {
+        for raw in [
+            json!({ "functionCall": { "name": "f", "args": { "a": 1 } }, "thoughtSignature": "abc" }),
+            json!({ "functionCall": { "id": "call-9", "name": "f", "args": {} } }),
+            json!({ "functionResponse": { "id": "call-9", "name": "f", "response": { "result": "ok" } } }),
+            json!({ "text": "summary", "thought": true }),
+            json!({ "text": "answer" }),
+            json!({ "inlineData": { "mimeType": "image/png", "data": "AAAA" } }),
+        ] {
+            let part: GeminiPart = serde_json::from_value(raw.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&part).unwrap(), raw);
+        }
+
+        let part: GeminiPart =
+            serde_json::from_value(json!({ "text": "summary", "thought": true })).unwrap();
+        assert!(matches!(part, GeminiPart::Thought { .. }));
+    }
+
+    #[test]
+    fn parses_stream_chunks() {
+        let chunk = parse_stream_chunk(
+            r#"{"candidates":[{"content":{"role":"model","parts":[{"text":"hi"}]},"finishReason":"STOP"}]}"#,
+        )
+        .unwrap();
+        let candidate = &chunk.candidates.unwrap()[0];
+        assert_eq!(candidate.finish_reason.as_deref(), Some("STOP"));
+        // parts のない最終チャンクも終了理由を取りこぼさない
+        let chunk = parse_stream_chunk(
+            r#"{"candidates":[{"content":{"role":"model"},"finishReason":"MAX_TOKENS"}]}"#,
+        )
+        .unwrap();
+        let candidate = &chunk.candidates.unwrap()[0];
+        assert_eq!(candidate.finish_reason.as_deref(), Some("MAX_TOKENS"));
+    }
+
+    #[test]
+    fn stream_error_payload_is_not_an_empty_chunk() {
+        let error = parse_stream_chunk(
+            r#"{"error":{"code":503,"message":"overloaded","status":"UNAVAILABLE"}}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(error, AppError::LlmUnavailable { .. }), "{error}");
+        let error =
+            parse_stream_chunk(r#"{"error":{"code":400,"message":"bad request"}}"#).unwrap_err();
+        assert!(matches!(error, AppError::LlmError(_)), "{error}");
+    }
+
+    #[test]
+    fn rejects_unparseable_stream_data() {
+        let error = parse_stream_chunk(r#"{"candidates":[{"content""#).unwrap_err();
+        assert!(matches!(error, AppError::LlmError(_)), "{error}");
+        assert!(parse_stream_chunk("not json").is_err());
+        // 既知のフィールドで形が違うものも読み飛ばさない
+        assert!(parse_stream_chunk(r#"{"candidates":"x"}"#).is_err());
+    }
 }
@@ -0,0 +1,206 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::models::LlmProvider;
+
+use super::types::{ContentBlock, LlmMessage, LlmRequest, MessageContent, TokenUsage};
+
+/// 1分あたりの上限を連続的に補充するトークンバケット
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: i32) -> Self {
+        let capacity = f64::from(limit.max(1));
+        Self {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// `amount` を取り出せるまでの待ち時間（上限を超える要求は上限まで丸める）
+    fn wait_for(&mut self, amount: f64) -> Duration {
+        self.refill();
+        let amount = amount.min(self.capacity);
+        if self.available >= amount {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((amount - self.available) / self.refill_per_sec)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+
+    /// 見積もりと実績の差を精算する（負になった分は以降の補充で返済する）
+    fn adjust(&mut self, delta: f64) {
+        self.refill();
+        self.available = (self.available - delta).min(self.capacity);
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    requests: Option<TokenBucket>,
+    tokens: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn wait_for(&mut self, estimated_tokens: u32) -> Duration {
+        let requests = self
+            .requests
+            .as_mut()
+            .map_or(Duration::ZERO, |b| b.wait_for(1.0));
+        let tokens = self
+            .tokens
+            .as_mut()
+            .map_or(Duration::ZERO, |b| b.wait_for(f64::from(estimated_tokens)));
+        requests.max(tokens)
+    }
+
+    fn take(&mut self, estimated_tokens: u32) {
+        if let Some(b) = self.requests.as_mut() {
+            b.take(1.0);
+        }
+        if let Some(b) = self.tokens.as_mut() {
+            b.take(f64::from(estimated_tokens));
+        }
+    }
+}
+
+/// プロバイダーごとの同時実行数・レート制限
+pub struct ProviderLimiter {
+    concurrency: Option<Arc<Semaphore>>,
+    buckets: Mutex<Buckets>,
+}
+
+/// 同時実行枠。ドロップで解放される。
+pub struct LimiterPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl ProviderLimiter {
+    pub fn unlimited() -> Self {
+        Self {
+            concurrency: None,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// llm_providers の requests_per_minute / tokens_per_minute / max_concurrency から構築する
+    pub fn from_row(row: &LlmProvider) -> Self {
+        let positive = |v: Option<i32>| v.filter(|n| *n > 0);
+        Self {
+            concurrency: positive(row.max_concurrency)
+                .map(|n| Arc::new(Semaphore::new(n as usize))),
+            buckets: Mutex::new(Buckets {
+                requests: positive(row.requests_per_minute).map(TokenBucket::per_minute),
+                tokens: positive(row.tokens_per_minute).map(TokenBucket::per_minute),
+            }),
+        }
+    }
+
+    /// 待たずに実行できるなら枠を確保して返す
+    pub fn try_acquire(&self, estimated_tokens: u32) -> Option<LimiterPermit> {
+        let permit = match &self.concurrency {
+            Some(semaphore) => Some(semaphore.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+        let mut buckets = self.buckets.lock().expect("ProviderLimiter lock poisoned");
+        if !buckets.wait_for(estimated_tokens).is_zero() {
+            return None;
+        }
+        buckets.take(estimated_tokens);
+        Some(LimiterPermit { _permit: permit })
+    }
+
+    /// 同時実行枠とレート枠が空くまで待って確保する
+    pub async fn acquire(&self, estimated_tokens: u32) -> LimiterPermit {
+        let permit = match &self.concurrency {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("ProviderLimiter semaphore closed"),
+            ),
+            None => None,
+        };
+        self.wait_rate(estimated_tokens).await;
+        LimiterPermit { _permit: permit }
+    }
+
+    /// レート枠だけを待って消費する（リトライ時の再送用）
+    pub async fn wait_rate(&self, estimated_tokens: u32) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().expect("ProviderLimiter lock poisoned");
+                let wait = buckets.wait_for(estimated_tokens);
+                if wait.is_zero() {
+                    buckets.take(estimated_tokens);
+                    return;
+                }
+                wait
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// 実際のトークン使用量で見積もりを精算する
+    pub fn settle(&self, estimated_tokens: u32, usage: &TokenUsage) {
+        let mut buckets = self.buckets.lock().expect("ProviderLimiter lock poisoned");
+        if let Some(b) = buckets.tokens.as_mut() {
+            let actual = f64::from(usage.total_input_tokens()) + f64::from(usage.output_tokens);
+            b.adjust(actual - f64::from(estimated_tokens));
+        }
+    }
+}
+
+/// 画像・ドキュメント1件あたりの見込み（文字数換算。実際の使用量は settle で精算する）
+const ESTIMATED_MEDIA_CHARS: usize = 6_400;
+
+/// メッセージ1件の文字数換算（トークン数の概算に使う）
+pub fn estimate_message_chars(message: &LlmMessage) -> usize {
+    match &message.content {
+        MessageContent::Text(text) => text.len(),
+        MessageContent::Blocks(blocks) => blocks
+            .iter()
+            .map(|b| match b {
+                ContentBlock::Text { text } => text.len(),
+                ContentBlock::ToolUse { input, .. } => input.to_string().len(),
+                ContentBlock::ToolResult { content, .. } => content.len(),
+                ContentBlock::Image { .. } | ContentBlock::Document { .. } => ESTIMATED_MEDIA_CHARS,
+                ContentBlock::Thinking { thinking, .. } => thinking.len(),
+                ContentBlock::RedactedThinking { data } => data.len(),
+            })
+            .sum(),
+    }
+}
+
+/// トークン数の概算（入力は4文字≒1トークン、出力は思考予算を含む上限まで見込む）
+pub fn estimate_tokens(request: &LlmRequest) -> u32 {
+    let chars = request.system.as_ref().map_or(0, |s| s.len())
+        + request
+            .messages
+            .iter()
+            .map(estimate_message_chars)
+            .sum::<usize>();
+    let input = u32::try_from(chars / 4).unwrap_or(u32::MAX);
+    input.saturating_add(request.max_output_tokens().max(0) as u32)
+}
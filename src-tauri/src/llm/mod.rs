@@ -1,44 +1,276 @@
 pub mod anthropic;
+pub mod cassette;
+pub mod catalog;
+pub mod continuation;
+pub mod embeddings;
 pub mod gemini;
+pub mod limiter;
+pub mod media;
+pub mod openai_compat;
+pub mod retry;
+pub mod routing;
+pub mod sampling;
+mod sse;
+pub mod structured;
 pub mod types;
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 use async_trait::async_trait;
 
+use crate::constants::{
+    PROVIDER_TYPE_ANTHROPIC, PROVIDER_TYPE_GOOGLE, PROVIDER_TYPE_OPENAI_COMPATIBLE,
+    PROVIDER_TYPE_REPLAY,
+};
 use crate::error::AppError;
-use types::{LlmRequest, LlmResponse};
+use crate::models::{LlmProvider, ModelInfo, ProviderStatus};
+use crate::secrets;
+use anthropic::AnthropicProvider;
+use cassette::{RecordingProvider, ReplayProvider};
+use catalog::CatalogCache;
+use embeddings::{embedding_provider_from_row, EmbeddingProviderTrait};
+use gemini::GeminiProvider;
+use limiter::ProviderLimiter;
+use openai_compat::OpenAiCompatProvider;
+use types::{DeltaSink, LlmRequest, LlmResponse, StreamDelta};
 
 #[async_trait]
 pub trait LlmProviderTrait: Send + Sync {
     fn name(&self) -> &str;
     async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError>;
-    #[allow(dead_code)]
+
+    /// ストリーミングで補完し、増分を `on_delta` に流しつつ最終レスポンスを返す。
+    /// ストリーミング非対応のプロバイダーは complete() の結果を1つの増分として流す。
+    async fn complete_stream(
+        &self,
+        request: &LlmRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<LlmResponse, AppError> {
+        let response = self.complete(request).await?;
+        if !response.content.is_empty() {
+            on_delta(StreamDelta::Text {
+                text: response.content.clone(),
+            });
+        }
+        Ok(response)
+    }
+
     async fn health_check(&self) -> Result<(), AppError>;
+
+    /// モデル一覧エンドポイントから使えるモデルを取得する。
+    /// 一覧を持たないプロバイダーは空を返す（組み込みの一覧で代用される）。
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError> {
+        Ok(Vec::new())
+    }
+}
+
+/// api_key_ref から資格情報ボルト経由でAPIキーを解決する。参照未設定なら None。
+/// 環境変数のキーは、その名前の既定の種別・送り先の行にだけ渡す。
+pub fn resolve_api_key(row: &LlmProvider) -> Result<Option<String>, AppError> {
+    match row.api_key_ref.as_deref().filter(|r| !r.is_empty()) {
+        Some(key_ref) => {
+            secrets::resolve_for_provider(key_ref, &row.provider_type, row.api_base_url.as_deref())?
+                .map(Some)
+                .ok_or_else(|| {
+                    AppError::LlmError(format!(
+                        "Credential '{key_ref}' is not set (provider '{}')",
+                        row.name
+                    ))
+                })
+        }
+        None => Ok(None),
+    }
+}
+
+/// APIキー必須のプロバイダー用
+fn require_api_key(row: &LlmProvider) -> Result<String, AppError> {
+    resolve_api_key(row)?.ok_or_else(|| {
+        AppError::LlmError(format!("Provider '{}' has no api_key_ref", row.name))
+    })
+}
+
+/// llm_providers の行から provider_type に応じたプロバイダーを生成する。
+/// カセットへの記録はレジストリへの登録時だけ行う（LlmRegistry::replace_all）。
+pub fn provider_from_row(row: &LlmProvider) -> Result<Box<dyn LlmProviderTrait>, AppError> {
+    Ok(match row.provider_type.as_str() {
+        PROVIDER_TYPE_ANTHROPIC => Box::new(AnthropicProvider::from_row(row)?),
+        PROVIDER_TYPE_GOOGLE => Box::new(GeminiProvider::from_row(row)?),
+        PROVIDER_TYPE_OPENAI_COMPATIBLE => Box::new(OpenAiCompatProvider::from_row(row)?),
+        PROVIDER_TYPE_REPLAY => Box::new(ReplayProvider::from_row(row)?),
+        other => {
+            return Err(AppError::InvalidInput(format!(
+                "Unknown provider_type '{other}' (provider '{}')",
+                row.name
+            )))
+        }
+    })
+}
+
+/// 登録済みプロバイダーとその同時実行数・レート制限
+#[derive(Clone)]
+pub struct ProviderHandle {
+    pub provider: Arc<dyn LlmProviderTrait>,
+    pub limiter: Arc<ProviderLimiter>,
+    /// (requests_per_minute, tokens_per_minute, max_concurrency)。再構築時に limiter を引き継ぐ判定に使う
+    limits: (Option<i32>, Option<i32>, Option<i32>),
+}
+
+#[derive(Default)]
+struct RegistryState {
+    providers: HashMap<String, ProviderHandle>,
+    /// 生成に失敗したプロバイダー名 → 理由（実行時エラーの説明に使う）
+    failures: HashMap<String, String>,
+    /// 埋め込みに対応するプロバイダー（名前は providers と共通）
+    embedders: HashMap<String, Arc<dyn EmbeddingProviderTrait>>,
 }
 
+/// プロバイダーのレジストリ。llm_providers の変更時にアプリを再起動せず
+/// 差し替えられるよう、内部状態は RwLock で保持し丸ごと入れ替える。
+/// 実行中のリクエストは取得済みの Arc を使い続けるため、入れ替えの影響を受けない。
 pub struct LlmRegistry {
-    providers: HashMap<String, Box<dyn LlmProviderTrait>>,
+    state: RwLock<RegistryState>,
+    /// プロバイダーごとのモデル一覧（プロバイダーの差し替え時に破棄する）
+    catalog: CatalogCache,
+    /// record_cassette の行の記録先。None なら記録できない
+    recording_dir: Option<PathBuf>,
 }
 
 impl LlmRegistry {
     pub fn new() -> Self {
         Self {
-            providers: HashMap::new(),
+            state: RwLock::new(RegistryState::default()),
+            catalog: CatalogCache::default(),
+            recording_dir: None,
         }
     }
 
-    pub fn register(&mut self, provider: Box<dyn LlmProviderTrait>) {
+    /// カセットの記録先をアプリのデータディレクトリ配下にしたレジストリ
+    pub fn with_data_dir(data_dir: &Path) -> Self {
+        Self {
+            recording_dir: Some(data_dir.join(cassette::RECORDING_DIR)),
+            ..Self::new()
+        }
+    }
+
+    /// record_cassette の行は、呼び出しをカセットに記録するラッパーで包む
+    fn with_recording(
+        &self,
+        provider: Box<dyn LlmProviderTrait>,
+        row: &LlmProvider,
+    ) -> Result<Box<dyn LlmProviderTrait>, AppError> {
+        if !row.record_cassette {
+            return Ok(provider);
+        }
+        let dir = self.recording_dir.as_deref().ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "Provider '{}' cannot record a cassette without an app data directory",
+                row.name
+            ))
+        })?;
+        Ok(Box::new(RecordingProvider::wrap(provider, row, dir)?))
+    }
+
+    pub fn register(&self, provider: Box<dyn LlmProviderTrait>) {
         let name = provider.name().to_string();
-        self.providers.insert(name, provider);
+        let handle = ProviderHandle {
+            provider: Arc::from(provider),
+            limiter: Arc::new(ProviderLimiter::unlimited()),
+            limits: (None, None, None),
+        };
+        let mut guard = self.state.write().expect("LlmRegistry lock poisoned");
+        guard.failures.remove(&name);
+        guard.providers.insert(name, handle);
+    }
+
+    /// llm_providers の行からレジストリ全体を再構築し、一括で差し替える。
+    /// 生成に失敗した行は理由を記録し、成功した行だけを登録する。
+    /// 制限値が変わっていないプロバイダーは limiter を引き継ぎ、待ち行列と消費量を保つ。
+    pub fn replace_all(&self, rows: &[LlmProvider]) -> Vec<ProviderStatus> {
+        let mut next = RegistryState::default();
+        let mut statuses = Vec::with_capacity(rows.len());
+        for row in rows {
+            let error = match provider_from_row(row).and_then(|p| self.with_recording(p, row)) {
+                Ok(provider) => {
+                    let limits = (
+                        row.requests_per_minute,
+                        row.tokens_per_minute,
+                        row.max_concurrency,
+                    );
+                    let limiter = self
+                        .state
+                        .read()
+                        .expect("LlmRegistry lock poisoned")
+                        .providers
+                        .get(&row.name)
+                        .filter(|h| h.limits == limits)
+                        .map(|h| h.limiter.clone())
+                        .unwrap_or_else(|| Arc::new(ProviderLimiter::from_row(row)));
+                    let mut embedder_error = None;
+                    match embedding_provider_from_row(row) {
+                        Ok(Some(embedder)) => {
+                            next.embedders.insert(row.name.clone(), Arc::from(embedder));
+                        }
+                        Ok(None) => {}
+                        // 補完は使えるので登録は続け、埋め込みだけ使えない理由を残す
+                        Err(e) => {
+                            let reason = format!("Embeddings unavailable: {e}");
+                            next.failures.insert(row.name.clone(), reason.clone());
+                            embedder_error = Some(reason);
+                        }
+                    }
+                    next.providers.insert(
+                        row.name.clone(),
+                        ProviderHandle {
+                            provider: Arc::from(provider),
+                            limiter,
+                            limits,
+                        },
+                    );
+                    embedder_error
+                }
+                Err(e) => {
+                    next.failures.insert(row.name.clone(), e.to_string());
+                    Some(e.to_string())
+                }
+            };
+            statuses.push(ProviderStatus {
+                name: row.name.clone(),
+                available: next.providers.contains_key(&row.name),
+                error,
+            });
+        }
+
+        let mut guard = self.state.write().expect("LlmRegistry lock poisoned");
+        *guard = next;
+        self.catalog.clear();
+        statuses
+    }
+
+    pub fn clear(&self) {
+        let mut guard = self.state.write().expect("LlmRegistry lock poisoned");
+        *guard = RegistryState::default();
+        self.catalog.clear();
     }
 
-    pub fn get(&self, name: &str) -> Option<&dyn LlmProviderTrait> {
-        self.providers.get(name).map(|p| p.as_ref())
+    /// プロバイダーを取得する。未登録なら生成失敗の理由を含むエラーを返す。
+    pub fn require(&self, name: &str) -> Result<ProviderHandle, AppError> {
+        let guard = self.state.read().expect("LlmRegistry lock poisoned");
+        if let Some(handle) = guard.providers.get(name) {
+            return Ok(handle.clone());
+        }
+        Err(match guard.failures.get(name) {
+            Some(reason) => {
+                AppError::LlmError(format!("Provider '{name}' is not available: {reason}"))
+            }
+            None => AppError::LlmError(format!("Provider '{name}' not registered")),
+        })
     }
 
     #[allow(dead_code)]
     pub fn provider_names(&self) -> Vec<String> {
-        self.providers.keys().cloned().collect()
+        let guard = self.state.read().expect("LlmRegistry lock poisoned");
+        guard.providers.keys().cloned().collect()
     }
 }
@@ -0,0 +1,1088 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::*;
+use crate::error::AppError;
+use crate::models::{LlmProvider, ModelInfo};
+
+use super::embeddings::{EmbeddingProviderTrait, EmbeddingRequest};
+use super::media;
+use super::retry;
+use super::sampling::{self, ToolChoice};
+use super::sse::{incomplete_stream, read_sse};
+use super::structured::STRUCTURED_OUTPUT_TOOL;
+use super::types::{
+    ContentBlock, DeltaSink, LlmMessage, LlmRequest, LlmResponse, MediaSource, MessageContent,
+    StreamDelta, TokenUsage,
+};
+use super::LlmProviderTrait;
+
+/// `/embeddings` の1回あたりの入力件数（サーバーごとに上限が異なるため控えめにする）
+const EMBED_BATCH_SIZE: usize = 128;
+
+/// ストリーミング時のタイムアウト（ローカルモデルは生成が遅いため長めに取る）
+const STREAM_TIMEOUT_SECS: u64 = 600;
+
+/// OpenAI `/v1/chat/completions` 互換 API のプロバイダー。
+/// Ollama / llama.cpp / vLLM / LM Studio などのローカルサーバーもこの形式で扱う。
+pub struct OpenAiCompatProvider {
+    name: String,
+    base_url: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl OpenAiCompatProvider {
+    /// llm_providers の行からプロバイダーを構築する。
+    /// api_base_url はバージョンパスまで含める（例: `http://localhost:11434/v1`）。
+    pub fn from_row(row: &LlmProvider) -> Result<Self, AppError> {
+        let base_url = row
+            .api_base_url
+            .as_deref()
+            .filter(|u| !u.trim().is_empty())
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!("Provider '{}' has no api_base_url", row.name))
+            })?
+            .trim_end_matches('/')
+            .to_string();
+
+        // ローカルサーバーはキー不要
+        let api_key = super::resolve_api_key(row)?;
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(std::time::Duration::from_secs(10))
+            .timeout(std::time::Duration::from_secs(120))
+            .build()
+            .map_err(|e| AppError::LlmError(format!("Failed to build HTTP client: {e}")))?;
+
+        Ok(Self {
+            name: row.name.clone(),
+            base_url,
+            api_key,
+            client,
+        })
+    }
+}
+
+// --- Chat Completions request types ---
+
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    temperature: f64,
+    max_tokens: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ChatTool>>,
+    /// "auto" / "required" / "none" または {"type": "function", "function": {"name": ...}}
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ChatResponseFormat>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<ChatStreamOptions>,
+}
+
+/// 構造化出力（json_schema 形式の response_format）
+#[derive(Debug, Serialize)]
+struct ChatResponseFormat {
+    #[serde(rename = "type")]
+    format_type: String,
+    json_schema: ChatJsonSchema,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatJsonSchema {
+    name: String,
+    schema: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatStreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage {
+    role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<ChatContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ChatToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+/// 画像・ファイルを含む場合だけ content を parts の配列にする
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+enum ChatContentPart {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "image_url")]
+    ImageUrl { image_url: ChatImageUrl },
+    #[serde(rename = "file")]
+    File { file: ChatFile },
+}
+
+#[derive(Debug, Serialize)]
+struct ChatImageUrl {
+    /// data URL（data:{media_type};base64,{data}）
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatFile {
+    filename: String,
+    /// data URL（data:{media_type};base64,{data}）
+    file_data: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    call_type: String,
+    function: ChatFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatFunctionCall {
+    name: String,
+    /// JSON 文字列としてエンコードされた引数
+    arguments: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatTool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    function: ChatFunctionDef,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatFunctionDef {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+// --- Chat Completions response types ---
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    model: Option<String>,
+    choices: Vec<ChatChoice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseMessage {
+    content: Option<String>,
+    tool_calls: Option<Vec<ChatToolCall>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    prompt_tokens_details: Option<ChatPromptTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatPromptTokensDetails {
+    /// prompt_tokens のうちキャッシュから読み込んだ分
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    #[serde(default)]
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+    /// OpenRouter などが返すコンテキスト長
+    context_length: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    #[serde(default)]
+    data: Vec<EmbeddingEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingEntry {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatErrorResponse {
+    error: ChatErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatErrorDetail {
+    message: String,
+}
+
+// --- Streaming chunk types ---
+
+#[derive(Debug, Deserialize)]
+struct ChatChunk {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<ChatChunkChoice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChunkChoice {
+    delta: ChatChunkDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChatChunkDelta {
+    content: Option<String>,
+    tool_calls: Option<Vec<ChatChunkToolCall>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChunkToolCall {
+    index: usize,
+    id: Option<String>,
+    function: Option<ChatChunkFunction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChunkFunction {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// ストリーム中に組み立て途中の tool_call
+#[derive(Default)]
+struct PartialToolCall {
+    id: Option<String>,
+    name: String,
+    arguments: String,
+    /// ToolUseStart を通知済みか（通知後は増分と最終レスポンスで id を揃えるため固定する）
+    started: bool,
+}
+
+/// ストリームのチャンクを集約して最終レスポンスを組み立てる
+struct ChatStream {
+    model: String,
+    text: String,
+    /// tool_call の index をキーに保持する（id・name は arguments より後に届くことがある）
+    calls: BTreeMap<usize, PartialToolCall>,
+    finish_reason: Option<String>,
+    usage: Option<ChatUsage>,
+}
+
+impl ChatStream {
+    fn new(model: String) -> Self {
+        Self {
+            model,
+            text: String::new(),
+            calls: BTreeMap::new(),
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    fn push(&mut self, chunk: ChatChunk, on_delta: DeltaSink<'_>) {
+        if let Some(m) = chunk.model {
+            self.model = m;
+        }
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+
+        for choice in chunk.choices {
+            if choice.finish_reason.is_some() {
+                self.finish_reason = choice.finish_reason;
+            }
+            if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                self.text.push_str(&content);
+                on_delta(StreamDelta::Text { text: content });
+            }
+            for call in choice.delta.tool_calls.unwrap_or_default() {
+                let entry = self.calls.entry(call.index).or_default();
+                if let Some(id) = call.id.filter(|id| !id.is_empty() && !entry.started) {
+                    entry.id = Some(id);
+                }
+                let (name, arguments) = match call.function {
+                    Some(f) => (f.name, f.arguments),
+                    None => (None, None),
+                };
+                if let Some(name) = name.filter(|n| !n.is_empty()) {
+                    entry.name = name;
+                }
+                let arguments = arguments.filter(|a| !a.is_empty());
+                if let Some(arguments) = &arguments {
+                    entry.arguments.push_str(arguments);
+                }
+
+                if entry.started {
+                    if let Some(arguments) = arguments {
+                        on_delta(StreamDelta::ToolUseInput {
+                            id: stream_call_id(entry, call.index),
+                            partial_json: arguments,
+                        });
+                    }
+                } else if !entry.name.is_empty() {
+                    // name が揃った時点で開始を通知し、先に届いていた arguments もまとめて流す
+                    entry.started = true;
+                    let id = stream_call_id(entry, call.index);
+                    entry.id = Some(id.clone());
+                    on_delta(StreamDelta::ToolUseStart {
+                        id: id.clone(),
+                        name: entry.name.clone(),
+                    });
+                    if !entry.arguments.is_empty() {
+                        on_delta(StreamDelta::ToolUseInput {
+                            id,
+                            partial_json: entry.arguments.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self, provider: &str) -> Result<LlmResponse, AppError> {
+        if self.finish_reason.is_none() {
+            return Err(incomplete_stream(provider));
+        }
+
+        let mut tool_uses = Vec::with_capacity(self.calls.len());
+        for (index, call) in self.calls {
+            let input = parse_arguments(&call.name, &call.arguments)?;
+            tool_uses.push(ContentBlock::ToolUse {
+                id: stream_call_id(&call, index),
+                name: call.name,
+                input,
+                signature: None,
+            });
+        }
+
+        Ok(build_llm_response(
+            (!self.text.is_empty()).then_some(self.text),
+            tool_uses,
+            self.finish_reason.as_deref(),
+            self.usage.as_ref(),
+            self.model,
+        ))
+    }
+}
+
+/// id を返さないサーバー向けに index から補う
+fn stream_call_id(call: &PartialToolCall, index: usize) -> String {
+    call.id.clone().unwrap_or_else(|| format!("call_{index}"))
+}
+
+// --- Conversion helpers ---
+
+/// 内部の LlmMessage を Chat Completions のメッセージ列に変換する。
+/// tool_result ブロックは role=tool のメッセージとして1件ずつ展開する。
+fn message_to_chat(message: &LlmMessage) -> Vec<ChatMessage> {
+    let blocks = match &message.content {
+        MessageContent::Text(s) => {
+            return vec![ChatMessage {
+                role: message.role.clone(),
+                content: Some(ChatContent::Text(s.clone())),
+                tool_calls: None,
+                tool_call_id: None,
+            }]
+        }
+        MessageContent::Blocks(blocks) => blocks,
+    };
+
+    let mut out = Vec::new();
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    let mut media_parts = Vec::new();
+
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text: t } => text.push_str(t),
+            ContentBlock::ToolUse {
+                id, name, input, ..
+            } => tool_calls.push(ChatToolCall {
+                id: id.clone(),
+                call_type: "function".to_string(),
+                function: ChatFunctionCall {
+                    name: name.clone(),
+                    arguments: input.to_string(),
+                },
+            }),
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => out.push(ChatMessage {
+                role: "tool".to_string(),
+                content: Some(ChatContent::Text(if *is_error {
+                    format!("Error: {content}")
+                } else {
+                    content.clone()
+                })),
+                tool_calls: None,
+                tool_call_id: Some(tool_use_id.clone()),
+            }),
+            // 思考ブロックは他プロバイダー由来なので送り返さない
+            ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. } => {}
+            ContentBlock::Image { source } => media_parts.push(match source {
+                MediaSource::Base64 { media_type, data } => ChatContentPart::ImageUrl {
+                    image_url: ChatImageUrl {
+                        url: format!("data:{media_type};base64,{data}"),
+                    },
+                },
+                MediaSource::File { path, .. } => ChatContentPart::Text {
+                    text: media::unresolved_note(path),
+                },
+            }),
+            ContentBlock::Document { source, title } => media_parts.push(match source {
+                MediaSource::Base64 { media_type, data } => ChatContentPart::File {
+                    file: ChatFile {
+                        filename: title.clone().unwrap_or_else(|| "document.pdf".to_string()),
+                        file_data: format!("data:{media_type};base64,{data}"),
+                    },
+                },
+                MediaSource::File { path, .. } => ChatContentPart::Text {
+                    text: media::unresolved_note(path),
+                },
+            }),
+        }
+    }
+
+    let content = if media_parts.is_empty() {
+        (!text.is_empty()).then_some(ChatContent::Text(text))
+    } else {
+        if !text.is_empty() {
+            media_parts.push(ChatContentPart::Text { text });
+        }
+        Some(ChatContent::Parts(media_parts))
+    };
+
+    if content.is_some() || !tool_calls.is_empty() {
+        out.push(ChatMessage {
+            role: message.role.clone(),
+            content,
+            tool_calls: if tool_calls.is_empty() {
+                None
+            } else {
+                Some(tool_calls)
+            },
+            tool_call_id: None,
+        });
+    }
+
+    out
+}
+
+/// finish_reason を内部の stop_reason に変換
+fn map_finish_reason(reason: &str) -> String {
+    match reason {
+        "stop" => STOP_END_TURN.to_string(),
+        "length" => STOP_MAX_TOKENS.to_string(),
+        "tool_calls" | "function_call" => STOP_TOOL_USE.to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// ストリームの data を解析する（終端の [DONE] は None）。全フィールドが省略可能な
+/// チャンクとして読めてしまうため error キーを先に確認し、解析できない data はエラーにする。
+fn parse_stream_chunk(provider: &str, data: &str) -> Result<Option<ChatChunk>, AppError> {
+    if data.trim() == "[DONE]" {
+        return Ok(None);
+    }
+    let parse_error = |e: serde_json::Error| {
+        let excerpt: String = data.chars().take(200).collect();
+        AppError::LlmError(format!(
+            "Failed to parse {provider} stream chunk: {e} (data: {excerpt})"
+        ))
+    };
+    let value: serde_json::Value = serde_json::from_str(data).map_err(parse_error)?;
+    if value.get("error").is_none() {
+        return serde_json::from_value(value).map(Some).map_err(parse_error);
+    }
+    let err: ChatErrorResponse = serde_json::from_value(value).map_err(parse_error)?;
+    Err(AppError::LlmError(format!(
+        "{provider} stream error: {}",
+        err.error.message
+    )))
+}
+
+/// tool_call の arguments（JSON 文字列）を解析する
+fn parse_arguments(name: &str, arguments: &str) -> Result<serde_json::Value, AppError> {
+    if arguments.trim().is_empty() {
+        return Ok(serde_json::json!({}));
+    }
+    serde_json::from_str(arguments).map_err(|e| {
+        AppError::LlmError(format!("Failed to parse tool arguments for '{name}': {e}"))
+    })
+}
+
+fn token_usage(usage: &ChatUsage) -> TokenUsage {
+    let cached = usage
+        .prompt_tokens_details
+        .as_ref()
+        .map_or(0, |d| d.cached_tokens);
+    TokenUsage {
+        input_tokens: usage.prompt_tokens.saturating_sub(cached),
+        output_tokens: usage.completion_tokens,
+        cache_read_tokens: cached,
+        cache_write_tokens: 0,
+    }
+}
+
+/// テキストと tool_use ブロックから LlmResponse を組み立てる
+fn build_llm_response(
+    text: Option<String>,
+    tool_uses: Vec<ContentBlock>,
+    finish_reason: Option<&str>,
+    usage: Option<&ChatUsage>,
+    model: String,
+) -> LlmResponse {
+    let content = text.unwrap_or_default();
+    let mut content_blocks = Vec::new();
+    if !content.is_empty() {
+        content_blocks.push(ContentBlock::Text {
+            text: content.clone(),
+        });
+    }
+    let has_tool_use = !tool_uses.is_empty();
+    content_blocks.extend(tool_uses);
+
+    // サーバーによっては tool_calls があっても finish_reason が "stop" になる
+    let stop_reason = if has_tool_use {
+        Some(STOP_TOOL_USE.to_string())
+    } else {
+        finish_reason.map(map_finish_reason)
+    };
+
+    LlmResponse {
+        content,
+        model,
+        token_usage: usage.map(token_usage).unwrap_or_default(),
+        content_blocks,
+        stop_reason,
+    }
+}
+
+impl OpenAiCompatProvider {
+    /// 共通の LlmRequest を Chat Completions のリクエストに変換
+    fn build_request(&self, request: &LlmRequest, stream: bool) -> Result<ChatRequest, AppError> {
+        sampling::validate_request(PROVIDER_TYPE_OPENAI_COMPATIBLE, request)?;
+
+        let mut messages = Vec::new();
+        if let Some(system) = &request.system {
+            messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: Some(ChatContent::Text(system.clone())),
+                tool_calls: None,
+                tool_call_id: None,
+            });
+        }
+        for message in request.messages.iter().filter(|m| m.role != "system") {
+            messages.extend(message_to_chat(message));
+        }
+
+        let tools = request.tools.as_ref().map(|tool_defs| {
+            tool_defs
+                .iter()
+                .map(|t| ChatTool {
+                    tool_type: "function".to_string(),
+                    function: ChatFunctionDef {
+                        name: t.name.clone(),
+                        description: t.description.clone(),
+                        parameters: t.input_schema.clone(),
+                    },
+                })
+                .collect()
+        });
+
+        let tool_choice = request.tool_choice.as_ref().map(|choice| match choice {
+            ToolChoice::Auto => serde_json::json!("auto"),
+            ToolChoice::Any => serde_json::json!("required"),
+            ToolChoice::None => serde_json::json!("none"),
+            ToolChoice::Tool { name } => serde_json::json!({
+                "type": "function",
+                "function": { "name": name }
+            }),
+        });
+
+        Ok(ChatRequest {
+            model: request.model.clone(),
+            messages,
+            temperature: request.temperature,
+            max_tokens: request.max_tokens,
+            top_p: request.sampling.top_p,
+            stop: request.sampling.stop_sequences.clone(),
+            seed: request.sampling.seed,
+            tools,
+            tool_choice,
+            response_format: request
+                .output_schema
+                .as_ref()
+                .map(|schema| ChatResponseFormat {
+                    format_type: "json_schema".to_string(),
+                    json_schema: ChatJsonSchema {
+                        name: STRUCTURED_OUTPUT_TOOL.to_string(),
+                        schema: schema.clone(),
+                    },
+                }),
+            stream,
+            stream_options: stream.then_some(ChatStreamOptions {
+                include_usage: true,
+            }),
+        })
+    }
+
+    async fn send(&self, api_request: &ChatRequest) -> Result<reqwest::Response, AppError> {
+        let mut builder = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("content-type", "application/json");
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        if api_request.stream {
+            builder = builder.timeout(std::time::Duration::from_secs(STREAM_TIMEOUT_SECS));
+        }
+
+        let response = builder
+            .json(api_request)
+            .send()
+            .await
+            .map_err(retry::request_error)?;
+
+        if !response.status().is_success() {
+            return Err(self.api_error(response).await);
+        }
+
+        Ok(response)
+    }
+
+    /// 失敗したレスポンスをエラー種別付きの AppError にする
+    async fn api_error(&self, response: reqwest::Response) -> AppError {
+        let status = response.status();
+        let retry_after = retry::retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        let message = match serde_json::from_str::<ChatErrorResponse>(&body) {
+            Ok(err) => err.error.message,
+            Err(_) => body,
+        };
+        retry::status_error(
+            status,
+            retry_after,
+            format!("{} API error ({status}): {message}", self.name),
+        )
+    }
+}
+
+#[async_trait]
+impl LlmProviderTrait for OpenAiCompatProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn complete(&self, request: &LlmRequest) -> Result<LlmResponse, AppError> {
+        let api_request = self.build_request(request, false)?;
+        let response = self.send(&api_request).await?;
+
+        let api_response: ChatResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::LlmError(format!("Failed to parse response: {e}")))?;
+
+        let choice = api_response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::LlmError("No choices in response".to_string()))?;
+
+        let mut tool_uses = Vec::new();
+        for call in choice.message.tool_calls.unwrap_or_default() {
+            let input = parse_arguments(&call.function.name, &call.function.arguments)?;
+            tool_uses.push(ContentBlock::ToolUse {
+                id: call.id,
+                name: call.function.name,
+                input,
+                signature: None,
+            });
+        }
+
+        Ok(build_llm_response(
+            choice.message.content,
+            tool_uses,
+            choice.finish_reason.as_deref(),
+            api_response.usage.as_ref(),
+            api_response.model.unwrap_or_else(|| request.model.clone()),
+        ))
+    }
+
+    async fn complete_stream(
+        &self,
+        request: &LlmRequest,
+        on_delta: DeltaSink<'_>,
+    ) -> Result<LlmResponse, AppError> {
+        let api_request = self.build_request(request, true)?;
+        let response = self.send(&api_request).await?;
+
+        let mut stream = ChatStream::new(request.model.clone());
+        read_sse(response, |sse| {
+            if let Some(chunk) = parse_stream_chunk(&self.name, &sse.data)? {
+                stream.push(chunk, on_delta);
+            }
+            Ok(())
+        })
+        .await?;
+        stream.finish(&self.name)
+    }
+
+    async fn health_check(&self) -> Result<(), AppError> {
+        let mut builder = self.client.get(format!("{}/models", self.base_url));
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| AppError::LlmError(format!("{} is unreachable: {e}", self.name)))?;
+        // 認証エラーなどの応答も到達扱いにしない
+        if !response.status().is_success() {
+            return Err(self.api_error(response).await);
+        }
+        Ok(())
+    }
+
+    /// GET /models（ローカルサーバーではダウンロード済みのモデル）
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, AppError> {
+        let mut builder = self.client.get(format!("{}/models", self.base_url));
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        let response = builder.send().await.map_err(retry::request_error)?;
+        if !response.status().is_success() {
+            return Err(self.api_error(response).await);
+        }
+        let list: ModelList = response
+            .json()
+            .await
+            .map_err(|e| AppError::LlmError(format!("Failed to parse model list: {e}")))?;
+
+        Ok(list
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id,
+                display_name: None,
+                context_window: m.context_length,
+                max_output_tokens: None,
+                input_price: None,
+                output_price: None,
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProviderTrait for OpenAiCompatProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn max_batch_size(&self) -> usize {
+        EMBED_BATCH_SIZE
+    }
+
+    /// task は OpenAI 形式に対応する項目がないため送らない
+    async fn embed_batch(
+        &self,
+        request: &EmbeddingRequest,
+        inputs: &[String],
+    ) -> Result<Vec<Vec<f32>>, AppError> {
+        let mut builder = self
+            .client
+            .post(format!("{}/embeddings", self.base_url))
+            .json(&EmbeddingsRequest {
+                model: &request.model,
+                input: inputs,
+                dimensions: request.dimensions,
+            });
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key);
+        }
+        let response = builder.send().await.map_err(retry::request_error)?;
+        if !response.status().is_success() {
+            return Err(self.api_error(response).await);
+        }
+        let mut body: EmbeddingsResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::LlmError(format!("Failed to parse embeddings: {e}")))?;
+
+        // index 順に返すとは限らないサーバーがある
+        body.data.sort_by_key(|e| e.index);
+        Ok(body.data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn blocks(role: &str, blocks: Vec<ContentBlock>) -> LlmMessage {
+        LlmMessage {
+            role: role.to_string(),
+            content: MessageContent::Blocks(blocks),
+        }
+    }
+
+    fn chunk(value: serde_json::Value) -> ChatChunk {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn tool_results_become_tool_messages_before_the_user_text() {
+        let message = blocks(
+            "user",
+            vec![
+                ContentBlock::ToolResult {
+                    tool_use_id: "call_a".to_string(),
+                    content: "ok".to_string(),
+                    is_error: false,
+                },
+                ContentBlock::ToolResult {
+                    tool_use_id: "call_b".to_string(),
+                    content: "denied".to_string(),
+                    is_error: true,
+                },
+                ContentBlock::Text {
+                    text: "continue".to_string(),
+                },
+            ],
+        );
+        let chat = serde_json::to_value(message_to_chat(&message)).unwrap();
+        assert_eq!(
+            chat,
+            json!([
+                { "role": "tool", "content": "ok", "tool_call_id": "call_a" },
+                { "role": "tool", "content": "Error: denied", "tool_call_id": "call_b" },
+                { "role": "user", "content": "continue" },
+            ])
+        );
+    }
+
+    #[test]
+    fn media_blocks_become_content_parts() {
+        let message = blocks(
+            "user",
+            vec![
+                ContentBlock::Text {
+                    text: "look".to_string(),
+                },
+                ContentBlock::Image {
+                    source: MediaSource::Base64 {
+                        media_type: "image/png".to_string(),
+                        data: "AAAA".to_string(),
+                    },
+                },
+                ContentBlock::Document {
+                    source: MediaSource::Base64 {
+                        media_type: "application/pdf".to_string(),
+                        data: "BBBB".to_string(),
+                    },
+                    title: None,
+                },
+                ContentBlock::Image {
+                    source: MediaSource::File {
+                        path: "missing.png".to_string(),
+                        media_type: None,
+                    },
+                },
+            ],
+        );
+        let chat = serde_json::to_value(message_to_chat(&message)).unwrap();
+        assert_eq!(
+            chat,
+            json!([{
+                "role": "user",
+                "content": [
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,AAAA" } },
+                    {
+                        "type": "file",
+                        "file": {
+                            "filename": "document.pdf",
+                            "file_data": "data:application/pdf;base64,BBBB",
+                        },
+                    },
+                    { "type": "text", "text": media::unresolved_note("missing.png") },
+                    { "type": "text", "text": "look" },
+                ],
+            }])
+        );
+    }
+
+    /// id・name が arguments より後に届いても index で1つの呼び出しにまとめ、
+    /// 増分は ToolUseStart → ToolUseInput の順に同じ id で流す
+    #[test]
+    fn assembles_streamed_tool_calls_by_index() {
+        let deltas = Mutex::new(Vec::new());
+        let sink = |d: StreamDelta| deltas.lock().unwrap().push(d);
+        let mut stream = ChatStream::new("m".to_string());
+        for value in [
+            json!({ "choices": [{ "delta": { "tool_calls": [
+                { "index": 1, "function": { "arguments": "{\"q\":" } },
+            ] } }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [
+                { "index": 0, "id": "call_x", "function": { "name": "read_file", "arguments": "{}" } },
+                { "index": 1, "id": "call_y", "function": { "name": "web_search" } },
+            ] } }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [
+                { "index": 1, "function": { "arguments": "\"rust\"}" } },
+            ] }, "finish_reason": "tool_calls" }] }),
+        ] {
+            stream.push(chunk(value), &sink);
+        }
+        let response = stream.finish("test").unwrap();
+
+        let calls: Vec<_> = response
+            .content_blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::ToolUse {
+                    id, name, input, ..
+                } => Some((id.as_str(), name.as_str(), input.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("call_x", "read_file", json!({})),
+                ("call_y", "web_search", json!({ "q": "rust" })),
+            ]
+        );
+
+        let deltas = deltas.into_inner().unwrap();
+        let y_deltas: Vec<_> = deltas
+            .iter()
+            .filter_map(|d| match d {
+                StreamDelta::ToolUseStart { id, name } if id == "call_y" => {
+                    Some(format!("start:{name}"))
+                }
+                StreamDelta::ToolUseInput { id, partial_json } if id == "call_y" => {
+                    Some(partial_json.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(y_deltas, ["start:web_search", "{\"q\":", "\"rust\"}"]);
+        // 開始前の arguments をプレースホルダの id で流さない
+        assert!(deltas.iter().all(|d| !matches!(
+            d,
+            StreamDelta::ToolUseInput { id, .. } if id == "call_1"
+        )));
+    }
+
+    #[test]
+    fn tool_calls_with_stop_finish_reason_map_to_tool_use() {
+        let tool_use = ContentBlock::ToolUse {
+            id: "call_x".to_string(),
+            name: "read_file".to_string(),
+            input: json!({}),
+            signature: None,
+        };
+        let response =
+            build_llm_response(None, vec![tool_use], Some("stop"), None, "m".to_string());
+        assert_eq!(response.stop_reason.as_deref(), Some(STOP_TOOL_USE));
+
+        let response = build_llm_response(
+            Some("done".to_string()),
+            Vec::new(),
+            Some("stop"),
+            None,
+            "m".to_string(),
+        );
+        assert_eq!(response.stop_reason.as_deref(), Some(STOP_END_TURN));
+        assert_eq!(map_finish_reason("length"), STOP_MAX_TOKENS);
+    }
+
+    #[test]
+    fn parses_stream_chunks_and_done_marker() {
+        let chunk = parse_stream_chunk(
+            "test",
+            r#"{"model":"m","choices":[{"delta":{"content":"hi"},"finish_reason":"stop"}]}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(chunk.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert!(parse_stream_chunk("test", " [DONE]").unwrap().is_none());
+    }
+
+    #[test]
+    fn stream_error_payload_is_not_an_empty_chunk() {
+        let error = parse_stream_chunk(
+            "test",
+            r#"{"error":{"message":"model overloaded","type":"server_error"}}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("model overloaded"), "{error}");
+    }
+
+    #[test]
+    fn rejects_unparseable_stream_data() {
+        let error = parse_stream_chunk("test", r#"{"choices":[{"delta""#).unwrap_err();
+        assert!(matches!(error, AppError::LlmError(_)), "{error}");
+        assert!(parse_stream_chunk("test", "not json").is_err());
+        // 既知のフィールドで形が違うものも読み飛ばさない
+        assert!(parse_stream_chunk(
+            "test",
+            r#"{"choices":[{"delta":{},"index":0,"finish_reason":1}]}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+use crate::error::AppError;
+
+use super::limiter::estimate_tokens;
+use super::types::{DeltaSink, LlmRequest, LlmResponse};
+use super::ProviderHandle;
+
+/// 初回を含む最大試行回数
+pub(super) const MAX_ATTEMPTS: u32 = 5;
+const BASE_DELAY_MS: u64 = 1_000;
+const MAX_DELAY_MS: u64 = 30_000;
+/// これより長い retry-after を指定された場合は待たずに失敗させる
+const MAX_RETRY_AFTER_SECS: u64 = 120;
+
+/// リトライ前に通知する内容
+#[derive(Debug, Clone)]
+pub struct RetryNotice {
+    /// これから行う試行の番号（2以上）
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay: Duration,
+    pub error: String,
+}
+
+pub type RetrySink<'a> = &'a (dyn Fn(RetryNotice) + Send + Sync);
+
+/// 送信時の reqwest エラーを分類する（リクエスト組み立て以外は一時的な障害とみなす）
+pub(super) fn request_error(e: reqwest::Error) -> AppError {
+    if e.is_builder() {
+        AppError::LlmError(format!("Request failed: {e}"))
+    } else {
+        AppError::LlmUnavailable {
+            message: format!("Request failed: {e}"),
+            retry_after: None,
+            rate_limited: false,
+        }
+    }
+}
+
+/// 429 / 408 / 5xx（Anthropic の 529 overloaded を含む）はリトライ対象
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status.is_server_error()
+}
+
+/// retry-after-ms / retry-after（秒）ヘッダーを読む。HTTP-date 形式は無視する。
+pub(super) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        let value: f64 = headers.get(name)?.to_str().ok()?.trim().parse().ok()?;
+        (value.is_finite() && value >= 0.0).then_some(value)
+    };
+    if let Some(ms) = header("retry-after-ms") {
+        return Some(Duration::from_secs_f64(ms / 1000.0));
+    }
+    header("retry-after").map(Duration::from_secs_f64)
+}
+
+/// エラーレスポンスをステータスに応じて AppError に変換する
+pub(super) fn status_error(
+    status: StatusCode,
+    retry_after: Option<Duration>,
+    message: String,
+) -> AppError {
+    if is_retryable_status(status) {
+        AppError::LlmUnavailable {
+            message,
+            retry_after,
+            rate_limited: status == StatusCode::TOO_MANY_REQUESTS,
+        }
+    } else {
+        AppError::LlmError(message)
+    }
+}
+
+/// 指数バックオフ + ジッター（上限の半分を固定、残り半分をランダム）
+fn backoff_delay(retry: u32) -> Duration {
+    let cap = BASE_DELAY_MS
+        .saturating_mul(1u64 << (retry - 1).min(16))
+        .min(MAX_DELAY_MS);
+    let half = cap / 2;
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(retry);
+    let jitter = hasher.finish() % (half + 1);
+    Duration::from_millis(half + jitter)
+}
+
+/// attempt 回目の失敗をリトライする場合の待ち時間。
+/// 一時的な障害で試行回数が残っており、retry-after が長すぎないときだけ Some を返す。
+pub(super) fn retry_delay(error: &AppError, attempt: u32) -> Option<Duration> {
+    match error {
+        AppError::LlmUnavailable { retry_after, .. } if attempt < MAX_ATTEMPTS => match retry_after
+        {
+            Some(d) if d.as_secs() > MAX_RETRY_AFTER_SECS => None,
+            Some(d) => Some(*d),
+            None => Some(backoff_delay(attempt)),
+        },
+        _ => None,
+    }
+}
+
+/// 一時的なエラーをリトライしながらストリーミング補完する。
+/// リトライのたびに `on_retry` を呼ぶので、呼び出し側は途中まで流した増分を破棄できる。
+/// 初回のレート枠と同時実行枠は呼び出し側が確保済みであること（再送分のレート枠はここで待つ）。
+pub async fn complete_stream_with_retry(
+    handle: &ProviderHandle,
+    request: &LlmRequest,
+    on_delta: DeltaSink<'_>,
+    on_retry: RetrySink<'_>,
+) -> Result<LlmResponse, AppError> {
+    let provider = handle.provider.as_ref();
+    let estimated_tokens = estimate_tokens(request);
+    let mut attempt = 1;
+    loop {
+        let error = match provider.complete_stream(request, on_delta).await {
+            Ok(response) => {
+                handle
+                    .limiter
+                    .settle(estimated_tokens, &response.token_usage);
+                return Ok(response);
+            }
+            Err(e) => e,
+        };
+
+        let Some(delay) = retry_delay(&error, attempt) else {
+            return Err(error);
+        };
+
+        attempt += 1;
+        eprintln!(
+            "[tebiki] {} call failed, retrying in {}ms (attempt {attempt}/{MAX_ATTEMPTS}): {error}",
+            provider.name(),
+            delay.as_millis()
+        );
+        on_retry(RetryNotice {
+            attempt,
+            max_attempts: MAX_ATTEMPTS,
+            delay,
+            error: error.to_string(),
+        });
+        tokio::time::sleep(delay).await;
+        handle.limiter.wait_rate(estimated_tokens).await;
+    }
+}
@@ -0,0 +1,163 @@
+use std::future::Future;
+
+use crate::constants::{FALLBACK_ON_ANY_ERROR, FALLBACK_ON_SERVER_ERROR, FALLBACK_ON_UNAVAILABLE};
+use crate::error::AppError;
+
+use super::limiter::{estimate_tokens, LimiterPermit};
+use super::retry::{complete_stream_with_retry, RetrySink};
+use super::types::{DeltaSink, LlmRequest, LlmResponse};
+use super::{LlmRegistry, ProviderHandle};
+
+/// 呼び出し先の候補（プロバイダー名とモデル）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelCandidate {
+    pub provider_name: String,
+    pub model: String,
+}
+
+/// どの失敗で次の候補に切り替えるか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackPolicy {
+    /// リトライしても回復しなかった一時的な障害（429 / 408 / 5xx / 接続断）
+    Unavailable,
+    /// 一時的な障害のうちレート制限（429）以外
+    ServerError,
+    /// 未登録のプロバイダーや 4xx を含むすべての LLM エラー
+    AnyError,
+}
+
+impl FallbackPolicy {
+    pub fn parse(value: &str) -> Result<Self, AppError> {
+        match value {
+            FALLBACK_ON_UNAVAILABLE => Ok(Self::Unavailable),
+            FALLBACK_ON_SERVER_ERROR => Ok(Self::ServerError),
+            FALLBACK_ON_ANY_ERROR => Ok(Self::AnyError),
+            other => Err(AppError::InvalidInput(format!(
+                "Invalid fallback_on '{other}'. Must be '{FALLBACK_ON_UNAVAILABLE}', '{FALLBACK_ON_SERVER_ERROR}' or '{FALLBACK_ON_ANY_ERROR}'."
+            ))),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Unavailable => FALLBACK_ON_UNAVAILABLE,
+            Self::ServerError => FALLBACK_ON_SERVER_ERROR,
+            Self::AnyError => FALLBACK_ON_ANY_ERROR,
+        }
+    }
+
+    fn allows(self, error: &AppError) -> bool {
+        match (self, error) {
+            (Self::AnyError, AppError::LlmError(_) | AppError::LlmUnavailable { .. }) => true,
+            (Self::Unavailable, AppError::LlmUnavailable { .. }) => true,
+            (Self::ServerError, AppError::LlmUnavailable { rate_limited, .. }) => !rate_limited,
+            _ => false,
+        }
+    }
+}
+
+/// 優先順の候補リストとフォールバック方針。先頭がエージェント本来のモデル。
+#[derive(Debug, Clone)]
+pub struct ModelRoute {
+    pub candidates: Vec<ModelCandidate>,
+    pub policy: FallbackPolicy,
+}
+
+impl ModelRoute {
+    /// index より前の候補を外す。フォールバック後の呼び出しを、応答した候補から始めるために使う。
+    pub fn retain_from(&mut self, index: usize) {
+        self.candidates.drain(..index.min(self.candidates.len()));
+    }
+}
+
+/// 次の候補に切り替える前に通知する内容
+#[derive(Debug, Clone)]
+pub struct FallbackNotice {
+    pub from: ModelCandidate,
+    pub to: ModelCandidate,
+    pub error: String,
+}
+
+pub type FallbackSink<'a> = &'a (dyn Fn(FallbackNotice) + Send + Sync);
+
+/// 実際に応答した候補とそのレスポンス
+#[derive(Debug)]
+pub struct RoutedResponse {
+    pub response: LlmResponse,
+    pub candidate: ModelCandidate,
+    /// route.candidates 内の位置（0 ならフォールバックなし）
+    pub candidate_index: usize,
+}
+
+impl LlmRegistry {
+    /// ルートの候補を先頭から順に試す。各候補ではリトライを使い切るまで同じモデルで再試行し、
+    /// 方針が許す失敗なら次の候補へ切り替える（モデルだけを差し替え、会話はそのまま送る）。
+    /// 枠の確保は `acquire` に任せ、呼び出しの間だけ保持する。
+    pub async fn complete_routed<A, F>(
+        &self,
+        route: &ModelRoute,
+        request: &LlmRequest,
+        acquire: A,
+        on_delta: DeltaSink<'_>,
+        on_retry: RetrySink<'_>,
+        on_fallback: FallbackSink<'_>,
+    ) -> Result<RoutedResponse, AppError>
+    where
+        A: Fn(ProviderHandle, u32) -> F,
+        F: Future<Output = LimiterPermit>,
+    {
+        for (index, candidate) in route.candidates.iter().enumerate() {
+            let result = match self.require(&candidate.provider_name) {
+                Ok(handle) => {
+                    let routed_request;
+                    let request = if candidate.model == request.model {
+                        request
+                    } else {
+                        routed_request = LlmRequest {
+                            model: candidate.model.clone(),
+                            ..request.clone()
+                        };
+                        &routed_request
+                    };
+                    let permit = acquire(handle.clone(), estimate_tokens(request)).await;
+                    let result =
+                        complete_stream_with_retry(&handle, request, on_delta, on_retry).await;
+                    drop(permit);
+                    result
+                }
+                Err(e) => Err(e),
+            };
+
+            let error = match result {
+                Ok(response) => {
+                    return Ok(RoutedResponse {
+                        response,
+                        candidate: candidate.clone(),
+                        candidate_index: index,
+                    })
+                }
+                Err(e) => e,
+            };
+            let Some(next) = route
+                .candidates
+                .get(index + 1)
+                .filter(|_| route.policy.allows(&error))
+            else {
+                return Err(error);
+            };
+
+            eprintln!(
+                "[tebiki] {}/{} failed, falling back to {}/{}: {error}",
+                candidate.provider_name, candidate.model, next.provider_name, next.model
+            );
+            on_fallback(FallbackNotice {
+                from: candidate.clone(),
+                to: next.clone(),
+                error: error.to_string(),
+            });
+        }
+        Err(AppError::LlmError(
+            "No model candidates to route to".to_string(),
+        ))
+    }
+}
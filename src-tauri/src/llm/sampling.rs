@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{
+    PROVIDER_TYPE_ANTHROPIC, PROVIDER_TYPE_GOOGLE, PROVIDER_TYPE_OPENAI_COMPATIBLE,
+};
+use crate::error::AppError;
+use crate::models::Agent;
+
+use super::types::LlmRequest;
+
+/// 拡張思考中に Anthropic が受け付ける top_p の下限
+const MIN_THINKING_TOP_P: f64 = 0.95;
+
+/// temperature 以外のサンプリング設定（未指定はプロバイダーの既定値）。
+/// 未指定のフィールドはシリアライズしないので、記録済みカセットのリクエストとも一致する。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+}
+
+impl SamplingParams {
+    /// エージェントの設定から組み立てる
+    pub fn from_agent(agent: &Agent) -> Self {
+        Self {
+            top_p: agent.top_p,
+            top_k: agent.top_k,
+            stop_sequences: agent.stop_sequences.clone(),
+            seed: agent.seed,
+        }
+    }
+}
+
+/// ツール使用の指定（Anthropic の tool_choice と同じ形）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// モデルに任せる
+    Auto,
+    /// いずれかのツールを必ず使わせる
+    Any,
+    /// ツールを使わせない
+    None,
+    /// 指定したツールを必ず使わせる
+    Tool { name: String },
+}
+
+impl ToolChoice {
+    /// agents.tool_choice（JSONB）を読み取る
+    pub fn from_value(value: Option<&serde_json::Value>) -> Result<Option<Self>, AppError> {
+        value
+            .map(|v| {
+                serde_json::from_value(v.clone()).map_err(|e| {
+                    AppError::InvalidInput(format!(
+                        "Invalid tool_choice: {e}. Expected {{\"type\": \"auto\" | \"any\" | \"none\"}} or {{\"type\": \"tool\", \"name\": ...}}"
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// ツールを強制する指定か
+    pub fn forces_tool(&self) -> bool {
+        matches!(self, Self::Any | Self::Tool { .. })
+    }
+}
+
+/// プロバイダー種別ごとの停止シーケンスの上限
+fn max_stop_sequences(provider_type: &str) -> Option<usize> {
+    match provider_type {
+        PROVIDER_TYPE_GOOGLE => Some(5),
+        PROVIDER_TYPE_OPENAI_COMPATIBLE => Some(4),
+        _ => None,
+    }
+}
+
+fn unsupported(provider_type: &str, what: &str) -> AppError {
+    AppError::InvalidInput(format!(
+        "{what} is not supported by provider type '{provider_type}'"
+    ))
+}
+
+/// サンプリング設定とツール指定がプロバイダー種別で使えるかを検証する
+pub fn validate(
+    provider_type: &str,
+    sampling: &SamplingParams,
+    tool_choice: Option<&ToolChoice>,
+    thinking_budget: Option<i32>,
+) -> Result<(), AppError> {
+    if sampling.top_p.is_some_and(|p| !(0.0..=1.0).contains(&p)) {
+        return Err(AppError::InvalidInput(
+            "top_p must be between 0 and 1".to_string(),
+        ));
+    }
+    if sampling.top_k.is_some_and(|k| k < 1) {
+        return Err(AppError::InvalidInput(
+            "top_k must be at least 1".to_string(),
+        ));
+    }
+    if sampling.stop_sequences.iter().any(|s| s.is_empty()) {
+        return Err(AppError::InvalidInput(
+            "stop_sequences must not contain empty strings".to_string(),
+        ));
+    }
+    if let Some(max) = max_stop_sequences(provider_type) {
+        if sampling.stop_sequences.len() > max {
+            return Err(AppError::InvalidInput(format!(
+                "Too many stop_sequences ({}). Provider type '{provider_type}' allows at most {max}.",
+                sampling.stop_sequences.len()
+            )));
+        }
+    }
+    if let Some(ToolChoice::Tool { name }) = tool_choice {
+        if name.trim().is_empty() {
+            return Err(AppError::InvalidInput(
+                "tool_choice name must not be empty".to_string(),
+            ));
+        }
+    }
+
+    let thinking = thinking_budget.is_some_and(|b| b > 0);
+    match provider_type {
+        PROVIDER_TYPE_ANTHROPIC => {
+            if sampling.seed.is_some() {
+                return Err(unsupported(provider_type, "seed"));
+            }
+            if thinking {
+                if sampling.top_k.is_some() {
+                    return Err(unsupported(provider_type, "top_k with extended thinking"));
+                }
+                if sampling.top_p.is_some_and(|p| p < MIN_THINKING_TOP_P) {
+                    return Err(AppError::InvalidInput(format!(
+                        "top_p must be at least {MIN_THINKING_TOP_P} with extended thinking"
+                    )));
+                }
+                if tool_choice.is_some_and(ToolChoice::forces_tool) {
+                    return Err(unsupported(
+                        provider_type,
+                        "Forcing tool use with extended thinking",
+                    ));
+                }
+            }
+        }
+        PROVIDER_TYPE_OPENAI_COMPATIBLE if sampling.top_k.is_some() => {
+            return Err(unsupported(provider_type, "top_k"));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// リクエストを検証する。ツールを強制する指定には対象のツール定義が必要。
+pub fn validate_request(provider_type: &str, request: &LlmRequest) -> Result<(), AppError> {
+    validate(
+        provider_type,
+        &request.sampling,
+        request.tool_choice.as_ref(),
+        request.thinking_budget,
+    )?;
+    let tools = request.tools.as_deref().unwrap_or_default();
+    match &request.tool_choice {
+        Some(ToolChoice::Any) if tools.is_empty() => Err(AppError::InvalidInput(
+            "tool_choice 'any' requires tools".to_string(),
+        )),
+        Some(ToolChoice::Tool { name }) if !tools.iter().any(|t| &t.name == name) => Err(
+            AppError::InvalidInput(format!("tool_choice refers to unknown tool '{name}'")),
+        ),
+        _ => Ok(()),
+    }
+}
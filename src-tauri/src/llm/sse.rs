@@ -0,0 +1,112 @@
+use crate::error::AppError;
+
+/// Server-Sent Events の1イベント分（各プロバイダーは data の JSON 内の type で判別する）
+#[derive(Debug, Clone)]
+pub struct SseEvent {
+    pub data: String,
+}
+
+/// チャンク単位で届くバイト列を SSE イベントに分解するバッファ
+#[derive(Default)]
+pub struct SseBuffer {
+    /// 未処理のバイト列（チャンク境界でマルチバイト文字が分割されうるため行単位で復号する）
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// チャンクを追加し、完結したイベントを返す
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                // 空行でイベント確定
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        data: self.data.join("\n"),
+                    });
+                    self.data.clear();
+                }
+                continue;
+            }
+
+            if line.starts_with(':') {
+                // コメント行
+                continue;
+            }
+
+            // event / id / retry フィールドは使わない
+            if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+        }
+
+        events
+    }
+}
+
+/// レスポンスボディをチャンクごとに読み、SSE イベント単位でハンドラに渡す
+pub async fn read_sse<F>(mut response: reqwest::Response, mut handler: F) -> Result<(), AppError>
+where
+    F: FnMut(SseEvent) -> Result<(), AppError>,
+{
+    let mut buffer = SseBuffer::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| AppError::LlmUnavailable {
+            message: format!("Stream read failed: {e}"),
+            retry_after: None,
+            rate_limited: false,
+        })?
+    {
+        for event in buffer.push(&chunk) {
+            handler(event)?;
+        }
+    }
+    // 末尾に空行がないまま閉じられた場合も取りこぼさない
+    for event in buffer.push(b"\n\n") {
+        handler(event)?;
+    }
+    Ok(())
+}
+
+/// 終了通知が届かないまま閉じたストリームのエラー。途中切断とみなし再試行可能にする
+pub fn incomplete_stream(provider: &str) -> AppError {
+    AppError::LlmUnavailable {
+        message: format!("{provider} stream ended before the response was complete"),
+        retry_after: None,
+        rate_limited: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_events_across_chunk_boundaries() {
+        let mut buffer = SseBuffer::new();
+        assert!(buffer.push(b"data: {\"a\":").is_empty());
+        let events = buffer.push(b"1}\n\n: ping\n\ndata: x\r\n\r\n");
+        let data: Vec<_> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, [r#"{"a":1}"#, "x"]);
+    }
+
+    #[test]
+    fn incomplete_stream_is_retryable() {
+        let error = incomplete_stream("test");
+        assert!(matches!(error, AppError::LlmUnavailable { .. }), "{error}");
+    }
+}
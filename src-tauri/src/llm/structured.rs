@@ -0,0 +1,101 @@
+use jsonschema::Validator;
+
+use crate::constants::STOP_END_TURN;
+use crate::error::AppError;
+
+use super::types::{ContentBlock, LlmResponse};
+
+/// 構造化出力を強制するためのツール名（ネイティブの JSON モードがないプロバイダー用）
+pub const STRUCTURED_OUTPUT_TOOL: &str = "structured_output";
+pub const STRUCTURED_OUTPUT_TOOL_DESCRIPTION: &str =
+    "Return the final answer. The input must match the required output schema.";
+
+/// スキーマ不一致時に修正を依頼する最大回数を含めた試行回数
+pub const MAX_STRUCTURED_OUTPUT_ATTEMPTS: u32 = 3;
+
+/// モデルに返す検証エラーの最大件数
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// output_schema を検証して Validator を作る。
+/// ツール入力として送れるよう、ルートはオブジェクトに限る。
+pub fn compile(schema: &serde_json::Value) -> Result<Validator, AppError> {
+    if schema.get("type").and_then(|t| t.as_str()) != Some("object") {
+        return Err(AppError::InvalidInput(
+            "output_schema must describe a JSON object (\"type\": \"object\")".to_string(),
+        ));
+    }
+    jsonschema::validator_for(schema)
+        .map_err(|e| AppError::InvalidInput(format!("Invalid output_schema: {e}")))
+}
+
+/// コードフェンスで囲まれていれば中身だけを取り出す
+fn strip_code_fence(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(rest) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let Some(body) = rest.strip_suffix("```") else {
+        return trimmed;
+    };
+    // ```json のような言語指定の行を飛ばす
+    match body.split_once('\n') {
+        Some((_, inner)) => inner.trim(),
+        None => body.trim(),
+    }
+}
+
+/// 出力テキストを JSON として解析し、スキーマで検証する。失敗時はモデルに返すエラー一覧を返す。
+pub fn parse_and_validate(
+    validator: &Validator,
+    text: &str,
+) -> Result<serde_json::Value, Vec<String>> {
+    let value: serde_json::Value = serde_json::from_str(strip_code_fence(text))
+        .map_err(|e| vec![format!("Output is not valid JSON: {e}")])?;
+    let errors: Vec<String> = validator
+        .iter_errors(&value)
+        .take(MAX_REPORTED_ERRORS)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            if path.is_empty() {
+                e.to_string()
+            } else {
+                format!("{path}: {e}")
+            }
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors)
+    }
+}
+
+/// 検証エラーを伝えて出力し直させるメッセージ
+pub fn repair_prompt(errors: &[String]) -> String {
+    format!(
+        "Your previous output did not match the required JSON schema:\n- {}\n\nRespond again with only a corrected JSON object that matches the schema.",
+        errors.join("\n- ")
+    )
+}
+
+/// 強制ツール呼び出しの入力を JSON テキストの出力に置き換える
+pub fn tool_call_to_output(mut response: LlmResponse) -> LlmResponse {
+    let Some(index) = response.content_blocks.iter().position(
+        |b| matches!(b, ContentBlock::ToolUse { name, .. } if name == STRUCTURED_OUTPUT_TOOL),
+    ) else {
+        return response;
+    };
+    let ContentBlock::ToolUse { input, .. } = response.content_blocks.remove(index) else {
+        return response;
+    };
+    let text = input.to_string();
+    response
+        .content_blocks
+        .retain(|b| !matches!(b, ContentBlock::Text { .. }));
+    response
+        .content_blocks
+        .push(ContentBlock::Text { text: text.clone() });
+    response.content = text;
+    response.stop_reason = Some(STOP_END_TURN.to_string());
+    response
+}
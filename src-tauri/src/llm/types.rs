@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::sampling::{SamplingParams, ToolChoice};
+
 // --- Content Blocks for tool_use support ---
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +14,9 @@ pub enum ContentBlock {
         id: String,
         name: String,
         input: serde_json::Value,
+        /// Gemini の thoughtSignature。思考ありの関数呼び出しでは同じ part に付けて送り返す必要がある。
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
     },
     #[serde(rename = "tool_result")]
     ToolResult {
@@ -20,6 +25,42 @@ pub enum ContentBlock {
         #[serde(default)]
         is_error: bool,
     },
+    #[serde(rename = "image")]
+    Image { source: MediaSource },
+    /// PDF などのドキュメント
+    #[serde(rename = "document")]
+    Document {
+        source: MediaSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    /// 拡張思考の内容。Anthropic ではツール使用を続けるときに signature ごと送り返す必要がある。
+    #[serde(rename = "thinking")]
+    Thinking {
+        thinking: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+    /// 安全上の理由で暗号化された思考（中身は読めないがそのまま送り返す）
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+}
+
+/// 画像・ドキュメントの中身。
+/// File はプロジェクト内のファイルへの参照（相対パスはプロジェクトルート基準）で、
+/// プロバイダーへ送る前に media::resolve_source で Base64 に変換する。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum MediaSource {
+    #[serde(rename = "base64")]
+    Base64 { media_type: String, data: String },
+    #[serde(rename = "file")]
+    File {
+        path: String,
+        /// 省略時は拡張子から推定する
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        media_type: Option<String>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,7 +123,7 @@ pub struct LlmMessage {
     pub content: MessageContent,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LlmRequest {
     pub model: String,
     pub messages: Vec<LlmMessage>,
@@ -90,6 +131,29 @@ pub struct LlmRequest {
     pub temperature: f64,
     pub max_tokens: i32,
     pub tools: Option<Vec<ToolDefinition>>,
+    /// system・tools・直近の会話にキャッシュのブレークポイントを置く（Anthropic のみ）
+    #[serde(default)]
+    pub prompt_cache: bool,
+    /// 拡張思考に使うトークン予算（None は無効）。max_tokens とは別枠で確保する。
+    #[serde(default)]
+    pub thinking_budget: Option<i32>,
+    /// 出力を従わせる JSON Schema（プロバイダーの JSON モードまたは強制ツール呼び出しで指定する）
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
+    /// top_p / top_k / 停止シーケンス / seed
+    #[serde(default, flatten)]
+    pub sampling: SamplingParams,
+    /// ツール使用の指定（None はプロバイダーの既定）。構造化出力の指定があればそちらを優先する。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+}
+
+impl LlmRequest {
+    /// 思考予算を含めた出力トークンの上限
+    pub fn max_output_tokens(&self) -> i32 {
+        self.max_tokens
+            .saturating_add(self.thinking_budget.unwrap_or(0).max(0))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -102,8 +166,76 @@ pub struct LlmResponse {
     pub stop_reason: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl LlmResponse {
+    /// 思考ブロックのテキストを結合したもの（思考がなければ None）
+    pub fn thinking_text(&self) -> Option<String> {
+        let parts: Vec<&str> = self
+            .content_blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Thinking { thinking, .. } if !thinking.is_empty() => {
+                    Some(thinking.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("\n\n"))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TokenUsage {
+    /// キャッシュを使わなかった入力トークン
     pub input_tokens: u32,
     pub output_tokens: u32,
+    /// プロンプトキャッシュから読み込んだ入力トークン
+    #[serde(default)]
+    pub cache_read_tokens: u32,
+    /// プロンプトキャッシュへ書き込んだ入力トークン
+    #[serde(default)]
+    pub cache_write_tokens: u32,
+}
+
+impl TokenUsage {
+    /// キャッシュ分を含む入力トークンの合計
+    pub fn total_input_tokens(&self) -> u32 {
+        self.input_tokens
+            .saturating_add(self.cache_read_tokens)
+            .saturating_add(self.cache_write_tokens)
+    }
+
+    /// 別の呼び出しの使用量を足し込む
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens = self.input_tokens.saturating_add(other.input_tokens);
+        self.output_tokens = self.output_tokens.saturating_add(other.output_tokens);
+        self.cache_read_tokens = self
+            .cache_read_tokens
+            .saturating_add(other.cache_read_tokens);
+        self.cache_write_tokens = self
+            .cache_write_tokens
+            .saturating_add(other.cache_write_tokens);
+    }
 }
+
+// --- Streaming ---
+
+/// ストリーミング中にプロバイダーから届く増分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum StreamDelta {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "thinking")]
+    Thinking { text: String },
+    #[serde(rename = "tool_use_start")]
+    ToolUseStart { id: String, name: String },
+    #[serde(rename = "tool_use_input")]
+    ToolUseInput { id: String, partial_json: String },
+}
+
+/// 増分を受け取るコールバック（EventBusへの転送などに使う）
+pub type DeltaSink<'a> = &'a (dyn Fn(StreamDelta) + Send + Sync);
@@ -43,10 +43,72 @@ pub struct LlmProvider {
     pub display_name: String,
     pub api_base_url: Option<String>,
     pub is_enabled: bool,
+    /// APIフォーマット（anthropic / google / openai_compatible）
+    pub provider_type: String,
+    /// APIキーの参照名（資格情報名。未保存なら既知のAPIキー名に限り同名の環境変数を公式エンドポイントへ送る）
+    pub api_key_ref: Option<String>,
+    /// レート制限（NULL は無制限）
+    pub requests_per_minute: Option<i32>,
+    pub tokens_per_minute: Option<i32>,
+    pub max_concurrency: Option<i32>,
+    /// 記録・再生用カセット（replay は再生元。record_cassette なら記録先で、
+    /// アプリのデータディレクトリの cassettes/ からの相対パス）
+    pub cassette_path: Option<String>,
+    /// 呼び出しを cassette_path に記録するか（replay 以外）
+    pub record_cassette: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// キーリングに保存された資格情報（値は含めない）
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialInfo {
+    pub name: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// プロバイダーの生成・疎通確認の結果
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderStatus {
+    pub name: String,
+    pub available: bool,
+    /// 生成・疎通確認の失敗理由。補完は使えても埋め込みの生成に失敗したときはその理由
+    pub error: Option<String>,
+}
+
+/// プロバイダーが提供するモデル（不明な項目は None）
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: Option<String>,
+    /// 入力トークンの上限
+    pub context_window: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+    /// 単価（USD / 100万トークン）。model_prices に無いモデルは None
+    pub input_price: Option<f64>,
+    pub output_price: Option<f64>,
+}
+
+/// embeddings は inputs と同じ順序
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingsResponse {
+    pub provider_name: String,
+    pub model: String,
+    pub dimensions: usize,
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// プロバイダーのモデル一覧
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelCatalog {
+    pub provider_name: String,
+    /// "api"（モデル一覧エンドポイント）または "static"（組み込みの一覧）
+    pub source: String,
+    /// モデル一覧エンドポイントの取得に失敗した理由
+    pub error: Option<String>,
+    pub models: Vec<ModelInfo>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Workflow {
     pub id: Uuid,
@@ -70,10 +132,53 @@ pub struct Agent {
     pub temperature: f64,
     pub max_tokens: i32,
     pub is_active: bool,
+    /// プロンプトキャッシュを使う（対応プロバイダーのみ）
+    pub prompt_cache: bool,
+    /// 拡張思考のトークン予算（None は無効）
+    pub thinking_budget_tokens: Option<i32>,
+    /// 出力を従わせる JSON Schema（None は自由形式のテキスト）
+    pub output_schema: Option<serde_json::Value>,
+    /// フォールバック先に切り替える失敗の種類（unavailable / server_error / any_error）
+    pub fallback_on: String,
+    /// オーケストレーターとして作成するサブエージェントのプロバイダー（None は自身と同じ）
+    pub sub_agent_llm_provider_id: Option<Uuid>,
+    /// オーケストレーターとして作成するサブエージェントのモデル（None は自身と同じ）
+    pub sub_agent_model: Option<String>,
+    pub top_p: Option<f64>,
+    pub top_k: Option<i32>,
+    pub stop_sequences: Vec<String>,
+    pub seed: Option<i64>,
+    /// ツール使用の指定（{"type": "auto" | "any" | "none"} / {"type": "tool", "name": ...}）。
+    /// ツールを渡すオーケストレーターの呼び出しにだけ適用する。
+    pub tool_choice: Option<serde_json::Value>,
+    /// 出力上限で打ち切られた応答の続きを書かせる回数（0 なら truncated で終える）
+    pub max_continuations: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// エージェントのフォールバック先（position の昇順に試す）
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AgentFallbackModel {
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    pub position: i32,
+    pub llm_provider_id: Uuid,
+    pub model: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// エージェントのフォールバックとサブエージェントのモデル割り当て
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentRouting {
+    pub agent_id: Uuid,
+    pub fallback_on: String,
+    pub sub_agent_llm_provider_id: Option<Uuid>,
+    pub sub_agent_model: Option<String>,
+    pub fallbacks: Vec<AgentFallbackModel>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct WorkflowRun {
     pub id: Uuid,
@@ -84,6 +189,9 @@ pub struct WorkflowRun {
     pub error_message: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub total_input_tokens: i64,
+    pub total_output_tokens: i64,
+    pub total_cost_usd: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -101,6 +209,18 @@ pub struct AgentExecution {
     pub completed_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub total_input_tokens: i64,
+    pub total_output_tokens: i64,
+    pub total_cost_usd: f64,
+    /// 拡張思考の内容（output_text とは別に保存する）
+    pub thinking_text: Option<String>,
+    /// この実行に適用した出力スキーマ
+    pub output_schema: Option<serde_json::Value>,
+    /// output_schema で検証済みの出力
+    pub output_json: Option<serde_json::Value>,
+    /// 実際に応答したプロバイダーとモデル（フォールバック時は切り替え先）
+    pub answered_provider: Option<String>,
+    pub answered_model: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -133,12 +253,102 @@ pub struct CreateAgentRequest {
     pub model: Option<String>,
     pub temperature: Option<f64>,
     pub max_tokens: Option<i32>,
+    pub prompt_cache: Option<bool>,
+    pub thinking_budget_tokens: Option<i32>,
+    pub output_schema: Option<serde_json::Value>,
+    pub top_p: Option<f64>,
+    pub top_k: Option<i32>,
+    pub stop_sequences: Option<Vec<String>>,
+    pub seed: Option<i64>,
+    pub tool_choice: Option<serde_json::Value>,
+    pub max_continuations: Option<i32>,
+}
+
+/// ルーティング設定を丸ごと置き換える（fallbacks は配列の順に試す）
+#[derive(Debug, Deserialize)]
+pub struct UpdateAgentRoutingRequest {
+    pub agent_id: Uuid,
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackModelEntry>,
+    /// 未指定は unavailable
+    pub fallback_on: Option<String>,
+    pub sub_agent_llm_provider_id: Option<Uuid>,
+    pub sub_agent_model: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FallbackModelEntry {
+    pub llm_provider_id: Uuid,
+    pub model: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateLlmProviderRequest {
+    pub name: String,
+    pub display_name: String,
+    pub provider_type: String,
+    pub api_base_url: Option<String>,
+    pub api_key_ref: Option<String>,
+    pub is_enabled: Option<bool>,
+    pub requests_per_minute: Option<i32>,
+    pub tokens_per_minute: Option<i32>,
+    pub max_concurrency: Option<i32>,
+    pub cassette_path: Option<String>,
+    pub record_cassette: Option<bool>,
+}
+
+/// 未指定のフィールドは変更しない。api_base_url / api_key_ref / cassette_path は空文字、
+/// レート制限は 0 でクリア（無制限）する。
+#[derive(Debug, Deserialize)]
+pub struct UpdateLlmProviderRequest {
+    pub display_name: Option<String>,
+    pub provider_type: Option<String>,
+    pub api_base_url: Option<String>,
+    pub api_key_ref: Option<String>,
+    pub is_enabled: Option<bool>,
+    pub requests_per_minute: Option<i32>,
+    pub tokens_per_minute: Option<i32>,
+    pub max_concurrency: Option<i32>,
+    pub cassette_path: Option<String>,
+    pub record_cassette: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetCredentialRequest {
+    pub name: String,
+    pub value: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ExecuteAgentRequest {
     pub agent_id: Uuid,
     pub input: String,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// 指定するとエージェントの output_schema より優先する
+    #[serde(default)]
+    pub output_schema: Option<serde_json::Value>,
+}
+
+/// provider_name は llm_providers.name（DB未接続時は組み込みプロバイダー名）
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingsRequest {
+    pub provider_name: String,
+    /// 未指定はプロバイダーの既定の埋め込みモデル
+    pub model: Option<String>,
+    pub inputs: Vec<String>,
+    /// "query" / "document" / "similarity"（Gemini の taskType に対応）
+    pub task: Option<String>,
+    pub dimensions: Option<u32>,
+}
+
+/// 実行に添付する画像・PDF（base64 またはローカルファイルのパス）
+#[derive(Debug, Clone, Deserialize)]
+pub struct Attachment {
+    #[serde(flatten)]
+    pub source: crate::llm::types::MediaSource,
+    /// ドキュメントのタイトル（ファイル名など）
+    pub name: Option<String>,
 }
 
 // --- Orchestration Models ---
@@ -156,6 +366,17 @@ pub struct OrchestrationRun {
     pub final_output: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub total_input_tokens: i64,
+    pub total_output_tokens: i64,
+    pub total_cost_usd: f64,
+    pub budget_max_tokens: Option<i64>,
+    pub budget_max_cost_usd: Option<f64>,
+    /// 会話履歴を圧縮し始める入力トークン数（None は既定値）
+    pub context_token_limit: Option<i32>,
+    /// 古いやり取りの要約に使うモデル（None は要約せずに削除）
+    pub compaction_model: Option<String>,
+    /// 実行中に行った圧縮の記録
+    pub compaction_log: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
@@ -163,6 +384,29 @@ pub struct OrchestrateRequest {
     pub agent_id: Uuid,
     pub input: String,
     pub mode: String,
+    /// 入力+出力トークンの上限（サブエージェント分を含む）
+    pub max_tokens_budget: Option<i64>,
+    /// コスト上限（USD、サブエージェント分を含む）
+    pub max_cost_usd: Option<f64>,
+    /// 会話履歴を圧縮し始める入力トークン数
+    pub context_token_limit: Option<i32>,
+    /// 古いやり取りの要約に使う安価なモデル（オーケストレーターと同じプロバイダー）
+    pub compaction_model: Option<String>,
+}
+
+/// モデル単価（USD / 100万トークン）
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModelPrice {
+    pub id: Uuid,
+    /// llm_providers.provider_type（プロバイダー名ではなく種別で引く）
+    pub provider_type: String,
+    pub model: String,
+    pub input_price: f64,
+    pub output_price: f64,
+    pub cache_read_price: Option<f64>,
+    pub cache_write_price: Option<f64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 // --- Tool System Models ---
@@ -179,7 +423,6 @@ pub struct AgentToolPermission {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
-#[allow(dead_code)]
 pub struct ToolExecution {
     pub id: Uuid,
     pub execution_id: Option<Uuid>,
@@ -189,6 +432,24 @@ pub struct ToolExecution {
     pub is_error: bool,
     pub duration_ms: Option<i64>,
     pub created_at: DateTime<Utc>,
+    pub orchestration_run_id: Option<Uuid>,
+    /// LLM の tool_use ブロックの ID
+    pub tool_use_id: Option<String>,
+    /// 出力が TOOL_AUDIT_MAX_OUTPUT_BYTES で切り詰められた
+    pub output_truncated: bool,
+}
+
+/// ツール実行履歴の絞り込み条件（いずれも省略可、created_at の新しい順）
+#[derive(Debug, Default, Deserialize)]
+pub struct ToolExecutionQuery {
+    pub execution_id: Option<Uuid>,
+    pub orchestration_run_id: Option<Uuid>,
+    pub tool_name: Option<String>,
+    /// この時刻以降（含む）
+    pub since: Option<DateTime<Utc>>,
+    /// この時刻より前
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
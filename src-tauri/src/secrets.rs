@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::constants::{
+    PROVIDER_TYPE_ANTHROPIC, PROVIDER_TYPE_GOOGLE, PROVIDER_TYPE_OPENAI_COMPATIBLE,
+};
+use crate::error::AppError;
+use crate::models::CredentialInfo;
+
+const SERVICE_NAME: &str = "tebiki";
+/// キーリングは列挙できないため、保存済みの名前と更新日時を別エントリで管理する
+const INDEX_KEY: &str = "credential-index";
+const MAX_NAME_LEN: usize = 100;
+/// キーリングに無いとき環境変数から読んでよい名前。
+/// 任意の名前を許すと、api_key_ref に別の環境変数を指定してプロセスの秘密を外部へ送らせられる
+const ENV_FALLBACK_NAMES: [&str; 5] = [
+    "ANTHROPIC_API_KEY",
+    "GOOGLE_AI_STUDIO_API_KEY",
+    "GEMINI_API_KEY",
+    "OPENAI_API_KEY",
+    "BRAVE_SEARCH_API_KEY",
+];
+
+/// プロバイダー行から環境変数のキーを使うときの送り先（名前, provider_type, ホスト）。
+/// 任意の api_base_url を許すと、既定のキーを外部のサーバーへ送らせられる
+const ENV_FALLBACK_TARGETS: [(&str, &str, &str); 4] = [
+    (
+        "ANTHROPIC_API_KEY",
+        PROVIDER_TYPE_ANTHROPIC,
+        "api.anthropic.com",
+    ),
+    (
+        "GOOGLE_AI_STUDIO_API_KEY",
+        PROVIDER_TYPE_GOOGLE,
+        "generativelanguage.googleapis.com",
+    ),
+    (
+        "GEMINI_API_KEY",
+        PROVIDER_TYPE_GOOGLE,
+        "generativelanguage.googleapis.com",
+    ),
+    (
+        "OPENAI_API_KEY",
+        PROVIDER_TYPE_OPENAI_COMPATIBLE,
+        "api.openai.com",
+    ),
+];
+
+/// インデックスの読み書きを直列化する
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+fn entry(account: &str) -> Result<keyring::Entry, AppError> {
+    keyring::Entry::new(SERVICE_NAME, account)
+        .map_err(|e| AppError::Internal(format!("Keyring error: {e}")))
+}
+
+fn credential_entry(name: &str) -> Result<keyring::Entry, AppError> {
+    entry(&format!("credential:{name}"))
+}
+
+fn validate_name(name: &str) -> Result<(), AppError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "Credential name must be 1-{MAX_NAME_LEN} characters of [A-Za-z0-9_.-]"
+        )))
+    }
+}
+
+fn load_index() -> Result<BTreeMap<String, DateTime<Utc>>, AppError> {
+    match entry(INDEX_KEY)?.get_password() {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| AppError::Internal(format!("Credential index is corrupted: {e}"))),
+        Err(keyring::Error::NoEntry) => Ok(BTreeMap::new()),
+        Err(e) => Err(AppError::Internal(format!(
+            "Failed to load credential index: {e}"
+        ))),
+    }
+}
+
+fn save_index(index: &BTreeMap<String, DateTime<Utc>>) -> Result<(), AppError> {
+    let json = serde_json::to_string(index)
+        .map_err(|e| AppError::Internal(format!("Credential index serialization error: {e}")))?;
+    entry(INDEX_KEY)?
+        .set_password(&json)
+        .map_err(|e| AppError::Internal(format!("Failed to save credential index: {e}")))
+}
+
+/// 保存済みの資格情報の一覧（値は返さない）
+pub fn list_credentials() -> Result<Vec<CredentialInfo>, AppError> {
+    let _guard = INDEX_LOCK.lock().expect("Credential index lock poisoned");
+    Ok(load_index()?
+        .into_iter()
+        .map(|(name, updated_at)| CredentialInfo { name, updated_at })
+        .collect())
+}
+
+/// 資格情報を保存する。既存の名前なら値を置き換える（ローテーション）。
+pub fn set_credential(name: &str, value: &str) -> Result<CredentialInfo, AppError> {
+    validate_name(name)?;
+    if value.is_empty() {
+        return Err(AppError::InvalidInput(
+            "Credential value must not be empty".to_string(),
+        ));
+    }
+
+    let _guard = INDEX_LOCK.lock().expect("Credential index lock poisoned");
+    credential_entry(name)?
+        .set_password(value)
+        .map_err(|e| AppError::Internal(format!("Failed to save credential: {e}")))?;
+
+    let mut index = load_index()?;
+    let updated_at = Utc::now();
+    index.insert(name.to_string(), updated_at);
+    save_index(&index)?;
+
+    Ok(CredentialInfo {
+        name: name.to_string(),
+        updated_at,
+    })
+}
+
+pub fn delete_credential(name: &str) -> Result<(), AppError> {
+    validate_name(name)?;
+
+    let _guard = INDEX_LOCK.lock().expect("Credential index lock poisoned");
+    match credential_entry(name)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => {
+            return Err(AppError::Internal(format!(
+                "Failed to delete credential: {e}"
+            )))
+        }
+    }
+
+    let mut index = load_index()?;
+    if index.remove(name).is_none() {
+        return Err(AppError::NotFound);
+    }
+    save_index(&index)
+}
+
+/// プロバイダー行の api_key_ref として使えるか確認する。ボルトに保存済みの名前か、
+/// 種別と送り先が ENV_FALLBACK_TARGETS に合う既定の環境変数名だけを許す
+pub fn validate_provider_ref(
+    name: &str,
+    provider_type: &str,
+    base_url: Option<&str>,
+) -> Result<(), AppError> {
+    validate_name(name)?;
+    {
+        let _guard = INDEX_LOCK.lock().expect("Credential index lock poisoned");
+        if load_index()?.contains_key(name) {
+            return Ok(());
+        }
+    }
+    if ENV_FALLBACK_TARGETS.iter().any(|(n, ..)| *n == name) {
+        return check_env_target(name, provider_type, base_url);
+    }
+    let names: Vec<&str> = ENV_FALLBACK_TARGETS.iter().map(|(n, ..)| *n).collect();
+    Err(AppError::InvalidInput(format!(
+        "Credential '{name}' is not saved. Save it as a credential first, or use one of {names:?}"
+    )))
+}
+
+/// 環境変数のキーを、その名前の既定の種別・ホスト以外へ送らない
+fn check_env_target(
+    name: &str,
+    provider_type: &str,
+    base_url: Option<&str>,
+) -> Result<(), AppError> {
+    let allowed = ENV_FALLBACK_TARGETS
+        .iter()
+        .any(|(n, t, host)| *n == name && *t == provider_type && is_default_host(base_url, host));
+    if allowed {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "Environment credential '{name}' is only sent to its official endpoint. \
+             Save the key as a credential to use it with this provider"
+        )))
+    }
+}
+
+/// base_url が既定のホスト宛てか（未設定は各プロバイダーの公式エンドポイントを使う）
+fn is_default_host(base_url: Option<&str>, host: &str) -> bool {
+    match base_url.map(str::trim).filter(|u| !u.is_empty()) {
+        None => true,
+        Some(u) => url::Url::parse(u).is_ok_and(|u| {
+            u.scheme() == "https" && u.host_str() == Some(host) && u.port().is_none()
+        }),
+    }
+}
+
+/// キーリングに保存済みの値
+fn resolve_saved(name: &str) -> Result<Option<String>, AppError> {
+    match credential_entry(name)?.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        // キーリングが使えない環境（ヘッドレス等）では環境変数だけで動かす
+        Err(e) => {
+            eprintln!("[tebiki] Keyring lookup for '{name}' failed: {e}");
+            Ok(None)
+        }
+    }
+}
+
+fn resolve_env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// 参照名（例: `ANTHROPIC_API_KEY`）から資格情報を解決する。
+/// キーリングに無ければ、ENV_FALLBACK_NAMES に限り同名の環境変数を使う（既存の `.env` 運用向け）。
+/// 送り先が固定の組み込みプロバイダー・ツール用で、プロバイダー行には resolve_for_provider を使う。
+pub fn resolve(name: &str) -> Result<Option<String>, AppError> {
+    if let Some(value) = resolve_saved(name)? {
+        return Ok(Some(value));
+    }
+    if !ENV_FALLBACK_NAMES.contains(&name) {
+        return Ok(None);
+    }
+    Ok(resolve_env(name))
+}
+
+/// プロバイダー行の api_key_ref を解決する。ボルトに保存済みならそのまま使い、
+/// 環境変数は ENV_FALLBACK_TARGETS の種別・ホスト宛てに限って読む。
+pub fn resolve_for_provider(
+    name: &str,
+    provider_type: &str,
+    base_url: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    if let Some(value) = resolve_saved(name)? {
+        return Ok(Some(value));
+    }
+    if !ENV_FALLBACK_TARGETS.iter().any(|(n, ..)| *n == name) {
+        return Ok(None);
+    }
+    check_env_target(name, provider_type, base_url)?;
+    Ok(resolve_env(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_credentials_go_only_to_their_official_endpoint() {
+        let ok =
+            |name, provider_type, base_url| check_env_target(name, provider_type, base_url).is_ok();
+        assert!(ok("ANTHROPIC_API_KEY", PROVIDER_TYPE_ANTHROPIC, None));
+        assert!(ok(
+            "ANTHROPIC_API_KEY",
+            PROVIDER_TYPE_ANTHROPIC,
+            Some("https://api.anthropic.com/")
+        ));
+        assert!(ok(
+            "OPENAI_API_KEY",
+            PROVIDER_TYPE_OPENAI_COMPATIBLE,
+            Some("https://api.openai.com/v1")
+        ));
+        assert!(ok("GEMINI_API_KEY", PROVIDER_TYPE_GOOGLE, Some(" ")));
+
+        // 別の種別・任意のホストには送らない
+        assert!(!ok(
+            "ANTHROPIC_API_KEY",
+            PROVIDER_TYPE_OPENAI_COMPATIBLE,
+            Some("https://api.anthropic.com")
+        ));
+        assert!(!ok(
+            "OPENAI_API_KEY",
+            PROVIDER_TYPE_OPENAI_COMPATIBLE,
+            Some("https://attacker.example/v1")
+        ));
+        assert!(!ok(
+            "ANTHROPIC_API_KEY",
+            PROVIDER_TYPE_ANTHROPIC,
+            Some("https://api.anthropic.com@attacker.example")
+        ));
+        assert!(!ok(
+            "ANTHROPIC_API_KEY",
+            PROVIDER_TYPE_ANTHROPIC,
+            Some("http://api.anthropic.com")
+        ));
+        assert!(!ok(
+            "ANTHROPIC_API_KEY",
+            PROVIDER_TYPE_ANTHROPIC,
+            Some("https://api.anthropic.com:8443")
+        ));
+        assert!(!ok("BRAVE_SEARCH_API_KEY", PROVIDER_TYPE_ANTHROPIC, None));
+    }
+}
@@ -1,15 +1,99 @@
 use uuid::Uuid;
 
+use crate::constants::{FALLBACK_ON_UNAVAILABLE, MAX_CONTINUATIONS, MIN_THINKING_BUDGET_TOKENS};
 use crate::db::DbPool;
 use crate::error::AppError;
-use crate::models::{Agent, CreateAgentRequest, LlmProvider};
+use crate::llm::catalog;
+use crate::llm::routing::{FallbackPolicy, ModelCandidate, ModelRoute};
+use crate::llm::sampling::{self, SamplingParams, ToolChoice};
+use crate::llm::structured;
+use crate::llm::LlmRegistry;
+use crate::models::{
+    Agent, AgentFallbackModel, AgentRouting, CreateAgentRequest, FallbackModelEntry, LlmProvider,
+    UpdateAgentRoutingRequest,
+};
 
-pub async fn create_agent(db: &DbPool, request: &CreateAgentRequest) -> Result<Agent, AppError> {
+/// 1エージェントに設定できるフォールバック先の最大数
+const MAX_FALLBACK_MODELS: usize = 5;
+
+pub async fn create_agent(
+    db: &DbPool,
+    registry: &LlmRegistry,
+    request: &CreateAgentRequest,
+) -> Result<Agent, AppError> {
+    if request
+        .thinking_budget_tokens
+        .is_some_and(|b| b < MIN_THINKING_BUDGET_TOKENS)
+    {
+        return Err(AppError::InvalidInput(format!(
+            "thinking_budget_tokens must be at least {MIN_THINKING_BUDGET_TOKENS}"
+        )));
+    }
+    if let Some(schema) = &request.output_schema {
+        structured::compile(schema)?;
+    }
+    if request
+        .max_continuations
+        .is_some_and(|n| !(0..=MAX_CONTINUATIONS).contains(&n))
+    {
+        return Err(AppError::InvalidInput(format!(
+            "max_continuations must be between 0 and {MAX_CONTINUATIONS}"
+        )));
+    }
     let pool = db.get()?;
+
+    let provider = sqlx::query_as::<_, LlmProvider>("SELECT * FROM llm_providers WHERE id = $1")
+        .bind(request.llm_provider_id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "LLM provider {} does not exist",
+                request.llm_provider_id
+            ))
+        })?;
+
+    // モデル一覧を取得できたプロバイダーでは、一覧にないモデルを弾く
+    let model = match request.model.as_deref().map(str::trim) {
+        Some(model) if !model.is_empty() => model.to_string(),
+        _ => catalog::default_model(&provider.provider_type)
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!(
+                    "model is required for provider type '{}'",
+                    provider.provider_type
+                ))
+            })?
+            .to_string(),
+    };
+    let models = registry.model_catalog(&provider).await;
+    if !models.accepts(&model) {
+        return Err(AppError::InvalidInput(format!(
+            "Model '{model}' is not offered by provider '{}'",
+            provider.name
+        )));
+    }
+
+    // プロバイダーが対応していないサンプリング設定は作成時に弾く
+    let provider_type = provider.provider_type;
+    let sampling = SamplingParams {
+        top_p: request.top_p,
+        top_k: request.top_k,
+        stop_sequences: request.stop_sequences.clone().unwrap_or_default(),
+        seed: request.seed,
+    };
+    let tool_choice = ToolChoice::from_value(request.tool_choice.as_ref())?;
+    sampling::validate(
+        &provider_type,
+        &sampling,
+        tool_choice.as_ref(),
+        request.thinking_budget_tokens,
+    )?;
+
     let agent = sqlx::query_as::<_, Agent>(
         r#"
-        INSERT INTO agents (workflow_id, llm_provider_id, name, description, system_prompt, model, temperature, max_tokens)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        INSERT INTO agents (workflow_id, llm_provider_id, name, description, system_prompt, model, temperature, max_tokens, prompt_cache, thinking_budget_tokens, output_schema,
+                            top_p, top_k, stop_sequences, seed, tool_choice, max_continuations)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, COALESCE($17, 0))
         RETURNING *
         "#,
     )
@@ -18,9 +102,18 @@ pub async fn create_agent(db: &DbPool, request: &CreateAgentRequest) -> Result<A
     .bind(&request.name)
     .bind(&request.description)
     .bind(&request.system_prompt)
-    .bind(request.model.as_deref().unwrap_or("claude-sonnet-4-5-20250929"))
+    .bind(&model)
     .bind(request.temperature.unwrap_or(0.7))
     .bind(request.max_tokens.unwrap_or(1024))
+    .bind(request.prompt_cache.unwrap_or(false))
+    .bind(request.thinking_budget_tokens)
+    .bind(&request.output_schema)
+    .bind(sampling.top_p)
+    .bind(sampling.top_k)
+    .bind(&sampling.stop_sequences)
+    .bind(sampling.seed)
+    .bind(&request.tool_choice)
+    .bind(request.max_continuations)
     .fetch_one(&pool)
     .await?;
 
@@ -42,7 +135,6 @@ pub async fn get_agents_by_workflow(
     Ok(agents)
 }
 
-#[allow(dead_code)]
 pub async fn get_agent(db: &DbPool, id: Uuid) -> Result<Agent, AppError> {
     let pool = db.get()?;
     let agent = sqlx::query_as::<_, Agent>("SELECT * FROM agents WHERE id = $1")
@@ -54,6 +146,182 @@ pub async fn get_agent(db: &DbPool, id: Uuid) -> Result<Agent, AppError> {
     Ok(agent)
 }
 
+/// フォールバック先のプロバイダーでエージェントのサンプリング設定が使えるか確認する。
+/// 実行時の検証エラー（InvalidInput）はフォールバックの対象にならないため、保存時に弾く。
+async fn validate_fallback_candidates(
+    pool: &sqlx::PgPool,
+    agent: &Agent,
+    fallbacks: &[FallbackModelEntry],
+) -> Result<(), AppError> {
+    let sampling = SamplingParams::from_agent(agent);
+    let tool_choice = ToolChoice::from_value(agent.tool_choice.as_ref())?;
+    for fallback in fallbacks {
+        let provider =
+            sqlx::query_as::<_, LlmProvider>("SELECT * FROM llm_providers WHERE id = $1")
+                .bind(fallback.llm_provider_id)
+                .fetch_optional(pool)
+                .await?
+                .ok_or_else(|| {
+                    AppError::InvalidInput(format!(
+                        "LLM provider {} does not exist",
+                        fallback.llm_provider_id
+                    ))
+                })?;
+        sampling::validate(
+            &provider.provider_type,
+            &sampling,
+            tool_choice.as_ref(),
+            agent.thinking_budget_tokens,
+        )
+        .map_err(|e| match e {
+            AppError::InvalidInput(message) => AppError::InvalidInput(format!(
+                "Fallback {}/{} cannot run this agent: {message}",
+                provider.name,
+                fallback.model.trim()
+            )),
+            other => other,
+        })?;
+    }
+    Ok(())
+}
+
+/// エージェントのフォールバック設定とサブエージェントのモデル割り当てを置き換える
+pub async fn update_agent_routing(
+    db: &DbPool,
+    request: &UpdateAgentRoutingRequest,
+) -> Result<AgentRouting, AppError> {
+    let fallback_on = request
+        .fallback_on
+        .as_deref()
+        .unwrap_or(FALLBACK_ON_UNAVAILABLE);
+    FallbackPolicy::parse(fallback_on)?;
+    if request.fallbacks.len() > MAX_FALLBACK_MODELS {
+        return Err(AppError::InvalidInput(format!(
+            "Too many fallbacks ({}). Maximum is {MAX_FALLBACK_MODELS}.",
+            request.fallbacks.len()
+        )));
+    }
+    if request.fallbacks.iter().any(|f| f.model.trim().is_empty()) {
+        return Err(AppError::InvalidInput(
+            "Fallback model must not be empty".to_string(),
+        ));
+    }
+    let sub_agent_model = request
+        .sub_agent_model
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty());
+    // 別プロバイダーのモデル名はオーケストレーターのものと互換とは限らない
+    if request.sub_agent_llm_provider_id.is_some() && sub_agent_model.is_none() {
+        return Err(AppError::InvalidInput(
+            "sub_agent_model is required when sub_agent_llm_provider_id is set".to_string(),
+        ));
+    }
+
+    let agent = get_agent(db, request.agent_id).await?;
+    let pool = db.get()?;
+    validate_fallback_candidates(&pool, &agent, &request.fallbacks).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let updated = sqlx::query(
+        r#"
+        UPDATE agents
+        SET fallback_on = $1, sub_agent_llm_provider_id = $2, sub_agent_model = $3, updated_at = NOW()
+        WHERE id = $4
+        "#,
+    )
+    .bind(fallback_on)
+    .bind(request.sub_agent_llm_provider_id)
+    .bind(sub_agent_model)
+    .bind(request.agent_id)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    sqlx::query("DELETE FROM agent_fallback_models WHERE agent_id = $1")
+        .bind(request.agent_id)
+        .execute(&mut *tx)
+        .await?;
+    for (position, fallback) in request.fallbacks.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO agent_fallback_models (agent_id, position, llm_provider_id, model)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(request.agent_id)
+        .bind(position as i32)
+        .bind(fallback.llm_provider_id)
+        .bind(fallback.model.trim())
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    get_agent_routing(db, request.agent_id).await
+}
+
+pub async fn get_agent_routing(db: &DbPool, agent_id: Uuid) -> Result<AgentRouting, AppError> {
+    let agent = get_agent(db, agent_id).await?;
+    let pool = db.get()?;
+    let fallbacks = sqlx::query_as::<_, AgentFallbackModel>(
+        "SELECT * FROM agent_fallback_models WHERE agent_id = $1 ORDER BY position ASC",
+    )
+    .bind(agent_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(AgentRouting {
+        agent_id,
+        fallback_on: agent.fallback_on,
+        sub_agent_llm_provider_id: agent.sub_agent_llm_provider_id,
+        sub_agent_model: agent.sub_agent_model,
+        fallbacks,
+    })
+}
+
+/// エージェント本来のモデルを先頭に、有効なプロバイダーのフォールバック先を続けたルートを作る
+pub async fn load_model_route(
+    pool: &sqlx::PgPool,
+    agent: &Agent,
+    provider_name: &str,
+) -> Result<ModelRoute, AppError> {
+    let fallbacks = sqlx::query_as::<_, (String, String)>(
+        r#"
+        SELECT p.name, f.model
+        FROM agent_fallback_models f
+        JOIN llm_providers p ON p.id = f.llm_provider_id
+        WHERE f.agent_id = $1 AND p.is_enabled = true
+        ORDER BY f.position ASC
+        "#,
+    )
+    .bind(agent.id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut candidates = vec![ModelCandidate {
+        provider_name: provider_name.to_string(),
+        model: agent.model.clone(),
+    }];
+    for (provider_name, model) in fallbacks {
+        let candidate = ModelCandidate {
+            provider_name,
+            model,
+        };
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+
+    Ok(ModelRoute {
+        candidates,
+        policy: FallbackPolicy::parse(&agent.fallback_on)?,
+    })
+}
+
 pub async fn get_llm_providers(db: &DbPool) -> Result<Vec<LlmProvider>, AppError> {
     let pool = db.get()?;
     let providers = sqlx::query_as::<_, LlmProvider>(
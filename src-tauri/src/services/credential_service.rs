@@ -0,0 +1,31 @@
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::llm::LlmRegistry;
+use crate::models::CredentialInfo;
+use crate::secrets;
+use crate::services::llm_provider_service;
+
+pub fn list_credentials() -> Result<Vec<CredentialInfo>, AppError> {
+    secrets::list_credentials()
+}
+
+/// 資格情報を保存（既存なら置き換え）し、参照しているプロバイダーを作り直す
+pub async fn set_credential(
+    db: &DbPool,
+    registry: &LlmRegistry,
+    name: &str,
+    value: &str,
+) -> Result<CredentialInfo, AppError> {
+    let info = secrets::set_credential(name, value)?;
+    llm_provider_service::refresh_registry(db, registry).await?;
+    Ok(info)
+}
+
+pub async fn delete_credential(
+    db: &DbPool,
+    registry: &LlmRegistry,
+    name: &str,
+) -> Result<(), AppError> {
+    secrets::delete_credential(name)?;
+    llm_provider_service::refresh_registry(db, registry).await
+}
@@ -0,0 +1,69 @@
+use crate::constants::MAX_EMBEDDING_INPUTS;
+use crate::error::AppError;
+use crate::llm::embeddings::{EmbeddingRequest, EmbeddingTask};
+use crate::llm::LlmRegistry;
+use crate::models::{EmbeddingsRequest, EmbeddingsResponse};
+
+/// 入力をベクトル化する。入力件数がプロバイダーの上限を超える場合はバッチに分けて送る。
+pub async fn create_embeddings(
+    registry: &LlmRegistry,
+    request: EmbeddingsRequest,
+) -> Result<EmbeddingsResponse, AppError> {
+    if request.inputs.is_empty() {
+        return Err(AppError::InvalidInput(
+            "inputs must not be empty".to_string(),
+        ));
+    }
+    if request.inputs.len() > MAX_EMBEDDING_INPUTS {
+        return Err(AppError::InvalidInput(format!(
+            "Too many inputs ({}). At most {MAX_EMBEDDING_INPUTS} are allowed per request.",
+            request.inputs.len()
+        )));
+    }
+    if request.inputs.iter().any(|s| s.trim().is_empty()) {
+        return Err(AppError::InvalidInput(
+            "inputs must not contain empty strings".to_string(),
+        ));
+    }
+    if request.dimensions == Some(0) {
+        return Err(AppError::InvalidInput(
+            "dimensions must be at least 1".to_string(),
+        ));
+    }
+    let task = request
+        .task
+        .as_deref()
+        .map(EmbeddingTask::parse)
+        .transpose()?;
+
+    let handle = registry.require_embedder(&request.provider_name)?;
+    let model = match request.model.filter(|m| !m.trim().is_empty()) {
+        Some(model) => model,
+        None => handle
+            .embedder
+            .default_model()
+            .map(str::to_string)
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!(
+                    "model is required for provider '{}'",
+                    request.provider_name
+                ))
+            })?,
+    };
+
+    let embedding_request = EmbeddingRequest {
+        model,
+        inputs: request.inputs,
+        task,
+        dimensions: request.dimensions,
+    };
+    let embeddings = registry
+        .embed(&request.provider_name, &embedding_request)
+        .await?;
+    Ok(EmbeddingsResponse {
+        provider_name: request.provider_name,
+        model: embedding_request.model,
+        dimensions: embeddings.first().map_or(0, Vec::len),
+        embeddings,
+    })
+}
@@ -7,13 +7,160 @@ use crate::constants::*;
 use crate::db::DbPool;
 use crate::error::AppError;
 use crate::event_bus::{EventBus, ExecutionEvent};
-use crate::llm::types::{LlmMessage, LlmRequest, MessageContent};
-use crate::llm::LlmRegistry;
+use crate::llm::continuation;
+use crate::llm::limiter::LimiterPermit;
+use crate::llm::media;
+use crate::llm::retry::RetryNotice;
+use crate::llm::routing::FallbackNotice;
+use crate::llm::sampling::SamplingParams;
+use crate::llm::structured::{self, MAX_STRUCTURED_OUTPUT_ATTEMPTS};
+use crate::llm::types::{
+    ContentBlock, LlmMessage, LlmRequest, LlmResponse, MessageContent, TokenUsage,
+};
+use crate::llm::{LlmRegistry, ProviderHandle};
 use crate::models::{AgentExecution, AgentMessage, ExecuteAgentRequest};
+use crate::services::{agent_service, usage_service};
+use crate::tools::types::ToolContext;
 
 /// Maximum allowed input text length (200KB — LLM APIのトークン上限は別途あるため余裕をもたせる)
 const MAX_INPUT_LENGTH: usize = 200_000;
 
+/// プロバイダーの同時実行枠とレート枠を確保する。
+/// すぐに確保できない場合は実行を queued にして通知し、空いた時点で running に戻す。
+pub(crate) async fn acquire_provider_slot(
+    db: &DbPool,
+    event_bus: &EventBus,
+    provider: &ProviderHandle,
+    estimated_tokens: u32,
+    execution_id: Uuid,
+    agent_id: Uuid,
+) -> LimiterPermit {
+    if let Some(permit) = provider.limiter.try_acquire(estimated_tokens) {
+        return permit;
+    }
+
+    set_execution_status(db, execution_id, STATUS_QUEUED).await;
+    event_bus.publish(ExecutionEvent::AgentExecutionQueued {
+        execution_id,
+        agent_id,
+        provider: provider.provider.name().to_string(),
+    });
+
+    let permit = provider.limiter.acquire(estimated_tokens).await;
+
+    set_execution_status(db, execution_id, STATUS_RUNNING).await;
+    event_bus.publish(ExecutionEvent::AgentExecutionDequeued {
+        execution_id,
+        agent_id,
+    });
+    permit
+}
+
+async fn set_execution_status(db: &DbPool, execution_id: Uuid, status: &str) {
+    let result = match db.get() {
+        Ok(pool) => sqlx::query("UPDATE agent_executions SET status = $1 WHERE id = $2")
+            .bind(status)
+            .bind(execution_id)
+            .execute(&pool)
+            .await
+            .map(|_| ())
+            .map_err(AppError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("[tebiki] Failed to set execution {execution_id} to {status}: {e}");
+    }
+}
+
+/// フォールバック先への切り替えを通知する
+pub(crate) fn publish_fallback(
+    event_bus: &EventBus,
+    execution_id: Uuid,
+    agent_id: Uuid,
+    notice: FallbackNotice,
+) {
+    event_bus.publish(ExecutionEvent::AgentExecutionFallback {
+        execution_id,
+        agent_id,
+        from_provider: notice.from.provider_name,
+        from_model: notice.from.model,
+        to_provider: notice.to.provider_name,
+        to_model: notice.to.model,
+        error: notice.error,
+    });
+}
+
+/// 実際に応答したプロバイダーとモデルを実行に記録する（ツールループでは呼び出しごとに上書きする）
+pub(crate) async fn set_answered_by(
+    db: &DbPool,
+    execution_id: Uuid,
+    provider_name: &str,
+    model: &str,
+) {
+    let result = match db.get() {
+        Ok(pool) => sqlx::query(
+            "UPDATE agent_executions SET answered_provider = $1, answered_model = $2 WHERE id = $3",
+        )
+        .bind(provider_name)
+        .bind(model)
+        .bind(execution_id)
+        .execute(&pool)
+        .await
+        .map(|_| ())
+        .map_err(AppError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("[tebiki] Failed to record answering model for execution {execution_id}: {e}");
+    }
+}
+
+/// 拡張思考の内容を実行の thinking_text に追記する（ツールループでは呼び出しごとに積み上げる）
+pub(crate) async fn append_thinking_text(db: &DbPool, execution_id: Uuid, thinking: &str) {
+    let result = match db.get() {
+        Ok(pool) => sqlx::query(
+            r#"
+            UPDATE agent_executions
+            SET thinking_text = COALESCE(thinking_text || E'\n\n', '') || $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(thinking)
+        .bind(execution_id)
+        .execute(&pool)
+        .await
+        .map(|_| ())
+        .map_err(AppError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        eprintln!("[tebiki] Failed to save thinking for execution {execution_id}: {e}");
+    }
+}
+
+/// 単発実行のLLM呼び出し1回分の使用量を記録する（失敗しても実行は止めない）
+async fn record_usage(
+    db: &DbPool,
+    execution_id: Uuid,
+    provider_name: &str,
+    response: &LlmResponse,
+    duration_ms: i64,
+) {
+    if let Err(e) = usage_service::record_llm_call(
+        db,
+        execution_id,
+        None,
+        provider_name,
+        &response.model,
+        &response.token_usage,
+        duration_ms,
+    )
+    .await
+    {
+        eprintln!("[tebiki] Failed to record usage for execution {execution_id}: {e}");
+    }
+}
+
 pub async fn execute_agent(
     db: &DbPool,
     registry: &Arc<LlmRegistry>,
@@ -27,6 +174,27 @@ pub async fn execute_agent(
             MAX_INPUT_LENGTH
         )));
     }
+    if request.attachments.len() > media::MAX_ATTACHMENTS {
+        return Err(AppError::InvalidInput(format!(
+            "Too many attachments ({}). Maximum is {}.",
+            request.attachments.len(),
+            media::MAX_ATTACHMENTS
+        )));
+    }
+
+    // 添付は実行レコードを作る前に検証・読み込みする。ファイルはプロジェクト内に限る
+    let attachment_ctx = ToolContext::for_project();
+    let mut attachment_blocks = Vec::with_capacity(request.attachments.len());
+    for attachment in &request.attachments {
+        attachment_blocks.push(
+            media::attachment_block(
+                attachment.source.clone(),
+                attachment.name.clone(),
+                &attachment_ctx,
+            )
+            .await?,
+        );
+    }
 
     let pool = db.get()?;
 
@@ -47,6 +215,19 @@ pub async fn execute_agent(
     .await?
     .ok_or(AppError::NotFound)?;
 
+    // フォールバック先を含む呼び出し候補
+    let mut route = agent_service::load_model_route(&pool, &agent, &provider_row.name).await?;
+
+    // 構造化出力: 実行時の指定がなければエージェントの既定スキーマを使う
+    let output_schema = request
+        .output_schema
+        .clone()
+        .or_else(|| agent.output_schema.clone());
+    let validator = output_schema
+        .as_ref()
+        .map(structured::compile)
+        .transpose()?;
+
     // 2. Create ad-hoc workflow_run
     let workflow_run = sqlx::query_as::<_, crate::models::WorkflowRun>(
         r#"
@@ -68,8 +249,8 @@ pub async fn execute_agent(
     // 3. Create agent_execution record (status: running)
     let execution = sqlx::query_as::<_, AgentExecution>(
         r#"
-        INSERT INTO agent_executions (agent_id, workflow_run_id, status, input_text, started_at)
-        VALUES ($1, $2, 'running', $3, $4)
+        INSERT INTO agent_executions (agent_id, workflow_run_id, status, input_text, started_at, output_schema)
+        VALUES ($1, $2, 'running', $3, $4, $5)
         RETURNING *
         "#,
     )
@@ -77,6 +258,7 @@ pub async fn execute_agent(
     .bind(workflow_run.id)
     .bind(&request.input)
     .bind(Utc::now())
+    .bind(&output_schema)
     .fetch_one(&pool)
     .await?;
 
@@ -89,32 +271,172 @@ pub async fn execute_agent(
     let mut messages = Vec::new();
     let system = agent.system_prompt.clone();
 
+    // 添付がある場合は画像・ドキュメントを先に置き、入力テキストを続ける
+    let content = if attachment_blocks.is_empty() {
+        MessageContent::Text(request.input.clone())
+    } else {
+        attachment_blocks.push(ContentBlock::Text {
+            text: request.input.clone(),
+        });
+        MessageContent::Blocks(attachment_blocks)
+    };
     messages.push(LlmMessage {
         role: ROLE_USER.to_string(),
-        content: MessageContent::Text(request.input.clone()),
+        content,
     });
 
-    let llm_request = LlmRequest {
+    let mut llm_request = LlmRequest {
         model: agent.model.clone(),
         messages,
         system,
         temperature: agent.temperature,
         max_tokens: agent.max_tokens,
         tools: None,
+        prompt_cache: agent.prompt_cache,
+        thinking_budget: agent.thinking_budget_tokens,
+        output_schema,
+        sampling: SamplingParams::from_agent(&agent),
+        tool_choice: None,
     };
 
-    // 5. Call LLM
-    let llm_provider = registry.get(&provider_row.name).ok_or_else(|| {
-        AppError::LlmError(format!("Provider '{}' not registered", provider_row.name))
-    })?;
+    // 5. Call LLM（プロバイダーの枠が空くまで queued で待つ。失敗時は方針に従いフォールバック先へ）
+    let (execution_id, agent_id) = (execution.id, agent.id);
+    let acquire = move |handle: ProviderHandle, estimated_tokens| async move {
+        acquire_provider_slot(
+            db,
+            event_bus,
+            &handle,
+            estimated_tokens,
+            execution_id,
+            agent_id,
+        )
+        .await
+    };
 
     let start_time = std::time::Instant::now();
-    let llm_result = llm_provider.complete(&llm_request).await;
+    let on_delta = |delta| {
+        event_bus.publish(ExecutionEvent::AgentExecutionDelta {
+            execution_id: execution.id,
+            agent_id: agent.id,
+            delta,
+        });
+    };
+    let on_retry = |notice: RetryNotice| {
+        event_bus.publish(ExecutionEvent::AgentExecutionRetrying {
+            execution_id: execution.id,
+            agent_id: agent.id,
+            attempt: notice.attempt,
+            max_attempts: notice.max_attempts,
+            delay_ms: notice.delay.as_millis() as u64,
+            error: notice.error,
+        });
+    };
+    let on_fallback =
+        |notice: FallbackNotice| publish_fallback(event_bus, execution_id, agent_id, notice);
+
+    // スキーマに合わない出力は検証エラーを伝えて出力し直させる
+    let mut output_json = None;
+    let mut attempt = 1;
+    // 出力上限で打ち切られた部分（続きを書かせている間だけ溜める）
+    let mut partial_output = String::new();
+    let mut continuations = 0;
+    // 今回の試行が始まった位置（修正依頼の前に、続きのやり取りを1つの回答にまとめるために使う）
+    let mut attempt_start = llm_request.messages.len();
+    // 続きと修正依頼を含むすべての呼び出しの使用量
+    let mut total_usage = TokenUsage::default();
+    let llm_result = loop {
+        let call_start = std::time::Instant::now();
+        let routed = match registry
+            .complete_routed(
+                &route,
+                &llm_request,
+                &acquire,
+                &on_delta,
+                &on_retry,
+                &on_fallback,
+            )
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => break Err(e),
+        };
+        let call_duration_ms = call_start.elapsed().as_millis() as i64;
+        record_usage(
+            db,
+            execution.id,
+            &routed.candidate.provider_name,
+            &routed.response,
+            call_duration_ms,
+        )
+        .await;
+        total_usage.add(&routed.response.token_usage);
+        // 修正依頼は応答した候補から始める
+        route.retain_from(routed.candidate_index);
+        let llm_response = routed.response;
+        let answered_by = routed.candidate;
+
+        // 出力上限で打ち切られたら、設定された回数まで続きを書かせる
+        if continuation::can_continue(&llm_response) && continuations < agent.max_continuations {
+            continuations += 1;
+            event_bus.publish(ExecutionEvent::AgentExecutionProgress {
+                execution_id: execution.id,
+                agent_id: agent.id,
+                message: format!(
+                    "Output token limit reached. Continuing ({continuations}/{})",
+                    agent.max_continuations
+                ),
+            });
+            partial_output.push_str(&llm_response.content);
+            continuation::push_continuation(&mut llm_request.messages, &llm_response);
+            continue;
+        }
+        let output = std::mem::take(&mut partial_output) + &llm_response.content;
+        let truncated = continuation::is_truncated(&llm_response);
+
+        // 打ち切られた出力はスキーマ検証せずに truncated として残す
+        let Some(validator) = validator.as_ref().filter(|_| !truncated) else {
+            break Ok((llm_response, answered_by, output, truncated));
+        };
+        match structured::parse_and_validate(validator, &output) {
+            Ok(value) => {
+                output_json = Some(value);
+                break Ok((llm_response, answered_by, output, truncated));
+            }
+            Err(errors) if attempt < MAX_STRUCTURED_OUTPUT_ATTEMPTS => {
+                attempt += 1;
+                event_bus.publish(ExecutionEvent::AgentExecutionProgress {
+                    execution_id: execution.id,
+                    agent_id: agent.id,
+                    message: format!(
+                        "Output did not match output_schema. Retrying ({attempt}/{MAX_STRUCTURED_OUTPUT_ATTEMPTS}): {}",
+                        errors.join("; ")
+                    ),
+                });
+                // 続きを書かせた場合も、検証した出力全体を1つの回答として送り返す
+                llm_request.messages.truncate(attempt_start);
+                llm_request.messages.push(LlmMessage {
+                    role: ROLE_ASSISTANT.to_string(),
+                    content: MessageContent::Text(output),
+                });
+                llm_request.messages.push(LlmMessage {
+                    role: ROLE_USER.to_string(),
+                    content: MessageContent::Text(structured::repair_prompt(&errors)),
+                });
+                attempt_start = llm_request.messages.len();
+            }
+            Err(errors) => {
+                break Err(AppError::LlmError(format!(
+                    "Output did not match output_schema after {attempt} attempts: {}",
+                    errors.join("; ")
+                )))
+            }
+        }
+    };
     let duration_ms = start_time.elapsed().as_millis() as i64;
 
     match llm_result {
-        Ok(llm_response) => {
-            // 6. Save messages to DB
+        Ok((llm_response, answered_by, output, truncated)) => {
+            // 6. Save messages to DB（修正依頼のやり取りも含める）
             let mut seq = 0;
             if let Some(ref sys) = agent.system_prompt {
                 save_message(&pool, execution.id, "system", sys, seq).await?;
@@ -122,48 +444,79 @@ pub async fn execute_agent(
             }
             save_message(&pool, execution.id, "user", &request.input, seq).await?;
             seq += 1;
+            for message in &llm_request.messages[1..] {
+                save_message(
+                    &pool,
+                    execution.id,
+                    &message.role,
+                    &message.content.text(),
+                    seq,
+                )
+                .await?;
+                seq += 1;
+            }
             save_message(&pool, execution.id, "assistant", &llm_response.content, seq).await?;
 
-            // 7. Update execution as completed
-            let token_usage =
-                serde_json::to_value(&llm_response.token_usage).unwrap_or(serde_json::Value::Null);
+            // 7. Update execution as completed（呼び出しごとの使用量は記録済み。実行には合計を残す）
+            // 続きを書かせても出力上限で終わった場合は truncated にする
+            let status = if truncated {
+                STATUS_TRUNCATED
+            } else {
+                STATUS_COMPLETED
+            };
+            let token_usage = serde_json::to_value(&total_usage).unwrap_or(serde_json::Value::Null);
 
             let updated_execution = sqlx::query_as::<_, AgentExecution>(
                 r#"
                 UPDATE agent_executions
-                SET status = 'completed', output_text = $1, token_usage = $2, duration_ms = $3, completed_at = $4
+                SET status = $10, output_text = $1, token_usage = $2, duration_ms = $3, completed_at = $4,
+                    thinking_text = $6, output_json = $7, answered_provider = $8, answered_model = $9
                 WHERE id = $5
                 RETURNING *
                 "#,
             )
-            .bind(&llm_response.content)
+            .bind(&output)
             .bind(&token_usage)
             .bind(duration_ms)
             .bind(Utc::now())
             .bind(execution.id)
+            .bind(llm_response.thinking_text())
+            .bind(&output_json)
+            .bind(&answered_by.provider_name)
+            .bind(&answered_by.model)
+            .bind(status)
             .fetch_one(&pool)
             .await?;
 
-            // Update workflow_run as completed
-            sqlx::query(
-                "UPDATE workflow_runs SET status = 'completed', completed_at = $1 WHERE id = $2",
-            )
-            .bind(Utc::now())
-            .bind(workflow_run.id)
-            .execute(&pool)
-            .await?;
-
-            event_bus.publish(ExecutionEvent::AgentExecutionCompleted {
-                execution_id: execution.id,
-                agent_id: agent.id,
-                output: llm_response.content,
-                duration_ms,
-            });
+            // Update workflow_run（打ち切られた実行はワークフロー実行も truncated にする）
+            sqlx::query("UPDATE workflow_runs SET status = $1, completed_at = $2 WHERE id = $3")
+                .bind(status)
+                .bind(Utc::now())
+                .bind(workflow_run.id)
+                .execute(&pool)
+                .await?;
+
+            if truncated {
+                event_bus.publish(ExecutionEvent::AgentExecutionTruncated {
+                    execution_id: execution.id,
+                    agent_id: agent.id,
+                    output,
+                    duration_ms,
+                    continuations,
+                });
+            } else {
+                event_bus.publish(ExecutionEvent::AgentExecutionCompleted {
+                    execution_id: execution.id,
+                    agent_id: agent.id,
+                    output,
+                    duration_ms,
+                });
+            }
 
             event_bus.publish(ExecutionEvent::WorkflowRunCompleted {
                 workflow_run_id: workflow_run.id,
                 workflow_id: agent.workflow_id,
-                status: STATUS_COMPLETED.to_string(),
+                status: status.to_string(),
             });
 
             Ok(updated_execution)
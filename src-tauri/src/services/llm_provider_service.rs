@@ -0,0 +1,344 @@
+use uuid::Uuid;
+
+use crate::constants::*;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::llm::anthropic::AnthropicProvider;
+use crate::llm::cassette;
+use crate::llm::gemini::GeminiProvider;
+use crate::llm::{self, LlmRegistry};
+use crate::models::{
+    CreateLlmProviderRequest, LlmProvider, ModelCatalog, ModelPrice, ProviderStatus,
+    UpdateLlmProviderRequest,
+};
+use crate::secrets;
+
+const PROVIDER_TYPES: [&str; 4] = [
+    PROVIDER_TYPE_ANTHROPIC,
+    PROVIDER_TYPE_GOOGLE,
+    PROVIDER_TYPE_OPENAI_COMPATIBLE,
+    PROVIDER_TYPE_REPLAY,
+];
+
+/// レート制限値は正の整数（更新時の 0 はクリア）
+fn validate_limits(limits: [(&str, Option<i32>); 3], allow_zero: bool) -> Result<(), AppError> {
+    let min = if allow_zero { 0 } else { 1 };
+    for (field, value) in limits {
+        if value.is_some_and(|v| v < min) {
+            return Err(AppError::InvalidInput(format!(
+                "{field} must be at least {min}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn validate_provider_type(provider_type: &str) -> Result<(), AppError> {
+    if PROVIDER_TYPES.contains(&provider_type) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!(
+            "provider_type must be one of {PROVIDER_TYPES:?}"
+        )))
+    }
+}
+
+/// api_key_ref はボルトに保存済みの名前か、種別・送り先が既定どおりの環境変数名に限る（空文字はクリア）
+fn validate_api_key_ref(
+    api_key_ref: Option<&str>,
+    provider_type: &str,
+    api_base_url: Option<&str>,
+) -> Result<(), AppError> {
+    match api_key_ref.filter(|r| !r.is_empty()) {
+        Some(key_ref) => secrets::validate_provider_ref(key_ref, provider_type, api_base_url),
+        None => Ok(()),
+    }
+}
+
+/// 記録は replay 以外で、cassette_path がデータディレクトリの cassettes/ 配下を指すときだけ許す
+fn validate_recording(
+    provider_type: &str,
+    record_cassette: bool,
+    cassette_path: Option<&str>,
+) -> Result<(), AppError> {
+    if !record_cassette {
+        return Ok(());
+    }
+    if provider_type == PROVIDER_TYPE_REPLAY {
+        return Err(AppError::InvalidInput(
+            "replay providers cannot record a cassette".to_string(),
+        ));
+    }
+    match cassette_path.filter(|p| !p.trim().is_empty()) {
+        Some(path) => cassette::validate_recording_path(path),
+        None => Err(AppError::InvalidInput(
+            "cassette_path is required to record a cassette".to_string(),
+        )),
+    }
+}
+
+/// DB未接続時の組み込みプロバイダー（資格情報ボルト／環境変数のキーで登録）
+pub fn register_fallback_providers(registry: &LlmRegistry) {
+    match AnthropicProvider::from_env() {
+        Ok(provider) => {
+            println!("[tebiki] Anthropic provider registered.");
+            registry.register(Box::new(provider));
+        }
+        Err(e) => {
+            eprintln!("[tebiki] Anthropic provider not available: {e}");
+        }
+    }
+    match GeminiProvider::from_env() {
+        Ok(provider) => {
+            println!("[tebiki] Google AI Studio provider registered.");
+            registry.register_embedder(Box::new(provider.clone()));
+            registry.register(Box::new(provider));
+        }
+        Err(e) => {
+            eprintln!("[tebiki] Google AI Studio provider not available: {e}");
+        }
+    }
+}
+
+/// 資格情報の変更をプロバイダーに反映する。DB未接続なら組み込みプロバイダーだけ作り直す。
+pub async fn refresh_registry(db: &DbPool, registry: &LlmRegistry) -> Result<(), AppError> {
+    match reload_registry(db, registry).await {
+        Ok(_) => Ok(()),
+        Err(AppError::DatabaseUnavailable) => {
+            registry.clear();
+            register_fallback_providers(registry);
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// 有効な llm_providers の行からレジストリを再構築して差し替える。
+/// 個々の行の設定不備は全体を止めずに結果へ含める。
+pub async fn reload_registry(
+    db: &DbPool,
+    registry: &LlmRegistry,
+) -> Result<Vec<ProviderStatus>, AppError> {
+    let pool = db.get()?;
+    let rows = sqlx::query_as::<_, LlmProvider>(
+        "SELECT * FROM llm_providers WHERE is_enabled = true ORDER BY name ASC",
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let statuses = registry.replace_all(&rows);
+    for status in &statuses {
+        match (&status.error, status.available) {
+            (None, _) => println!("[tebiki] Provider '{}' registered.", status.name),
+            (Some(e), true) => eprintln!("[tebiki] Provider '{}' registered: {e}", status.name),
+            (Some(e), false) => eprintln!("[tebiki] Provider '{}' not available: {e}", status.name),
+        }
+    }
+
+    Ok(statuses)
+}
+
+pub async fn create_provider(
+    db: &DbPool,
+    registry: &LlmRegistry,
+    request: &CreateLlmProviderRequest,
+) -> Result<LlmProvider, AppError> {
+    if request.name.trim().is_empty() {
+        return Err(AppError::InvalidInput("name is required".to_string()));
+    }
+    validate_provider_type(&request.provider_type)?;
+    validate_api_key_ref(
+        request.api_key_ref.as_deref(),
+        &request.provider_type,
+        request.api_base_url.as_deref(),
+    )?;
+    validate_recording(
+        &request.provider_type,
+        request.record_cassette.unwrap_or(false),
+        request.cassette_path.as_deref(),
+    )?;
+    validate_limits(
+        [
+            ("requests_per_minute", request.requests_per_minute),
+            ("tokens_per_minute", request.tokens_per_minute),
+            ("max_concurrency", request.max_concurrency),
+        ],
+        false,
+    )?;
+
+    let pool = db.get()?;
+    let provider = sqlx::query_as::<_, LlmProvider>(
+        r#"
+        INSERT INTO llm_providers (name, display_name, provider_type, api_base_url, api_key_ref, is_enabled,
+                                   requests_per_minute, tokens_per_minute, max_concurrency, cassette_path,
+                                   record_cassette)
+        VALUES ($1, $2, $3, NULLIF($4, ''), NULLIF($5, ''), COALESCE($6, true), $7, $8, $9, NULLIF($10, ''),
+                COALESCE($11, false))
+        RETURNING *
+        "#,
+    )
+    .bind(request.name.trim())
+    .bind(&request.display_name)
+    .bind(&request.provider_type)
+    .bind(&request.api_base_url)
+    .bind(&request.api_key_ref)
+    .bind(request.is_enabled)
+    .bind(request.requests_per_minute)
+    .bind(request.tokens_per_minute)
+    .bind(request.max_concurrency)
+    .bind(&request.cassette_path)
+    .bind(request.record_cassette)
+    .fetch_one(&pool)
+    .await?;
+
+    reload_registry(db, registry).await?;
+    Ok(provider)
+}
+
+pub async fn update_provider(
+    db: &DbPool,
+    registry: &LlmRegistry,
+    id: Uuid,
+    request: &UpdateLlmProviderRequest,
+) -> Result<LlmProvider, AppError> {
+    if let Some(provider_type) = &request.provider_type {
+        validate_provider_type(provider_type)?;
+    }
+    validate_limits(
+        [
+            ("requests_per_minute", request.requests_per_minute),
+            ("tokens_per_minute", request.tokens_per_minute),
+            ("max_concurrency", request.max_concurrency),
+        ],
+        true,
+    )?;
+
+    let pool = db.get()?;
+    // 種別・送り先・記録先の一部だけを変えても制限をすり抜けないよう、更新後の組み合わせで確認する
+    if request.api_key_ref.is_some()
+        || request.provider_type.is_some()
+        || request.api_base_url.is_some()
+        || request.cassette_path.is_some()
+        || request.record_cassette.is_some()
+    {
+        let current = sqlx::query_as::<_, LlmProvider>("SELECT * FROM llm_providers WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        validate_api_key_ref(
+            request
+                .api_key_ref
+                .as_deref()
+                .or(current.api_key_ref.as_deref()),
+            request
+                .provider_type
+                .as_deref()
+                .unwrap_or(&current.provider_type),
+            request
+                .api_base_url
+                .as_deref()
+                .or(current.api_base_url.as_deref()),
+        )?;
+        validate_recording(
+            request
+                .provider_type
+                .as_deref()
+                .unwrap_or(&current.provider_type),
+            request.record_cassette.unwrap_or(current.record_cassette),
+            request
+                .cassette_path
+                .as_deref()
+                .or(current.cassette_path.as_deref()),
+        )?;
+    }
+    // 未指定(NULL)は現状維持、空文字・0 はクリア
+    let provider = sqlx::query_as::<_, LlmProvider>(
+        r#"
+        UPDATE llm_providers SET
+            display_name = COALESCE($2, display_name),
+            provider_type = COALESCE($3, provider_type),
+            api_base_url = CASE WHEN $4::TEXT IS NULL THEN api_base_url ELSE NULLIF($4, '') END,
+            api_key_ref = CASE WHEN $5::TEXT IS NULL THEN api_key_ref ELSE NULLIF($5, '') END,
+            is_enabled = COALESCE($6, is_enabled),
+            requests_per_minute = CASE WHEN $7::INT IS NULL THEN requests_per_minute ELSE NULLIF($7, 0) END,
+            tokens_per_minute = CASE WHEN $8::INT IS NULL THEN tokens_per_minute ELSE NULLIF($8, 0) END,
+            max_concurrency = CASE WHEN $9::INT IS NULL THEN max_concurrency ELSE NULLIF($9, 0) END,
+            cassette_path = CASE WHEN $10::TEXT IS NULL THEN cassette_path ELSE NULLIF($10, '') END,
+            record_cassette = COALESCE($11, record_cassette)
+        WHERE id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(id)
+    .bind(&request.display_name)
+    .bind(&request.provider_type)
+    .bind(&request.api_base_url)
+    .bind(&request.api_key_ref)
+    .bind(request.is_enabled)
+    .bind(request.requests_per_minute)
+    .bind(request.tokens_per_minute)
+    .bind(request.max_concurrency)
+    .bind(&request.cassette_path)
+    .bind(request.record_cassette)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    reload_registry(db, registry).await?;
+    Ok(provider)
+}
+
+/// 行の設定でプロバイダーを生成し、疎通確認する（レジストリは変更しない）
+pub async fn test_provider(db: &DbPool, id: Uuid) -> Result<ProviderStatus, AppError> {
+    let pool = db.get()?;
+    let row = sqlx::query_as::<_, LlmProvider>("SELECT * FROM llm_providers WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let result = match llm::provider_from_row(&row) {
+        Ok(provider) => provider.health_check().await,
+        Err(e) => Err(e),
+    };
+
+    Ok(ProviderStatus {
+        name: row.name,
+        available: result.is_ok(),
+        error: result.err().map(|e| e.to_string()),
+    })
+}
+
+/// プロバイダーのモデル一覧。単価はコスト計算と同じく、プロバイダーの種別で model_prices から引く。
+pub async fn list_models(
+    db: &DbPool,
+    registry: &LlmRegistry,
+    id: Uuid,
+) -> Result<ModelCatalog, AppError> {
+    let pool = db.get()?;
+    let row = sqlx::query_as::<_, LlmProvider>("SELECT * FROM llm_providers WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let mut catalog = registry.model_catalog(&row).await;
+    let prices =
+        sqlx::query_as::<_, ModelPrice>("SELECT * FROM model_prices WHERE provider_type = $1")
+            .bind(&row.provider_type)
+            .fetch_all(&pool)
+            .await?;
+    for model in &mut catalog.models {
+        // usage_service と同じく最長前方一致で引く
+        if let Some(price) = prices
+            .iter()
+            .filter(|p| model.id.starts_with(&p.model))
+            .max_by_key(|p| p.model.len())
+        {
+            model.input_price = Some(price.input_price);
+            model.output_price = Some(price.output_price);
+        }
+    }
+    Ok(catalog)
+}
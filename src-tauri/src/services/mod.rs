@@ -1,6 +1,11 @@
 pub mod agent_service;
 pub mod auth_service;
+pub mod credential_service;
+pub mod embedding_service;
 pub mod execution_service;
+pub mod llm_provider_service;
 pub mod orchestration;
+pub mod tool_service;
+pub mod usage_service;
 pub mod user_service;
 pub mod workflow_service;
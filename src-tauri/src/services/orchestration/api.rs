@@ -7,11 +7,15 @@ use crate::constants::*;
 use crate::db::DbPool;
 use crate::error::AppError;
 use crate::event_bus::{EventBus, ExecutionEvent};
+use crate::llm::sampling::{SamplingParams, ToolChoice};
 use crate::llm::types::{ContentBlock, LlmMessage, MessageContent};
 use crate::llm::LlmRegistry;
 use crate::models::{AgentExecution, OrchestrateRequest, OrchestrationRun};
+use crate::services::usage_service::Budget;
+use crate::services::{agent_service, tool_service};
 use crate::tools::ToolRegistry;
 
+use super::compaction::{CompactionSettings, DEFAULT_CONTEXT_TOKEN_LIMIT};
 use super::context::OrchestrationContext;
 use super::finalize::finalize_orchestration;
 use super::tool_loop::{run_tool_loop, run_tool_loop_approval};
@@ -38,32 +42,6 @@ fn validate_mode(mode: &str) -> Result<(), AppError> {
     }
 }
 
-/// DBからエージェントの有効ツールリストを取得
-async fn load_enabled_tools(pool: &sqlx::PgPool, agent_id: Uuid) -> Vec<String> {
-    sqlx::query_scalar::<_, String>(
-        "SELECT tool_name FROM agent_tool_permissions WHERE agent_id = $1 AND is_enabled = true",
-    )
-    .bind(agent_id)
-    .fetch_all(pool)
-    .await
-    .unwrap_or_default()
-}
-
-/// プロジェクトルートを取得してToolContextを構築
-fn build_tool_context() -> crate::tools::types::ToolContext {
-    let working_dir = std::env::var("TEBIKI_PROJECT_ROOT")
-        .map(std::path::PathBuf::from)
-        .unwrap_or_else(|_| {
-            std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."))
-        });
-
-    crate::tools::types::ToolContext {
-        working_dir: working_dir.clone(),
-        allowed_write_dirs: vec![working_dir],
-        ..Default::default()
-    }
-}
-
 /// メインエントリーポイント: オーケストレーション実行を作成しツールループを開始
 pub async fn orchestrate_agent(
     db: &DbPool,
@@ -74,6 +52,21 @@ pub async fn orchestrate_agent(
 ) -> Result<OrchestrationRun, AppError> {
     // Validate input before any DB work
     validate_mode(&request.mode)?;
+    let budget = Budget {
+        max_tokens: request.max_tokens_budget,
+        max_cost_usd: request.max_cost_usd,
+    };
+    budget.validate()?;
+    if request.context_token_limit.is_some_and(|v| v <= 0) {
+        return Err(AppError::InvalidInput(
+            "context_token_limit must be positive".to_string(),
+        ));
+    }
+    let compaction_model = request
+        .compaction_model
+        .as_deref()
+        .map(str::trim)
+        .filter(|m| !m.is_empty());
 
     if request.input.len() > MAX_INPUT_LENGTH {
         return Err(AppError::InvalidInput(format!(
@@ -102,8 +95,16 @@ pub async fn orchestrate_agent(
     .await?
     .ok_or(AppError::NotFound)?;
 
-    // エージェントの有効ツールを取得
-    let enabled_tools = load_enabled_tools(&pool, agent.id).await;
+    // エージェントの有効ツールと、ツール設定を反映したコンテキストを取得
+    let permissions = tool_service::load_permissions(&pool, agent.id).await;
+    let tool_context = tool_service::build_tool_context(tool_registry, &permissions)?;
+    let enabled_tools = permissions
+        .into_iter()
+        .filter(|p| p.is_enabled)
+        .map(|p| p.tool_name)
+        .collect();
+    let route = agent_service::load_model_route(&pool, &agent, &provider_row.name).await?;
+    let tool_choice = ToolChoice::from_value(agent.tool_choice.as_ref())?;
 
     // 2. トランザクション内でworkflow_run、agent_execution、orchestration_runを作成
     let mut tx = pool.begin().await.map_err(|e| AppError::Database(e))?;
@@ -136,8 +137,10 @@ pub async fn orchestrate_agent(
 
     let orchestration_run = sqlx::query_as::<_, OrchestrationRun>(
         r#"
-        INSERT INTO orchestration_runs (orchestrator_agent_id, workflow_run_id, execution_id, mode, status)
-        VALUES ($1, $2, $3, $4, 'running')
+        INSERT INTO orchestration_runs (orchestrator_agent_id, workflow_run_id, execution_id, mode, status,
+                                        budget_max_tokens, budget_max_cost_usd,
+                                        context_token_limit, compaction_model)
+        VALUES ($1, $2, $3, $4, 'running', $5, $6, $7, $8)
         RETURNING *
         "#,
     )
@@ -145,6 +148,10 @@ pub async fn orchestrate_agent(
     .bind(workflow_run.id)
     .bind(execution.id)
     .bind(&request.mode)
+    .bind(budget.max_tokens)
+    .bind(budget.max_cost_usd)
+    .bind(request.context_token_limit)
+    .bind(compaction_model)
     .fetch_one(&mut *tx)
     .await?;
 
@@ -177,15 +184,34 @@ pub async fn orchestrate_agent(
         execution_id: execution.id,
         orchestrator_agent_id: agent.id,
         workflow_id: agent.workflow_id,
-        llm_provider_id: agent.llm_provider_id,
         provider_name: provider_row.name.clone(),
         model: agent.model.clone(),
+        route,
+        sub_agent_llm_provider_id: agent
+            .sub_agent_llm_provider_id
+            .unwrap_or(agent.llm_provider_id),
+        sub_agent_model: agent
+            .sub_agent_model
+            .clone()
+            .unwrap_or_else(|| agent.model.clone()),
         system_prompt: Some(orchestrator_system),
         temperature: agent.temperature,
         max_tokens: agent.max_tokens,
+        prompt_cache: agent.prompt_cache,
+        thinking_budget: agent.thinking_budget_tokens,
+        sampling: SamplingParams::from_agent(&agent),
+        tool_choice,
+        max_continuations: agent.max_continuations,
+        budget,
+        compaction: CompactionSettings {
+            token_limit: orchestration_run
+                .context_token_limit
+                .unwrap_or(DEFAULT_CONTEXT_TOKEN_LIMIT),
+            model: orchestration_run.compaction_model.clone(),
+        },
         tool_registry: tool_registry.clone(),
         enabled_tools,
-        tool_context: build_tool_context(),
+        tool_context,
     };
 
     let mode = request.mode.clone();
@@ -285,8 +311,16 @@ pub async fn approve_orchestration(
     .fetch_one(&pool)
     .await?;
 
-    // エージェントの有効ツールを取得
-    let enabled_tools = load_enabled_tools(&pool, agent.id).await;
+    // エージェントの有効ツールと、ツール設定を反映したコンテキストを取得
+    let permissions = tool_service::load_permissions(&pool, agent.id).await;
+    let tool_context = tool_service::build_tool_context(tool_registry, &permissions)?;
+    let enabled_tools = permissions
+        .into_iter()
+        .filter(|p| p.is_enabled)
+        .map(|p| p.tool_name)
+        .collect();
+    let route = agent_service::load_model_route(&pool, &agent, &provider_row.name).await?;
+    let tool_choice = ToolChoice::from_value(agent.tool_choice.as_ref())?;
 
     let orchestrator_system = build_orchestrator_system(agent.system_prompt.as_deref());
 
@@ -298,15 +332,37 @@ pub async fn approve_orchestration(
         execution_id: orch_run.execution_id,
         orchestrator_agent_id: orch_run.orchestrator_agent_id,
         workflow_id: agent.workflow_id,
-        llm_provider_id: agent.llm_provider_id,
         provider_name: provider_row.name.clone(),
         model: agent.model.clone(),
+        route,
+        sub_agent_llm_provider_id: agent
+            .sub_agent_llm_provider_id
+            .unwrap_or(agent.llm_provider_id),
+        sub_agent_model: agent
+            .sub_agent_model
+            .clone()
+            .unwrap_or_else(|| agent.model.clone()),
         system_prompt: Some(orchestrator_system),
         temperature: agent.temperature,
         max_tokens: agent.max_tokens,
+        prompt_cache: agent.prompt_cache,
+        thinking_budget: agent.thinking_budget_tokens,
+        sampling: SamplingParams::from_agent(&agent),
+        tool_choice,
+        max_continuations: agent.max_continuations,
+        budget: Budget {
+            max_tokens: orch_run.budget_max_tokens,
+            max_cost_usd: orch_run.budget_max_cost_usd,
+        },
+        compaction: CompactionSettings {
+            token_limit: orch_run
+                .context_token_limit
+                .unwrap_or(DEFAULT_CONTEXT_TOKEN_LIMIT),
+            model: orch_run.compaction_model.clone(),
+        },
         tool_registry: tool_registry.clone(),
         enabled_tools,
-        tool_context: build_tool_context(),
+        tool_context,
     };
 
     // 再開: 最後のアシスタントメッセージからtool_useブロックを抽出し、実行後ループを継続
@@ -0,0 +1,316 @@
+use chrono::Utc;
+
+use crate::constants::*;
+use crate::llm::limiter::estimate_message_chars;
+use crate::llm::routing::{ModelCandidate, ModelRoute};
+use crate::llm::sampling::SamplingParams;
+use crate::llm::types::{ContentBlock, LlmMessage, LlmRequest, MessageContent, ToolDefinition};
+use crate::llm::ProviderHandle;
+use crate::services::usage_service;
+
+use super::context::OrchestrationContext;
+
+/// context_token_limit 未指定時の入力トークン上限（概算）
+pub(super) const DEFAULT_CONTEXT_TOKEN_LIMIT: i32 = 100_000;
+
+/// 圧縮せずに残す直近のメッセージ数（最後の tool_use と tool_result の組を含む）
+const KEEP_RECENT_MESSAGES: usize = 4;
+/// この文字数を超える古いツール結果を切り詰める
+const COMPACT_RESULT_THRESHOLD: usize = 2_000;
+/// 切り詰めたツール結果に残す先頭の文字数
+const TRUNCATED_RESULT_CHARS: usize = 1_000;
+/// 要約用の書き起こしに入れるツール入出力1件あたりの最大文字数
+const SUMMARY_ITEM_CHARS: usize = 2_000;
+const SUMMARY_MAX_TOKENS: i32 = 1_024;
+const SUMMARY_SYSTEM_PROMPT: &str = "You compress the history of an agent's tool loop. Summarize the transcript below: what was attempted, which tools were called, the key results and facts that later steps may need (names, ids, paths, numbers), and what remains unresolved. Be concise and factual. Do not add advice.";
+
+/// 会話履歴の圧縮設定
+pub(super) struct CompactionSettings {
+    /// 入力トークン（system・ツール定義・履歴の概算）の上限
+    pub token_limit: i32,
+    /// 古いやり取りの要約に使うモデル（同じプロバイダー。失敗時はルートの候補へ切り替える）。
+    /// None なら要約せずに取り除く。
+    pub model: Option<String>,
+}
+
+/// system・ツール定義・履歴の入力トークン数の概算（4文字≒1トークン）
+fn estimate_context_tokens(
+    ctx: &OrchestrationContext,
+    messages: &[LlmMessage],
+    tools: &[ToolDefinition],
+) -> usize {
+    let system = ctx.system_prompt.as_ref().map_or(0, |s| s.len());
+    let tools = serde_json::to_string(tools).map_or(0, |s| s.len());
+    let history: usize = messages.iter().map(estimate_message_chars).sum();
+    (system + tools + history) / 4
+}
+
+/// 文字境界を保って先頭 max_chars 文字に切り詰める（短ければ None）
+fn truncate_chars(text: &str, max_chars: usize) -> Option<&str> {
+    text.char_indices()
+        .nth(max_chars)
+        .map(|(index, _)| &text[..index])
+}
+
+/// 古いツール結果の本文を切り詰める。tool_use_id は残すので tool_use との対応は崩れない。
+fn truncate_tool_results(messages: &mut [LlmMessage]) -> usize {
+    let mut truncated = 0;
+    for message in messages {
+        let MessageContent::Blocks(blocks) = &mut message.content else {
+            continue;
+        };
+        for block in blocks {
+            let ContentBlock::ToolResult { content, .. } = block else {
+                continue;
+            };
+            if content.chars().count() <= COMPACT_RESULT_THRESHOLD {
+                continue;
+            }
+            if let Some(head) = truncate_chars(content, TRUNCATED_RESULT_CHARS) {
+                let removed = content.len() - head.len();
+                *content = format!("{head}\n...[{removed} bytes removed by context compaction]");
+                truncated += 1;
+            }
+        }
+    }
+    truncated
+}
+
+/// 取り除いても tool_use と tool_result の組が崩れない境界（アシスタントの発話の先頭）を探す。
+/// 最初のユーザーメッセージ（タスク）と直近のメッセージは残す。
+fn removable_prefix_end(messages: &[LlmMessage]) -> Option<usize> {
+    let limit = messages.len().checked_sub(KEEP_RECENT_MESSAGES)?;
+    (2..=limit)
+        .rev()
+        .find(|&i| messages[i].role == ROLE_ASSISTANT && messages[i - 1].role == ROLE_USER)
+}
+
+/// 要約モデルに渡す書き起こし
+fn render_transcript(messages: &[LlmMessage]) -> String {
+    let clip = |text: &str| match truncate_chars(text, SUMMARY_ITEM_CHARS) {
+        Some(head) => format!("{head}...[truncated]"),
+        None => text.to_string(),
+    };
+    let mut lines = Vec::new();
+    for message in messages {
+        match &message.content {
+            MessageContent::Text(text) => lines.push(format!("[{}] {}", message.role, clip(text))),
+            MessageContent::Blocks(blocks) => {
+                for block in blocks {
+                    match block {
+                        ContentBlock::Text { text } => {
+                            lines.push(format!("[{}] {}", message.role, clip(text)))
+                        }
+                        ContentBlock::ToolUse { name, input, .. } => {
+                            lines.push(format!("[tool call] {name} {}", clip(&input.to_string())))
+                        }
+                        ContentBlock::ToolResult {
+                            content, is_error, ..
+                        } => lines.push(format!(
+                            "[tool {}] {}",
+                            if *is_error { "error" } else { "result" },
+                            clip(content)
+                        )),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// 最初のユーザーメッセージ（タスク）の後ろにテキストを足す
+fn append_to_task(messages: &mut [LlmMessage], note: String) {
+    let Some(first) = messages.first_mut() else {
+        return;
+    };
+    let mut blocks = match std::mem::replace(&mut first.content, MessageContent::Blocks(vec![])) {
+        MessageContent::Text(text) => vec![ContentBlock::Text { text }],
+        MessageContent::Blocks(blocks) => blocks,
+    };
+    blocks.push(ContentBlock::Text { text: note });
+    first.content = MessageContent::Blocks(blocks);
+}
+
+/// 要約の呼び出し先。同じプロバイダーの要約モデルを先頭に、失敗時はエージェントのルートと同じ方針で
+/// その候補へ切り替える
+fn summary_route(ctx: &OrchestrationContext, model: &str) -> ModelRoute {
+    let mut candidates = vec![ModelCandidate {
+        provider_name: ctx.provider_name.clone(),
+        model: model.to_string(),
+    }];
+    candidates.extend(ctx.route.candidates.iter().cloned());
+    ModelRoute {
+        candidates,
+        policy: ctx.route.policy,
+    }
+}
+
+/// 安価なモデルで古いやり取りを要約し、要約と応答したモデルを返す（失敗時は None）
+async fn summarize(
+    ctx: &OrchestrationContext,
+    model: &str,
+    removed: &[LlmMessage],
+) -> Option<(String, String)> {
+    let request = LlmRequest {
+        model: model.to_string(),
+        messages: vec![LlmMessage {
+            role: ROLE_USER.to_string(),
+            content: MessageContent::Text(render_transcript(removed)),
+        }],
+        system: Some(SUMMARY_SYSTEM_PROMPT.to_string()),
+        temperature: 0.0,
+        max_tokens: SUMMARY_MAX_TOKENS,
+        tools: None,
+        prompt_cache: false,
+        thinking_budget: None,
+        output_schema: None,
+        sampling: SamplingParams::default(),
+        tool_choice: None,
+    };
+
+    let acquire = |handle: ProviderHandle, estimated_tokens| async move {
+        handle.limiter.acquire(estimated_tokens).await
+    };
+    let start_time = std::time::Instant::now();
+    let routed = match ctx
+        .registry
+        .complete_routed(
+            &summary_route(ctx, model),
+            &request,
+            acquire,
+            &|_| {},
+            &|_| {},
+            &|_| {},
+        )
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!(
+                "[orchestration] Failed to summarize history for {}: {e}",
+                ctx.orchestration_run_id
+            );
+            return None;
+        }
+    };
+    let duration_ms = start_time.elapsed().as_millis() as i64;
+    let response = routed.response;
+
+    if let Err(e) = usage_service::record_llm_call(
+        &ctx.db,
+        ctx.execution_id,
+        Some(ctx.orchestration_run_id),
+        &routed.candidate.provider_name,
+        &response.model,
+        &response.token_usage,
+        duration_ms,
+    )
+    .await
+    {
+        eprintln!(
+            "[orchestration] Failed to record usage for orchestration {}: {e}",
+            ctx.orchestration_run_id
+        );
+    }
+    Some(response.content)
+        .filter(|s| !s.trim().is_empty())
+        .map(|summary| (summary, routed.candidate.model))
+}
+
+/// 圧縮内容を orchestration_runs.compaction_log に追記する
+async fn record_compaction(ctx: &OrchestrationContext, entry: serde_json::Value) {
+    let result = match ctx.db.get() {
+        Ok(pool) => sqlx::query(
+            r#"
+            UPDATE orchestration_runs
+            SET compaction_log = compaction_log || $1, updated_at = $2
+            WHERE id = $3
+            "#,
+        )
+        .bind(serde_json::json!([entry]))
+        .bind(Utc::now())
+        .bind(ctx.orchestration_run_id)
+        .execute(&pool)
+        .await
+        .map(|_| ()),
+        Err(e) => {
+            eprintln!("[orchestration] Failed to get DB pool for compaction log: {e}");
+            return;
+        }
+    };
+    if let Err(e) = result {
+        eprintln!(
+            "[orchestration] Failed to record compaction for {}: {e}",
+            ctx.orchestration_run_id
+        );
+    }
+}
+
+/// 履歴が上限を超えていれば圧縮する。
+/// まず古いツール結果を切り詰め、それでも超える場合は古いやり取りを要約（モデル未指定なら削除）して
+/// タスクのメッセージに添える。
+pub(super) async fn compact_history(
+    ctx: &OrchestrationContext,
+    messages: &mut Vec<LlmMessage>,
+    tools: &[ToolDefinition],
+    iteration: u32,
+) {
+    let limit = ctx.compaction.token_limit.max(0) as usize;
+    let tokens_before = estimate_context_tokens(ctx, messages, tools);
+    if tokens_before <= limit {
+        return;
+    }
+
+    let recent_start = messages.len().saturating_sub(KEEP_RECENT_MESSAGES).max(1);
+    let truncated_results = if recent_start > 1 {
+        truncate_tool_results(&mut messages[1..recent_start])
+    } else {
+        0
+    };
+
+    let mut removed_messages = 0;
+    let mut summary_model = None;
+    if estimate_context_tokens(ctx, messages, tools) > limit {
+        if let Some(end) = removable_prefix_end(messages) {
+            let removed: Vec<LlmMessage> = messages.drain(1..end).collect();
+            removed_messages = removed.len();
+            let summary = match &ctx.compaction.model {
+                Some(model) => summarize(ctx, model, &removed).await,
+                None => None,
+            };
+            let note = match summary {
+                Some((summary, model)) => {
+                    summary_model = Some(model);
+                    format!(
+                    "[Context compaction: {removed_messages} earlier messages were replaced by this summary]\n{summary}"
+                )
+                }
+                None => format!(
+                    "[Context compaction: {removed_messages} earlier messages were removed to fit the context window]"
+                ),
+            };
+            append_to_task(messages, note);
+        }
+    }
+
+    if truncated_results == 0 && removed_messages == 0 {
+        return;
+    }
+    let tokens_after = estimate_context_tokens(ctx, messages, tools);
+    record_compaction(
+        ctx,
+        serde_json::json!({
+            "iteration": iteration,
+            "at": Utc::now(),
+            "tokens_before": tokens_before,
+            "tokens_after": tokens_after,
+            "truncated_tool_results": truncated_results,
+            "removed_messages": removed_messages,
+            "summarized": summary_model.is_some(),
+            "summary_model": summary_model,
+        }),
+    )
+    .await;
+}
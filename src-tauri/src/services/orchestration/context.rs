@@ -4,7 +4,12 @@ use uuid::Uuid;
 
 use crate::db::DbPool;
 use crate::event_bus::EventBus;
+use crate::llm::routing::ModelRoute;
+use crate::llm::sampling::{SamplingParams, ToolChoice};
 use crate::llm::LlmRegistry;
+use crate::services::usage_service::Budget;
+
+use super::compaction::CompactionSettings;
 use crate::tools::ToolRegistry;
 
 /// オーケストレーション実行全体で共有する状態をまとめた構造体。
@@ -17,12 +22,30 @@ pub(super) struct OrchestrationContext {
     pub execution_id: Uuid,
     pub orchestrator_agent_id: Uuid,
     pub workflow_id: Uuid,
-    pub llm_provider_id: Uuid,
     pub provider_name: String,
     pub model: String,
+    /// オーケストレーター自身の呼び出し候補（フォールバック先を含む）
+    pub route: ModelRoute,
+    /// 作成するサブエージェントに割り当てるプロバイダーとモデル
+    pub sub_agent_llm_provider_id: Uuid,
+    pub sub_agent_model: String,
     pub system_prompt: Option<String>,
     pub temperature: f64,
     pub max_tokens: i32,
+    /// オーケストレーターと作成するサブエージェントでプロンプトキャッシュを使う
+    pub prompt_cache: bool,
+    /// オーケストレーター自身の拡張思考の予算（サブエージェントには引き継がない）
+    pub thinking_budget: Option<i32>,
+    /// オーケストレーター自身のサンプリング設定（サブエージェントには引き継がない）
+    pub sampling: SamplingParams,
+    /// ツール使用の指定。ツールを強制する指定は最初のツール呼び出しまでに限る。
+    pub tool_choice: Option<ToolChoice>,
+    /// 出力上限で打ち切られた回答の続きを書かせる回数
+    pub max_continuations: i32,
+    /// サブエージェント分を含む実行全体の予算
+    pub budget: Budget,
+    /// 長いツールループでの会話履歴の圧縮設定
+    pub compaction: CompactionSettings,
     // Tool system fields
     pub tool_registry: Arc<ToolRegistry>,
     pub enabled_tools: Vec<String>,
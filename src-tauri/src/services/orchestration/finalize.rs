@@ -44,7 +44,8 @@ pub(super) async fn finalize_orchestration(
     // agent_executionを更新
     let exec_status = match status {
         STATUS_COMPLETED => STATUS_COMPLETED,
-        STATUS_FAILED | STATUS_REJECTED => STATUS_FAILED,
+        STATUS_TRUNCATED => STATUS_TRUNCATED,
+        STATUS_FAILED | STATUS_REJECTED | STATUS_BUDGET_EXCEEDED => STATUS_FAILED,
         _ => return,
     };
 
@@ -69,6 +70,8 @@ pub(super) async fn finalize_orchestration(
     // orchestration_runのworkflow_run_id経由でworkflow_runを更新
     let wf_status = match status {
         STATUS_COMPLETED => STATUS_COMPLETED,
+        // 途中までの回答は残るが、完了とは区別する
+        STATUS_TRUNCATED => STATUS_TRUNCATED,
         _ => STATUS_FAILED,
     };
 
@@ -1,4 +1,5 @@
 mod api;
+mod compaction;
 mod context;
 mod finalize;
 mod tool_loop;
@@ -1,9 +1,22 @@
 use chrono::Utc;
 
 use crate::constants::*;
+use crate::error::AppError;
 use crate::event_bus::ExecutionEvent;
-use crate::llm::types::{ContentBlock, LlmMessage, LlmRequest, MessageContent};
+use crate::llm::continuation;
+use crate::llm::retry::RetryNotice;
+use crate::llm::routing::{ModelRoute, RoutedResponse};
+use crate::llm::sampling::ToolChoice;
+use crate::llm::types::{
+    ContentBlock, LlmMessage, LlmRequest, LlmResponse, MessageContent, StreamDelta,
+};
+use crate::llm::ProviderHandle;
+use crate::services::execution_service::{
+    acquire_provider_slot, append_thinking_text, publish_fallback, set_answered_by,
+};
+use crate::services::usage_service::{self, UsageTotals};
 
+use super::compaction::compact_history;
 use super::context::OrchestrationContext;
 use super::finalize::finalize_orchestration;
 use super::tools::{orchestrator_tools, process_tool_calls};
@@ -11,31 +24,185 @@ use super::tools::{orchestrator_tools, process_tool_calls};
 /// 暴走API呼び出しを防ぐためのツールループ最大反復回数
 const MAX_TOOL_LOOP_ITERATIONS: u32 = 20;
 
-/// オーケストレーターのツールループを実行する
-pub(super) async fn run_tool_loop(ctx: &OrchestrationContext, mut messages: Vec<LlmMessage>) {
-    let tools = orchestrator_tools(ctx);
+/// このターンに使うツール指定。ツールを強制し続けると終了できないため、
+/// 強制する指定はオーケストレーターが一度ツールを呼ぶまでに限る。
+fn tool_choice_for(ctx: &OrchestrationContext, messages: &[LlmMessage]) -> Option<ToolChoice> {
+    let choice = ctx.tool_choice.clone()?;
+    let called_tool = messages.iter().any(|m| {
+        matches!(&m.content, MessageContent::Blocks(blocks)
+            if blocks.iter().any(|b| matches!(b, ContentBlock::ToolUse { .. })))
+    });
+    if called_tool && choice.forces_tool() {
+        None
+    } else {
+        Some(choice)
+    }
+}
 
-    let llm_provider = match ctx.registry.get(&ctx.provider_name) {
-        Some(p) => p,
-        None => {
-            let error = format!("Provider '{}' not registered", ctx.provider_name);
-            finalize_orchestration(
-                &ctx.db,
-                ctx.orchestration_run_id,
-                ctx.execution_id,
-                STATUS_FAILED,
-                None,
-                Some(&error),
-            )
-            .await;
-            ctx.event_bus.publish(ExecutionEvent::OrchestratorFailed {
-                orchestration_run_id: ctx.orchestration_run_id,
-                orchestrator_agent_id: ctx.orchestrator_agent_id,
-                error,
-            });
-            return;
+/// オーケストレーターのストリーミング増分をEventBusに流す
+fn publish_delta(ctx: &OrchestrationContext, delta: StreamDelta) {
+    ctx.event_bus.publish(ExecutionEvent::AgentExecutionDelta {
+        execution_id: ctx.execution_id,
+        agent_id: ctx.orchestrator_agent_id,
+        delta,
+    });
+}
+
+/// LLM呼び出しの再試行をEventBusに流す
+fn publish_retry(ctx: &OrchestrationContext, notice: RetryNotice) {
+    ctx.event_bus.publish(ExecutionEvent::AgentExecutionRetrying {
+        execution_id: ctx.execution_id,
+        agent_id: ctx.orchestrator_agent_id,
+        attempt: notice.attempt,
+        max_attempts: notice.max_attempts,
+        delay_ms: notice.delay.as_millis() as u64,
+        error: notice.error,
+    });
+}
+
+/// ルートに従ってLLMを呼び出し、応答したプロバイダーとモデルを実行に記録する。
+/// 枠はLLM呼び出しの間だけ保持する（サブエージェント実行中に同じプロバイダーの枠を塞がない）。
+async fn call_llm(
+    ctx: &OrchestrationContext,
+    route: &ModelRoute,
+    request: &LlmRequest,
+) -> Result<RoutedResponse, AppError> {
+    let acquire = |handle: ProviderHandle, estimated_tokens| async move {
+        acquire_provider_slot(
+            &ctx.db,
+            &ctx.event_bus,
+            &handle,
+            estimated_tokens,
+            ctx.execution_id,
+            ctx.orchestrator_agent_id,
+        )
+        .await
+    };
+    let routed = ctx
+        .registry
+        .complete_routed(
+            route,
+            request,
+            acquire,
+            &|delta| publish_delta(ctx, delta),
+            &|notice| publish_retry(ctx, notice),
+            &|notice| {
+                publish_fallback(
+                    &ctx.event_bus,
+                    ctx.execution_id,
+                    ctx.orchestrator_agent_id,
+                    notice,
+                )
+            },
+        )
+        .await?;
+    set_answered_by(
+        &ctx.db,
+        ctx.execution_id,
+        &routed.candidate.provider_name,
+        &routed.candidate.model,
+    )
+    .await;
+    Ok(routed)
+}
+
+/// LLM呼び出しの使用量を記録し、オーケストレーション実行全体の累計を返す（記録失敗時は None）
+async fn record_usage(
+    ctx: &OrchestrationContext,
+    provider_name: &str,
+    response: &LlmResponse,
+    duration_ms: i64,
+) -> Option<UsageTotals> {
+    match usage_service::record_llm_call(
+        &ctx.db,
+        ctx.execution_id,
+        Some(ctx.orchestration_run_id),
+        provider_name,
+        &response.model,
+        &response.token_usage,
+        duration_ms,
+    )
+    .await
+    {
+        Ok(totals) => Some(totals),
+        Err(e) => {
+            eprintln!(
+                "[orchestration] Failed to record usage for orchestration {}: {e}",
+                ctx.orchestration_run_id
+            );
+            None
         }
+    }
+}
+
+/// 拡張思考があれば output_text とは別に実行へ追記する
+async fn save_thinking(ctx: &OrchestrationContext, response: &LlmResponse) {
+    if let Some(thinking) = response.thinking_text() {
+        append_thinking_text(&ctx.db, ctx.execution_id, &thinking).await;
+    }
+}
+
+/// 予算を超過していれば budget_exceeded で終了処理し true を返す
+async fn stop_if_over_budget(ctx: &OrchestrationContext, totals: Option<UsageTotals>) -> bool {
+    let Some(error) = totals.and_then(|t| ctx.budget.exceeded(&t)) else {
+        return false;
     };
+    finalize_orchestration(
+        &ctx.db,
+        ctx.orchestration_run_id,
+        ctx.execution_id,
+        STATUS_BUDGET_EXCEEDED,
+        None,
+        Some(&error),
+    )
+    .await;
+    ctx.event_bus.publish(ExecutionEvent::OrchestratorFailed {
+        orchestration_run_id: ctx.orchestration_run_id,
+        orchestrator_agent_id: ctx.orchestrator_agent_id,
+        error,
+    });
+    true
+}
+
+/// 続きを書かせていることを通知する
+fn publish_continuation(ctx: &OrchestrationContext, continuations: i32) {
+    ctx.event_bus.publish(ExecutionEvent::AgentExecutionProgress {
+        execution_id: ctx.execution_id,
+        agent_id: ctx.orchestrator_agent_id,
+        message: format!(
+            "Output token limit reached. Continuing ({continuations}/{})",
+            ctx.max_continuations
+        ),
+    });
+}
+
+/// 続きを書かせても出力上限で終わった回答を truncated として残す
+async fn finish_truncated(ctx: &OrchestrationContext, output: String, continuations: i32) {
+    finalize_orchestration(
+        &ctx.db,
+        ctx.orchestration_run_id,
+        ctx.execution_id,
+        STATUS_TRUNCATED,
+        Some(&output),
+        None,
+    )
+    .await;
+    ctx.event_bus.publish(ExecutionEvent::OrchestratorTruncated {
+        orchestration_run_id: ctx.orchestration_run_id,
+        orchestrator_agent_id: ctx.orchestrator_agent_id,
+        output,
+        continuations,
+    });
+}
+
+/// オーケストレーターのツールループを実行する
+pub(super) async fn run_tool_loop(ctx: &OrchestrationContext, mut messages: Vec<LlmMessage>) {
+    let tools = orchestrator_tools(ctx);
+    // フォールバックした後は、応答した候補から呼び出しを始める
+    let mut route = ctx.route.clone();
+    // 出力上限で打ち切られた最終回答（続きを書かせている間だけ溜める）
+    let mut partial_output = String::new();
+    let mut continuations = 0;
 
     let mut iteration: u32 = 0;
 
@@ -62,6 +229,17 @@ pub(super) async fn run_tool_loop(ctx: &OrchestrationContext, mut messages: Vec<
             return;
         }
 
+        // サブエージェントの使用量も含めて、次の呼び出し前に予算を確認する
+        let totals = usage_service::get_orchestration_totals(&ctx.db, ctx.orchestration_run_id)
+            .await
+            .ok();
+        if stop_if_over_budget(ctx, totals).await {
+            return;
+        }
+
+        // 履歴がコンテキストの上限を超えそうなら古いツール結果から圧縮する
+        compact_history(ctx, &mut messages, &tools, iteration).await;
+
         let llm_request = LlmRequest {
             model: ctx.model.clone(),
             messages: messages.clone(),
@@ -69,9 +247,18 @@ pub(super) async fn run_tool_loop(ctx: &OrchestrationContext, mut messages: Vec<
             temperature: ctx.temperature,
             max_tokens: ctx.max_tokens,
             tools: Some(tools.clone()),
+            prompt_cache: ctx.prompt_cache,
+            thinking_budget: ctx.thinking_budget,
+            output_schema: None,
+            sampling: ctx.sampling.clone(),
+            tool_choice: tool_choice_for(ctx, &messages),
         };
 
-        let llm_response = match llm_provider.complete(&llm_request).await {
+        let start_time = std::time::Instant::now();
+        let llm_result = call_llm(ctx, &route, &llm_request).await;
+        let duration_ms = start_time.elapsed().as_millis() as i64;
+
+        let routed = match llm_result {
             Ok(r) => r,
             Err(e) => {
                 let error = e.to_string();
@@ -93,10 +280,35 @@ pub(super) async fn run_tool_loop(ctx: &OrchestrationContext, mut messages: Vec<
             }
         };
 
+        route.retain_from(routed.candidate_index);
+        let llm_response = routed.response;
+        let totals = record_usage(
+            ctx,
+            &routed.candidate.provider_name,
+            &llm_response,
+            duration_ms,
+        )
+        .await;
+        save_thinking(ctx, &llm_response).await;
+
+        // 出力上限で打ち切られたら、設定された回数まで続きを書かせる
+        if continuation::can_continue(&llm_response) && continuations < ctx.max_continuations {
+            continuations += 1;
+            publish_continuation(ctx, continuations);
+            partial_output.push_str(&llm_response.content);
+            continuation::push_continuation(&mut messages, &llm_response);
+            continue;
+        }
+        if continuation::is_truncated(&llm_response) {
+            let output = std::mem::take(&mut partial_output) + &llm_response.content;
+            finish_truncated(ctx, output, continuations).await;
+            return;
+        }
+
         let stop_reason = llm_response.stop_reason.as_deref().unwrap_or(STOP_END_TURN);
 
         if stop_reason == STOP_END_TURN || stop_reason == STOP_STOP {
-            let output = llm_response.content.clone();
+            let output = std::mem::take(&mut partial_output) + &llm_response.content;
             finalize_orchestration(
                 &ctx.db,
                 ctx.orchestration_run_id,
@@ -115,6 +327,14 @@ pub(super) async fn run_tool_loop(ctx: &OrchestrationContext, mut messages: Vec<
         }
 
         if stop_reason == STOP_TOOL_USE {
+            // 予算超過ならツール（サブエージェント）を実行せずに止める
+            if stop_if_over_budget(ctx, totals).await {
+                return;
+            }
+
+            // 続きの途中で呼ばれたツールの前の文章は履歴に残っているので最終回答には含めない
+            partial_output.clear();
+
             // tool_useブロック付きのアシスタントメッセージを追加
             messages.push(LlmMessage {
                 role: ROLE_ASSISTANT.to_string(),
@@ -142,7 +362,7 @@ pub(super) async fn run_tool_loop(ctx: &OrchestrationContext, mut messages: Vec<
         }
 
         // 不明な停止理由 - 完了として処理
-        let output = llm_response.content.clone();
+        let output = partial_output + &llm_response.content;
         finalize_orchestration(
             &ctx.db,
             ctx.orchestration_run_id,
@@ -168,65 +388,82 @@ pub(super) async fn run_tool_loop_approval(
     mut messages: Vec<LlmMessage>,
 ) {
     let tools = orchestrator_tools(ctx);
+    let mut route = ctx.route.clone();
+    let mut partial_output = String::new();
+    let mut continuations = 0;
 
-    let llm_provider = match ctx.registry.get(&ctx.provider_name) {
-        Some(p) => p,
-        None => {
-            let error = format!("Provider '{}' not registered", ctx.provider_name);
-            finalize_orchestration(
-                &ctx.db,
-                ctx.orchestration_run_id,
-                ctx.execution_id,
-                STATUS_FAILED,
-                None,
-                Some(&error),
-            )
-            .await;
-            ctx.event_bus.publish(ExecutionEvent::OrchestratorFailed {
-                orchestration_run_id: ctx.orchestration_run_id,
-                orchestrator_agent_id: ctx.orchestrator_agent_id,
-                error,
-            });
-            return;
-        }
-    };
+    // 最初のLLM呼び出し（出力上限で打ち切られたら続きを書かせる）
+    let (llm_response, totals) = loop {
+        let llm_request = LlmRequest {
+            model: ctx.model.clone(),
+            messages: messages.clone(),
+            system: ctx.system_prompt.clone(),
+            temperature: ctx.temperature,
+            max_tokens: ctx.max_tokens,
+            tools: Some(tools.clone()),
+            prompt_cache: ctx.prompt_cache,
+            thinking_budget: ctx.thinking_budget,
+            output_schema: None,
+            sampling: ctx.sampling.clone(),
+            tool_choice: tool_choice_for(ctx, &messages),
+        };
 
-    // 最初のLLM呼び出し
-    let llm_request = LlmRequest {
-        model: ctx.model.clone(),
-        messages: messages.clone(),
-        system: ctx.system_prompt.clone(),
-        temperature: ctx.temperature,
-        max_tokens: ctx.max_tokens,
-        tools: Some(tools.clone()),
-    };
+        let start_time = std::time::Instant::now();
+        let llm_result = call_llm(ctx, &route, &llm_request).await;
+        let duration_ms = start_time.elapsed().as_millis() as i64;
 
-    let llm_response = match llm_provider.complete(&llm_request).await {
-        Ok(r) => r,
-        Err(e) => {
-            let error = e.to_string();
-            finalize_orchestration(
-                &ctx.db,
-                ctx.orchestration_run_id,
-                ctx.execution_id,
-                STATUS_FAILED,
-                None,
-                Some(&error),
-            )
-            .await;
-            ctx.event_bus.publish(ExecutionEvent::OrchestratorFailed {
-                orchestration_run_id: ctx.orchestration_run_id,
-                orchestrator_agent_id: ctx.orchestrator_agent_id,
-                error,
-            });
-            return;
+        let routed = match llm_result {
+            Ok(r) => r,
+            Err(e) => {
+                let error = e.to_string();
+                finalize_orchestration(
+                    &ctx.db,
+                    ctx.orchestration_run_id,
+                    ctx.execution_id,
+                    STATUS_FAILED,
+                    None,
+                    Some(&error),
+                )
+                .await;
+                ctx.event_bus.publish(ExecutionEvent::OrchestratorFailed {
+                    orchestration_run_id: ctx.orchestration_run_id,
+                    orchestrator_agent_id: ctx.orchestrator_agent_id,
+                    error,
+                });
+                return;
+            }
+        };
+
+        route.retain_from(routed.candidate_index);
+        let llm_response = routed.response;
+        let totals = record_usage(
+            ctx,
+            &routed.candidate.provider_name,
+            &llm_response,
+            duration_ms,
+        )
+        .await;
+        save_thinking(ctx, &llm_response).await;
+
+        if continuation::can_continue(&llm_response) && continuations < ctx.max_continuations {
+            continuations += 1;
+            publish_continuation(ctx, continuations);
+            partial_output.push_str(&llm_response.content);
+            continuation::push_continuation(&mut messages, &llm_response);
+            continue;
         }
+        break (llm_response, totals);
     };
 
+    if continuation::is_truncated(&llm_response) {
+        finish_truncated(ctx, partial_output + &llm_response.content, continuations).await;
+        return;
+    }
+
     let stop_reason = llm_response.stop_reason.as_deref().unwrap_or(STOP_END_TURN);
 
     if stop_reason == STOP_END_TURN || stop_reason == STOP_STOP {
-        let output = llm_response.content.clone();
+        let output = partial_output + &llm_response.content;
         finalize_orchestration(
             &ctx.db,
             ctx.orchestration_run_id,
@@ -245,6 +482,11 @@ pub(super) async fn run_tool_loop_approval(
     }
 
     if stop_reason == STOP_TOOL_USE {
+        // 予算超過なら承認待ちにせず止める
+        if stop_if_over_budget(ctx, totals).await {
+            return;
+        }
+
         // アシスタントメッセージを追加
         messages.push(LlmMessage {
             role: ROLE_ASSISTANT.to_string(),
@@ -256,7 +498,9 @@ pub(super) async fn run_tool_loop_approval(
             .content_blocks
             .iter()
             .filter_map(|b| match b {
-                ContentBlock::ToolUse { id, name, input } => Some(serde_json::json!({
+                ContentBlock::ToolUse {
+                    id, name, input, ..
+                } => Some(serde_json::json!({
                     "tool_use_id": id,
                     "name": name,
                     "input": input
@@ -267,7 +511,7 @@ pub(super) async fn run_tool_loop_approval(
 
         let plan = serde_json::json!({
             "steps": tool_uses,
-            "text": llm_response.content
+            "text": partial_output + &llm_response.content
         });
 
         // 会話状態とプランを保存
@@ -308,3 +552,213 @@ pub(super) async fn run_tool_loop_approval(
         // ここで一時停止 - approve_orchestration()で再開される
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::db::DbPool;
+    use crate::event_bus::EventBus;
+    use crate::llm::cassette::{RecordingProvider, ReplayProvider};
+    use crate::llm::routing::{FallbackPolicy, ModelCandidate};
+    use crate::llm::sampling::SamplingParams;
+    use crate::llm::types::TokenUsage;
+    use crate::llm::{LlmProviderTrait, LlmRegistry};
+    use crate::models::LlmProvider;
+    use crate::services::orchestration::compaction::CompactionSettings;
+    use crate::services::usage_service::Budget;
+    use crate::tools::file_read::FileReadTool;
+    use crate::tools::types::ToolContext;
+    use crate::tools::ToolRegistry;
+
+    const PROVIDER: &str = "cassette-test";
+
+    /// 決められた順にレスポンスを返すプロバイダー（記録時の実プロバイダーの代わり）
+    struct ScriptedProvider {
+        responses: Mutex<VecDeque<LlmResponse>>,
+    }
+
+    #[async_trait]
+    impl LlmProviderTrait for ScriptedProvider {
+        fn name(&self) -> &str {
+            PROVIDER
+        }
+
+        async fn complete(&self, _request: &LlmRequest) -> Result<LlmResponse, AppError> {
+            self.responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| AppError::LlmError("script exhausted".to_string()))
+        }
+
+        async fn health_check(&self) -> Result<(), AppError> {
+            Ok(())
+        }
+    }
+
+    fn response(blocks: Vec<ContentBlock>, stop_reason: &str) -> LlmResponse {
+        let content = blocks
+            .iter()
+            .filter_map(|b| match b {
+                ContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        LlmResponse {
+            content,
+            model: "test-model".to_string(),
+            token_usage: TokenUsage {
+                input_tokens: 10,
+                output_tokens: 5,
+                ..Default::default()
+            },
+            content_blocks: blocks,
+            stop_reason: Some(stop_reason.to_string()),
+        }
+    }
+
+    fn provider_row(provider_type: &str, cassette: &Path) -> LlmProvider {
+        LlmProvider {
+            id: Uuid::new_v4(),
+            name: PROVIDER.to_string(),
+            display_name: PROVIDER.to_string(),
+            api_base_url: None,
+            is_enabled: true,
+            provider_type: provider_type.to_string(),
+            api_key_ref: None,
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_concurrency: None,
+            cassette_path: Some(cassette.display().to_string()),
+            record_cassette: provider_type != PROVIDER_TYPE_REPLAY,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn context(provider: Box<dyn LlmProviderTrait>, dir: &Path) -> OrchestrationContext {
+        let registry = LlmRegistry::new();
+        registry.register(provider);
+        let mut tool_registry = ToolRegistry::new();
+        tool_registry.register(Box::new(FileReadTool));
+        OrchestrationContext {
+            db: DbPool::unavailable(),
+            registry: Arc::new(registry),
+            event_bus: EventBus::new(64),
+            orchestration_run_id: Uuid::new_v4(),
+            execution_id: Uuid::new_v4(),
+            orchestrator_agent_id: Uuid::new_v4(),
+            workflow_id: Uuid::new_v4(),
+            provider_name: PROVIDER.to_string(),
+            model: "test-model".to_string(),
+            route: ModelRoute {
+                candidates: vec![ModelCandidate {
+                    provider_name: PROVIDER.to_string(),
+                    model: "test-model".to_string(),
+                }],
+                policy: FallbackPolicy::Unavailable,
+            },
+            sub_agent_llm_provider_id: Uuid::new_v4(),
+            sub_agent_model: "test-model".to_string(),
+            system_prompt: Some("Answer from the notes.".to_string()),
+            temperature: 0.0,
+            max_tokens: 256,
+            prompt_cache: false,
+            thinking_budget: None,
+            sampling: SamplingParams::default(),
+            tool_choice: None,
+            max_continuations: 0,
+            budget: Budget::default(),
+            compaction: CompactionSettings {
+                token_limit: 100_000,
+                model: None,
+            },
+            tool_registry: Arc::new(tool_registry),
+            enabled_tools: vec!["file_read".to_string()],
+            tool_context: ToolContext {
+                working_dir: dir.to_path_buf(),
+                allowed_read_dirs: vec![dir.to_path_buf()],
+                ..Default::default()
+            },
+        }
+    }
+
+    /// ツールループを最後まで実行し、完了なら Ok(出力)、失敗なら Err(エラー) を返す
+    async fn run(ctx: OrchestrationContext) -> Result<String, String> {
+        let mut events = ctx.event_bus.subscribe();
+        let messages = vec![LlmMessage {
+            role: ROLE_USER.to_string(),
+            content: MessageContent::Text("What do the notes say?".to_string()),
+        }];
+        run_tool_loop(&ctx, messages).await;
+        while let Ok(envelope) = events.try_recv() {
+            match envelope.event {
+                ExecutionEvent::OrchestratorCompleted { output, .. } => return Ok(output),
+                ExecutionEvent::OrchestratorFailed { error, .. } => return Err(error),
+                _ => {}
+            }
+        }
+        panic!("tool loop finished without a completion event");
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_tool_loop_and_fails_when_requests_diverge() {
+        let dir = std::env::temp_dir().join(format!("tebiki-cassette-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "the meeting is on tuesday\n").unwrap();
+        let cassette = dir.join("cassette.json");
+
+        // 記録: file_read を1回呼んでから回答する会話
+        let scripted = ScriptedProvider {
+            responses: Mutex::new(VecDeque::from([
+                response(
+                    vec![ContentBlock::ToolUse {
+                        id: "toolu_1".to_string(),
+                        name: "file_read".to_string(),
+                        input: serde_json::json!({ "path": "notes.txt" }),
+                        signature: None,
+                    }],
+                    STOP_TOOL_USE,
+                ),
+                response(
+                    vec![ContentBlock::Text {
+                        text: "The meeting is on Tuesday.".to_string(),
+                    }],
+                    STOP_END_TURN,
+                ),
+            ])),
+        };
+        // 記録先は記録用ディレクトリからの相対パスで指定する
+        let recorder = RecordingProvider::wrap(
+            Box::new(scripted),
+            &provider_row("anthropic", Path::new("cassette.json")),
+            &dir,
+        )
+        .unwrap();
+        let recorded = run(context(Box::new(recorder), &dir)).await;
+        assert_eq!(recorded.as_deref(), Ok("The meeting is on Tuesday."));
+
+        // 再生: ツール結果まで含めて記録どおりのリクエストなら同じ結果になる
+        let replay = ReplayProvider::from_row(&provider_row("replay", &cassette)).unwrap();
+        assert_eq!(run(context(Box::new(replay), &dir)).await, recorded);
+
+        // ツール結果が変わると2回目のリクエストが記録と一致せず、実行は失敗する
+        std::fs::write(dir.join("notes.txt"), "the meeting is cancelled\n").unwrap();
+        let replay = ReplayProvider::from_row(&provider_row("replay", &cassette)).unwrap();
+        let error = run(context(Box::new(replay), &dir)).await.unwrap_err();
+        assert!(error.contains("does not match cassette"), "{error}");
+        assert!(
+            error.contains("interaction 2 differs at messages["),
+            "{error}"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
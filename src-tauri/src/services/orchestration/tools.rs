@@ -3,7 +3,8 @@ use uuid::Uuid;
 use crate::constants::*;
 use crate::event_bus::ExecutionEvent;
 use crate::llm::types::{ContentBlock, ToolDefinition};
-use crate::services::execution_service;
+use crate::services::tool_service::{self, ToolExecutionRecord};
+use crate::services::{execution_service, usage_service};
 
 use super::context::OrchestrationContext;
 
@@ -77,7 +78,7 @@ fn builtin_orchestrator_tools() -> Vec<ToolDefinition> {
 }
 
 /// 単一のツール呼び出しを処理し、結果コンテンツを返す
-pub(super) async fn handle_tool_call(
+async fn handle_tool_call(
     tool_name: &str,
     tool_input: &serde_json::Value,
     ctx: &OrchestrationContext,
@@ -95,17 +96,19 @@ pub(super) async fn handle_tool_call(
 
             let agent = match sqlx::query_as::<_, crate::models::Agent>(
                 r#"
-                INSERT INTO agents (workflow_id, llm_provider_id, name, description, system_prompt, model, temperature, max_tokens)
-                VALUES ($1, $2, $3, $4, $5, $6, 0.7, 2048)
+                INSERT INTO agents (workflow_id, llm_provider_id, name, description, system_prompt, model, temperature, max_tokens, prompt_cache, fallback_on)
+                VALUES ($1, $2, $3, $4, $5, $6, 0.7, 2048, $7, $8)
                 RETURNING *
                 "#,
             )
             .bind(ctx.workflow_id)
-            .bind(ctx.llm_provider_id)
+            .bind(ctx.sub_agent_llm_provider_id)
             .bind(name)
             .bind(Some(description))
             .bind(system_prompt)
-            .bind(&ctx.model)
+            .bind(&ctx.sub_agent_model)
+            .bind(ctx.prompt_cache)
+            .bind(ctx.route.policy.as_str())
             .fetch_one(&pool)
             .await
             {
@@ -113,6 +116,26 @@ pub(super) async fn handle_tool_call(
                 Err(e) => return (format!("Failed to create sub-agent: {e}"), true),
             };
 
+            // フォールバック先はオーケストレーターのものを引き継ぐ
+            if let Err(e) = sqlx::query(
+                r#"
+                INSERT INTO agent_fallback_models (agent_id, position, llm_provider_id, model)
+                SELECT $1, position, llm_provider_id, model
+                FROM agent_fallback_models
+                WHERE agent_id = $2
+                "#,
+            )
+            .bind(agent.id)
+            .bind(ctx.orchestrator_agent_id)
+            .execute(&pool)
+            .await
+            {
+                eprintln!(
+                    "[orchestration] Failed to copy fallbacks to sub-agent {}: {e}",
+                    agent.id
+                );
+            }
+
             ctx.event_bus.publish(ExecutionEvent::SubAgentCreated {
                 agent_id: agent.id,
                 orchestrator_agent_id: ctx.orchestrator_agent_id,
@@ -143,20 +166,37 @@ pub(super) async fn handle_tool_call(
             let request = crate::models::ExecuteAgentRequest {
                 agent_id,
                 input: input.to_string(),
+                attachments: Vec::new(),
+                output_schema: None,
             };
 
             match execution_service::execute_agent(&ctx.db, &ctx.registry, &ctx.event_bus, &request)
                 .await
             {
-                Ok(execution) => (
-                    serde_json::json!({
-                        "execution_id": execution.id.to_string(),
-                        "status": execution.status,
-                        "output": execution.output_text.unwrap_or_default()
-                    })
-                    .to_string(),
-                    false,
-                ),
+                Ok(execution) => {
+                    // サブエージェントの使用量をオーケストレーション実行の予算に計上する
+                    if let Err(e) = usage_service::add_sub_execution_usage(
+                        &ctx.db,
+                        ctx.orchestration_run_id,
+                        execution.id,
+                    )
+                    .await
+                    {
+                        eprintln!(
+                            "[orchestration] Failed to add usage of execution {}: {e}",
+                            execution.id
+                        );
+                    }
+                    (
+                        serde_json::json!({
+                            "execution_id": execution.id.to_string(),
+                            "status": execution.status,
+                            "output": execution.output_text.unwrap_or_default()
+                        })
+                        .to_string(),
+                        false,
+                    )
+                }
                 Err(e) => (format!("Execution failed: {e}"), true),
             }
         }
@@ -195,6 +235,50 @@ pub(super) async fn handle_tool_call(
     }
 }
 
+/// ツール呼び出しを実行し、開始・完了イベントの発行と監査ログへの記録を行う。
+/// 記録に失敗してもツールの結果はそのまま返す。
+async fn run_tool_call(
+    tool_use_id: &str,
+    tool_name: &str,
+    tool_input: &serde_json::Value,
+    ctx: &OrchestrationContext,
+) -> (String, bool) {
+    ctx.event_bus.publish(ExecutionEvent::ToolExecutionStarted {
+        execution_id: ctx.execution_id,
+        tool_use_id: tool_use_id.to_string(),
+        tool_name: tool_name.to_string(),
+    });
+
+    let start = std::time::Instant::now();
+    let (content, is_error) = handle_tool_call(tool_name, tool_input, ctx).await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+
+    ctx.event_bus
+        .publish(ExecutionEvent::ToolExecutionCompleted {
+            execution_id: ctx.execution_id,
+            tool_use_id: tool_use_id.to_string(),
+            tool_name: tool_name.to_string(),
+            duration_ms,
+            is_error,
+        });
+
+    let record = ToolExecutionRecord {
+        execution_id: ctx.execution_id,
+        orchestration_run_id: ctx.orchestration_run_id,
+        tool_use_id,
+        tool_name,
+        input: tool_input,
+        output: &content,
+        is_error,
+        duration_ms,
+    };
+    if let Err(e) = tool_service::record_tool_execution(&ctx.db, &record).await {
+        eprintln!("[orchestration] Failed to record tool execution of {tool_name}: {e}");
+    }
+
+    (content, is_error)
+}
+
 /// LLMレスポンスからのツール呼び出しを処理する
 pub(super) async fn process_tool_calls(
     tool_uses: Vec<ContentBlock>,
@@ -203,8 +287,11 @@ pub(super) async fn process_tool_calls(
     let mut results = Vec::new();
 
     for tool_use in &tool_uses {
-        if let ContentBlock::ToolUse { id, name, input } = tool_use {
-            let (content, is_error) = handle_tool_call(name, input, ctx).await;
+        if let ContentBlock::ToolUse {
+            id, name, input, ..
+        } = tool_use
+        {
+            let (content, is_error) = run_tool_call(id, name, input, ctx).await;
             results.push(ContentBlock::ToolResult {
                 tool_use_id: id.clone(),
                 content,
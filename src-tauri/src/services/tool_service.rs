@@ -0,0 +1,219 @@
+use uuid::Uuid;
+
+use crate::constants::*;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::{
+    AgentToolPermission, ToolExecution, ToolExecutionQuery, UpdateToolPermissionsRequest,
+};
+use crate::tools::config;
+use crate::tools::types::ToolContext;
+use crate::tools::ToolRegistry;
+
+/// ツール設定を検証する。登録ツール以外（オーケストレーター組み込みのツールなど）は設定を持てない。
+fn validate_config(
+    tool_registry: &ToolRegistry,
+    tool_name: &str,
+    config: Option<&serde_json::Value>,
+) -> Result<(), AppError> {
+    let Some(config) = config.filter(|c| !config::is_empty(c)) else {
+        return Ok(());
+    };
+    let tool = tool_registry.get(tool_name).ok_or_else(|| {
+        AppError::InvalidInput(format!("Tool '{tool_name}' does not accept config"))
+    })?;
+    let mut ctx = ToolContext::for_project();
+    tool.apply_config(config, &mut ctx)
+        .map_err(AppError::InvalidInput)
+}
+
+/// ツール権限をまとめて保存する。設定はすべて検証してから書き込む。
+pub async fn update_tool_permissions(
+    db: &DbPool,
+    tool_registry: &ToolRegistry,
+    request: &UpdateToolPermissionsRequest,
+) -> Result<(), AppError> {
+    for entry in &request.tools {
+        validate_config(tool_registry, &entry.tool_name, entry.config.as_ref())?;
+    }
+
+    let pool = db.get()?;
+    let mut tx = pool.begin().await?;
+    for entry in &request.tools {
+        sqlx::query(
+            r#"
+            INSERT INTO agent_tool_permissions (agent_id, tool_name, is_enabled, config)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (agent_id, tool_name) DO UPDATE
+            SET is_enabled = EXCLUDED.is_enabled, config = EXCLUDED.config, updated_at = NOW()
+            "#,
+        )
+        .bind(request.agent_id)
+        .bind(&entry.tool_name)
+        .bind(entry.is_enabled)
+        .bind(&entry.config)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_tool_permissions(
+    db: &DbPool,
+    agent_id: Uuid,
+) -> Result<Vec<AgentToolPermission>, AppError> {
+    let pool = db.get()?;
+    let permissions = sqlx::query_as::<_, AgentToolPermission>(
+        "SELECT * FROM agent_tool_permissions WHERE agent_id = $1 ORDER BY tool_name",
+    )
+    .bind(agent_id)
+    .fetch_all(&pool)
+    .await?;
+    Ok(permissions)
+}
+
+/// エージェントのツール権限を無効なものも含めて取得（無効なツールの設定も権限の制限に使う）
+pub async fn load_permissions(pool: &sqlx::PgPool, agent_id: Uuid) -> Vec<AgentToolPermission> {
+    sqlx::query_as::<_, AgentToolPermission>(
+        "SELECT * FROM agent_tool_permissions WHERE agent_id = $1",
+    )
+    .bind(agent_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+}
+
+/// プロジェクトの既定のコンテキストに、ツールの設定を重ねる。
+/// 読み書きの許可ディレクトリなど複数のツールで共有する設定は狭める方向にしか働かないため、
+/// 無効にしたツールの設定も適用する（ツールを無効にして他のツールの権限が広がらないように）。
+/// 保存後にディレクトリが消えたなどで設定を適用できなければ、既定の権限で動かさずエラーにする。
+pub fn build_tool_context(
+    tool_registry: &ToolRegistry,
+    permissions: &[AgentToolPermission],
+) -> Result<ToolContext, AppError> {
+    let mut ctx = ToolContext::for_project();
+    for permission in permissions {
+        let (Some(tool), Some(config)) = (
+            tool_registry.get(&permission.tool_name),
+            permission.config.as_ref(),
+        ) else {
+            continue;
+        };
+        tool.apply_config(config, &mut ctx).map_err(|e| {
+            AppError::InvalidInput(format!(
+                "Tool config of '{}' cannot be applied: {e}",
+                permission.tool_name
+            ))
+        })?;
+    }
+    Ok(ctx)
+}
+
+/// tool_executions に記録する1回分のツール呼び出し
+pub struct ToolExecutionRecord<'a> {
+    pub execution_id: Uuid,
+    pub orchestration_run_id: Uuid,
+    pub tool_use_id: &'a str,
+    pub tool_name: &'a str,
+    pub input: &'a serde_json::Value,
+    pub output: &'a str,
+    pub is_error: bool,
+    pub duration_ms: u64,
+}
+
+/// UTF-8 の文字境界を保って max_bytes 以内に切り詰める
+fn truncate_bytes(text: &str, max_bytes: usize) -> (&str, bool) {
+    if text.len() <= max_bytes {
+        return (text, false);
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (&text[..end], true)
+}
+
+/// 大きすぎる入力は JSON テキストの先頭だけを保存する
+fn audit_input(input: &serde_json::Value) -> serde_json::Value {
+    let text = input.to_string();
+    match truncate_bytes(&text, TOOL_AUDIT_MAX_INPUT_BYTES) {
+        (_, false) => input.clone(),
+        (preview, true) => serde_json::json!({
+            "truncated": true,
+            "size_bytes": text.len(),
+            "preview": preview,
+        }),
+    }
+}
+
+/// ツール呼び出しを監査ログに記録する
+pub async fn record_tool_execution(
+    db: &DbPool,
+    record: &ToolExecutionRecord<'_>,
+) -> Result<(), AppError> {
+    let pool = db.get()?;
+    let (output, output_truncated) = truncate_bytes(record.output, TOOL_AUDIT_MAX_OUTPUT_BYTES);
+    sqlx::query(
+        r#"
+        INSERT INTO tool_executions
+            (execution_id, orchestration_run_id, tool_use_id, tool_name, input, output, output_truncated, is_error, duration_ms)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+    )
+    .bind(record.execution_id)
+    .bind(record.orchestration_run_id)
+    .bind(record.tool_use_id)
+    .bind(record.tool_name)
+    .bind(audit_input(record.input))
+    .bind(output)
+    .bind(output_truncated)
+    .bind(record.is_error)
+    .bind(i64::try_from(record.duration_ms).unwrap_or(i64::MAX))
+    .execute(&pool)
+    .await?;
+    Ok(())
+}
+
+/// ツール実行履歴を新しい順に取得する。条件を指定しなければ直近の全ツール実行を返す。
+pub async fn list_tool_executions(
+    db: &DbPool,
+    query: &ToolExecutionQuery,
+) -> Result<Vec<ToolExecution>, AppError> {
+    if let (Some(since), Some(until)) = (query.since, query.until) {
+        if since >= until {
+            return Err(AppError::InvalidInput(
+                "since must be earlier than until".to_string(),
+            ));
+        }
+    }
+    let limit = query.limit.unwrap_or(TOOL_EXECUTIONS_DEFAULT_LIMIT);
+    if !(1..=TOOL_EXECUTIONS_MAX_LIMIT).contains(&limit) {
+        return Err(AppError::InvalidInput(format!(
+            "limit must be between 1 and {TOOL_EXECUTIONS_MAX_LIMIT}"
+        )));
+    }
+
+    let pool = db.get()?;
+    let executions = sqlx::query_as::<_, ToolExecution>(
+        r#"
+        SELECT * FROM tool_executions
+        WHERE ($1::uuid IS NULL OR execution_id = $1)
+          AND ($2::uuid IS NULL OR orchestration_run_id = $2)
+          AND ($3::text IS NULL OR tool_name = $3)
+          AND ($4::timestamptz IS NULL OR created_at >= $4)
+          AND ($5::timestamptz IS NULL OR created_at < $5)
+        ORDER BY created_at DESC
+        LIMIT $6
+        "#,
+    )
+    .bind(query.execution_id)
+    .bind(query.orchestration_run_id)
+    .bind(query.tool_name.as_deref())
+    .bind(query.since)
+    .bind(query.until)
+    .bind(limit)
+    .fetch_all(&pool)
+    .await?;
+    Ok(executions)
+}
@@ -0,0 +1,285 @@
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::llm::types::TokenUsage;
+use crate::models::ModelPrice;
+
+/// model_prices の単価の基準トークン数
+const TOKENS_PER_PRICE_UNIT: f64 = 1_000_000.0;
+
+/// 実行・ワークフロー実行・オーケストレーション実行に集計された使用量
+#[derive(Debug, Clone, Copy, Default, FromRow)]
+pub struct UsageTotals {
+    pub total_input_tokens: i64,
+    pub total_output_tokens: i64,
+    pub total_cost_usd: f64,
+}
+
+/// オーケストレーションの予算（None は無制限）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    pub max_tokens: Option<i64>,
+    pub max_cost_usd: Option<f64>,
+}
+
+impl Budget {
+    /// 予算の指定値を検証する
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.max_tokens.is_some_and(|v| v <= 0) {
+            return Err(AppError::InvalidInput(
+                "max_tokens_budget must be positive".to_string(),
+            ));
+        }
+        if self
+            .max_cost_usd
+            .is_some_and(|v| !v.is_finite() || v <= 0.0)
+        {
+            return Err(AppError::InvalidInput(
+                "max_cost_usd must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 超過していればその理由を返す
+    pub fn exceeded(&self, totals: &UsageTotals) -> Option<String> {
+        let tokens = totals.total_input_tokens + totals.total_output_tokens;
+        if let Some(max) = self.max_tokens.filter(|max| tokens >= *max) {
+            return Some(format!(
+                "Token budget exceeded: used {tokens} tokens (limit {max})"
+            ));
+        }
+        if let Some(max) = self
+            .max_cost_usd
+            .filter(|max| totals.total_cost_usd >= *max)
+        {
+            return Some(format!(
+                "Cost budget exceeded: used ${:.4} (limit ${max:.4})",
+                totals.total_cost_usd
+            ));
+        }
+        None
+    }
+}
+
+/// 単価表から料金を計算する。キャッシュ単価が未設定なら入力単価で計算する。
+fn compute_cost(price: &ModelPrice, usage: &TokenUsage) -> f64 {
+    let cost = |tokens: u32, unit_price: f64| f64::from(tokens) * unit_price;
+    (cost(usage.input_tokens, price.input_price)
+        + cost(usage.output_tokens, price.output_price)
+        + cost(
+            usage.cache_read_tokens,
+            price.cache_read_price.unwrap_or(price.input_price),
+        )
+        + cost(
+            usage.cache_write_tokens,
+            price.cache_write_price.unwrap_or(price.input_price),
+        ))
+        / TOKENS_PER_PRICE_UNIT
+}
+
+/// プロバイダーの種別と model の最長前方一致で単価を引く。
+/// 種別は llm_providers から引き、行がなければ（組み込みプロバイダー）名前を種別とみなす。
+async fn find_price(
+    pool: &sqlx::PgPool,
+    provider_name: &str,
+    model: &str,
+) -> Result<Option<ModelPrice>, AppError> {
+    let price = sqlx::query_as::<_, ModelPrice>(
+        r#"
+        SELECT mp.* FROM model_prices mp
+        WHERE mp.provider_type = COALESCE(
+                  (SELECT provider_type FROM llm_providers WHERE name = $1), $1)
+          AND left($2, length(mp.model)) = mp.model
+        ORDER BY length(mp.model) DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(provider_name)
+    .bind(model)
+    .fetch_optional(pool)
+    .await?;
+    Ok(price)
+}
+
+/// LLM呼び出し1回分を llm_calls に記録し、実行・ワークフロー実行・オーケストレーション実行に加算する。
+/// 加算後の累計（オーケストレーション中ならその実行全体、それ以外はエージェント実行）を返す。
+/// 単価が見つからないモデルはトークンのみ加算する。
+pub async fn record_llm_call(
+    db: &DbPool,
+    execution_id: Uuid,
+    orchestration_run_id: Option<Uuid>,
+    provider_name: &str,
+    model: &str,
+    usage: &TokenUsage,
+    duration_ms: i64,
+) -> Result<UsageTotals, AppError> {
+    let pool = db.get()?;
+    let cost_usd = find_price(&pool, provider_name, model)
+        .await?
+        .map(|price| compute_cost(&price, usage));
+    if cost_usd.is_none() {
+        eprintln!(
+            "[tebiki] Warning: no model_prices row matches model '{model}' for the type of provider '{provider_name}'. \
+             Cost is recorded as NULL and cost budgets do not count this call; add a price to model_prices."
+        );
+    }
+    let input_tokens = i64::from(usage.total_input_tokens());
+    let output_tokens = i64::from(usage.output_tokens);
+    let cost = cost_usd.unwrap_or(0.0);
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO llm_calls (execution_id, orchestration_run_id, provider_name, model,
+                               input_tokens, output_tokens, cache_read_tokens, cache_write_tokens,
+                               cost_usd, duration_ms)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+    )
+    .bind(execution_id)
+    .bind(orchestration_run_id)
+    .bind(provider_name)
+    .bind(model)
+    .bind(usage.input_tokens as i32)
+    .bind(usage.output_tokens as i32)
+    .bind(usage.cache_read_tokens as i32)
+    .bind(usage.cache_write_tokens as i32)
+    .bind(cost_usd)
+    .bind(duration_ms)
+    .execute(&mut *tx)
+    .await?;
+
+    let execution_totals = sqlx::query_as::<_, UsageTotals>(
+        r#"
+        UPDATE agent_executions SET
+            total_input_tokens = total_input_tokens + $2,
+            total_output_tokens = total_output_tokens + $3,
+            total_cost_usd = total_cost_usd + $4
+        WHERE id = $1
+        RETURNING total_input_tokens, total_output_tokens, total_cost_usd
+        "#,
+    )
+    .bind(execution_id)
+    .bind(input_tokens)
+    .bind(output_tokens)
+    .bind(cost)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE workflow_runs SET
+            total_input_tokens = total_input_tokens + $2,
+            total_output_tokens = total_output_tokens + $3,
+            total_cost_usd = total_cost_usd + $4
+        WHERE id = (SELECT workflow_run_id FROM agent_executions WHERE id = $1)
+        "#,
+    )
+    .bind(execution_id)
+    .bind(input_tokens)
+    .bind(output_tokens)
+    .bind(cost)
+    .execute(&mut *tx)
+    .await?;
+
+    let totals = match orchestration_run_id {
+        Some(run_id) => {
+            add_to_orchestration_run(&mut tx, run_id, input_tokens, output_tokens, cost).await?
+        }
+        None => execution_totals,
+    };
+
+    tx.commit().await?;
+    Ok(totals)
+}
+
+/// サブエージェント実行の使用量をオーケストレーション実行（とそのワークフロー実行）に加算し、
+/// 加算後のオーケストレーション実行の累計を返す。
+/// サブエージェント自身のワークフロー実行にも計上済みなので、ワークフロー実行をまたいだ合算は二重になる。
+pub async fn add_sub_execution_usage(
+    db: &DbPool,
+    orchestration_run_id: Uuid,
+    sub_execution_id: Uuid,
+) -> Result<UsageTotals, AppError> {
+    let pool = db.get()?;
+    let sub = sqlx::query_as::<_, UsageTotals>(
+        "SELECT total_input_tokens, total_output_tokens, total_cost_usd FROM agent_executions WHERE id = $1",
+    )
+    .bind(sub_execution_id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        UPDATE workflow_runs SET
+            total_input_tokens = total_input_tokens + $2,
+            total_output_tokens = total_output_tokens + $3,
+            total_cost_usd = total_cost_usd + $4
+        WHERE id = (SELECT workflow_run_id FROM orchestration_runs WHERE id = $1)
+        "#,
+    )
+    .bind(orchestration_run_id)
+    .bind(sub.total_input_tokens)
+    .bind(sub.total_output_tokens)
+    .bind(sub.total_cost_usd)
+    .execute(&mut *tx)
+    .await?;
+    let totals = add_to_orchestration_run(
+        &mut tx,
+        orchestration_run_id,
+        sub.total_input_tokens,
+        sub.total_output_tokens,
+        sub.total_cost_usd,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(totals)
+}
+
+async fn add_to_orchestration_run(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    orchestration_run_id: Uuid,
+    input_tokens: i64,
+    output_tokens: i64,
+    cost: f64,
+) -> Result<UsageTotals, AppError> {
+    let totals = sqlx::query_as::<_, UsageTotals>(
+        r#"
+        UPDATE orchestration_runs SET
+            total_input_tokens = total_input_tokens + $2,
+            total_output_tokens = total_output_tokens + $3,
+            total_cost_usd = total_cost_usd + $4
+        WHERE id = $1
+        RETURNING total_input_tokens, total_output_tokens, total_cost_usd
+        "#,
+    )
+    .bind(orchestration_run_id)
+    .bind(input_tokens)
+    .bind(output_tokens)
+    .bind(cost)
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(totals)
+}
+
+/// オーケストレーション実行の現在の累計
+pub async fn get_orchestration_totals(
+    db: &DbPool,
+    orchestration_run_id: Uuid,
+) -> Result<UsageTotals, AppError> {
+    let pool = db.get()?;
+    let totals = sqlx::query_as::<_, UsageTotals>(
+        "SELECT total_input_tokens, total_output_tokens, total_cost_usd FROM orchestration_runs WHERE id = $1",
+    )
+    .bind(orchestration_run_id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or(AppError::NotFound)?;
+    Ok(totals)
+}
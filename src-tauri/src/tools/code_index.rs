@@ -0,0 +1,569 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// インデックスファイルの形式のバージョン（変わったら作り直す）
+const INDEX_VERSION: u32 = 1;
+/// インデックスを置くディレクトリ（アプリのデータディレクトリからの相対パス）。
+/// プロジェクトの外に置き、書き込み許可のないエージェントでもプロジェクトに書き込まない。
+const INDEX_DIR: &str = "code-index";
+/// 1チャンクの行数と、前のチャンクと重ねる行数
+const CHUNK_LINES: usize = 60;
+const CHUNK_OVERLAP: usize = 10;
+/// 埋め込みに送る1チャンクの最大文字数（超えた分は切り捨てる）
+const MAX_CHUNK_CHARS: usize = 6_000;
+/// これより大きいファイルは生成物やデータとみなして対象外にする
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+/// 対象にするファイル数の上限（超えた分は走査順で切り捨てる）
+const MAX_INDEXED_FILES: usize = 20_000;
+/// 検索結果に含めるスニペットの最大行数
+const SNIPPET_MAX_LINES: usize = 40;
+
+/// working_dir のチャンク単位の埋め込みインデックス。
+/// ファイルのサイズ・更新時刻・内容のハッシュで変更を判定し、変わったファイルだけ埋め込み直す。
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeIndex {
+    version: u32,
+    provider: String,
+    model: String,
+    dimensions: Option<u32>,
+    /// working_dir からの相対パス（区切りは '/'）→ ファイルのチャンク
+    files: BTreeMap<String, IndexedFile>,
+    /// 保存していない変更があるか
+    #[serde(skip)]
+    dirty: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedFile {
+    size: u64,
+    modified_ms: u64,
+    hash: String,
+    chunks: Vec<IndexedChunk>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexedChunk {
+    start_line: usize,
+    end_line: usize,
+    /// 正規化済みのベクトル（ファイル上は f32 リトルエンディアンの base64）
+    #[serde(with = "vector_base64")]
+    embedding: Vec<f32>,
+}
+
+/// 埋め込みが必要なファイル（新規または内容が変わったもの）
+pub struct PendingFile {
+    path: String,
+    size: u64,
+    modified_ms: u64,
+    hash: String,
+    pub chunks: Vec<PendingChunk>,
+}
+
+pub struct PendingChunk {
+    start_line: usize,
+    end_line: usize,
+    /// 埋め込みに送るテキスト（先頭にファイルパスを付ける）
+    pub text: String,
+}
+
+/// 走査の結果
+pub struct ScanResult {
+    pub pending: Vec<PendingFile>,
+    pub removed: usize,
+    /// ファイル数の上限で対象外にした数
+    pub skipped: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub score: f32,
+    pub snippet: String,
+}
+
+mod vector_base64 {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(vector: &[f32], serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = STANDARD.decode(encoded).map_err(serde::de::Error::custom)?;
+        if bytes.len() % 4 != 0 {
+            return Err(serde::de::Error::custom(
+                "vector length is not a multiple of 4",
+            ));
+        }
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+}
+
+/// 長さ 1 に正規化する（内積がそのままコサイン類似度になる）
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// 行単位で重なりのあるチャンクに分ける。空白だけのチャンクは捨てる。
+fn chunk_text(path: &str, text: &str) -> Vec<PendingChunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let end = (start + CHUNK_LINES).min(lines.len());
+        let body = lines[start..end].join("\n");
+        if !body.trim().is_empty() {
+            let mut text = format!("{path}\n\n{body}");
+            if let Some((cut, _)) = text.char_indices().nth(MAX_CHUNK_CHARS) {
+                text.truncate(cut);
+            }
+            chunks.push(PendingChunk {
+                start_line: start + 1,
+                end_line: end,
+                text,
+            });
+        }
+        if end == lines.len() {
+            break;
+        }
+        start = end - CHUNK_OVERLAP;
+    }
+    chunks
+}
+
+/// root からの相対パス（区切りは '/'、root 自身は空文字）。root の外なら None
+pub fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    Some(
+        relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// 相対パス path が dir（相対パス、空文字は root 全体）の中か
+fn is_under(path: &str, dir: &str) -> bool {
+    dir.is_empty() || path == dir || path.strip_prefix(dir).is_some_and(|r| r.starts_with('/'))
+}
+
+/// インデックスファイルの置き場所（プロジェクト・プロバイダー・モデル・次元数ごとに分ける）
+pub fn index_path(
+    data_dir: &Path,
+    root: &Path,
+    provider: &str,
+    model: &str,
+    dimensions: Option<u32>,
+) -> PathBuf {
+    let project = format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()));
+    let dimensions = dimensions.map_or_else(|| "default".to_string(), |d| d.to_string());
+    let stem: String = format!("{provider}-{model}-{dimensions}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    data_dir
+        .join(INDEX_DIR)
+        .join(&project[..16])
+        .join(format!("{stem}.json"))
+}
+
+impl CodeIndex {
+    /// 保存済みのインデックスを読み込む。無いか形式が合わなければ空から作り直す。
+    pub fn load(path: &Path, provider: &str, model: &str, dimensions: Option<u32>) -> Self {
+        let empty = || Self {
+            version: INDEX_VERSION,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            dimensions,
+            files: BTreeMap::new(),
+            dirty: false,
+        };
+        let Ok(bytes) = std::fs::read(path) else {
+            return empty();
+        };
+        match serde_json::from_slice::<Self>(&bytes) {
+            Ok(index)
+                if index.version == INDEX_VERSION
+                    && index.provider == provider
+                    && index.model == model
+                    && index.dimensions == dimensions =>
+            {
+                index
+            }
+            Ok(_) => empty(),
+            Err(e) => {
+                eprintln!(
+                    "[tebiki] Ignoring unreadable code index '{}': {e}",
+                    path.display()
+                );
+                empty()
+            }
+        }
+    }
+
+    /// 一時ファイルに書いてから置き換える
+    pub fn save(&mut self, path: &Path) -> std::io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let bytes = serde_json::to_vec(self).map_err(std::io::Error::other)?;
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// root 内の dirs（root からの相対パス、空文字は root 全体）を .gitignore に従って走査し、
+    /// 埋め込みが必要なファイルを集める。dirs の外のファイルは読まず、埋め込みにも送らない。
+    /// dirs の中で削除されたファイルはここでインデックスから外す。
+    pub fn scan(&mut self, root: &Path, dirs: &[String]) -> ScanResult {
+        let mut seen = HashSet::new();
+        let mut pending = Vec::new();
+        let mut skipped = 0;
+
+        // シンボリックリンクはたどらない（許可ディレクトリの外を指していても読まない）
+        let walkers = dirs.iter().map(|dir| {
+            ignore::WalkBuilder::new(root.join(dir))
+                .require_git(false)
+                .build()
+        });
+        for entry in walkers.flatten().filter_map(Result::ok) {
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let Some(relative) = relative_path(root, entry.path()) else {
+                continue;
+            };
+            if seen.contains(&relative) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.len() == 0 || metadata.len() > MAX_FILE_BYTES {
+                continue;
+            }
+            if seen.len() >= MAX_INDEXED_FILES {
+                skipped += 1;
+                continue;
+            }
+            let size = metadata.len();
+            let modified_ms = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX));
+
+            if let Some(known) = self.files.get(&relative) {
+                if known.size == size && known.modified_ms == modified_ms {
+                    seen.insert(relative);
+                    continue;
+                }
+            }
+
+            // バイナリ（NUL を含む・UTF-8 でない）は対象外
+            let Ok(bytes) = std::fs::read(entry.path()) else {
+                continue;
+            };
+            if bytes.contains(&0) {
+                continue;
+            }
+            let Ok(text) = String::from_utf8(bytes) else {
+                continue;
+            };
+            let hash = format!("{:x}", Sha256::digest(text.as_bytes()));
+            seen.insert(relative.clone());
+
+            if let Some(known) = self.files.get_mut(&relative) {
+                if known.hash == hash {
+                    // 内容は同じ（touch やチェックアウトで時刻だけ変わった）
+                    known.size = size;
+                    known.modified_ms = modified_ms;
+                    self.dirty = true;
+                    continue;
+                }
+            }
+            let chunks = chunk_text(&relative, &text);
+            pending.push(PendingFile {
+                path: relative,
+                size,
+                modified_ms,
+                hash,
+                chunks,
+            });
+        }
+
+        let before = self.files.len();
+        self.files
+            .retain(|path, _| seen.contains(path) || !dirs.iter().any(|d| is_under(path, d)));
+        let removed = before - self.files.len();
+        if removed > 0 {
+            self.dirty = true;
+        }
+        ScanResult {
+            pending,
+            removed,
+            skipped,
+        }
+    }
+
+    /// 埋め込み済みのファイルを登録する（embeddings は file.chunks と同じ順序）
+    pub fn insert(&mut self, file: PendingFile, embeddings: Vec<Vec<f32>>) {
+        let chunks = file
+            .chunks
+            .into_iter()
+            .zip(embeddings)
+            .map(|(chunk, embedding)| IndexedChunk {
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                embedding: normalize(embedding),
+            })
+            .collect();
+        self.files.insert(
+            file.path,
+            IndexedFile {
+                size: file.size,
+                modified_ms: file.modified_ms,
+                hash: file.hash,
+                chunks,
+            },
+        );
+        self.dirty = true;
+    }
+
+    /// dirs（root からの相対パス）の中でクエリのベクトルに近いチャンクを返す。
+    /// 同じファイルで行が重なるチャンクはまとめる。
+    pub fn search(
+        &self,
+        root: &Path,
+        query: Vec<f32>,
+        dirs: &[String],
+        limit: usize,
+    ) -> Vec<SearchHit> {
+        let query = normalize(query);
+        let mut scored: Vec<(f32, &str, &IndexedChunk)> = self
+            .files
+            .iter()
+            .filter(|(path, _)| dirs.iter().any(|d| is_under(path, d)))
+            .flat_map(|(path, file)| {
+                file.chunks
+                    .iter()
+                    .filter(|c| c.embedding.len() == query.len())
+                    .map(|c| (dot(&c.embedding, &query), path.as_str(), c))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut hits: Vec<SearchHit> = Vec::new();
+        for (score, path, chunk) in scored {
+            if hits.len() >= limit {
+                break;
+            }
+            let overlaps = hits.iter().any(|h| {
+                h.path == path && chunk.start_line <= h.end_line && h.start_line <= chunk.end_line
+            });
+            if overlaps {
+                continue;
+            }
+            hits.push(SearchHit {
+                path: path.to_string(),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                score,
+                snippet: read_snippet(&root.join(path), chunk.start_line, chunk.end_line),
+            });
+        }
+        hits
+    }
+}
+
+/// 行番号付きで該当範囲を読む（長い範囲は先頭だけ）
+fn read_snippet(path: &Path, start_line: usize, end_line: usize) -> String {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return String::new();
+    };
+    let take = (end_line + 1 - start_line).min(SNIPPET_MAX_LINES);
+    text.lines()
+        .enumerate()
+        .skip(start_line - 1)
+        .take(take)
+        .map(|(i, line)| format!("{:>5}  {line}", i + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|i| format!("line {i}\n")).collect()
+    }
+
+    fn empty_index() -> CodeIndex {
+        CodeIndex::load(Path::new("/nonexistent/index.json"), "p", "m", None)
+    }
+
+    /// 埋め込みの代わりに固定のベクトルで登録する
+    fn insert_all(index: &mut CodeIndex, pending: Vec<PendingFile>) {
+        for file in pending {
+            let vectors = vec![vec![1.0, 0.0]; file.chunks.len()];
+            index.insert(file, vectors);
+        }
+    }
+
+    fn paths(pending: &[PendingFile]) -> Vec<&str> {
+        let mut paths: Vec<&str> = pending.iter().map(|f| f.path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn chunk_text_overlaps_chunks_and_prefixes_path() {
+        let chunks = chunk_text("src/a.rs", &numbered_lines(150));
+        let ranges: Vec<_> = chunks.iter().map(|c| (c.start_line, c.end_line)).collect();
+        assert_eq!(ranges, [(1, 60), (51, 110), (101, 150)]);
+        assert!(chunks[0].text.starts_with("src/a.rs\n\nline 1\n"));
+        assert!(chunks[2].text.ends_with("line 150"));
+    }
+
+    #[test]
+    fn chunk_text_skips_blank_chunks_and_truncates_long_ones() {
+        let text = format!("{}{}", "\n".repeat(120), "x".repeat(10_000));
+        let chunks = chunk_text("a.txt", &text);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].end_line, 121);
+        assert_eq!(chunks[0].text.chars().count(), MAX_CHUNK_CHARS);
+        assert!(chunk_text("a.txt", "").is_empty());
+    }
+
+    #[test]
+    fn scan_reembeds_only_changed_files_and_drops_removed_ones() {
+        let root = std::env::temp_dir().join(format!("tebiki-code-index-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(root.join("src/b.rs"), "fn b() {}\n").unwrap();
+        std::fs::write(root.join("src/c.rs"), "fn c() {}\n").unwrap();
+        let all = [String::new()];
+        let mut index = empty_index();
+
+        let scan = index.scan(&root, &all);
+        assert_eq!(paths(&scan.pending), ["a.rs", "src/b.rs", "src/c.rs"]);
+        insert_all(&mut index, scan.pending);
+
+        // 変更なし
+        let scan = index.scan(&root, &all);
+        assert!(scan.pending.is_empty());
+        assert_eq!(scan.removed, 0);
+
+        // 時刻だけ変わったファイルは埋め込み直さない。内容が変わったファイルだけを返す
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(root.join("a.rs"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        std::fs::write(root.join("src/b.rs"), "fn b() { changed() }\n").unwrap();
+        std::fs::remove_file(root.join("src/c.rs")).unwrap();
+        let scan = index.scan(&root, &all);
+        assert_eq!(paths(&scan.pending), ["src/b.rs"]);
+        assert_eq!(scan.removed, 1);
+        assert_eq!(index.file_count(), 2);
+
+        // 走査の範囲外のファイルは読まず、インデックスからも外さない
+        std::fs::write(root.join("secret.env"), "TOKEN=abc\n").unwrap();
+        let scan = index.scan(&root, &["src".to_string()]);
+        assert_eq!(paths(&scan.pending), ["src/b.rs"]);
+        assert_eq!(scan.removed, 0);
+        assert!(index.files.contains_key("a.rs"));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn search_merges_overlapping_chunks_and_filters_by_dir() {
+        let mut index = empty_index();
+        let chunk = |start_line, end_line| PendingChunk {
+            start_line,
+            end_line,
+            text: String::new(),
+        };
+        let file = |path: &str, chunks| PendingFile {
+            path: path.to_string(),
+            size: 1,
+            modified_ms: 0,
+            hash: String::new(),
+            chunks,
+        };
+        index.insert(
+            file(
+                "src/a.rs",
+                vec![chunk(1, 60), chunk(51, 110), chunk(101, 150)],
+            ),
+            vec![vec![1.0, 0.0], vec![0.9, 0.1], vec![0.8, 0.2]],
+        );
+        index.insert(file("src2/b.rs", vec![chunk(1, 10)]), vec![vec![0.7, 0.3]]);
+
+        let root = Path::new("/nonexistent");
+        let hits = index.search(root, vec![1.0, 0.0], &[String::new()], 10);
+        let found: Vec<_> = hits
+            .iter()
+            .map(|h| (h.path.as_str(), h.start_line, h.end_line))
+            .collect();
+        // 51-110 は 1-60 と重なるので除き、重ならない 101-150 は残す
+        assert_eq!(
+            found,
+            [
+                ("src/a.rs", 1, 60),
+                ("src/a.rs", 101, 150),
+                ("src2/b.rs", 1, 10)
+            ]
+        );
+
+        let hits = index.search(root, vec![1.0, 0.0], &["src".to_string()], 10);
+        assert!(hits.iter().all(|h| h.path == "src/a.rs"));
+        assert_eq!(
+            index
+                .search(root, vec![1.0, 0.0], &[String::new()], 1)
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::llm::embeddings::{EmbeddingRequest, EmbeddingTask};
+use crate::llm::types::ToolDefinition;
+use crate::llm::LlmRegistry;
+
+use super::code_index::{self, CodeIndex, PendingFile};
+use super::types::{ToolContext, ToolResult};
+use super::{config, sandbox};
+use super::{Tool, ToolCategory};
+
+const DEFAULT_MAX_RESULTS: u64 = 8;
+const MAX_RESULTS: u64 = 20;
+/// 一度に埋め込むチャンク数の目安。まとまるごとにインデックスへ反映するので、
+/// 初回の大きなインデックス作成が途中で失敗しても次回はその続きから始まる。
+const EMBED_GROUP_CHUNKS: usize = 256;
+
+pub struct CodeSearchTool {
+    llm_registry: Arc<LlmRegistry>,
+    /// インデックスを置くアプリのデータディレクトリ
+    data_dir: PathBuf,
+    /// インデックスファイルのパス → 読み込み済みのインデックス。
+    /// 同じインデックスを同時に更新しないよう、検索はこのロックの中で行う。
+    indexes: Mutex<HashMap<PathBuf, CodeIndex>>,
+}
+
+impl CodeSearchTool {
+    pub fn new(llm_registry: Arc<LlmRegistry>, data_dir: PathBuf) -> Self {
+        Self {
+            llm_registry,
+            data_dir,
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 変更のあったファイルをまとめて埋め込み、インデックスに反映する
+    async fn embed_pending(
+        &self,
+        index: &mut CodeIndex,
+        provider: &str,
+        model: &str,
+        dimensions: Option<u32>,
+        pending: Vec<PendingFile>,
+    ) -> Result<(), String> {
+        let mut group: Vec<PendingFile> = Vec::new();
+        let mut group_chunks = 0;
+        let mut files = pending.into_iter().peekable();
+        while let Some(file) = files.next() {
+            group_chunks += file.chunks.len();
+            group.push(file);
+            if group_chunks < EMBED_GROUP_CHUNKS && files.peek().is_some() {
+                continue;
+            }
+
+            let request = EmbeddingRequest {
+                model: model.to_string(),
+                inputs: group
+                    .iter()
+                    .flat_map(|f| f.chunks.iter().map(|c| c.text.clone()))
+                    .collect(),
+                task: Some(EmbeddingTask::Document),
+                dimensions,
+            };
+            let mut embeddings = if request.inputs.is_empty() {
+                Vec::new()
+            } else {
+                self.llm_registry
+                    .embed(provider, &request)
+                    .await
+                    .map_err(|e| format!("Failed to embed project files: {e}"))?
+            }
+            .into_iter();
+            for file in group.drain(..) {
+                let vectors = embeddings.by_ref().take(file.chunks.len()).collect();
+                index.insert(file, vectors);
+            }
+            group_chunks = 0;
+        }
+        Ok(())
+    }
+}
+
+/// 走査・検索するディレクトリ（root からの相対パス）。
+/// 読み取り許可ディレクトリに限り、path の指定があればその中だけにする。
+fn search_dirs(ctx: &ToolContext, root: &Path, path: Option<&str>) -> Result<Vec<String>, String> {
+    let dirs: Vec<PathBuf> = match path {
+        Some(path) => vec![sandbox::resolve_read_path(ctx, path)?],
+        None => {
+            if ctx.allowed_read_dirs.is_empty() {
+                return Err(
+                    "File reading is disabled. No allowed read directories configured.".into(),
+                );
+            }
+            ctx.allowed_read_dirs
+                .iter()
+                .filter_map(|dir| std::fs::canonicalize(dir).ok())
+                .collect()
+        }
+    };
+    let dirs: Vec<String> = dirs
+        .iter()
+        .filter_map(|dir| code_index::relative_path(root, dir))
+        .collect();
+    if dirs.is_empty() {
+        return Err(format!(
+            "No allowed read directory is inside the project root '{}'",
+            root.display()
+        ));
+    }
+    Ok(dirs)
+}
+
+#[async_trait]
+impl Tool for CodeSearchTool {
+    fn name(&self) -> &str {
+        "code_search"
+    }
+    fn category(&self) -> ToolCategory {
+        ToolCategory::ReadOnly
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "code_search".to_string(),
+            description: "Semantic search over the project files. Describe what you are looking for in natural language (e.g. 'where are HTTP retries handled') and get the most relevant file locations with line numbers and code snippets. Only files within the allowed read directories are searched, and files ignored by .gitignore are skipped. The index is updated automatically for changed files before each search.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "What to look for, in natural language or as code identifiers"
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "description": "Number of results to return (default: 8, max: 20)"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Only search files under this directory (relative to the project root, e.g. 'src/llm'). Must be within the allowed read directories."
+                    }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn execute(&self, input: &serde_json::Value, ctx: &ToolContext) -> ToolResult {
+        let query = match input["query"].as_str().filter(|q| !q.trim().is_empty()) {
+            Some(q) => q,
+            None => return ToolResult::error("Missing 'query' parameter".into()),
+        };
+        let max_results = input["max_results"]
+            .as_u64()
+            .unwrap_or(DEFAULT_MAX_RESULTS)
+            .clamp(1, MAX_RESULTS) as usize;
+        let path = input["path"]
+            .as_str()
+            .map(str::trim)
+            .filter(|p| !p.is_empty());
+
+        let Some(provider) = ctx.embedding_provider.as_deref() else {
+            return ToolResult::error(
+                "Code search is not configured. Set TEBIKI_EMBEDDING_PROVIDER to the name of an LLM provider that supports embeddings (google or openai_compatible).".into(),
+            );
+        };
+        let embedder = match self.llm_registry.require_embedder(provider) {
+            Ok(handle) => handle.embedder,
+            Err(e) => return ToolResult::error(e.to_string()),
+        };
+        let Some(model) = ctx
+            .embedding_model
+            .clone()
+            .or_else(|| embedder.default_model().map(str::to_string))
+        else {
+            return ToolResult::error(format!(
+                "Provider '{provider}' has no default embedding model. Set TEBIKI_EMBEDDING_MODEL."
+            ));
+        };
+        let dimensions = ctx.embedding_dimensions;
+
+        let root = match tokio::fs::canonicalize(&ctx.working_dir).await {
+            Ok(p) => p,
+            Err(e) => {
+                return ToolResult::error(format!(
+                    "Failed to resolve working directory '{}': {e}",
+                    ctx.working_dir.display()
+                ))
+            }
+        };
+        let dirs = match search_dirs(ctx, &root, path) {
+            Ok(dirs) => dirs,
+            Err(e) => return ToolResult::error(e),
+        };
+        let index_path =
+            code_index::index_path(&self.data_dir, &root, provider, &model, dimensions);
+
+        let mut indexes = self.indexes.lock().await;
+        let index = indexes
+            .entry(index_path.clone())
+            .or_insert_with(|| CodeIndex::load(&index_path, provider, &model, dimensions));
+
+        let scan = index.scan(&root, &dirs);
+        let updated_files = scan.pending.len();
+        let embedded = self
+            .embed_pending(index, provider, &model, dimensions, scan.pending)
+            .await;
+        // 失敗しても埋め込めた分は残す
+        if let Err(e) = index.save(&index_path) {
+            eprintln!(
+                "[tebiki] Failed to save code index '{}': {e}",
+                index_path.display()
+            );
+        }
+        if let Err(e) = embedded {
+            return ToolResult::error(e);
+        }
+
+        let query_request = EmbeddingRequest {
+            model: model.clone(),
+            inputs: vec![query.to_string()],
+            task: Some(EmbeddingTask::Query),
+            dimensions,
+        };
+        let query_vector = match self.llm_registry.embed(provider, &query_request).await {
+            Ok(mut vectors) if !vectors.is_empty() => vectors.swap_remove(0),
+            Ok(_) => return ToolResult::error("Embedding provider returned no vector".into()),
+            Err(e) => return ToolResult::error(format!("Failed to embed query: {e}")),
+        };
+        let results = index.search(&root, query_vector, &dirs, max_results);
+
+        ToolResult::ok(
+            serde_json::json!({
+                "query": query,
+                "indexed_files": index.file_count(),
+                "updated_files": updated_files,
+                "removed_files": scan.removed,
+                "skipped_files": scan.skipped,
+                "results": results,
+            })
+            .to_string(),
+        )
+    }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: config::ReadDirsConfig = config::parse(self.name(), config)?;
+        if let Some(dirs) = config.allowed_read_dirs {
+            config::apply_read_dirs(ctx, &dirs)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::types::ToolContext;
+
+/// 設定なしとみなす値（null と空オブジェクト）
+pub fn is_empty(config: &serde_json::Value) -> bool {
+    config.is_null() || config.as_object().is_some_and(|o| o.is_empty())
+}
+
+/// agent_tool_permissions.config をツールの設定型に読み取る。未知の項目はエラーにする
+/// （設定型には `#[serde(deny_unknown_fields)]` を付けること）。
+pub fn parse<T: DeserializeOwned + Default>(
+    tool_name: &str,
+    config: &serde_json::Value,
+) -> Result<T, String> {
+    if is_empty(config) {
+        return Ok(T::default());
+    }
+    serde_json::from_value(config.clone())
+        .map_err(|e| format!("Invalid config for tool '{tool_name}': {e}"))
+}
+
+/// 設定できるCPU時間（秒）とメモリ（MB）の範囲
+const MIN_CPU_TIME_SECS: u64 = 1;
+const MAX_CPU_TIME_SECS: u64 = 3_600;
+const MIN_MEMORY_MB: u64 = 64;
+const MAX_MEMORY_MB: u64 = 65_536;
+
+/// 読み取り許可ディレクトリだけを設定できるツール（file_read / list_dir / grep_files / code_search）の設定
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadDirsConfig {
+    /// 読み取りを許可するディレクトリ（プロジェクトルート基準）。空配列なら読み取り禁止
+    pub allowed_read_dirs: Option<Vec<String>>,
+}
+
+/// 書き込み許可ディレクトリだけを設定できるツール（file_write / file_edit）の設定
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WriteDirsConfig {
+    /// 書き込みを許可するディレクトリ（プロジェクトルート基準）。空配列なら書き込み禁止
+    pub allowed_write_dirs: Option<Vec<String>>,
+}
+
+/// shell_exec / self_eval の設定のうち、両ツールで共有するプロセスの制限
+#[derive(Debug, Default)]
+pub struct ProcessLimitsConfig {
+    pub env_passthrough: Option<Vec<String>>,
+    pub cpu_time_secs: Option<u64>,
+    pub memory_mb: Option<u64>,
+    pub network: Option<bool>,
+}
+
+/// 設定項目を持たないツール用
+pub fn reject(tool_name: &str, config: &serde_json::Value) -> Result<(), String> {
+    if is_empty(config) {
+        Ok(())
+    } else {
+        Err(format!("Tool '{tool_name}' does not accept config"))
+    }
+}
+
+/// タイムアウトなどの数値が範囲内か検証する
+pub fn check_range(name: &str, value: u64, min: u64, max: u64) -> Result<u64, String> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{name} must be between {min} and {max}"))
+    }
+}
+
+/// ディレクトリ指定を working_dir 内の正規化済みパスに解決する。
+/// 相対パスは working_dir 基準。working_dir の外や存在しないディレクトリはエラー。
+pub fn resolve_dirs(ctx: &ToolContext, dirs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let root = std::fs::canonicalize(&ctx.working_dir).map_err(|e| {
+        format!(
+            "Failed to resolve project root '{}': {e}",
+            ctx.working_dir.display()
+        )
+    })?;
+    dirs.iter()
+        .map(|dir| {
+            let path = ctx.working_dir.join(dir);
+            let canonical = std::fs::canonicalize(&path)
+                .map_err(|e| format!("Failed to resolve directory '{dir}': {e}"))?;
+            if !canonical.is_dir() {
+                return Err(format!("'{dir}' is not a directory"));
+            }
+            if !canonical.starts_with(&root) {
+                return Err(format!(
+                    "Directory '{dir}' is outside the project root '{}'",
+                    root.display()
+                ));
+            }
+            Ok(canonical)
+        })
+        .collect()
+}
+
+/// 2つの許可ディレクトリの共通部分（どちらでも許可される範囲）。
+/// 同じ allowlist を複数のツールが設定しても、適用順によらず狭い方になる。
+fn narrow_dirs(current: &[PathBuf], configured: Vec<PathBuf>) -> Vec<PathBuf> {
+    let within = |dir: &Path, dirs: &[PathBuf]| dirs.iter().any(|d| dir.starts_with(d));
+    let mut dirs: Vec<PathBuf> = configured
+        .iter()
+        .filter(|dir| within(dir, current))
+        .chain(current.iter().filter(|dir| within(dir, &configured)))
+        .cloned()
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+/// 読み取り許可ディレクトリを設定で狭める（file_read / list_dir / grep_files / code_search 共通の allowlist）
+pub fn apply_read_dirs(ctx: &mut ToolContext, dirs: &[String]) -> Result<(), String> {
+    let dirs = resolve_dirs(ctx, dirs)?;
+    ctx.allowed_read_dirs = narrow_dirs(&ctx.allowed_read_dirs, dirs);
+    Ok(())
+}
+
+/// 書き込み許可ディレクトリを設定で狭める（file_write / file_edit 共通の allowlist）
+pub fn apply_write_dirs(ctx: &mut ToolContext, dirs: &[String]) -> Result<(), String> {
+    let dirs = resolve_dirs(ctx, dirs)?;
+    ctx.allowed_write_dirs = narrow_dirs(&ctx.allowed_write_dirs, dirs);
+    Ok(())
+}
+
+/// 環境変数名として妥当か（英字か '_' で始まり、英数字と '_' だけ）
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// プロセスの制限を設定に合わせる。別のツールの設定で指定済みの項目は厳しい方を取るため、
+/// shell_exec と self_eval のどちらの設定を先に適用しても結果は変わらない。
+pub fn apply_process_limits(
+    ctx: &mut ToolContext,
+    config: ProcessLimitsConfig,
+) -> Result<(), String> {
+    let limits = &mut ctx.process_limits;
+    let configured = &mut ctx.configured_limits;
+    if let Some(names) = config.env_passthrough {
+        if let Some(invalid) = names.iter().find(|n| !is_env_name(n)) {
+            return Err(format!(
+                "Invalid env_passthrough entry '{invalid}': must be an environment variable name"
+            ));
+        }
+        if configured.insert("env_passthrough") {
+            limits.env_passthrough = names;
+        } else {
+            limits.env_passthrough.retain(|n| names.contains(n));
+        }
+    }
+    if let Some(secs) = config.cpu_time_secs {
+        let secs = check_range("cpu_time_secs", secs, MIN_CPU_TIME_SECS, MAX_CPU_TIME_SECS)?;
+        limits.cpu_time_secs = if configured.insert("cpu_time_secs") {
+            secs
+        } else {
+            limits.cpu_time_secs.min(secs)
+        };
+    }
+    if let Some(mb) = config.memory_mb {
+        let mb = check_range("memory_mb", mb, MIN_MEMORY_MB, MAX_MEMORY_MB)?;
+        limits.memory_mb = if configured.insert("memory_mb") {
+            mb
+        } else {
+            limits.memory_mb.min(mb)
+        };
+    }
+    if let Some(network) = config.network {
+        if !network && !cfg!(target_os = "linux") {
+            return Err("network: false is only supported on Linux".into());
+        }
+        limits.network = if configured.insert("network") {
+            network
+        } else {
+            limits.network && network
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrow_dirs_keeps_only_the_common_part_in_any_order() {
+        let root = || vec![PathBuf::from("/p")];
+        let src = PathBuf::from("/p/src");
+        let src_a = PathBuf::from("/p/src/a");
+        let docs = PathBuf::from("/p/docs");
+
+        let first = narrow_dirs(&root(), vec![src.clone()]);
+        assert_eq!(first, vec![src.clone()]);
+        assert_eq!(
+            narrow_dirs(&first, vec![src_a.clone(), docs.clone()]),
+            vec![src_a.clone()]
+        );
+
+        let first = narrow_dirs(&root(), vec![src_a.clone(), docs]);
+        assert_eq!(narrow_dirs(&first, vec![src]), vec![src_a]);
+    }
+
+    #[test]
+    fn narrow_dirs_with_empty_list_denies_everything() {
+        assert!(narrow_dirs(&[PathBuf::from("/p")], Vec::new()).is_empty());
+        assert!(narrow_dirs(&[], vec![PathBuf::from("/p/src")]).is_empty());
+    }
+}
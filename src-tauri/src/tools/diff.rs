@@ -0,0 +1,540 @@
+/// 差分の前後に付ける行数
+const CONTEXT_LINES: usize = 3;
+/// 行単位の LCS を計算する表の上限（超えたら変更範囲全体を削除＋追加として扱う）
+const MAX_LCS_CELLS: usize = 4_000_000;
+/// パッチの当たる位置が見つからないとき、エラーに載せるファイルの行数
+const CONFLICT_EXCERPT_LINES: usize = 12;
+/// 最終行の後に改行がないことを示す行
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// テキストを行に分ける。改行（\n / \r\n）は含めない
+fn split_lines(text: &str) -> Vec<&str> {
+    text.lines().collect()
+}
+
+/// 差分用に行へ分ける。2番目の値は「この行の後に改行がない」（ファイル末尾の改行なし）
+fn diff_lines(text: &str) -> Vec<(&str, bool)> {
+    let mut lines: Vec<(&str, bool)> = text.lines().map(|l| (l, false)).collect();
+    if !text.ends_with('\n') {
+        if let Some(last) = lines.last_mut() {
+            last.1 = true;
+        }
+    }
+    lines
+}
+
+/// 行の編集列を求める。共通の先頭・末尾を除いた部分だけ LCS を取る
+fn diff_ops<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(Op, usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<(Op, usize, usize)> = (0..prefix).map(|i| (Op::Equal, i, i)).collect();
+
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if n * m <= MAX_LCS_CELLS {
+        // lcs[i][j] = old_mid[i..] と new_mid[j..] の LCS の長さ
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                ops.push((Op::Equal, prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+                // 削除を追加より先に並べる
+                ops.push((Op::Delete, prefix + i, prefix + j));
+                i += 1;
+            } else {
+                ops.push((Op::Insert, prefix + i, prefix + j));
+                j += 1;
+            }
+        }
+    } else {
+        ops.extend((0..n).map(|i| (Op::Delete, prefix + i, prefix)));
+        ops.extend((0..m).map(|j| (Op::Insert, prefix + n, prefix + j)));
+    }
+
+    ops.extend((0..suffix).map(|k| (Op::Equal, old.len() - suffix + k, new.len() - suffix + k)));
+    ops
+}
+
+/// unified diff 形式の差分を作る。変更がなければ空文字列
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines = diff_lines(old);
+    let new_lines = diff_lines(new);
+    let ops = diff_ops(&old_lines, &new_lines);
+
+    // 変更のある位置を、前後の文脈を含むハンクにまとめる
+    let changed: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _, _))| *op != Op::Equal)
+        .map(|(k, _)| k)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &k in &changed {
+        let start = k.saturating_sub(CONTEXT_LINES);
+        let end = (k + CONTEXT_LINES + 1).min(ops.len());
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- a/{path}\n+++ b/{path}\n");
+    for (start, end) in ranges {
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|(op, _, _)| *op != Op::Insert).count();
+        let new_count = hunk.iter().filter(|(op, _, _)| *op != Op::Delete).count();
+        let (_, old_start, new_start) = hunk[0];
+        // 0行の範囲は直前の行番号で表す（unified diff の慣習）
+        let old_start = if old_count == 0 {
+            old_start
+        } else {
+            old_start + 1
+        };
+        let new_start = if new_count == 0 {
+            new_start
+        } else {
+            new_start + 1
+        };
+        out.push_str(&format!(
+            "@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"
+        ));
+        for &(op, i, j) in hunk {
+            let (prefix, (line, no_newline)) = match op {
+                Op::Equal => (' ', old_lines[i]),
+                Op::Delete => ('-', old_lines[i]),
+                Op::Insert => ('+', new_lines[j]),
+            };
+            out.push(prefix);
+            out.push_str(line);
+            out.push('\n');
+            if no_newline {
+                out.push_str(NO_NEWLINE_MARKER);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+/// パッチの1ハンク
+struct Hunk {
+    header: String,
+    /// ヘッダに書かれた元ファイルの開始行（1始まり。追加だけのハンクでは直前の行）
+    old_start: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+    /// 元 / 変更後の最終行の後に改行がない（"\ No newline at end of file"）
+    old_no_newline: bool,
+    new_no_newline: bool,
+}
+
+/// "-12,5" / "+12" のような範囲を (開始行, 行数) にする。行数の省略は1行
+fn parse_range(range: &str, sign: char) -> Option<(usize, usize)> {
+    let range = range.strip_prefix(sign)?;
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// "@@ -12,5 +12,6 @@" から元と変更後の範囲を読む
+fn parse_hunk_header(line: &str) -> Option<((usize, usize), (usize, usize))> {
+    let mut parts = line.strip_prefix("@@ ")?.split_whitespace();
+    let old = parse_range(parts.next()?, '-')?;
+    let new = parse_range(parts.next()?, '+')?;
+    (parts.next()? == "@@").then_some((old, new))
+}
+
+/// 直前に読んだハンクの行がどちら側のものか（改行なしの印の対象）
+#[derive(Clone, Copy)]
+enum Side {
+    Old,
+    New,
+    Both,
+}
+
+/// ハンクの本体を、ヘッダに書かれた行数だけ読む。
+/// 行数で区切るので、"-- " で始まる行の削除や "++ " で始まる行の追加もファイルヘッダと取り違えない。
+fn parse_hunk<'a>(
+    header: &str,
+    index: usize,
+    lines: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> Result<Hunk, String> {
+    let label = format!("Hunk {} ({header})", index + 1);
+    let ((old_start, mut old_left), (_, mut new_left)) = parse_hunk_header(header)
+        .ok_or_else(|| format!("Invalid hunk header: '{header}' (expected '@@ -l,s +l,s @@')"))?;
+    let mut hunk = Hunk {
+        header: header.to_string(),
+        old_start,
+        old_lines: Vec::new(),
+        new_lines: Vec::new(),
+        old_no_newline: false,
+        new_no_newline: false,
+    };
+    let too_short = || {
+        format!(
+            "{label} has fewer lines than its header declares. Fix the line counts in the '@@' header."
+        )
+    };
+
+    let mut last: Option<Side> = None;
+    loop {
+        // 行数を満たした後も、直後の改行なしの印はこのハンクに属する
+        let done = old_left == 0 && new_left == 0;
+        let line = match lines.peek() {
+            Some(line) if line.starts_with('\\') => *line,
+            Some(_) if done => break,
+            Some(line) => *line,
+            None if done => break,
+            None => return Err(too_short()),
+        };
+        lines.next();
+
+        if line.starts_with('\\') {
+            match last {
+                Some(Side::Old) => hunk.old_no_newline = true,
+                Some(Side::New) => hunk.new_no_newline = true,
+                Some(Side::Both) => {
+                    hunk.old_no_newline = true;
+                    hunk.new_no_newline = true;
+                }
+                None => return Err(format!("{label}: '{line}' does not follow a line")),
+            }
+            continue;
+        }
+        let side = match line.chars().next() {
+            // 文脈行。先頭の空白が落とされた空行も文脈として扱う
+            Some(' ') | None => {
+                if old_left == 0 || new_left == 0 {
+                    return Err(too_short());
+                }
+                old_left -= 1;
+                new_left -= 1;
+                let text = line.get(1..).unwrap_or("");
+                hunk.old_lines.push(text.to_string());
+                hunk.new_lines.push(text.to_string());
+                Side::Both
+            }
+            Some('-') => {
+                if old_left == 0 {
+                    return Err(too_short());
+                }
+                old_left -= 1;
+                hunk.old_lines.push(line[1..].to_string());
+                Side::Old
+            }
+            Some('+') => {
+                if new_left == 0 {
+                    return Err(too_short());
+                }
+                new_left -= 1;
+                hunk.new_lines.push(line[1..].to_string());
+                Side::New
+            }
+            _ => {
+                return Err(format!(
+                    "{label}: unexpected line '{line}'. Every line in a hunk must start with ' ', '-' or '+'."
+                ))
+            }
+        };
+        last = Some(side);
+    }
+    Ok(hunk)
+}
+
+/// unified diff を解釈する。ハンクの本体はヘッダの行数どおりに読み、
+/// ハンクの外の行（diff --git, index, 説明など）は無視する。1ファイル分のパッチだけを受け付ける。
+fn parse_patch(patch: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut file_headers = 0;
+    let mut lines = patch.lines().peekable();
+    while let Some(line) = lines.next() {
+        if line.starts_with("--- ") && lines.peek().is_some_and(|next| next.starts_with("+++ ")) {
+            lines.next();
+            file_headers += 1;
+            if file_headers > 1 {
+                return Err(
+                    "Patch changes more than one file. file_edit applies a patch to the single file given in 'path'; split it into one call per file."
+                        .into(),
+                );
+            }
+            continue;
+        }
+        if line.starts_with("@@") {
+            hunks.push(parse_hunk(line, hunks.len(), &mut lines)?);
+            continue;
+        }
+        if let Some(hunk) = hunks.last() {
+            if line.starts_with([' ', '-', '+']) {
+                return Err(format!(
+                    "Hunk {} ({}) has more lines than its header declares. Fix the line counts in the '@@' header.",
+                    hunks.len(),
+                    hunk.header
+                ));
+            }
+        }
+    }
+    if hunks.is_empty() {
+        return Err("Patch contains no hunks (expected lines starting with '@@ -')".into());
+    }
+    Ok(hunks)
+}
+
+/// lines[from..] で old が一致する位置のうち expected に最も近いものを探す
+fn find_hunk(
+    lines: &[String],
+    old: &[String],
+    from: usize,
+    expected: usize,
+    eq: impl Fn(&str, &str) -> bool,
+) -> Option<usize> {
+    if lines.len() < old.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    if from > last {
+        return None;
+    }
+    let matches_at = |pos: usize| {
+        lines[pos..pos + old.len()]
+            .iter()
+            .zip(old)
+            .all(|(a, b)| eq(a, b))
+    };
+    let expected = expected.clamp(from, last);
+    for distance in 0..=(last - from) {
+        if let Some(pos) = expected.checked_sub(distance).filter(|p| *p >= from) {
+            if matches_at(pos) {
+                return Some(pos);
+            }
+        }
+        let pos = expected + distance;
+        if distance > 0 && pos <= last && matches_at(pos) {
+            return Some(pos);
+        }
+    }
+    None
+}
+
+/// 当たらなかったハンクのエラー。モデルが読み直して修正できるよう、期待した行と実際の行を載せる
+fn conflict_message(
+    lines: &[String],
+    hunk: &Hunk,
+    index: usize,
+    total: usize,
+    expected: usize,
+) -> String {
+    let expected_lines = hunk
+        .old_lines
+        .iter()
+        .map(|l| format!("        {l}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let start = expected.saturating_sub(3).min(lines.len());
+    let end = (start + CONFLICT_EXCERPT_LINES.max(hunk.old_lines.len() + 6)).min(lines.len());
+    let excerpt = lines[start..end]
+        .iter()
+        .enumerate()
+        .map(|(k, l)| format!("{:>6}  {l}", start + k + 1))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "Hunk {} of {total} ({}) does not apply: its context and removed lines were not found in the file. \
+         The file may have changed; re-read it and regenerate the patch, or use old_string/new_string.\n\
+         Lines the hunk expects:\n{expected_lines}\n\
+         File around line {}:\n{excerpt}",
+        index + 1,
+        hunk.header,
+        start + 1
+    )
+}
+
+/// unified diff をテキストに当てる。ハンクの位置がずれていても文脈が一致すれば当てる
+/// （行末の空白の違いは許す）。当たらないハンクがあれば何も変更せずエラーにする。
+/// 末尾の改行は元のテキストに合わせ、"\ No newline at end of file" があればそれに従う。
+/// 戻り値は変更後のテキストと当てたハンク数。
+pub fn apply_patch(original: &str, patch: &str) -> Result<(String, usize), String> {
+    let hunks = parse_patch(patch)?;
+    let eol = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut ends_with_newline = original.is_empty() || original.ends_with('\n');
+
+    let mut lines: Vec<String> = split_lines(original)
+        .into_iter()
+        .map(String::from)
+        .collect();
+    // 前のハンクを当てたことによる行番号のずれ
+    let mut offset: isize = 0;
+    // 前のハンクの末尾（ハンクは順に当てる）
+    let mut from = 0;
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+        let position = if hunk.old_lines.is_empty() {
+            // 文脈のない追加は記載された行の直後に入れる（"-0,0" はファイル先頭）
+            let at = (hunk.old_start as isize + offset).max(0) as usize;
+            Some(at.clamp(from, lines.len()))
+        } else {
+            find_hunk(&lines, &hunk.old_lines, from, expected, |a, b| a == b).or_else(|| {
+                find_hunk(&lines, &hunk.old_lines, from, expected, |a, b| {
+                    a.trim_end() == b.trim_end()
+                })
+            })
+        };
+
+        let Some(position) = position else {
+            return Err(conflict_message(&lines, hunk, index, hunks.len(), expected));
+        };
+
+        // ファイル末尾に当たるハンクだけが、末尾の改行の有無を変えられる
+        if position + hunk.old_lines.len() == lines.len()
+            && (hunk.old_no_newline || hunk.new_no_newline)
+        {
+            ends_with_newline = !hunk.new_no_newline;
+        }
+        lines.splice(
+            position..position + hunk.old_lines.len(),
+            hunk.new_lines.iter().cloned(),
+        );
+        offset += hunk.new_lines.len() as isize - hunk.old_lines.len() as isize;
+        offset += position as isize - expected as isize;
+        from = position + hunk.new_lines.len();
+    }
+
+    let mut text = lines.join(eol);
+    if ends_with_newline && !text.is_empty() {
+        text.push_str(eol);
+    }
+    Ok((text, hunks.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// unified_diff の出力を元のテキストに当てると変更後のテキストに戻る
+    fn assert_round_trip(old: &str, new: &str) {
+        let patch = unified_diff("file.txt", old, new);
+        let (patched, _) = apply_patch(old, &patch)
+            .unwrap_or_else(|e| panic!("patch did not apply: {e}\n{patch}"));
+        assert_eq!(patched, new, "patch:\n{patch}");
+    }
+
+    #[test]
+    fn round_trips_unified_diff() {
+        let long: String = (1..=40).map(|i| format!("line {i}\n")).collect();
+        let cases = [
+            ("", "a\nb\n"),
+            ("a\nb\n", ""),
+            ("a\nb\nc\n", "a\nB\nc\n"),
+            ("a\nb\nc\n", "x\na\nb\nc\ny\n"),
+            (
+                long.as_str(),
+                &long
+                    .replace("line 3\n", "")
+                    .replace("line 30\n", "changed\n"),
+            ),
+            ("a\r\nb\r\nc\r\n", "a\r\nB\r\nc\r\n"),
+        ];
+        for (old, new) in cases {
+            assert_round_trip(old, new);
+        }
+    }
+
+    #[test]
+    fn round_trips_lines_that_look_like_file_headers() {
+        assert_round_trip("a\n-- c\nb\n", "a\n++ d\nb\n");
+        assert_round_trip("-- c\n-- e\n", "++ d\n++ f\n");
+        assert_round_trip("x\n", "x\n--- a/y\n+++ b/y\n");
+    }
+
+    #[test]
+    fn round_trips_missing_trailing_newline() {
+        assert_round_trip("a\nb", "a\nc");
+        assert_round_trip("a\nb", "a\nb\n");
+        assert_round_trip("a\nb\n", "a\nb");
+        assert_round_trip("a", "a\nb");
+        let patch = unified_diff("file.txt", "a\nb\n", "a\nb");
+        assert!(patch.contains(NO_NEWLINE_MARKER), "{patch}");
+    }
+
+    #[test]
+    fn applies_hunk_at_shifted_position() {
+        let original = "0\n1\n2\na\nb\nc\n";
+        let patch = "@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n";
+        let (patched, hunks) = apply_patch(original, patch).unwrap();
+        assert_eq!(patched, "0\n1\n2\na\nB\nc\n");
+        assert_eq!(hunks, 1);
+    }
+
+    #[test]
+    fn reads_hunk_body_by_line_counts() {
+        // "-- c" の削除と "++ d" の追加は、行数の範囲内なのでファイルヘッダではない
+        let patch = "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n a\n--- c\n+++ d\n b\n";
+        let (patched, _) = apply_patch("a\n-- c\nb\n", patch).unwrap();
+        assert_eq!(patched, "a\n++ d\nb\n");
+    }
+
+    #[test]
+    fn rejects_wrong_line_counts() {
+        let short = apply_patch("a\nb\n", "@@ -1,3 +1,3 @@\n a\n-b\n+B\n").unwrap_err();
+        assert!(short.contains("fewer lines"), "{short}");
+        let long = apply_patch("a\nb\nc\n", "@@ -1,1 +1,1 @@\n-a\n+A\n b\n").unwrap_err();
+        assert!(long.contains("more lines"), "{long}");
+    }
+
+    #[test]
+    fn rejects_lines_without_prefix_inside_hunk() {
+        let error =
+            apply_patch("a\nb\n", "@@ -1,2 +1,2 @@\n a\nindex 123..456\n-b\n+B\n").unwrap_err();
+        assert!(error.contains("unexpected line"), "{error}");
+    }
+
+    #[test]
+    fn rejects_multi_file_patch() {
+        let patch = "diff --git a/x b/x\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n\
+                     diff --git a/y b/y\n--- a/y\n+++ b/y\n@@ -1 +1 @@\n-c\n+d\n";
+        let error = apply_patch("a\n", patch).unwrap_err();
+        assert!(error.contains("more than one file"), "{error}");
+    }
+
+    #[test]
+    fn reports_conflicting_hunk() {
+        let error = apply_patch("a\nb\nc\n", "@@ -1,2 +1,2 @@\n a\n-x\n+y\n").unwrap_err();
+        assert!(error.starts_with("Hunk 1 of 1"), "{error}");
+        assert!(error.contains("     1  a"), "{error}");
+    }
+}
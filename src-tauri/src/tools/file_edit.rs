@@ -0,0 +1,313 @@
+use async_trait::async_trait;
+
+use crate::llm::types::ToolDefinition;
+
+use super::types::{ToolContext, ToolResult};
+use super::{config, diff, sandbox};
+use super::{Tool, ToolCategory};
+
+/// これより大きいファイルは編集しない
+const MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+/// 結果に載せる差分の上限
+const MAX_DIFF_BYTES: usize = 50_000;
+/// 一致箇所が複数あるときにエラーに載せる行番号の数
+const MAX_REPORTED_MATCHES: usize = 10;
+
+pub struct FileEditTool;
+
+/// content 内の byte 位置の行番号（1始まり）
+fn line_number(content: &str, index: usize) -> usize {
+    content[..index].matches('\n').count() + 1
+}
+
+/// old_string を new_string に置き換える。一致しない・一意でないときはモデルが直せるエラーを返す
+fn replace_exact(
+    content: &str,
+    old: &str,
+    new: &str,
+    replace_all: bool,
+) -> Result<(String, usize), String> {
+    if old.is_empty() {
+        return Err(
+            "'old_string' must not be empty. Use file_write to create a file or a patch to insert lines."
+                .into(),
+        );
+    }
+    if old == new {
+        return Err("'old_string' and 'new_string' are identical".into());
+    }
+
+    // CRLF のファイルに LF の文字列で指定された場合は改行をそろえる
+    let (old, new) = if content.contains("\r\n") && old.contains('\n') && !old.contains("\r\n") {
+        (old.replace('\n', "\r\n"), new.replace('\n', "\r\n"))
+    } else {
+        (old.to_string(), new.to_string())
+    };
+
+    let positions: Vec<usize> = content.match_indices(&old).map(|(i, _)| i).collect();
+    match positions.len() {
+        0 => {
+            let mut message = "'old_string' was not found in the file. Re-read the file with file_read and copy the text exactly, including indentation and line breaks.".to_string();
+            // 空白だけが違う行があれば場所を教える
+            let normalize = |line: &str| line.split_whitespace().collect::<Vec<_>>().join(" ");
+            if let Some(first) = old.lines().map(normalize).find(|l| !l.is_empty()) {
+                let similar: Vec<String> = content
+                    .lines()
+                    .enumerate()
+                    .filter(|(_, l)| normalize(l) == first)
+                    .map(|(i, _)| (i + 1).to_string())
+                    .take(MAX_REPORTED_MATCHES)
+                    .collect();
+                if !similar.is_empty() {
+                    message.push_str(&format!(
+                        " A line matching the first line of 'old_string' except for whitespace exists at line(s) {}.",
+                        similar.join(", ")
+                    ));
+                }
+            }
+            Err(message)
+        }
+        1 => Ok((content.replacen(&old, &new, 1), 1)),
+        n if replace_all => Ok((content.replace(&old, &new), n)),
+        n => {
+            let lines: Vec<String> = positions
+                .iter()
+                .take(MAX_REPORTED_MATCHES)
+                .map(|&i| line_number(content, i).to_string())
+                .collect();
+            Err(format!(
+                "'old_string' matches {n} times (at lines {}). Include more surrounding lines to make it unique, or set 'replace_all' to true to replace every occurrence.",
+                lines.join(", ")
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FileEditTool {
+    fn name(&self) -> &str {
+        "file_edit"
+    }
+    fn category(&self) -> ToolCategory {
+        ToolCategory::FileSystem
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "file_edit".to_string(),
+            description: "Edit an existing file without resending it. Either replace 'old_string' with 'new_string' (old_string must match exactly once unless replace_all is set), or apply a unified diff given in 'patch'. Returns the resulting diff. The file must be within the allowed write directories.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "File path to edit (relative to working directory, or absolute)"
+                    },
+                    "old_string": {
+                        "type": "string",
+                        "description": "Exact text to replace, including indentation and line breaks"
+                    },
+                    "new_string": {
+                        "type": "string",
+                        "description": "Text to replace 'old_string' with"
+                    },
+                    "replace_all": {
+                        "type": "boolean",
+                        "description": "Replace every occurrence of 'old_string' (default: false)"
+                    },
+                    "patch": {
+                        "type": "string",
+                        "description": "Unified diff for this one file to apply instead of old_string/new_string. Each hunk starts with '@@ -l,s +l,s @@' and the line counts must match the hunk body; file headers are optional"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn execute(&self, input: &serde_json::Value, ctx: &ToolContext) -> ToolResult {
+        let path_str = match input["path"].as_str() {
+            Some(p) => p,
+            None => return ToolResult::error("Missing 'path' parameter".into()),
+        };
+        let patch = input["patch"].as_str();
+        if patch.is_some() && !input["old_string"].is_null() {
+            return ToolResult::error(
+                "Specify either 'patch' or 'old_string'/'new_string', not both".into(),
+            );
+        }
+
+        let path = match sandbox::resolve_write_path(ctx, path_str) {
+            Ok(p) => p,
+            Err(e) => return ToolResult::error(e),
+        };
+        let metadata = match tokio::fs::metadata(&path).await {
+            Ok(m) if m.is_file() => m,
+            Ok(_) => return ToolResult::error(format!("'{path_str}' is not a file")),
+            Err(e) => {
+                return ToolResult::error(format!(
+                    "Failed to open '{path_str}': {e}. Use file_write to create a new file."
+                ))
+            }
+        };
+        if metadata.len() > MAX_FILE_BYTES {
+            return ToolResult::error(format!(
+                "File is too large to edit ({} bytes, limit {MAX_FILE_BYTES})",
+                metadata.len()
+            ));
+        }
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(b) => b,
+            Err(e) => return ToolResult::error(format!("Failed to read file: {e}")),
+        };
+        if bytes.contains(&0) {
+            return ToolResult::error(format!("'{path_str}' is a binary file"));
+        }
+        let Ok(original) = String::from_utf8(bytes) else {
+            return ToolResult::error(format!("'{path_str}' is not valid UTF-8"));
+        };
+
+        let (mode, edited, count) = if let Some(patch) = patch {
+            match diff::apply_patch(&original, patch) {
+                Ok((text, hunks)) => ("patch", text, hunks),
+                Err(e) => return ToolResult::error(e),
+            }
+        } else {
+            let (Some(old), Some(new)) =
+                (input["old_string"].as_str(), input["new_string"].as_str())
+            else {
+                return ToolResult::error(
+                    "Missing 'old_string'/'new_string' (or 'patch') parameter".into(),
+                );
+            };
+            let replace_all = input["replace_all"].as_bool().unwrap_or(false);
+            match replace_exact(&original, old, new, replace_all) {
+                Ok((text, replacements)) => ("replace", text, replacements),
+                Err(e) => return ToolResult::error(e),
+            }
+        };
+        if edited == original {
+            return ToolResult::error("The edit produced no changes".into());
+        }
+
+        if let Err(e) = tokio::fs::write(&path, &edited).await {
+            return ToolResult::error(format!("Failed to write file: {e}"));
+        }
+
+        let display = sandbox::display_path(&sandbox::project_root(ctx), &path);
+        let mut diff = diff::unified_diff(&display, &original, &edited);
+        let diff_truncated = diff.len() > MAX_DIFF_BYTES;
+        if diff_truncated {
+            let mut end = MAX_DIFF_BYTES;
+            while !diff.is_char_boundary(end) {
+                end -= 1;
+            }
+            diff.truncate(end);
+        }
+
+        let mut result = serde_json::json!({
+            "path": display,
+            "mode": mode,
+            "diff": diff,
+            "diff_truncated": diff_truncated,
+            "success": true
+        });
+        let count_key = if mode == "patch" {
+            "hunks_applied"
+        } else {
+            "replacements"
+        };
+        result[count_key] = count.into();
+        ToolResult::ok(result.to_string())
+    }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: config::WriteDirsConfig = config::parse(self.name(), config)?;
+        if let Some(dirs) = config.allowed_write_dirs {
+            config::apply_write_dirs(ctx, &dirs)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_unique_match() {
+        let (text, count) = replace_exact("fn a() {}\nfn b() {}\n", "fn b", "fn c", false).unwrap();
+        assert_eq!(text, "fn a() {}\nfn c() {}\n");
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn reports_missing_match_with_whitespace_hint() {
+        let content = "fn main() {\n    let  x = 1;\n}\n";
+        let error = replace_exact(content, "let x = 1;", "let x = 2;", false).unwrap_err();
+        assert!(error.contains("was not found"), "{error}");
+        assert!(error.contains("line(s) 2"), "{error}");
+
+        let error = replace_exact(content, "missing", "x", false).unwrap_err();
+        assert!(!error.contains("line(s)"), "{error}");
+    }
+
+    #[test]
+    fn reports_multiple_matches_unless_replace_all() {
+        let content = "x = 1\ny = 2\nx = 1\n";
+        let error = replace_exact(content, "x = 1", "x = 3", false).unwrap_err();
+        assert!(error.contains("matches 2 times (at lines 1, 3)"), "{error}");
+
+        let (text, count) = replace_exact(content, "x = 1", "x = 3", true).unwrap();
+        assert_eq!(text, "x = 3\ny = 2\nx = 3\n");
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let (text, _) = replace_exact("a\r\nb\r\nc\r\n", "a\nb", "a\nB", false).unwrap();
+        assert_eq!(text, "a\r\nB\r\nc\r\n");
+    }
+
+    #[test]
+    fn rejects_empty_or_identical_strings() {
+        assert!(replace_exact("abc", "", "x", false).is_err());
+        assert!(replace_exact("abc", "b", "b", false).is_err());
+    }
+
+    #[test]
+    fn config_narrows_shared_write_allowlist() {
+        let root = std::env::temp_dir().join(format!("tebiki-file-edit-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src/gen")).unwrap();
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        let root = std::fs::canonicalize(root).unwrap();
+        let mut ctx = ToolContext {
+            working_dir: root.clone(),
+            allowed_write_dirs: vec![root.clone()],
+            ..ToolContext::default()
+        };
+
+        FileEditTool
+            .apply_config(
+                &serde_json::json!({ "allowed_write_dirs": ["src"] }),
+                &mut ctx,
+            )
+            .unwrap();
+        assert_eq!(ctx.allowed_write_dirs, vec![root.join("src")]);
+
+        // file_write の設定と重なる部分だけが残る
+        crate::tools::file_write::FileWriteTool
+            .apply_config(
+                &serde_json::json!({ "allowed_write_dirs": ["src/gen", "docs"] }),
+                &mut ctx,
+            )
+            .unwrap();
+        assert_eq!(ctx.allowed_write_dirs, vec![root.join("src/gen")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+use async_trait::async_trait;
+
+use crate::llm::types::ToolDefinition;
+
+use super::types::{ToolContext, ToolResult};
+use super::{config, sandbox};
+use super::{Tool, ToolCategory};
+
+/// これより大きいファイルは読まない（行範囲を指定しても同じ）
+const MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+/// 1回で返す内容の上限。超えたら行の区切りで打ち切り、続きの開始行を返す
+const MAX_OUTPUT_BYTES: usize = 100_000;
+/// 行範囲を指定しないときに返す最大行数
+const DEFAULT_MAX_LINES: usize = 2_000;
+
+pub struct FileReadTool;
+
+#[async_trait]
+impl Tool for FileReadTool {
+    fn name(&self) -> &str {
+        "file_read"
+    }
+    fn category(&self) -> ToolCategory {
+        ToolCategory::ReadOnly
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "file_read".to_string(),
+            description: "Read a text file from disk, optionally a range of lines. Long output is cut at a line boundary; continue from 'next_start_line'. The file path must be within the allowed read directories.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "File path to read (relative to working directory, or absolute)"
+                    },
+                    "start_line": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "First line to read, 1-based (default: 1)"
+                    },
+                    "end_line": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Last line to read, inclusive (default: up to 2000 lines from start_line)"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn execute(&self, input: &serde_json::Value, ctx: &ToolContext) -> ToolResult {
+        let path_str = match input["path"].as_str() {
+            Some(p) => p,
+            None => return ToolResult::error("Missing 'path' parameter".into()),
+        };
+        let start_line = match input["start_line"].as_u64() {
+            Some(0) => return ToolResult::error("'start_line' must be 1 or greater".into()),
+            Some(n) => n as usize,
+            None => 1,
+        };
+        let end_line = match input["end_line"].as_u64() {
+            Some(n) if (n as usize) < start_line => {
+                return ToolResult::error(format!(
+                    "'end_line' ({n}) must not be less than 'start_line' ({start_line})"
+                ))
+            }
+            Some(n) => n as usize,
+            None => start_line.saturating_add(DEFAULT_MAX_LINES - 1),
+        };
+
+        let path = match sandbox::resolve_read_path(ctx, path_str) {
+            Ok(p) => p,
+            Err(e) => return ToolResult::error(e),
+        };
+        let metadata = match tokio::fs::metadata(&path).await {
+            Ok(m) => m,
+            Err(e) => return ToolResult::error(format!("Failed to read file: {e}")),
+        };
+        if metadata.is_dir() {
+            return ToolResult::error(format!(
+                "'{path_str}' is a directory. Use list_dir to list its contents."
+            ));
+        }
+        if metadata.len() > MAX_FILE_BYTES {
+            return ToolResult::error(format!(
+                "File is too large ({} bytes, limit {MAX_FILE_BYTES}). Use grep_files to find the relevant lines.",
+                metadata.len()
+            ));
+        }
+
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(b) => b,
+            Err(e) => return ToolResult::error(format!("Failed to read file: {e}")),
+        };
+        if bytes.contains(&0) {
+            return ToolResult::error(format!("'{path_str}' is a binary file"));
+        }
+        let text = String::from_utf8_lossy(&bytes);
+
+        let total_lines = text.lines().count();
+        if start_line > total_lines.max(1) {
+            return ToolResult::error(format!(
+                "'start_line' ({start_line}) is beyond the end of the file ({total_lines} lines)"
+            ));
+        }
+
+        let mut content = String::new();
+        let mut last_line = start_line - 1;
+        for line in text
+            .lines()
+            .skip(start_line - 1)
+            .take(end_line - start_line + 1)
+        {
+            if !content.is_empty() && content.len() + line.len() + 1 > MAX_OUTPUT_BYTES {
+                break;
+            }
+            content.push_str(line);
+            content.push('\n');
+            last_line += 1;
+        }
+        // 1行だけで上限を超える場合はその行の途中で切る
+        let line_cut = content.len() > MAX_OUTPUT_BYTES;
+        if line_cut {
+            let mut end = MAX_OUTPUT_BYTES;
+            while !content.is_char_boundary(end) {
+                end -= 1;
+            }
+            content.truncate(end);
+        }
+
+        let mut result = serde_json::json!({
+            "path": sandbox::display_path(&sandbox::project_root(ctx), &path),
+            "start_line": start_line,
+            "end_line": last_line,
+            "total_lines": total_lines,
+            "truncated": line_cut || last_line < end_line.min(total_lines),
+            "content": content
+        });
+        if last_line < total_lines {
+            result["next_start_line"] = (last_line + 1).into();
+        }
+        ToolResult::ok(result.to_string())
+    }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: config::ReadDirsConfig = config::parse(self.name(), config)?;
+        if let Some(dirs) = config.allowed_read_dirs {
+            config::apply_read_dirs(ctx, &dirs)?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,9 @@
-use std::path::Path;
-
 use async_trait::async_trait;
 
 use crate::llm::types::ToolDefinition;
 
 use super::types::{ToolContext, ToolResult};
+use super::{config, sandbox};
 use super::{Tool, ToolCategory};
 
 pub struct FileWriteTool;
@@ -55,23 +54,11 @@ impl Tool for FileWriteTool {
         };
         let mode = input["mode"].as_str().unwrap_or("overwrite");
 
-        // パスを解決
-        let path = if Path::new(path_str).is_absolute() {
-            std::path::PathBuf::from(path_str)
-        } else {
-            ctx.working_dir.join(path_str)
+        let path = match sandbox::resolve_write_path(ctx, path_str) {
+            Ok(p) => p,
+            Err(e) => return ToolResult::error(e),
         };
 
-        // allowed_write_dirsチェック
-        if ctx.allowed_write_dirs.is_empty() {
-            return ToolResult::error(
-                "File writing is disabled. No allowed write directories configured.".into(),
-            );
-        }
-
-        // パストラバーサル対策: canonicalize() で正規化してから比較する。
-        // path.starts_with(allowed) だけでは "../../etc/passwd" のような
-        // 相対パスでのディレクトリ脱出を防げない。
         if let Some(parent) = path.parent() {
             if !parent.exists() {
                 if let Err(e) = tokio::fs::create_dir_all(parent).await {
@@ -80,32 +67,7 @@ impl Tool for FileWriteTool {
             }
         }
 
-        let canonical_path = match tokio::fs::canonicalize(path.parent().unwrap_or(&path)).await {
-            Ok(p) => p.join(path.file_name().unwrap_or_default()),
-            Err(e) => {
-                return ToolResult::error(format!(
-                    "Failed to resolve path '{}': {e}",
-                    path.display()
-                ))
-            }
-        };
-
-        let is_allowed = ctx.allowed_write_dirs.iter().any(|allowed| {
-            match std::fs::canonicalize(allowed) {
-                Ok(canonical_allowed) => canonical_path.starts_with(&canonical_allowed),
-                Err(_) => false,
-            }
-        });
-
-        if !is_allowed {
-            return ToolResult::error(format!(
-                "Path '{}' is not within any allowed write directory. Allowed: {:?}",
-                canonical_path.display(),
-                ctx.allowed_write_dirs
-            ));
-        }
-
-        // モード別書き込み（ディレクトリは上記セキュリティチェック内で作成済み）
+        // モード別書き込み
         match mode {
             "create" => {
                 if path.exists() {
@@ -152,4 +114,16 @@ impl Tool for FileWriteTool {
             .to_string(),
         )
     }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: config::WriteDirsConfig = config::parse(self.name(), config)?;
+        if let Some(dirs) = config.allowed_write_dirs {
+            config::apply_write_dirs(ctx, &dirs)?;
+        }
+        Ok(())
+    }
 }
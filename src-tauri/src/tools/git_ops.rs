@@ -1,12 +1,22 @@
 use async_trait::async_trait;
+use serde::Deserialize;
 
 use crate::llm::types::ToolDefinition;
 
+use super::config;
 use super::types::{GitPermission, ToolContext, ToolResult};
 use super::{Tool, ToolCategory};
 
 pub struct GitOpsTool;
 
+/// agent_tool_permissions.config（git_ops）
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GitOpsConfig {
+    /// "disabled" / "read_only" / "read_write"
+    permission: Option<GitPermission>,
+}
+
 const WRITE_ACTIONS: &[&str] = &["add", "commit", "branch_create", "checkout"];
 
 #[async_trait]
@@ -142,6 +152,18 @@ impl Tool for GitOpsTool {
             _ => ToolResult::error(format!("Unknown git action: {action}")),
         }
     }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: GitOpsConfig = config::parse(self.name(), config)?;
+        if let Some(permission) = config.permission {
+            ctx.git_permission = permission;
+        }
+        Ok(())
+    }
 }
 
 async fn run_git(working_dir: &std::path::Path, args: &[&str]) -> ToolResult {
@@ -0,0 +1,264 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+
+use crate::llm::types::ToolDefinition;
+
+use super::types::{ToolContext, ToolResult};
+use super::{config, sandbox};
+use super::{Tool, ToolCategory};
+
+/// 結果件数の既定値と上限
+const DEFAULT_MAX_RESULTS: usize = 100;
+const MAX_RESULTS: usize = 500;
+/// 前後に付ける行数の上限
+const MAX_CONTEXT_LINES: usize = 10;
+/// これより大きいファイルは検索しない
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+/// 検索するファイル数の上限
+const MAX_SEARCHED_FILES: usize = 20_000;
+/// 結果に載せる1行の最大文字数
+const MAX_LINE_CHARS: usize = 500;
+/// 正規表現のコンパイル後のサイズ上限
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+pub struct GrepFilesTool;
+
+/// 1ファイル内の一致を探す。remaining 件を超える一致があれば true も返す
+fn search_file(
+    regex: &regex::Regex,
+    text: &str,
+    display: &str,
+    context_lines: usize,
+    remaining: usize,
+) -> (Vec<serde_json::Value>, bool) {
+    let lines: Vec<&str> = text.lines().collect();
+    let clip = |line: &str| -> String {
+        match line.char_indices().nth(MAX_LINE_CHARS) {
+            Some((end, _)) => format!("{}…", &line[..end]),
+            None => line.to_string(),
+        }
+    };
+
+    let mut matches = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if !regex.is_match(line) {
+            continue;
+        }
+        if matches.len() >= remaining {
+            return (matches, true);
+        }
+        let mut item = serde_json::json!({
+            "path": display,
+            "line": index + 1,
+            "text": clip(line)
+        });
+        if context_lines > 0 {
+            let before = index.saturating_sub(context_lines);
+            let after = (index + 1 + context_lines).min(lines.len());
+            item["context_before"] = lines[before..index].iter().map(|l| clip(l)).collect();
+            item["context_after"] = lines[index + 1..after].iter().map(|l| clip(l)).collect();
+        }
+        matches.push(item);
+    }
+    (matches, false)
+}
+
+/// 検索対象のテキストを読む。大きすぎるもの・バイナリ・UTF-8 でないものは None
+fn read_text(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    if bytes.contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[async_trait]
+impl Tool for GrepFilesTool {
+    fn name(&self) -> &str {
+        "grep_files"
+    }
+    fn category(&self) -> ToolCategory {
+        ToolCategory::ReadOnly
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "grep_files".to_string(),
+            description: "Search file contents with a regular expression (Rust regex syntax). Files ignored by .gitignore, hidden files and binary files are skipped. The search path must be within the allowed read directories.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Regular expression to search for, matched against each line"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "File or directory to search (relative to working directory, or absolute; default: working directory)"
+                    },
+                    "glob": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only search files matching these globs, e.g. [\"*.rs\", \"src/**/*.ts\"]. Prefix with '!' to exclude, e.g. [\"!*.test.ts\"]"
+                    },
+                    "case_insensitive": {
+                        "type": "boolean",
+                        "description": "Ignore case (default: false)"
+                    },
+                    "context_lines": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "maximum": MAX_CONTEXT_LINES,
+                        "description": "Number of lines to include before and after each match (default: 0)"
+                    },
+                    "max_results": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_RESULTS,
+                        "description": "Maximum number of matching lines to return (default: 100)"
+                    },
+                    "include_hidden": {
+                        "type": "boolean",
+                        "description": "Also search hidden files and directories (default: false)"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+        }
+    }
+
+    async fn execute(&self, input: &serde_json::Value, ctx: &ToolContext) -> ToolResult {
+        let pattern = match input["pattern"].as_str() {
+            Some(p) if !p.is_empty() => p,
+            _ => return ToolResult::error("Missing 'pattern' parameter".into()),
+        };
+        let path_str = input["path"].as_str().unwrap_or(".");
+        let include_hidden = input["include_hidden"].as_bool().unwrap_or(false);
+        let context_lines = match input["context_lines"].as_u64() {
+            Some(n) if n as usize <= MAX_CONTEXT_LINES => n as usize,
+            Some(_) => {
+                return ToolResult::error(format!(
+                    "'context_lines' must be between 0 and {MAX_CONTEXT_LINES}"
+                ))
+            }
+            None => 0,
+        };
+        let max_results = match input["max_results"].as_u64() {
+            Some(n) if (1..=MAX_RESULTS as u64).contains(&n) => n as usize,
+            Some(_) => {
+                return ToolResult::error(format!(
+                    "'max_results' must be between 1 and {MAX_RESULTS}"
+                ))
+            }
+            None => DEFAULT_MAX_RESULTS,
+        };
+        let globs: Vec<String> = input["glob"]
+            .as_array()
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let regex = match regex::RegexBuilder::new(pattern)
+            .case_insensitive(input["case_insensitive"].as_bool().unwrap_or(false))
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+        {
+            Ok(r) => r,
+            Err(e) => return ToolResult::error(format!("Invalid regex pattern: {e}")),
+        };
+
+        let target = match sandbox::resolve_read_path(ctx, path_str) {
+            Ok(p) => p,
+            Err(e) => return ToolResult::error(e),
+        };
+
+        let root = sandbox::project_root(ctx);
+        let searched = tokio::task::spawn_blocking(move || {
+            let mut matches = Vec::new();
+            let mut files_searched = 0;
+            let mut truncated = false;
+
+            if target.is_file() {
+                if let Some(text) = read_text(&target) {
+                    files_searched = 1;
+                    let display = sandbox::display_path(&root, &target);
+                    (matches, truncated) =
+                        search_file(&regex, &text, &display, context_lines, max_results);
+                }
+            } else {
+                let mut overrides = ignore::overrides::OverrideBuilder::new(&target);
+                for glob in &globs {
+                    overrides
+                        .add(glob)
+                        .map_err(|e| format!("Invalid glob '{glob}': {e}"))?;
+                }
+                let overrides = overrides
+                    .build()
+                    .map_err(|e| format!("Invalid glob: {e}"))?;
+                let walker = ignore::WalkBuilder::new(&target)
+                    .require_git(false)
+                    .hidden(!include_hidden)
+                    .overrides(overrides)
+                    .sort_by_file_name(|a, b| a.cmp(b))
+                    .build();
+
+                for entry in walker.filter_map(Result::ok) {
+                    if !entry.file_type().is_some_and(|t| t.is_file()) {
+                        continue;
+                    }
+                    if files_searched >= MAX_SEARCHED_FILES {
+                        truncated = true;
+                        break;
+                    }
+                    let Some(text) = read_text(entry.path()) else {
+                        continue;
+                    };
+                    files_searched += 1;
+                    let display = sandbox::display_path(&root, entry.path());
+                    let remaining = max_results - matches.len();
+                    let (found, hit_limit) =
+                        search_file(&regex, &text, &display, context_lines, remaining);
+                    matches.extend(found);
+                    if hit_limit {
+                        truncated = true;
+                        break;
+                    }
+                }
+            }
+
+            Ok::<_, String>(serde_json::json!({
+                "pattern": regex.as_str(),
+                "matches": matches,
+                "files_searched": files_searched,
+                "truncated": truncated
+            }))
+        })
+        .await;
+
+        match searched {
+            Ok(Ok(result)) => ToolResult::ok(result.to_string()),
+            Ok(Err(e)) => ToolResult::error(e),
+            Err(e) => ToolResult::error(format!("Search failed: {e}")),
+        }
+    }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: config::ReadDirsConfig = config::parse(self.name(), config)?;
+        if let Some(dirs) = config.allowed_read_dirs {
+            config::apply_read_dirs(ctx, &dirs)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use async_trait::async_trait;
+
+use crate::llm::types::ToolDefinition;
+
+use super::types::{ToolContext, ToolResult};
+use super::{config, sandbox};
+use super::{Tool, ToolCategory};
+
+/// 再帰時の既定の深さと上限
+const DEFAULT_RECURSIVE_DEPTH: usize = 5;
+const MAX_DEPTH: usize = 20;
+/// 返すエントリ数の上限（超えた分は truncated として省略する）
+const MAX_ENTRIES: usize = 1_000;
+
+pub struct ListDirTool;
+
+#[async_trait]
+impl Tool for ListDirTool {
+    fn name(&self) -> &str {
+        "list_dir"
+    }
+    fn category(&self) -> ToolCategory {
+        ToolCategory::ReadOnly
+    }
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "list_dir".to_string(),
+            description: "List the entries of a directory, optionally recursively. Files ignored by .gitignore and hidden files are skipped. The directory must be within the allowed read directories.".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to list (relative to working directory, or absolute; default: working directory)"
+                    },
+                    "recursive": {
+                        "type": "boolean",
+                        "description": "List subdirectories recursively (default: false)"
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_DEPTH,
+                        "description": "Maximum depth when recursive (default: 5)"
+                    },
+                    "include_hidden": {
+                        "type": "boolean",
+                        "description": "Include hidden files and directories (default: false)"
+                    }
+                }
+            }),
+        }
+    }
+
+    async fn execute(&self, input: &serde_json::Value, ctx: &ToolContext) -> ToolResult {
+        let path_str = input["path"].as_str().unwrap_or(".");
+        let recursive = input["recursive"].as_bool().unwrap_or(false);
+        let include_hidden = input["include_hidden"].as_bool().unwrap_or(false);
+        let max_depth = if recursive {
+            match input["max_depth"].as_u64() {
+                Some(n) if (1..=MAX_DEPTH as u64).contains(&n) => n as usize,
+                Some(_) => {
+                    return ToolResult::error(format!(
+                        "'max_depth' must be between 1 and {MAX_DEPTH}"
+                    ))
+                }
+                None => DEFAULT_RECURSIVE_DEPTH,
+            }
+        } else {
+            1
+        };
+
+        let dir = match sandbox::resolve_read_path(ctx, path_str) {
+            Ok(p) => p,
+            Err(e) => return ToolResult::error(e),
+        };
+        if !dir.is_dir() {
+            return ToolResult::error(format!(
+                "'{path_str}' is not a directory. Use file_read to read a file."
+            ));
+        }
+
+        let root = sandbox::project_root(ctx);
+        let listed = tokio::task::spawn_blocking(move || {
+            let walker = ignore::WalkBuilder::new(&dir)
+                .require_git(false)
+                .hidden(!include_hidden)
+                .max_depth(Some(max_depth))
+                .sort_by_file_name(|a, b| a.cmp(b))
+                .build();
+
+            let mut entries = Vec::new();
+            let mut truncated = false;
+            for entry in walker.filter_map(Result::ok) {
+                // 深さ0は指定したディレクトリ自身
+                if entry.depth() == 0 {
+                    continue;
+                }
+                if entries.len() >= MAX_ENTRIES {
+                    truncated = true;
+                    break;
+                }
+                let file_type = entry.file_type();
+                let kind = match file_type {
+                    Some(t) if t.is_dir() => "dir",
+                    Some(t) if t.is_symlink() => "symlink",
+                    _ => "file",
+                };
+                let mut item = serde_json::json!({
+                    "path": sandbox::display_path(&root, entry.path()),
+                    "type": kind
+                });
+                if kind == "file" {
+                    if let Ok(metadata) = entry.metadata() {
+                        item["size"] = metadata.len().into();
+                    }
+                }
+                entries.push(item);
+            }
+            serde_json::json!({
+                "path": sandbox::display_path(&root, &dir),
+                "entries": entries,
+                "truncated": truncated
+            })
+        })
+        .await;
+
+        match listed {
+            Ok(result) => ToolResult::ok(result.to_string()),
+            Err(e) => ToolResult::error(format!("Failed to list directory: {e}")),
+        }
+    }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: config::ReadDirsConfig = config::parse(self.name(), config)?;
+        if let Some(dirs) = config.allowed_read_dirs {
+            config::apply_read_dirs(ctx, &dirs)?;
+        }
+        Ok(())
+    }
+}
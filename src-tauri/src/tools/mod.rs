@@ -1,8 +1,18 @@
 pub mod types;
+pub mod code_index;
+pub mod code_search;
+pub mod config;
+pub mod diff;
 pub mod web_fetch;
 pub mod web_search;
+pub mod file_edit;
+pub mod file_read;
 pub mod file_write;
 pub mod git_ops;
+pub mod grep_files;
+pub mod list_dir;
+pub mod process;
+pub mod sandbox;
 pub mod self_eval;
 pub mod shell_exec;
 
@@ -28,16 +38,26 @@ pub trait Tool: Send + Sync {
 
     /// ツールを実行し結果を返す
     async fn execute(&self, input: &serde_json::Value, ctx: &ToolContext) -> ToolResult;
+
+    /// agent_tool_permissions.config を検証し、エージェントの ToolContext に反映する。
+    /// 設定項目を持たないツールは空の設定だけを受け付ける。
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        _ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        config::reject(self.name(), config)
+    }
 }
 
 /// ツールのカテゴリ（**UI表示専用**）
 /// アクセス制御にはこのカテゴリではなく、ToolContextのフィールド
-/// （allowed_commands, allowed_write_dirs, git_permission）を使用する。
+/// （allowed_commands, allowed_read_dirs, allowed_write_dirs, git_permission）を使用する。
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub enum ToolCategory {
-    ReadOnly,       // web_fetch, web_search
-    FileSystem,     // file_write
+    ReadOnly,       // web_fetch, web_search, code_search, file_read, list_dir, grep_files
+    FileSystem,     // file_write, file_edit
     Execution,      // shell_exec
     VersionControl, // git_ops（読み取り・書き込み両方を含む）
     Composite,      // self_eval（内部で他ツールを呼ぶ）
@@ -60,7 +80,6 @@ impl ToolRegistry {
     }
 
     /// 名前でツールを取得
-    #[allow(dead_code)]
     pub fn get(&self, name: &str) -> Option<&dyn Tool> {
         self.tools.get(name).map(|t| t.as_ref())
     }
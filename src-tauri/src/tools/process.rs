@@ -0,0 +1,420 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::types::ProcessLimits;
+
+/// プロセス終了後、出力の読み取りを待つ時間（グループ外に逃げた子孫がパイプを握っている場合）
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// サンドボックス内で実行したコマンドの結果
+pub struct ProcessOutput {
+    /// シグナルで終了した場合は None
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// タイムアウトでプロセスグループごと停止した
+    pub timed_out: bool,
+    pub duration_ms: u64,
+}
+
+/// シェルを通さずに実行するため、コマンドラインを引数に分ける。
+/// クォート（'…' と "…"）とバックスラッシュだけを解釈し、展開は一切しない。
+/// パイプ・連結・リダイレクト・変数展開はクォートされていなければエラーにする。
+pub fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_token = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => current.push(ch),
+                        None => return Err("Unterminated single quote in command".into()),
+                    }
+                }
+            }
+            '"' => {
+                in_token = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\')) => current.push(ch),
+                            Some(ch) => {
+                                current.push('\\');
+                                current.push(ch);
+                            }
+                            None => return Err("Unterminated double quote in command".into()),
+                        },
+                        Some(ch) => current.push(ch),
+                        None => return Err("Unterminated double quote in command".into()),
+                    }
+                }
+            }
+            '\\' => {
+                in_token = true;
+                match chars.next() {
+                    Some(ch) => current.push(ch),
+                    None => return Err("Trailing backslash in command".into()),
+                }
+            }
+            '|' | '&' | ';' | '<' | '>' | '`' | '$' | '(' | ')' => {
+                return Err(format!(
+                    "Command contains unquoted shell metacharacter '{c}'. \
+                     Commands run without a shell: pipes, chaining, redirection and expansion are not supported. \
+                     Run one command per call, or quote the character to pass it literally."
+                ));
+            }
+            c if c.is_whitespace() => {
+                if in_token {
+                    args.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                in_token = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_token {
+        args.push(current);
+    }
+    if args.is_empty() {
+        return Err("Empty command".into());
+    }
+    Ok(args)
+}
+
+/// 出力を上限まで保持し、残りは読み捨てる（子プロセスがパイプ詰まりで止まらないように）
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, cap: usize) -> (Vec<u8>, bool) {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = cap.saturating_sub(kept.len());
+                if n > room {
+                    truncated = true;
+                }
+                kept.extend_from_slice(&buf[..n.min(room)]);
+            }
+        }
+    }
+    (kept, truncated)
+}
+
+async fn collect_output(task: Option<tokio::task::JoinHandle<(Vec<u8>, bool)>>) -> String {
+    let Some(task) = task else {
+        return String::new();
+    };
+    let (bytes, truncated) = match tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, task).await {
+        Ok(Ok(read)) => read,
+        _ => (Vec::new(), true),
+    };
+    let mut text = String::from_utf8_lossy(&bytes).into_owned();
+    if truncated {
+        text.push_str("...\n[truncated]");
+    }
+    text
+}
+
+/// プロセスグループごと強制終了する（グループが既にない場合は何もしない）
+#[cfg(unix)]
+fn kill_group(pid: Option<u32>) {
+    if let Some(pid) = pid.and_then(|p| libc::pid_t::try_from(p).ok()) {
+        // SAFETY: killpg はシグナルを送るだけで、メモリには触れない
+        unsafe {
+            libc::killpg(pid, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_pid: Option<u32>) {}
+
+/// コマンドをシェルを通さずに実行する。
+/// 環境変数は limits.env_passthrough だけを引き継ぎ、子プロセスは専用のプロセスグループで動かす。
+/// タイムアウト時と終了後には、グループに残った子孫ごと停止する。
+pub async fn run(
+    program: &str,
+    args: &[String],
+    working_dir: &Path,
+    timeout: Duration,
+    limits: &ProcessLimits,
+    capture_bytes: usize,
+) -> Result<ProcessOutput, String> {
+    let mut command = tokio::process::Command::new(program);
+    command
+        .args(args)
+        .current_dir(working_dir)
+        .env_clear()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    for name in &limits.env_passthrough {
+        if let Some(value) = std::env::var_os(name) {
+            command.env(name, value);
+        }
+    }
+    #[cfg(unix)]
+    command.process_group(0);
+    #[cfg(target_os = "linux")]
+    linux::restrict(&mut command, limits);
+
+    let start = Instant::now();
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Failed to execute '{program}': {e}"))?;
+    let pid = child.id();
+    let stdout = child
+        .stdout
+        .take()
+        .map(|s| tokio::spawn(read_capped(s, capture_bytes)));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|s| tokio::spawn(read_capped(s, capture_bytes)));
+
+    let (status, timed_out) = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(Ok(status)) => (Some(status), false),
+        Ok(Err(e)) => {
+            kill_group(pid);
+            return Err(format!("Failed to wait for '{program}': {e}"));
+        }
+        Err(_) => {
+            kill_group(pid);
+            let _ = child.kill().await;
+            (None, true)
+        }
+    };
+    // バックグラウンドに回った子孫も残さない
+    kill_group(pid);
+
+    let stdout = collect_output(stdout).await;
+    let stderr = collect_output(stderr).await;
+    Ok(ProcessOutput {
+        exit_code: status.and_then(|s| s.code()),
+        success: status.is_some_and(|s| s.success()),
+        stdout,
+        stderr,
+        timed_out,
+        duration_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::io;
+
+    use super::ProcessLimits;
+
+    /// CPU時間のソフト上限（SIGXCPU）からハード上限（SIGKILL）までの猶予（秒）
+    const CPU_GRACE_SECS: libc::rlim_t = 5;
+
+    /// fork 後・exec 前の子プロセスに、リソース上限・no_new_privs・ネットワーク分離を設定する
+    pub(super) fn restrict(command: &mut tokio::process::Command, limits: &ProcessLimits) {
+        let cpu = limits.cpu_time_secs as libc::rlim_t;
+        let memory = limits.memory_mb.saturating_mul(1024 * 1024) as libc::rlim_t;
+        let isolate_network = !limits.network;
+
+        // user namespace 内の uid/gid の対応付け。fork 後はメモリを確保できないので先に作る
+        // SAFETY: getuid / getgid は失敗しない
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let uid_map = CString::new(format!("{uid} {uid} 1")).unwrap_or_default();
+        let gid_map = CString::new(format!("{gid} {gid} 1")).unwrap_or_default();
+
+        let apply = move || -> io::Result<()> {
+            let set_limit = |resource, limit: libc::rlim_t| -> io::Result<()> {
+                let mut current = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                // SAFETY: current は有効な rlimit を指す
+                if unsafe { libc::getrlimit(resource, &mut current) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // ハード上限は引き上げられないので、既存の上限を超えないようにする
+                let hard = if resource == libc::RLIMIT_CPU {
+                    limit.saturating_add(CPU_GRACE_SECS)
+                } else {
+                    limit
+                }
+                .min(current.rlim_max);
+                let new = libc::rlimit {
+                    rlim_cur: limit.min(hard),
+                    rlim_max: hard,
+                };
+                // SAFETY: new は有効な rlimit を指す
+                if unsafe { libc::setrlimit(resource, &new) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            };
+            set_limit(libc::RLIMIT_CORE, 0)?;
+            set_limit(libc::RLIMIT_CPU, cpu)?;
+            set_limit(libc::RLIMIT_DATA, memory)?;
+
+            // SAFETY: prctl(PR_SET_NO_NEW_PRIVS) は引数に整数だけを取る
+            if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if isolate_network {
+                // 特権なしで network namespace を作るため、user namespace も作る
+                // SAFETY: unshare は呼び出したプロセス（fork 直後の子）だけに作用する
+                if unsafe { libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                write_proc(c"/proc/self/setgroups", c"deny")?;
+                write_proc(c"/proc/self/uid_map", &uid_map)?;
+                write_proc(c"/proc/self/gid_map", &gid_map)?;
+            }
+            Ok(())
+        };
+
+        // SAFETY: apply はシステムコールだけを使い、メモリ確保やロックをしない（fork 後でも安全）
+        unsafe {
+            command.pre_exec(apply);
+        }
+    }
+
+    /// /proc のファイルに書き込む（fork 後に使えるようシステムコールだけで行う）
+    fn write_proc(path: &std::ffi::CStr, content: &std::ffi::CStr) -> io::Result<()> {
+        // SAFETY: path は NUL 終端の文字列、fd はこの関数内で閉じる
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let bytes = content.to_bytes();
+            let written = libc::write(fd, bytes.as_ptr().cast(), bytes.len());
+            libc::close(fd);
+            if written < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(line: &str) -> Vec<String> {
+        split_command_line(line).unwrap()
+    }
+
+    #[test]
+    fn splits_on_whitespace_and_keeps_quoted_text_together() {
+        assert_eq!(split("  ls   -la\tsrc "), ["ls", "-la", "src"]);
+        assert_eq!(
+            split(r#"grep "two words" 'single $quoted'"#),
+            ["grep", "two words", "single $quoted"]
+        );
+        assert_eq!(split(r#"echo a"b"'c'"#), ["echo", "abc"]);
+        assert_eq!(split(r#"echo "" ''"#), ["echo", "", ""]);
+    }
+
+    #[test]
+    fn interprets_backslash_escapes() {
+        assert_eq!(split(r"echo a\ b \|"), ["echo", "a b", "|"]);
+        // ダブルクォート内では \" と \\ だけを解釈し、それ以外はそのまま残す
+        assert_eq!(split(r#"echo "a\"b\\c\n""#), ["echo", r#"a"b\c\n"#]);
+        assert_eq!(split(r"echo 'a\b'"), ["echo", r"a\b"]);
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes_and_trailing_backslash() {
+        assert!(split_command_line("echo 'abc")
+            .unwrap_err()
+            .contains("single quote"));
+        assert!(split_command_line(r#"echo "abc"#)
+            .unwrap_err()
+            .contains("double quote"));
+        assert!(split_command_line(r#"echo "abc\"#)
+            .unwrap_err()
+            .contains("double quote"));
+        assert!(split_command_line(r"echo abc\")
+            .unwrap_err()
+            .contains("Trailing backslash"));
+        assert_eq!(split_command_line("   ").unwrap_err(), "Empty command");
+    }
+
+    #[test]
+    fn rejects_unquoted_shell_metacharacters() {
+        for line in [
+            "ls | wc",
+            "ls; rm -rf /",
+            "ls && rm x",
+            "echo $(id)",
+            "echo $HOME",
+            "echo `id`",
+            "cat < /etc/passwd",
+            "echo x > out",
+        ] {
+            let error = split_command_line(line).unwrap_err();
+            assert!(error.contains("shell metacharacter"), "{line}: {error}");
+        }
+        assert_eq!(split("echo '|' \"; $(id)\""), ["echo", "|", "; $(id)"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn passes_only_allowlisted_environment() {
+        std::env::set_var("TEBIKI_PROCESS_TEST_SECRET", "leaked");
+        let limits = ProcessLimits::default();
+        let output = run(
+            "env",
+            &[],
+            &std::env::temp_dir(),
+            Duration::from_secs(10),
+            &limits,
+            64 * 1024,
+        )
+        .await
+        .unwrap();
+        assert!(output.success, "{}", output.stderr);
+        assert!(!output.stdout.contains("TEBIKI_PROCESS_TEST_SECRET"));
+        assert!(output.stdout.lines().any(|l| l.starts_with("PATH=")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn kills_process_group_on_timeout() {
+        let limits = ProcessLimits::default();
+        let marker = std::env::temp_dir().join(format!("tebiki-process-{}", uuid::Uuid::new_v4()));
+        // 子孫（バックグラウンドの sleep）がタイムアウト後に残っていれば marker を作る
+        let script = format!("(sleep 1; touch '{}') & sleep 30", marker.display());
+        let output = run(
+            "sh",
+            &["-c".to_string(), script],
+            &std::env::temp_dir(),
+            Duration::from_millis(200),
+            &limits,
+            1024,
+        )
+        .await
+        .unwrap();
+        assert!(output.timed_out);
+        assert!(!output.success);
+        assert!(output.duration_ms < 10_000, "{}", output.duration_ms);
+
+        tokio::time::sleep(Duration::from_millis(1_500)).await;
+        assert!(!marker.exists(), "descendant survived the timeout");
+    }
+}
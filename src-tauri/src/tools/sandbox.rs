@@ -0,0 +1,215 @@
+use std::path::{Component, Path, PathBuf};
+
+use super::types::ToolContext;
+
+/// ツール入力のパスを絶対パスにする（相対パスは working_dir 基準）
+fn input_path(ctx: &ToolContext, path: &str) -> PathBuf {
+    if Path::new(path).is_absolute() {
+        PathBuf::from(path)
+    } else {
+        ctx.working_dir.join(path)
+    }
+}
+
+/// path が allowlist のいずれかのディレクトリ内か。allowlist も正規化してから比較する。
+fn is_within(path: &Path, allowed_dirs: &[PathBuf]) -> bool {
+    allowed_dirs
+        .iter()
+        .any(|allowed| match std::fs::canonicalize(allowed) {
+            Ok(canonical_allowed) => path.starts_with(&canonical_allowed),
+            Err(_) => false,
+        })
+}
+
+/// 読み取るファイル・ディレクトリを正規化し、読み取り許可ディレクトリ内か確認する。
+/// シンボリックリンクは解決後のパスで判定する。
+pub fn resolve_read_path(ctx: &ToolContext, path: &str) -> Result<PathBuf, String> {
+    if ctx.allowed_read_dirs.is_empty() {
+        return Err("File reading is disabled. No allowed read directories configured.".into());
+    }
+    let path = input_path(ctx, path);
+    let canonical_path = std::fs::canonicalize(&path)
+        .map_err(|e| format!("Failed to resolve path '{}': {e}", path.display()))?;
+    if !is_within(&canonical_path, &ctx.allowed_read_dirs) {
+        return Err(format!(
+            "Path '{}' is not within any allowed read directory. Allowed: {:?}",
+            canonical_path.display(),
+            ctx.allowed_read_dirs
+        ));
+    }
+    Ok(canonical_path)
+}
+
+/// 書き込み先を正規化し、書き込み許可ディレクトリ内か確認する。
+/// 存在しない親ディレクトリは作らない（確認後に呼び出し側で作る）。
+pub fn resolve_write_path(ctx: &ToolContext, path: &str) -> Result<PathBuf, String> {
+    if ctx.allowed_write_dirs.is_empty() {
+        return Err("File writing is disabled. No allowed write directories configured.".into());
+    }
+    let path = input_path(ctx, path);
+
+    // パストラバーサル対策: 存在する最も近い祖先を canonicalize() で正規化し、残りを付け直してから比較する。
+    // path.starts_with(allowed) だけでは "../../etc/passwd" のような
+    // 相対パスでのディレクトリ脱出を防げない。
+    let mut existing = path.as_path();
+    let mut rest = Vec::new();
+    while !existing.exists() {
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+            return Err(format!(
+                "Failed to resolve path '{}'. Do not use '..' below a directory that does not exist.",
+                path.display()
+            ));
+        };
+        rest.push(name);
+        existing = parent;
+    }
+    let mut canonical_path = std::fs::canonicalize(existing)
+        .map_err(|e| format!("Failed to resolve path '{}': {e}", path.display()))?;
+    for name in rest.iter().rev() {
+        // file_name() は ".." を返さないが、念のため通常の名前だけを許す
+        match Path::new(name).components().next() {
+            Some(Component::Normal(part)) => canonical_path.push(part),
+            _ => return Err(format!("Invalid path '{}'", path.display())),
+        }
+    }
+
+    if !is_within(&canonical_path, &ctx.allowed_write_dirs) {
+        return Err(format!(
+            "Path '{}' is not within any allowed write directory. Allowed: {:?}",
+            canonical_path.display(),
+            ctx.allowed_write_dirs
+        ));
+    }
+    Ok(canonical_path)
+}
+
+/// コマンドを実行するディレクトリを解決する。プロジェクトルート（working_dir）の外は許さない。
+pub fn resolve_command_dir(ctx: &ToolContext, path: Option<&str>) -> Result<PathBuf, String> {
+    let root = std::fs::canonicalize(&ctx.working_dir).map_err(|e| {
+        format!(
+            "Failed to resolve project root '{}': {e}",
+            ctx.working_dir.display()
+        )
+    })?;
+    let Some(path) = path else {
+        return Ok(root);
+    };
+    let dir = input_path(ctx, path);
+    let canonical_dir = std::fs::canonicalize(&dir).map_err(|e| {
+        format!(
+            "Failed to resolve working directory '{}': {e}",
+            dir.display()
+        )
+    })?;
+    if !canonical_dir.is_dir() {
+        return Err(format!("'{path}' is not a directory"));
+    }
+    if !canonical_dir.starts_with(&root) {
+        return Err(format!(
+            "Working directory '{}' is outside the project root '{}'",
+            canonical_dir.display(),
+            root.display()
+        ));
+    }
+    Ok(canonical_dir)
+}
+
+/// 正規化済みのプロジェクトルート（display_path の基準）
+pub fn project_root(ctx: &ToolContext) -> PathBuf {
+    std::fs::canonicalize(&ctx.working_dir).unwrap_or_else(|_| ctx.working_dir.clone())
+}
+
+/// 結果に載せるパス。プロジェクトルート内なら相対パス（区切りは '/'）にする。
+pub fn display_path(root: &Path, path: &Path) -> String {
+    match path.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用のプロジェクト（project/、src/ だけ読み書き可）とその外のディレクトリ（outside/）を作る
+    fn setup() -> (PathBuf, ToolContext) {
+        let base = std::env::temp_dir().join(format!("tebiki-sandbox-{}", uuid::Uuid::new_v4()));
+        let project = base.join("project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::create_dir_all(base.join("outside")).unwrap();
+        std::fs::write(project.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(project.join("README.md"), "readme\n").unwrap();
+        std::fs::write(base.join("outside/secret.txt"), "secret\n").unwrap();
+
+        let ctx = ToolContext {
+            working_dir: project.clone(),
+            allowed_read_dirs: vec![project.join("src")],
+            allowed_write_dirs: vec![project.join("src")],
+            ..ToolContext::default()
+        };
+        (base, ctx)
+    }
+
+    #[test]
+    fn resolves_paths_inside_allowlist() {
+        let (base, ctx) = setup();
+        let read = resolve_read_path(&ctx, "src/main.rs").unwrap();
+        assert!(read.ends_with("project/src/main.rs"));
+        let write = resolve_write_path(&ctx, "src/lib.rs").unwrap();
+        assert!(write.ends_with("project/src/lib.rs"));
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn rejects_dot_dot_escape() {
+        let (base, ctx) = setup();
+        assert!(resolve_read_path(&ctx, "src/../README.md").is_err());
+        assert!(resolve_read_path(&ctx, "src/../../outside/secret.txt").is_err());
+        assert!(resolve_write_path(&ctx, "src/../README.md").is_err());
+        assert!(resolve_write_path(&ctx, "src/../../outside/new.txt").is_err());
+        // 存在しないディレクトリの下の '..' は正規化できないので拒否する
+        assert!(resolve_write_path(&ctx, "src/missing/../../README.md").is_err());
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_out_of_root() {
+        let (base, ctx) = setup();
+        std::os::unix::fs::symlink(base.join("outside"), base.join("project/src/linked")).unwrap();
+        assert!(resolve_read_path(&ctx, "src/linked/secret.txt").is_err());
+        assert!(resolve_read_path(&ctx, "src/linked").is_err());
+        assert!(resolve_write_path(&ctx, "src/linked/secret.txt").is_err());
+        assert!(resolve_write_path(&ctx, "src/linked/new/file.txt").is_err());
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn empty_allowlist_denies_everything() {
+        let (base, mut ctx) = setup();
+        ctx.allowed_read_dirs.clear();
+        ctx.allowed_write_dirs.clear();
+        let read = resolve_read_path(&ctx, "src/main.rs").unwrap_err();
+        assert!(read.contains("File reading is disabled"), "{read}");
+        let write = resolve_write_path(&ctx, "src/main.rs").unwrap_err();
+        assert!(write.contains("File writing is disabled"), "{write}");
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn write_target_may_not_exist_yet() {
+        let (base, ctx) = setup();
+        let path = resolve_write_path(&ctx, "src/new/nested/file.rs").unwrap();
+        assert!(path.ends_with("project/src/new/nested/file.rs"));
+        // 親ディレクトリは作らない
+        assert!(!base.join("project/src/new").exists());
+        // 読み取りは存在するファイルに限る
+        assert!(resolve_read_path(&ctx, "src/new/nested/file.rs").is_err());
+        std::fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -1,12 +1,34 @@
 use async_trait::async_trait;
+use serde::Deserialize;
 
 use crate::llm::types::ToolDefinition;
 
 use super::types::{ToolContext, ToolResult};
+use super::{config, process, sandbox};
 use super::{Tool, ToolCategory};
 
+/// エージェントごとに設定できるタイムアウトの範囲（ミリ秒）
+const MIN_TIMEOUT_MS: u64 = 1_000;
+const MAX_TIMEOUT_MS: u64 = 1_800_000;
+/// 各チェックの stdout / stderr の出力サイズ上限
+const MAX_OUTPUT_BYTES: usize = 5_000;
+
 pub struct SelfEvalTool;
 
+/// agent_tool_permissions.config（self_eval）。プロセスの制限は shell_exec と共有する
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SelfEvalConfig {
+    /// 各チェックのタイムアウト
+    timeout_ms: Option<u64>,
+    /// 子プロセスに引き継ぐ環境変数。既定のリストを置き換える
+    env_passthrough: Option<Vec<String>>,
+    cpu_time_secs: Option<u64>,
+    memory_mb: Option<u64>,
+    /// false でネットワークを使えなくする（Linux のみ）
+    network: Option<bool>,
+}
+
 #[async_trait]
 impl Tool for SelfEvalTool {
     fn name(&self) -> &str {
@@ -42,61 +64,54 @@ impl Tool for SelfEvalTool {
             .map(|arr| arr.iter().filter_map(|v| v.as_str()).collect())
             .unwrap_or_else(|| vec!["build", "lint", "type_check"]);
 
-        let working_dir = &ctx.working_dir;
-        let timeout = std::time::Duration::from_millis(ctx.shell_timeout_ms.max(120_000));
+        let working_dir = match sandbox::resolve_command_dir(ctx, None) {
+            Ok(dir) => dir,
+            Err(e) => return ToolResult::error(e),
+        };
+        let timeout = std::time::Duration::from_millis(ctx.self_eval_timeout_ms);
         let mut results = serde_json::Map::new();
         let mut all_passed = true;
 
-        let shell = if cfg!(target_os = "windows") {
-            "cmd"
-        } else {
-            "bash"
-        };
-        let shell_arg = if cfg!(target_os = "windows") {
-            "/C"
-        } else {
-            "-c"
-        };
-
         for check in &checks {
-            let (cmd, label) = match *check {
-                "build" => ("bun run build", "build"),
-                "lint" => ("bun run lint", "lint"),
-                "type_check" => ("bun run check", "type_check"),
-                "test" => ("cargo test", "test"),
+            let (program, args, label): (&str, &[&str], &str) = match *check {
+                "build" => ("bun", &["run", "build"], "build"),
+                "lint" => ("bun", &["run", "lint"], "lint"),
+                "type_check" => ("bun", &["run", "check"], "type_check"),
+                "test" => ("cargo", &["test"], "test"),
                 _ => continue,
             };
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
 
             let start = std::time::Instant::now();
-            let output = match tokio::time::timeout(timeout, async {
-                tokio::process::Command::new(shell)
-                    .arg(shell_arg)
-                    .arg(cmd)
-                    .current_dir(working_dir)
-                    .output()
-                    .await
-            })
+            let output = match process::run(
+                program,
+                &args,
+                &working_dir,
+                timeout,
+                &ctx.process_limits,
+                MAX_OUTPUT_BYTES,
+            )
             .await
             {
-                Ok(Ok(o)) => o,
-                Ok(Err(e)) => {
+                Ok(o) if o.timed_out => {
                     results.insert(
                         label.into(),
                         serde_json::json!({
                             "passed": false,
-                            "error": format!("Failed to run: {e}"),
-                            "duration_ms": start.elapsed().as_millis() as u64
+                            "error": "Timeout",
+                            "duration_ms": o.duration_ms
                         }),
                     );
                     all_passed = false;
                     continue;
                 }
-                Err(_) => {
+                Ok(o) => o,
+                Err(e) => {
                     results.insert(
                         label.into(),
                         serde_json::json!({
                             "passed": false,
-                            "error": "Timeout",
+                            "error": format!("Failed to run: {e}"),
                             "duration_ms": start.elapsed().as_millis() as u64
                         }),
                     );
@@ -105,35 +120,18 @@ impl Tool for SelfEvalTool {
                 }
             };
 
-            let duration_ms = start.elapsed().as_millis() as u64;
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let passed = output.status.success();
-
-            if !passed {
+            if !output.success {
                 all_passed = false;
             }
 
-            // 出力を制限（各5KB）
-            let max = 5_000;
-            let stdout_val: &str = if stdout.len() > max {
-                &stdout[..max]
-            } else {
-                &stdout
-            };
-            let stderr_val: &str = if stderr.len() > max {
-                &stderr[..max]
-            } else {
-                &stderr
-            };
             results.insert(
                 label.into(),
                 serde_json::json!({
-                    "passed": passed,
-                    "exit_code": output.status.code(),
-                    "stdout": stdout_val,
-                    "stderr": stderr_val,
-                    "duration_ms": duration_ms
+                    "passed": output.success,
+                    "exit_code": output.exit_code,
+                    "stdout": output.stdout,
+                    "stderr": output.stderr,
+                    "duration_ms": output.duration_ms
                 }),
             );
         }
@@ -147,4 +145,71 @@ impl Tool for SelfEvalTool {
             .to_string(),
         )
     }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: SelfEvalConfig = config::parse(self.name(), config)?;
+        if let Some(timeout_ms) = config.timeout_ms {
+            ctx.self_eval_timeout_ms =
+                config::check_range("timeout_ms", timeout_ms, MIN_TIMEOUT_MS, MAX_TIMEOUT_MS)?;
+        }
+        config::apply_process_limits(
+            ctx,
+            config::ProcessLimitsConfig {
+                env_passthrough: config.env_passthrough,
+                cpu_time_secs: config.cpu_time_secs,
+                memory_mb: config.memory_mb,
+                network: config.network,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::shell_exec::ShellExecTool;
+
+    #[test]
+    fn config_sets_process_limits_without_shell_exec() {
+        let mut ctx = ToolContext::default();
+        SelfEvalTool
+            .apply_config(
+                &serde_json::json!({ "memory_mb": 1024, "cpu_time_secs": 60, "env_passthrough": ["PATH"] }),
+                &mut ctx,
+            )
+            .unwrap();
+        assert_eq!(ctx.process_limits.memory_mb, 1024);
+        assert_eq!(ctx.process_limits.cpu_time_secs, 60);
+        assert_eq!(ctx.process_limits.env_passthrough, ["PATH"]);
+
+        let error = SelfEvalTool
+            .apply_config(&serde_json::json!({ "memory_mb": 1 }), &mut ctx)
+            .unwrap_err();
+        assert!(error.contains("memory_mb"), "{error}");
+    }
+
+    #[test]
+    fn shared_process_limits_take_the_stricter_value_in_any_order() {
+        let self_eval =
+            serde_json::json!({ "memory_mb": 2048, "env_passthrough": ["PATH", "HOME"] });
+        let shell = serde_json::json!({ "memory_mb": 16384, "cpu_time_secs": 30, "env_passthrough": ["PATH"] });
+
+        let mut first = ToolContext::default();
+        SelfEvalTool.apply_config(&self_eval, &mut first).unwrap();
+        ShellExecTool.apply_config(&shell, &mut first).unwrap();
+
+        let mut second = ToolContext::default();
+        ShellExecTool.apply_config(&shell, &mut second).unwrap();
+        SelfEvalTool.apply_config(&self_eval, &mut second).unwrap();
+
+        for ctx in [first, second] {
+            assert_eq!(ctx.process_limits.memory_mb, 2048);
+            assert_eq!(ctx.process_limits.cpu_time_secs, 30);
+            assert_eq!(ctx.process_limits.env_passthrough, ["PATH"]);
+        }
+    }
 }
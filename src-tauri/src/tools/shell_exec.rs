@@ -1,17 +1,35 @@
 use async_trait::async_trait;
+use serde::Deserialize;
 
 use crate::llm::types::ToolDefinition;
 
 use super::types::{ToolContext, ToolResult};
+use super::{config, process, sandbox};
 use super::{Tool, ToolCategory};
 
-/// シェルメタ文字として禁止するパターン
-const FORBIDDEN_PATTERNS: &[&str] = &[
-    "&&", "||", ";", "|", "$(", "`", "${", ">", ">>", "<", "\n", "\r",
-];
+/// エージェントごとに設定できるタイムアウトの範囲（ミリ秒）
+const MIN_TIMEOUT_MS: u64 = 1_000;
+const MAX_TIMEOUT_MS: u64 = 600_000;
+/// stdout / stderr それぞれの出力サイズ上限
+const MAX_OUTPUT_BYTES: usize = 50_000;
 
 pub struct ShellExecTool;
 
+/// agent_tool_permissions.config（shell_exec）。プロセスの制限は self_eval と共有する
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShellExecConfig {
+    /// 実行を許可するコマンド名。既定の読み取り系コマンドを置き換える
+    allowed_commands: Option<Vec<String>>,
+    timeout_ms: Option<u64>,
+    /// 子プロセスに引き継ぐ環境変数。既定のリストを置き換える
+    env_passthrough: Option<Vec<String>>,
+    cpu_time_secs: Option<u64>,
+    memory_mb: Option<u64>,
+    /// false でネットワークを使えなくする（Linux のみ）
+    network: Option<bool>,
+}
+
 #[async_trait]
 impl Tool for ShellExecTool {
     fn name(&self) -> &str {
@@ -24,17 +42,22 @@ impl Tool for ShellExecTool {
     fn definition(&self) -> ToolDefinition {
         ToolDefinition {
             name: "shell_exec".to_string(),
-            description: "Execute a shell command and return stdout/stderr. Commands must be in the allowed list. Use this to run build tools, tests, linters, and other development commands.".to_string(),
+            description: "Execute a command and return stdout/stderr. The command runs directly without a shell: pipes, chaining, redirection, globbing and variable expansion are not available, but quoted arguments are passed literally (e.g. grep -n \"a|b\" src/main.rs). The program must be in the allowed list. Use this to run build tools, tests, linters, and other development commands.".to_string(),
             input_schema: serde_json::json!({
                 "type": "object",
                 "properties": {
                     "command": {
                         "type": "string",
-                        "description": "The shell command to execute (e.g., 'bun run build', 'cargo test')"
+                        "description": "The command line to execute (e.g., 'bun run build', 'cargo test'), or only the program name when 'args' is given"
+                    },
+                    "args": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Arguments passed as-is, without any quoting rules (optional)"
                     },
                     "working_dir": {
                         "type": "string",
-                        "description": "Working directory for the command (optional, defaults to project root)"
+                        "description": "Working directory inside the project (optional, relative to the project root; defaults to project root)"
                     }
                 },
                 "required": ["command"]
@@ -48,99 +71,116 @@ impl Tool for ShellExecTool {
             None => return ToolResult::error("Missing 'command' parameter".into()),
         };
 
-        let working_dir = input["working_dir"]
-            .as_str()
-            .map(std::path::PathBuf::from)
-            .unwrap_or_else(|| ctx.working_dir.clone());
-
-        // コマンドインジェクション対策:
-        // "ls && rm -rf /" のようなチェーン攻撃を防ぐため、
-        // シェルメタ文字を含むコマンドを拒否する。
-        for pattern in FORBIDDEN_PATTERNS {
-            if command.contains(pattern) {
-                return ToolResult::error(format!(
-                    "Command contains forbidden shell metacharacter '{}'. \
-                     Each command must be a single, simple command without chaining or redirection.",
-                    pattern
-                ));
+        // コマンドインジェクション対策: シェルを通さず、引数の配列として実行する。
+        // "ls && rm -rf /" のような連結はクォートされていなければ分割時にエラーになり、
+        // クォートされていれば ls への引数になるだけで実行はされない。
+        let argv = match input["args"].as_array() {
+            Some(args) => {
+                let program = command.trim();
+                if program.is_empty() || program.contains(char::is_whitespace) {
+                    return ToolResult::error(
+                        "When 'args' is given, 'command' must be only the program name".into(),
+                    );
+                }
+                let mut argv = vec![program.to_string()];
+                for arg in args {
+                    match arg.as_str() {
+                        Some(a) => argv.push(a.to_string()),
+                        None => {
+                            return ToolResult::error("'args' must be an array of strings".into())
+                        }
+                    }
+                }
+                argv
             }
-        }
+            None => match process::split_command_line(command) {
+                Ok(argv) => argv,
+                Err(e) => return ToolResult::error(e),
+            },
+        };
 
-        // コマンドのホワイトリストチェック（先頭トークンのみ完全一致）
-        let base_command = command.split_whitespace().next().unwrap_or("");
+        // コマンドのホワイトリストチェック（プログラム名の完全一致）
+        let program = argv[0].as_str();
         if !ctx
             .allowed_commands
             .iter()
-            .any(|allowed| base_command == allowed.as_str())
+            .any(|allowed| program == allowed.as_str())
         {
             return ToolResult::error(format!(
                 "Command '{}' is not in the allowed command list. Allowed: {:?}",
-                base_command, ctx.allowed_commands
+                program, ctx.allowed_commands
             ));
         }
 
-        // プロセス実行
-        let timeout = std::time::Duration::from_millis(ctx.shell_timeout_ms);
-        let start = std::time::Instant::now();
-
-        let shell = if cfg!(target_os = "windows") {
-            "cmd"
-        } else {
-            "bash"
-        };
-        let shell_arg = if cfg!(target_os = "windows") {
-            "/C"
-        } else {
-            "-c"
+        let working_dir = match sandbox::resolve_command_dir(ctx, input["working_dir"].as_str()) {
+            Ok(dir) => dir,
+            Err(e) => return ToolResult::error(e),
         };
 
-        let output = match tokio::time::timeout(timeout, async {
-            tokio::process::Command::new(shell)
-                .arg(shell_arg)
-                .arg(command)
-                .current_dir(&working_dir)
-                .output()
-                .await
-        })
+        // プロセス実行
+        let timeout = std::time::Duration::from_millis(ctx.shell_timeout_ms);
+        let output = match process::run(
+            program,
+            &argv[1..],
+            &working_dir,
+            timeout,
+            &ctx.process_limits,
+            MAX_OUTPUT_BYTES,
+        )
         .await
         {
-            Ok(Ok(output)) => output,
-            Ok(Err(e)) => return ToolResult::error(format!("Failed to execute command: {e}")),
-            Err(_) => {
-                return ToolResult::error(format!(
-                    "Command timed out after {}ms",
-                    ctx.shell_timeout_ms
-                ))
-            }
-        };
-
-        let duration_ms = start.elapsed().as_millis();
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-
-        // 出力サイズ制限（50KB）
-        let max_output = 50_000;
-        let stdout_truncated = if stdout.len() > max_output {
-            format!("{}...\n[truncated]", &stdout[..max_output])
-        } else {
-            stdout.to_string()
-        };
-        let stderr_truncated = if stderr.len() > max_output {
-            format!("{}...\n[truncated]", &stderr[..max_output])
-        } else {
-            stderr.to_string()
+            Ok(output) => output,
+            Err(e) => return ToolResult::error(e),
         };
+        if output.timed_out {
+            return ToolResult::error(format!(
+                "Command timed out after {}ms and was killed",
+                ctx.shell_timeout_ms
+            ));
+        }
 
         ToolResult::ok(
             serde_json::json!({
                 "command": command,
-                "exit_code": output.status.code(),
-                "stdout": stdout_truncated,
-                "stderr": stderr_truncated,
-                "duration_ms": duration_ms as u64,
-                "success": output.status.success()
+                "exit_code": output.exit_code,
+                "stdout": output.stdout,
+                "stderr": output.stderr,
+                "duration_ms": output.duration_ms,
+                "success": output.success
             })
             .to_string(),
         )
     }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: ShellExecConfig = config::parse(self.name(), config)?;
+        if let Some(commands) = config.allowed_commands {
+            if let Some(invalid) = commands
+                .iter()
+                .find(|c| c.is_empty() || c.contains(char::is_whitespace))
+            {
+                return Err(format!(
+                    "Invalid allowed_commands entry '{invalid}': must be a single command name"
+                ));
+            }
+            ctx.allowed_commands = commands;
+        }
+        if let Some(timeout_ms) = config.timeout_ms {
+            ctx.shell_timeout_ms =
+                config::check_range("timeout_ms", timeout_ms, MIN_TIMEOUT_MS, MAX_TIMEOUT_MS)?;
+        }
+        config::apply_process_limits(
+            ctx,
+            config::ProcessLimitsConfig {
+                env_passthrough: config.env_passthrough,
+                cpu_time_secs: config.cpu_time_secs,
+                memory_mb: config.memory_mb,
+                network: config.network,
+            },
+        )
+    }
 }
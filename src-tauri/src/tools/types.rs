@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
 
 /// ツール実行時のコンテキスト（セキュリティ境界を含む）
@@ -6,16 +7,32 @@ use std::path::PathBuf;
 pub struct ToolContext {
     /// ツール実行のワーキングディレクトリ
     pub working_dir: PathBuf,
-    /// ファイル書き込み許可ディレクトリ（allowlist）
+    /// ファイル書き込み許可ディレクトリ（allowlist、file_write / file_edit 共通）
     pub allowed_write_dirs: Vec<PathBuf>,
+    /// ファイル読み取り許可ディレクトリ（file_read / list_dir / grep_files / code_search 共通、書き込みとは別のallowlist）
+    pub allowed_read_dirs: Vec<PathBuf>,
     /// コマンド実行許可リスト
     pub allowed_commands: Vec<String>,
     /// Git操作許可レベル
     pub git_permission: GitPermission,
+    /// web_fetch の取得を許可するホスト（サブドメインを含む）。空なら制限なし
+    pub allowed_url_hosts: Vec<String>,
     /// HTTP要求タイムアウト（秒）
     pub http_timeout_secs: u64,
     /// シェル実行タイムアウト（ミリ秒）
     pub shell_timeout_ms: u64,
+    /// self_eval の各チェックのタイムアウト（ミリ秒）
+    pub self_eval_timeout_ms: u64,
+    /// shell_exec / self_eval の子プロセスに課す制限
+    pub process_limits: ProcessLimits,
+    /// エージェントの設定で指定済みのプロセス制限の項目（shell_exec と self_eval の両方で指定したら厳しい方を取る）
+    pub configured_limits: HashSet<&'static str>,
+    /// code_search が使う埋め込みプロバイダー名（None なら code_search は使えない）
+    pub embedding_provider: Option<String>,
+    /// 埋め込みモデル（None はプロバイダーの既定）
+    pub embedding_model: Option<String>,
+    /// 埋め込みの次元数（None はモデルの既定）
+    pub embedding_dimensions: Option<u32>,
 }
 
 impl Default for ToolContext {
@@ -25,6 +42,7 @@ impl Default for ToolContext {
             // 実際の初期化は lib.rs の setup() 内で明示的にプロジェクトルートを設定する。
             working_dir: PathBuf::from("."),
             allowed_write_dirs: vec![],
+            allowed_read_dirs: vec![],
             allowed_commands: vec![
                 // デフォルトは読み取り系のみ
                 "ls".into(),
@@ -36,13 +54,95 @@ impl Default for ToolContext {
                 "tree".into(),
             ],
             git_permission: GitPermission::ReadOnly,
+            allowed_url_hosts: vec![],
             http_timeout_secs: 30,
             shell_timeout_ms: 30_000,
+            self_eval_timeout_ms: 120_000,
+            process_limits: ProcessLimits::default(),
+            configured_limits: HashSet::new(),
+            embedding_provider: None,
+            embedding_model: None,
+            embedding_dimensions: None,
+        }
+    }
+}
+
+impl ToolContext {
+    /// プロジェクトルート（TEBIKI_PROJECT_ROOT、未設定ならカレントディレクトリ）を基準にした既定のコンテキスト。
+    /// エージェントごとの設定はこれに各ツールの apply_config() で上書きする。
+    pub fn for_project() -> Self {
+        let working_dir = std::env::var("TEBIKI_PROJECT_ROOT")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+        Self {
+            working_dir: working_dir.clone(),
+            allowed_write_dirs: vec![working_dir.clone()],
+            allowed_read_dirs: vec![working_dir],
+            embedding_provider: env("TEBIKI_EMBEDDING_PROVIDER"),
+            embedding_model: env("TEBIKI_EMBEDDING_MODEL"),
+            embedding_dimensions: env("TEBIKI_EMBEDDING_DIMENSIONS").and_then(|d| d.parse().ok()),
+            ..Default::default()
+        }
+    }
+}
+
+/// 子プロセスのサンドボックス設定（CPU時間・メモリ・ネットワークは Linux のみ有効）
+#[derive(Debug, Clone)]
+pub struct ProcessLimits {
+    /// 子プロセスに引き継ぐ環境変数（それ以外は渡さない）
+    pub env_passthrough: Vec<String>,
+    /// プロセスごとのCPU時間の上限（秒）
+    pub cpu_time_secs: u64,
+    /// プロセスごとのメモリ（データ領域）の上限（MB）
+    pub memory_mb: u64,
+    /// false ならネットワークから切り離した名前空間で実行する
+    pub network: bool,
+}
+
+impl Default for ProcessLimits {
+    fn default() -> Self {
+        Self {
+            // APIキーなどを渡さないよう、ビルドツールの動作に必要なものだけを引き継ぐ
+            env_passthrough: [
+                "PATH",
+                "HOME",
+                "USER",
+                "LOGNAME",
+                "LANG",
+                "LC_ALL",
+                "LC_CTYPE",
+                "TERM",
+                "TMPDIR",
+                "TZ",
+                "CARGO_HOME",
+                "RUSTUP_HOME",
+                "RUSTUP_TOOLCHAIN",
+                "BUN_INSTALL",
+                // Windows
+                "SYSTEMROOT",
+                "WINDIR",
+                "COMSPEC",
+                "PATHEXT",
+                "TEMP",
+                "TMP",
+                "USERPROFILE",
+                "APPDATA",
+                "LOCALAPPDATA",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            cpu_time_secs: 600,
+            memory_mb: 8_192,
+            network: true,
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum GitPermission {
     Disabled,
     ReadOnly,  // status, log, diff のみ
@@ -1,23 +1,186 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
 use async_trait::async_trait;
+use serde::Deserialize;
 
 use crate::llm::types::ToolDefinition;
 
+use super::config;
 use super::types::{ToolContext, ToolResult};
 use super::{Tool, ToolCategory};
 
-pub struct WebFetchTool {
-    client: reqwest::Client,
+/// エージェントごとに設定できるタイムアウトの範囲（秒）
+const MIN_TIMEOUT_SECS: u64 = 1;
+const MAX_TIMEOUT_SECS: u64 = 120;
+/// たどるリダイレクトの上限
+const MAX_REDIRECTS: usize = 10;
+
+/// agent_tool_permissions.config（web_fetch）
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WebFetchConfig {
+    /// 取得を許可するホスト（"example.com" はサブドメインも含む）。未指定なら制限なし
+    allowed_hosts: Option<Vec<String>>,
+    timeout_secs: Option<u64>,
+}
+
+/// インターネット上の宛先か。ループバック・プライベート・リンクローカル（169.254.169.254 など）は拒否する
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match embedded_ipv4(ip) {
+            Some(embedded) => is_public_ipv4(embedded),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+/// IPv4 へ変換されて届く IPv6 アドレスから宛先の IPv4 アドレスを取り出す
+/// （::ffff:0:0/96 の IPv4 射影、64:ff9b::/96 の NAT64、2002::/16 の 6to4）
+fn embedded_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    if let Some(mapped) = ip.to_ipv4_mapped() {
+        return Some(mapped);
+    }
+    let s = ip.segments();
+    let from_segments = |hi: u16, lo: u16| Ipv4Addr::from((u32::from(hi) << 16) | u32::from(lo));
+    match s {
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(from_segments(hi, lo)),
+        [0x2002, hi, lo, ..] => Some(from_segments(hi, lo)),
+        _ => None,
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // 100.64.0.0/10（キャリアグレード NAT）
+        || (a == 100 && (64..128).contains(&b))
+        // 198.18.0.0/15（ベンチマーク用）
+        || (a == 198 && (b == 18 || b == 19))
+        // 240.0.0.0/4（予約済み）
+        || a >= 240)
 }
 
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7（ユニークローカル）と fe80::/10（リンクローカル）
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        // 64:ff9b:1::/48（ローカル用の NAT64。変換先が分からない）
+        || ip.segments()[..3] == [0x64, 0xff9b, 1])
+}
+
+/// 取得してよい URL か（最初の URL とリダイレクトの各ホップで確認する）。
+/// IP アドレスで指定された宛先はここで、ホスト名は名前解決の時点で公開アドレスか確認する。
+fn check_url(allowed_hosts: &[String], url: &url::Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("URL '{url}' must use http:// or https://"));
+    }
+    let Some(host) = url.host() else {
+        return Err(format!("URL '{url}' has no host"));
+    };
+    let ip = match host {
+        url::Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+        url::Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+        url::Host::Domain(_) => None,
+    };
+    if ip.is_some_and(|ip| !is_public_ip(ip)) {
+        return Err(format!(
+            "'{url}' points to a private or loopback address, which web_fetch does not access"
+        ));
+    }
+
+    // 許可リスト（空のリストは制限なし）。"example.com" はサブドメインも含む
+    if allowed_hosts.is_empty() {
+        return Ok(());
+    }
+    let host = host.to_string().to_ascii_lowercase();
+    let allowed = allowed_hosts.iter().any(|allowed| {
+        host == *allowed
+            || host
+                .strip_suffix(allowed.as_str())
+                .is_some_and(|rest| rest.ends_with('.'))
+    });
+    if allowed {
+        Ok(())
+    } else {
+        Err(format!(
+            "Host '{host}' is not in the allowed host list. Allowed: {allowed_hosts:?}"
+        ))
+    }
+}
+
+/// 公開アドレスだけを返す名前解決。接続先そのものを確認するので、
+/// "localhost" や内部向けの名前、DNS の応答をすり替える攻撃でも内部へは接続しない。
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!(
+                    "'{host}' does not resolve to a public address; web_fetch does not access private or loopback addresses"
+                )
+                .into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// エラーの原因までつなげたメッセージ（リダイレクトを拒否した理由は source 側にある）
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+pub struct WebFetchTool;
+
 impl WebFetchTool {
-    pub fn new() -> Self {
-        let client = reqwest::Client::builder()
+    /// エージェントの許可リストで各リダイレクトを確認するクライアント。
+    /// 確認はリクエストを送る前に行われるので、許可されない宛先には届かない。
+    fn client(ctx: &ToolContext) -> Result<reqwest::Client, String> {
+        let allowed_hosts = ctx.allowed_url_hosts.clone();
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error(format!("Too many redirects (more than {MAX_REDIRECTS})"));
+            }
+            match check_url(&allowed_hosts, attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(format!("Redirect refused: {e}")),
+            }
+        });
+        reqwest::Client::builder()
             .connect_timeout(std::time::Duration::from_secs(10))
-            .timeout(std::time::Duration::from_secs(30))
+            .timeout(std::time::Duration::from_secs(ctx.http_timeout_secs))
             .user_agent("tebiki/0.1.0")
+            .redirect(redirect)
+            .dns_resolver(Arc::new(PublicResolver))
+            // プロキシ経由だと名前解決がプロキシ側で行われ、上の確認が効かない
+            .no_proxy()
             .build()
-            .expect("Failed to build HTTP client for WebFetchTool");
-        Self { client }
+            .map_err(|e| format!("Failed to build HTTP client: {e}"))
     }
 }
 
@@ -33,7 +196,7 @@ impl Tool for WebFetchTool {
     fn definition(&self) -> ToolDefinition {
         ToolDefinition {
             name: "web_fetch".to_string(),
-            description: "Fetch content from a web URL. Returns the page text content (HTML tags stripped). Use this to read documentation, articles, or any public web page.".to_string(),
+            description: "Fetch content from a web URL. Returns the page text content (HTML tags stripped). Use this to read documentation, articles, or any public web page. Private, loopback and link-local addresses cannot be fetched.".to_string(),
             input_schema: serde_json::json!({
                 "type": "object",
                 "properties": {
@@ -60,21 +223,28 @@ impl Tool for WebFetchTool {
         if !url.starts_with("http://") && !url.starts_with("https://") {
             return ToolResult::error("URL must start with http:// or https://".into());
         }
+        let parsed = match url::Url::parse(url) {
+            Ok(u) => u,
+            Err(e) => return ToolResult::error(format!("Invalid URL '{url}': {e}")),
+        };
+        if let Err(e) = check_url(&ctx.allowed_url_hosts, &parsed) {
+            return ToolResult::error(e);
+        }
+        let client = match Self::client(ctx) {
+            Ok(c) => c,
+            Err(e) => return ToolResult::error(e),
+        };
 
         let max_length = input["max_length"]
             .as_u64()
             .unwrap_or(50_000)
             .min(100_000) as usize;
 
-        let response = match self
-            .client
-            .get(url)
-            .timeout(std::time::Duration::from_secs(ctx.http_timeout_secs))
-            .send()
-            .await
-        {
+        let response = match client.get(parsed).send().await {
             Ok(r) => r,
-            Err(e) => return ToolResult::error(format!("HTTP request failed: {e}")),
+            Err(e) => {
+                return ToolResult::error(format!("HTTP request failed: {}", error_chain(&e)))
+            }
         };
 
         let status = response.status();
@@ -109,6 +279,38 @@ impl Tool for WebFetchTool {
             .to_string(),
         )
     }
+
+    fn apply_config(
+        &self,
+        config: &serde_json::Value,
+        ctx: &mut ToolContext,
+    ) -> Result<(), String> {
+        let config: WebFetchConfig = config::parse(self.name(), config)?;
+        if let Some(hosts) = config.allowed_hosts {
+            ctx.allowed_url_hosts = hosts
+                .iter()
+                .map(|host| {
+                    let host = host.trim().to_ascii_lowercase();
+                    if host.is_empty() || host.contains(['/', ':', '*']) || host.contains(char::is_whitespace) {
+                        Err(format!(
+                            "Invalid allowed_hosts entry '{host}': must be a bare host name such as 'docs.rs'"
+                        ))
+                    } else {
+                        Ok(host)
+                    }
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(timeout_secs) = config.timeout_secs {
+            ctx.http_timeout_secs = config::check_range(
+                "timeout_secs",
+                timeout_secs,
+                MIN_TIMEOUT_SECS,
+                MAX_TIMEOUT_SECS,
+            )?;
+        }
+        Ok(())
+    }
 }
 
 /// 簡易HTMLタグ除去（<script>, <style>ブロックも除去）
@@ -157,3 +359,57 @@ fn strip_html_tags(html: &str) -> String {
     }
     compressed.join("\n")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::dns::Resolve;
+
+    fn url(s: &str) -> url::Url {
+        url::Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn rejects_private_and_loopback_addresses() {
+        for blocked in [
+            "http://127.0.0.1:11419/api/agents",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.0.0.1/",
+            "http://192.168.1.1/",
+            "http://100.64.0.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[fe80::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            // NAT64 と 6to4 は埋め込まれた IPv4 アドレスで判定する
+            "http://[64:ff9b::127.0.0.1]/",
+            "http://[64:ff9b::a9fe:a9fe]/",
+            "http://[64:ff9b:1::a00:1]/",
+            "http://[2002:7f00:1::1]/",
+            "http://[2002:c0a8:101::]/",
+        ] {
+            assert!(check_url(&[], &url(blocked)).is_err(), "{blocked}");
+        }
+        assert!(check_url(&[], &url("https://93.184.215.14/")).is_ok());
+        assert!(check_url(&[], &url("https://[2606:4700::1111]/")).is_ok());
+        assert!(check_url(&[], &url("https://[64:ff9b::5db8:d70e]/")).is_ok());
+        assert!(check_url(&[], &url("https://[2002:5db8:d70e::1]/")).is_ok());
+    }
+
+    #[test]
+    fn checks_allowed_hosts_including_subdomains() {
+        let allowed = vec!["docs.rs".to_string()];
+        assert!(check_url(&allowed, &url("https://docs.rs/serde")).is_ok());
+        assert!(check_url(&allowed, &url("https://static.docs.rs/x.js")).is_ok());
+        assert!(check_url(&allowed, &url("https://evildocs.rs/")).is_err());
+        assert!(check_url(&allowed, &url("https://example.com/")).is_err());
+        assert!(check_url(&allowed, &url("ftp://docs.rs/")).is_err());
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_names_of_local_addresses() {
+        let name: reqwest::dns::Name = "localhost".parse().unwrap();
+        assert!(PublicResolver.resolve(name).await.is_err());
+    }
+}
@@ -1,23 +1,25 @@
 use async_trait::async_trait;
 
 use crate::llm::types::ToolDefinition;
+use crate::secrets;
 
 use super::types::{ToolContext, ToolResult};
 use super::{Tool, ToolCategory};
 
+/// APIキーの資格情報名（キーリング未保存なら同名の環境変数）
+const API_KEY_REF: &str = "BRAVE_SEARCH_API_KEY";
+
 pub struct WebSearchTool {
     client: reqwest::Client,
-    api_key: Option<String>,
 }
 
 impl WebSearchTool {
     pub fn new() -> Self {
-        let api_key = std::env::var("BRAVE_SEARCH_API_KEY").ok();
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(15))
             .build()
             .expect("Failed to build HTTP client for WebSearchTool");
-        Self { client, api_key }
+        Self { client }
     }
 }
 
@@ -59,19 +61,21 @@ impl Tool for WebSearchTool {
 
         let num_results = input["num_results"].as_u64().unwrap_or(5).min(20) as usize;
 
-        let api_key = match &self.api_key {
-            Some(k) => k,
-            None => {
+        // キーのローテーションを即時反映するため実行ごとに解決する
+        let api_key = match secrets::resolve(API_KEY_REF) {
+            Ok(Some(k)) => k,
+            Ok(None) => {
                 return ToolResult::error(
-                    "BRAVE_SEARCH_API_KEY not configured. Set this environment variable to enable web search.".into(),
+                    "BRAVE_SEARCH_API_KEY not configured. Save it as a credential or set this environment variable to enable web search.".into(),
                 )
             }
+            Err(e) => return ToolResult::error(e.to_string()),
         };
 
         let response = match self
             .client
             .get("https://api.search.brave.com/res/v1/web/search")
-            .header("X-Subscription-Token", api_key)
+            .header("X-Subscription-Token", &api_key)
             .header("Accept", "application/json")
             .query(&[("q", query), ("count", &num_results.to_string())])
             .send()
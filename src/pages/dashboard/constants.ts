@@ -112,16 +112,40 @@ export const AVAILABLE_TOOLS: {
 		description: "Brave Search APIで検索",
 		category: "readonly",
 	},
+	{
+		name: "file_read",
+		label: "ファイル読み込み",
+		description: "ファイルの内容を行範囲指定で取得",
+		category: "readonly",
+	},
+	{
+		name: "list_dir",
+		label: "ディレクトリ一覧",
+		description: "ディレクトリの一覧（.gitignore を考慮）",
+		category: "readonly",
+	},
+	{
+		name: "grep_files",
+		label: "ファイル検索",
+		description: "正規表現でファイル内容を検索",
+		category: "readonly",
+	},
 	{
 		name: "file_write",
 		label: "ファイル書き出し",
 		description: "ファイルの作成・上書き・追記",
 		category: "filesystem",
 	},
+	{
+		name: "file_edit",
+		label: "ファイル編集",
+		description: "文字列置換・パッチによる部分編集",
+		category: "filesystem",
+	},
 	{
 		name: "shell_exec",
 		label: "コマンド実行",
-		description: "許可リスト内のシェルコマンド実行",
+		description: "許可リスト内のコマンド実行（シェルなし・サンドボックス）",
 		category: "execution",
 	},
 	{
@@ -6,6 +6,7 @@ export type BadgeStatus =
 	| "completed"
 	| "failed"
 	| "awaiting"
+	| "queued"
 	| "skipped";
 
 /** ウィジェットのステータスバッジを更新する */
@@ -32,6 +33,16 @@ export function setOutputText(
 	if (outEl) outEl.textContent = text;
 }
 
+/** ウィジェットの出力エリアにテキストを追記する（ストリーミング用） */
+export function appendOutputText(
+	gridRef: Element,
+	widgetId: number | string,
+	text: string,
+): void {
+	const outEl = gridRef.querySelector(`[data-output-id="${widgetId}"]`);
+	if (outEl) outEl.textContent = (outEl.textContent ?? "") + text;
+}
+
 /** data-widget-type属性からWidgetTypeを取得する */
 export function getWidgetType(
 	gridRef: Element,
@@ -191,9 +191,66 @@ export interface BackendToolDef {
 export interface AgentToolConfig {
 	tool_name: string;
 	is_enabled: boolean;
+	/** ツールごとの設定（ToolConfigMap の型）。未指定の項目はプロジェクトの既定値 */
 	config?: Record<string, unknown>;
 }
 
+/** ツールごとに設定できる項目（保存時にバックエンドで検証される） */
+export interface ToolConfigMap {
+	shell_exec: {
+		/** 実行を許可するコマンド名（既定の読み取り系コマンドを置き換える） */
+		allowed_commands?: string[];
+		timeout_ms?: number;
+		/** 子プロセスに引き継ぐ環境変数（既定のリストを置き換える）。以下のプロセス制限は self_eval と共有し、両方で指定すると厳しい方になる */
+		env_passthrough?: string[];
+		cpu_time_secs?: number;
+		memory_mb?: number;
+		/** false でネットワークを使えなくする（Linux のみ） */
+		network?: boolean;
+	};
+	file_read: {
+		/** プロジェクトルート基準の読み取り許可ディレクトリ（空配列で読み取り禁止）。list_dir / grep_files / code_search と共有し、複数指定すると共通部分になる */
+		allowed_read_dirs?: string[];
+	};
+	list_dir: {
+		/** file_read と共有の読み取り許可ディレクトリ */
+		allowed_read_dirs?: string[];
+	};
+	grep_files: {
+		/** file_read と共有の読み取り許可ディレクトリ */
+		allowed_read_dirs?: string[];
+	};
+	code_search: {
+		/** file_read と共有の読み取り許可ディレクトリ（この中のファイルだけを埋め込みに送る） */
+		allowed_read_dirs?: string[];
+	};
+	file_write: {
+		/** プロジェクトルート基準の書き込み許可ディレクトリ（空配列で書き込み禁止）。file_edit と共有し、複数指定すると共通部分になる */
+		allowed_write_dirs?: string[];
+	};
+	file_edit: {
+		/** file_write と共有の書き込み許可ディレクトリ */
+		allowed_write_dirs?: string[];
+	};
+	git_ops: {
+		permission?: "disabled" | "read_only" | "read_write";
+	};
+	web_fetch: {
+		/** 取得を許可するホスト（サブドメインを含む） */
+		allowed_hosts?: string[];
+		timeout_secs?: number;
+	};
+	self_eval: {
+		timeout_ms?: number;
+		/** shell_exec と共有のプロセス制限 */
+		env_passthrough?: string[];
+		cpu_time_secs?: number;
+		memory_mb?: number;
+		/** false でネットワークを使えなくする（Linux のみ） */
+		network?: boolean;
+	};
+}
+
 /** エージェントのツール権限レコード（DBから取得） */
 export interface AgentToolPermission {
 	id: string;
@@ -205,6 +262,32 @@ export interface AgentToolPermission {
 	updated_at: string;
 }
 
+/** ツール呼び出しの監査ログ（tool_executions） */
+export interface ToolExecution {
+	id: string;
+	execution_id: string | null;
+	orchestration_run_id: string | null;
+	tool_use_id: string | null;
+	tool_name: string;
+	input: unknown;
+	output: string | null;
+	/** 出力が保存上限で切り詰められた */
+	output_truncated: boolean;
+	is_error: boolean;
+	duration_ms: number | null;
+	created_at: string;
+}
+
+/** ツール実行履歴の絞り込み条件（since / until は ISO 8601） */
+export interface ToolExecutionQuery {
+	execution_id?: string;
+	orchestration_run_id?: string;
+	tool_name?: string;
+	since?: string;
+	until?: string;
+	limit?: number;
+}
+
 // === 10. コラボレーション ===
 export interface CollaborationUser {
 	id: string;
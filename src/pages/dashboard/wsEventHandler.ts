@@ -2,7 +2,11 @@ import type { GridStack } from "gridstack";
 import type { EventEnvelope } from "../../utils/agent";
 import { executeAgent } from "../../utils/agent";
 import { TYPE_LABELS } from "./constants";
-import { setOutputText, updateStatusBadge } from "./domHelpers";
+import {
+	appendOutputText,
+	setOutputText,
+	updateStatusBadge,
+} from "./domHelpers";
 import {
 	buildAugmentedPrompt,
 	collectUpstreamOutputs,
@@ -33,8 +37,47 @@ export function handleWsEvent(
 ): void {
 	const evt = event.event;
 	if (evt.type === "AgentExecutionStarted") {
+		const wid = resolveWidgetId(gridRef, evt.agent_id);
+		if (wid) {
+			updateStatusBadge(gridRef, wid, "running", "実行中...");
+			setOutputText(gridRef, wid, "");
+		}
+	} else if (evt.type === "AgentExecutionQueued") {
+		const wid = resolveWidgetId(gridRef, evt.agent_id);
+		if (wid) updateStatusBadge(gridRef, wid, "queued", "待機中...");
+	} else if (evt.type === "AgentExecutionDequeued") {
 		const wid = resolveWidgetId(gridRef, evt.agent_id);
 		if (wid) updateStatusBadge(gridRef, wid, "running", "実行中...");
+	} else if (evt.type === "AgentExecutionDelta") {
+		// ストリーミング中のテキストを逐次表示（tool_use・思考の増分は表示しない）
+		if (evt.delta.type === "text") {
+			const wid = resolveWidgetId(gridRef, evt.agent_id);
+			if (wid) appendOutputText(gridRef, wid, evt.delta.text);
+		}
+	} else if (evt.type === "AgentExecutionRetrying") {
+		// 再試行では途中まで流れたテキストを破棄する
+		const wid = resolveWidgetId(gridRef, evt.agent_id);
+		if (wid) {
+			updateStatusBadge(
+				gridRef,
+				wid,
+				"running",
+				`再試行中 (${evt.attempt}/${evt.max_attempts})...`,
+			);
+			setOutputText(gridRef, wid, "");
+		}
+	} else if (evt.type === "AgentExecutionFallback") {
+		// フォールバック先で最初から生成し直すため、途中まで流れたテキストを破棄する
+		const wid = resolveWidgetId(gridRef, evt.agent_id);
+		if (wid) {
+			updateStatusBadge(
+				gridRef,
+				wid,
+				"running",
+				`${evt.to_model} に切り替え中...`,
+			);
+			setOutputText(gridRef, wid, "");
+		}
 	} else if (evt.type === "AgentExecutionCompleted") {
 		const wid = resolveWidgetId(gridRef, evt.agent_id);
 		if (wid) {
@@ -47,6 +90,13 @@ export function handleWsEvent(
 				triggerAutoChain(widNum, gridRef, wsCtx);
 			}
 		}
+	} else if (evt.type === "AgentExecutionTruncated") {
+		// 途中までの出力なので後続のパネルには流さない
+		const wid = resolveWidgetId(gridRef, evt.agent_id);
+		if (wid) {
+			updateStatusBadge(gridRef, wid, "failed", "出力上限で打ち切り");
+			setOutputText(gridRef, wid, evt.output);
+		}
 	} else if (evt.type === "AgentExecutionFailed") {
 		const wid = resolveWidgetId(gridRef, evt.agent_id);
 		if (wid) {
@@ -169,6 +219,12 @@ export function handleWsEvent(
 				triggerAutoChain(widNum, gridRef, wsCtx);
 			}
 		}
+	} else if (evt.type === "OrchestratorTruncated") {
+		const wid = resolveWidgetId(gridRef, evt.orchestrator_agent_id);
+		if (wid) {
+			updateStatusBadge(gridRef, wid, "failed", "出力上限で打ち切り");
+			setOutputText(gridRef, wid, evt.output);
+		}
 	} else if (evt.type === "OrchestratorFailed") {
 		const wid = resolveWidgetId(gridRef, evt.orchestrator_agent_id);
 		if (wid) {
@@ -1,4 +1,10 @@
-import type { AgentToolConfig, AgentToolPermission, BackendToolDef } from "../pages/dashboard/types";
+import type {
+	AgentToolConfig,
+	AgentToolPermission,
+	BackendToolDef,
+	ToolExecution,
+	ToolExecutionQuery,
+} from "../pages/dashboard/types";
 import { apiCall } from "./api";
 
 // --- Type Definitions ---
@@ -9,10 +15,59 @@ export interface LlmProvider {
 	display_name: string;
 	api_base_url: string | null;
 	is_enabled: boolean;
+	provider_type: string;
+	api_key_ref: string | null;
+	requests_per_minute: number | null;
+	tokens_per_minute: number | null;
+	max_concurrency: number | null;
+	/** provider_type が replay なら再生元、record_cassette なら記録先（データディレクトリの cassettes/ からの相対パス）のカセット */
+	cassette_path: string | null;
+	/** 呼び出しを cassette_path に記録するか（replay 以外） */
+	record_cassette: boolean;
 	created_at: string;
 	updated_at: string;
 }
 
+export interface ProviderStatus {
+	name: string;
+	available: boolean;
+	/** 失敗理由。available でも埋め込みの生成に失敗したときはその理由が入る */
+	error: string | null;
+}
+
+export interface ModelInfo {
+	id: string;
+	display_name: string | null;
+	context_window: number | null;
+	max_output_tokens: number | null;
+	/** USD / 100万トークン */
+	input_price: number | null;
+	output_price: number | null;
+}
+
+export interface ModelCatalog {
+	provider_name: string;
+	/** static はモデル一覧を取得できず組み込みの一覧を返したとき */
+	source: "api" | "static";
+	error: string | null;
+	models: ModelInfo[];
+}
+
+export type EmbeddingTask = "query" | "document" | "similarity";
+
+export interface EmbeddingsResponse {
+	provider_name: string;
+	model: string;
+	dimensions: number;
+	/** inputs と同じ順序 */
+	embeddings: number[][];
+}
+
+export interface CredentialInfo {
+	name: string;
+	updated_at: string;
+}
+
 export interface Workflow {
 	id: string;
 	user_id: string;
@@ -34,10 +89,63 @@ export interface Agent {
 	temperature: number;
 	max_tokens: number;
 	is_active: boolean;
+	/** プロンプトキャッシュを使う（Anthropic のみ有効） */
+	prompt_cache: boolean;
+	/** 拡張思考のトークン予算（null は無効） */
+	thinking_budget_tokens: number | null;
+	/** 出力を従わせる JSON Schema（null は自由形式） */
+	output_schema: Record<string, unknown> | null;
+	/** フォールバック先に切り替える失敗の種類 */
+	fallback_on: FallbackOn;
+	/** 作成するサブエージェントのプロバイダー（null は自身と同じ） */
+	sub_agent_llm_provider_id: string | null;
+	/** 作成するサブエージェントのモデル（null は自身と同じ） */
+	sub_agent_model: string | null;
+	top_p: number | null;
+	top_k: number | null;
+	stop_sequences: string[];
+	seed: number | null;
+	/** ツール使用の指定（オーケストレーターの呼び出しにだけ適用） */
+	tool_choice: ToolChoice | null;
+	/** 出力上限で打ち切られた応答の続きを書かせる回数（0 なら truncated で終える） */
+	max_continuations: number;
 	created_at: string;
 	updated_at: string;
 }
 
+/** any / tool は最初のツール呼び出しまで強制する */
+export type ToolChoice =
+	| { type: "auto" }
+	| { type: "any" }
+	| { type: "none" }
+	| { type: "tool"; name: string };
+
+/**
+ * unavailable: リトライで回復しなかった 429 / 408 / 5xx / 接続断
+ * server_error: 上記のうち 429 以外
+ * any_error: 4xx や未登録のプロバイダーを含むすべての LLM エラー
+ */
+export type FallbackOn = "unavailable" | "server_error" | "any_error";
+
+export interface AgentFallbackModel {
+	id: string;
+	agent_id: string;
+	position: number;
+	llm_provider_id: string;
+	model: string;
+	created_at: string;
+	updated_at: string;
+}
+
+export interface AgentRouting {
+	agent_id: string;
+	fallback_on: FallbackOn;
+	sub_agent_llm_provider_id: string | null;
+	sub_agent_model: string | null;
+	/** position の昇順に試す */
+	fallbacks: AgentFallbackModel[];
+}
+
 export interface AgentExecution {
 	id: string;
 	agent_id: string;
@@ -52,11 +160,25 @@ export interface AgentExecution {
 	completed_at: string | null;
 	created_at: string;
 	updated_at: string;
+	total_input_tokens: number;
+	total_output_tokens: number;
+	total_cost_usd: number;
+	/** 拡張思考の内容（output_text とは別に保存される） */
+	thinking_text: string | null;
+	/** この実行に適用した出力スキーマ */
+	output_schema: Record<string, unknown> | null;
+	/** output_schema で検証済みの出力 */
+	output_json: unknown | null;
+	/** 実際に応答したプロバイダーとモデル（フォールバック時は切り替え先） */
+	answered_provider: string | null;
+	answered_model: string | null;
 }
 
 export interface TokenUsage {
 	input_tokens: number;
 	output_tokens: number;
+	cache_read_tokens?: number;
+	cache_write_tokens?: number;
 }
 
 export interface AgentMessage {
@@ -87,8 +209,48 @@ export interface OrchestrationRun {
 	final_output: string | null;
 	created_at: string;
 	updated_at: string;
+	total_input_tokens: number;
+	total_output_tokens: number;
+	total_cost_usd: number;
+	budget_max_tokens: number | null;
+	budget_max_cost_usd: number | null;
+	context_token_limit: number | null;
+	compaction_model: string | null;
+	/** 実行中に行った会話履歴の圧縮の記録 */
+	compaction_log: CompactionLogEntry[];
+}
+
+export interface CompactionLogEntry {
+	iteration: number;
+	at: string;
+	tokens_before: number;
+	tokens_after: number;
+	truncated_tool_results: number;
+	removed_messages: number;
+	summarized: boolean;
+	summary_model: string | null;
 }
 
+/** 長いツールループでの会話履歴の圧縮設定 */
+export interface ContextCompaction {
+	/** 圧縮を始める入力トークン数（未指定は 100,000） */
+	tokenLimit?: number;
+	/** 古いやり取りの要約に使う安価なモデル（未指定なら要約せずに削除） */
+	model?: string;
+}
+
+/** オーケストレーションの予算（サブエージェント分を含む。未指定は無制限） */
+export interface OrchestrationBudget {
+	maxTokens?: number;
+	maxCostUsd?: number;
+}
+
+export type StreamDelta =
+	| { type: "text"; text: string }
+	| { type: "thinking"; text: string }
+	| { type: "tool_use_start"; id: string; name: string }
+	| { type: "tool_use_input"; id: string; partial_json: string };
+
 export type ExecutionEvent =
 	| { type: "WorkflowRunStarted"; workflow_run_id: string; workflow_id: string }
 	| {
@@ -98,12 +260,44 @@ export type ExecutionEvent =
 			status: string;
 	  }
 	| { type: "AgentExecutionStarted"; execution_id: string; agent_id: string }
+	| {
+			type: "AgentExecutionQueued";
+			execution_id: string;
+			agent_id: string;
+			provider: string;
+	  }
+	| { type: "AgentExecutionDequeued"; execution_id: string; agent_id: string }
 	| {
 			type: "AgentExecutionProgress";
 			execution_id: string;
 			agent_id: string;
 			message: string;
 	  }
+	| {
+			type: "AgentExecutionDelta";
+			execution_id: string;
+			agent_id: string;
+			delta: StreamDelta;
+	  }
+	| {
+			type: "AgentExecutionRetrying";
+			execution_id: string;
+			agent_id: string;
+			attempt: number;
+			max_attempts: number;
+			delay_ms: number;
+			error: string;
+	  }
+	| {
+			type: "AgentExecutionFallback";
+			execution_id: string;
+			agent_id: string;
+			from_provider: string;
+			from_model: string;
+			to_provider: string;
+			to_model: string;
+			error: string;
+	  }
 	| {
 			type: "AgentExecutionCompleted";
 			execution_id: string;
@@ -111,6 +305,15 @@ export type ExecutionEvent =
 			output: string;
 			duration_ms: number;
 	  }
+	| {
+			/** 続きを書かせても出力上限で終わった（output は途中まで） */
+			type: "AgentExecutionTruncated";
+			execution_id: string;
+			agent_id: string;
+			output: string;
+			duration_ms: number;
+			continuations: number;
+	  }
 	| {
 			type: "AgentExecutionFailed";
 			execution_id: string;
@@ -154,6 +357,13 @@ export type ExecutionEvent =
 			orchestrator_agent_id: string;
 			output: string;
 	  }
+	| {
+			type: "OrchestratorTruncated";
+			orchestration_run_id: string;
+			orchestrator_agent_id: string;
+			output: string;
+			continuations: number;
+	  }
 	| {
 			type: "OrchestratorFailed";
 			orchestration_run_id: string;
@@ -163,11 +373,13 @@ export type ExecutionEvent =
 	| {
 			type: "ToolExecutionStarted";
 			execution_id: string;
+			tool_use_id: string;
 			tool_name: string;
 	  }
 	| {
 			type: "ToolExecutionCompleted";
 			execution_id: string;
+			tool_use_id: string;
 			tool_name: string;
 			duration_ms: number;
 			is_error: boolean;
@@ -183,6 +395,125 @@ export async function getLlmProviders(): Promise<LlmProvider[]> {
 	);
 }
 
+export async function createLlmProvider(params: {
+	name: string;
+	display_name: string;
+	provider_type: string;
+	api_base_url?: string;
+	api_key_ref?: string;
+	is_enabled?: boolean;
+	requests_per_minute?: number;
+	tokens_per_minute?: number;
+	max_concurrency?: number;
+	cassette_path?: string;
+	record_cassette?: boolean;
+}): Promise<LlmProvider> {
+	return apiCall<LlmProvider>(
+		"create_llm_provider",
+		"POST",
+		"/api/llm-providers",
+		params,
+	);
+}
+
+/** 未指定のフィールドは変更しない。api_base_url / api_key_ref / cassette_path は空文字、レート制限は 0 でクリア */
+export async function updateLlmProvider(
+	id: string,
+	params: {
+		display_name?: string;
+		provider_type?: string;
+		api_base_url?: string;
+		api_key_ref?: string;
+		is_enabled?: boolean;
+		requests_per_minute?: number;
+		tokens_per_minute?: number;
+		max_concurrency?: number;
+		cassette_path?: string;
+		record_cassette?: boolean;
+	},
+): Promise<LlmProvider> {
+	return apiCall<LlmProvider>(
+		"update_llm_provider",
+		"POST",
+		`/api/llm-providers/${id}`,
+		{ id, ...params },
+	);
+}
+
+export async function testLlmProvider(id: string): Promise<ProviderStatus> {
+	return apiCall<ProviderStatus>(
+		"test_llm_provider",
+		"POST",
+		`/api/llm-providers/${id}/test`,
+		{ id },
+	);
+}
+
+export async function listLlmProviderModels(id: string): Promise<ModelCatalog> {
+	return apiCall<ModelCatalog>(
+		"list_llm_provider_models",
+		"GET",
+		`/api/llm-providers/${id}/models`,
+		{ id },
+	);
+}
+
+export async function reloadLlmProviders(): Promise<ProviderStatus[]> {
+	return apiCall<ProviderStatus[]>(
+		"reload_llm_providers",
+		"POST",
+		"/api/llm-providers/reload",
+	);
+}
+
+// --- Embedding API Functions ---
+
+/** model 未指定はプロバイダーの既定の埋め込みモデル（OpenAI 互換は指定必須） */
+export async function createEmbeddings(params: {
+	provider_name: string;
+	inputs: string[];
+	model?: string;
+	task?: EmbeddingTask;
+	dimensions?: number;
+}): Promise<EmbeddingsResponse> {
+	return apiCall<EmbeddingsResponse>(
+		"create_embeddings",
+		"POST",
+		"/api/embeddings",
+		params,
+	);
+}
+
+// --- Credential API Functions ---
+
+export async function listCredentials(): Promise<CredentialInfo[]> {
+	return apiCall<CredentialInfo[]>(
+		"list_credentials",
+		"GET",
+		"/api/credentials",
+	);
+}
+
+/** 保存（既存の名前なら値を置き換え） */
+export async function setCredential(
+	name: string,
+	value: string,
+): Promise<CredentialInfo> {
+	return apiCall<CredentialInfo>("set_credential", "POST", "/api/credentials", {
+		name,
+		value,
+	});
+}
+
+export async function deleteCredential(name: string): Promise<void> {
+	return apiCall<void>(
+		"delete_credential",
+		"POST",
+		`/api/credentials/${encodeURIComponent(name)}/delete`,
+		{ name },
+	);
+}
+
 export async function createWorkflow(
 	userId: string,
 	name: string,
@@ -219,10 +550,52 @@ export async function createAgent(params: {
 	model?: string;
 	temperature?: number;
 	max_tokens?: number;
+	prompt_cache?: boolean;
+	/** 1024 以上 */
+	thinking_budget_tokens?: number;
+	/** ルートが "type": "object" の JSON Schema */
+	output_schema?: Record<string, unknown>;
+	/** 0〜1。拡張思考中の Anthropic は 0.95 以上 */
+	top_p?: number;
+	/** OpenAI 互換では使えない */
+	top_k?: number;
+	/** Google は5件、OpenAI 互換は4件まで */
+	stop_sequences?: string[];
+	/** Anthropic では使えない */
+	seed?: number;
+	tool_choice?: ToolChoice;
+	/** 0〜10 */
+	max_continuations?: number;
 }): Promise<Agent> {
 	return apiCall<Agent>("create_agent", "POST", "/api/agents", params);
 }
 
+export async function getAgentRouting(agentId: string): Promise<AgentRouting> {
+	return apiCall<AgentRouting>(
+		"get_agent_routing",
+		"GET",
+		`/api/agents/${agentId}/routing`,
+		{ agent_id: agentId },
+	);
+}
+
+/** フォールバック設定を丸ごと置き換える（fallbacks は配列の順に試す） */
+export async function updateAgentRouting(params: {
+	agent_id: string;
+	fallbacks: Array<{ llm_provider_id: string; model: string }>;
+	fallback_on?: FallbackOn;
+	/** 指定する場合は sub_agent_model も必須 */
+	sub_agent_llm_provider_id?: string;
+	sub_agent_model?: string;
+}): Promise<AgentRouting> {
+	return apiCall<AgentRouting>(
+		"update_agent_routing",
+		"POST",
+		"/api/agents/routing",
+		params,
+	);
+}
+
 export async function getAgents(workflowId: string): Promise<Agent[]> {
 	return apiCall<Agent[]>(
 		"get_agents",
@@ -232,13 +605,25 @@ export async function getAgents(workflowId: string): Promise<Agent[]> {
 	);
 }
 
+/**
+ * 実行に添付する画像（png / jpeg / gif / webp）または PDF。
+ * file はバックエンドがパスから読み込む（media_type 省略時は拡張子から推定）。
+ */
+export type Attachment =
+	| { type: "base64"; media_type: string; data: string; name?: string }
+	| { type: "file"; path: string; media_type?: string; name?: string };
+
 export async function executeAgent(
 	agentId: string,
 	input: string,
+	attachments?: Attachment[],
+	outputSchema?: Record<string, unknown>,
 ): Promise<AgentExecution> {
 	return apiCall<AgentExecution>("execute_agent", "POST", "/api/execute", {
 		agent_id: agentId,
 		input,
+		attachments: attachments ?? [],
+		output_schema: outputSchema ?? null,
 	});
 }
 
@@ -268,12 +653,22 @@ export async function orchestrateAgent(
 	agentId: string,
 	input: string,
 	mode: string,
+	budget?: OrchestrationBudget,
+	compaction?: ContextCompaction,
 ): Promise<OrchestrationRun> {
 	return apiCall<OrchestrationRun>(
 		"orchestrate_agent",
 		"POST",
 		"/api/orchestrate",
-		{ agent_id: agentId, input, mode },
+		{
+			agent_id: agentId,
+			input,
+			mode,
+			max_tokens_budget: budget?.maxTokens ?? null,
+			max_cost_usd: budget?.maxCostUsd ?? null,
+			context_token_limit: compaction?.tokenLimit ?? null,
+			compaction_model: compaction?.model ?? null,
+		},
 	);
 }
 
@@ -339,3 +734,19 @@ export async function updateToolPermissions(
 		{ agent_id: agentId, tools },
 	);
 }
+
+export async function listToolExecutions(
+	query: ToolExecutionQuery = {},
+): Promise<ToolExecution[]> {
+	const params = new URLSearchParams();
+	for (const [key, value] of Object.entries(query)) {
+		if (value !== undefined) params.set(key, String(value));
+	}
+	const search = params.toString();
+	return apiCall<ToolExecution[]>(
+		"list_tool_executions",
+		"GET",
+		search ? `/api/tool-executions?${search}` : "/api/tool-executions",
+		{ ...query },
+	);
+}